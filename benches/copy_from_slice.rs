@@ -0,0 +1,33 @@
+//! Compares the generic `From<&[T]>` with `copy_from_slice` on a 64 MB slice of bytes.
+//!
+//! Run with `cargo bench --bench copy_from_slice`. With optimizations, the compiler usually turns
+//! the element-wise clones of `u8` into a memcpy as well, so both paths are bound by the page
+//! faults of the fresh allocation; `--profile dev` shows the cost of the generic loop.
+
+use std::hint::black_box;
+use std::time::{Duration, Instant};
+
+use reference_counted::{Arc, Rc};
+
+const LEN: usize = 64 << 20;
+const RUNS: usize = 10;
+
+fn bench<R>(name: &str, mut f: impl FnMut() -> R) {
+    let mut best = Duration::MAX;
+    for _ in 0..RUNS {
+        let start = Instant::now();
+        drop(black_box(f()));
+        best = best.min(start.elapsed());
+    }
+    println!("{:<24} {:>10.2?}", name, best);
+}
+
+fn main() {
+    let src = vec![0x5a_u8; LEN];
+    let src = black_box(&src[..]);
+
+    bench("Arc::from", || Arc::<[u8]>::from(src));
+    bench("Arc::copy_from_slice", || Arc::copy_from_slice(src));
+    bench("Rc::from", || Rc::<[u8]>::from(src));
+    bench("Rc::copy_from_slice", || Rc::copy_from_slice(src));
+}
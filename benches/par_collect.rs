@@ -0,0 +1,39 @@
+//! Compares the ways of collecting 16 million items into an `Arc<[u64]>` with rayon.
+//!
+//! Run with `cargo bench --bench par_collect --features rayon`. Collecting an indexed iterator
+//! writes the items into the allocation in place, like `Arc::from_par_fn`, while going through
+//! a `Vec` first costs a second allocation and a copy of the whole slice.
+
+use std::hint::black_box;
+use std::time::{Duration, Instant};
+
+use rayon::prelude::*;
+use reference_counted::Arc;
+
+const LEN: usize = 16 << 20;
+const RUNS: usize = 10;
+
+fn bench<R>(name: &str, mut f: impl FnMut() -> R) {
+    let mut best = Duration::MAX;
+    for _ in 0..RUNS {
+        let start = Instant::now();
+        drop(black_box(f()));
+        best = best.min(start.elapsed());
+    }
+    println!("{:<24} {:>10.2?}", name, best);
+}
+
+fn item(i: usize) -> u64 {
+    (i as u64).wrapping_mul(0x9e37_79b9_7f4a_7c15)
+}
+
+fn main() {
+    let len = black_box(LEN);
+
+    bench("sequential", || Arc::<[u64]>::from((0..len).map(item).collect::<Vec<_>>()));
+    bench("through Vec", || {
+        Arc::<[u64]>::from((0..len).into_par_iter().map(item).collect::<Vec<_>>())
+    });
+    bench("collect", || (0..len).into_par_iter().map(item).collect::<Arc<[u64]>>());
+    bench("Arc::from_par_fn", || Arc::<[u64]>::from_par_fn(len, item));
+}
@@ -0,0 +1,76 @@
+//! Functions that create, clone and drop pointers, which must not contain any panicking code
+//! paths with the `panic-free` feature. `tests/panic_free.rs` checks their LLVM IR.
+
+use std::hint::black_box;
+
+use reference_counted::{Align16, AlignedBytes, Arc, Rc};
+use smart_pointer::SmartPointer;
+
+#[no_mangle]
+pub extern "C" fn canary_arc(value: u64) -> u64 {
+    let arc = Arc::new(value);
+    let clone = arc.clone();
+    drop(arc);
+    *clone
+}
+
+#[no_mangle]
+pub extern "C" fn canary_rc(value: u64) -> u64 {
+    let rc = Rc::new(value);
+    let clone = rc.clone();
+    drop(rc);
+    *clone
+}
+
+#[no_mangle]
+pub extern "C" fn canary_arc_slice(value: u64, len: usize) -> usize {
+    let slice = Arc::from_elem(value, len);
+    let clone = slice.clone();
+    drop(slice);
+    clone.len()
+}
+
+#[no_mangle]
+pub extern "C" fn canary_rc_slice(value: u64, len: usize) -> usize {
+    let slice = Rc::from_elem(value, len);
+    let clone = slice.clone();
+    drop(slice);
+    clone.len()
+}
+
+#[no_mangle]
+pub extern "C" fn canary_arc_str() -> usize {
+    let s: Arc<str> = Arc::from(black_box("ab"));
+    let shared = s.clone();
+    // Copies the shared string, then appends in place.
+    let mut s = s + black_box("cd");
+    s += black_box("ef");
+    drop(shared);
+    s.len()
+}
+
+#[no_mangle]
+pub extern "C" fn canary_rc_str() -> usize {
+    let s: Rc<str> = Rc::from(black_box("ab"));
+    let shared = s.clone();
+    let mut s = s + black_box("cd");
+    s += black_box("ef");
+    drop(shared);
+    s.len()
+}
+
+#[no_mangle]
+pub extern "C" fn canary_aligned(len: usize) -> usize {
+    let bytes = Arc::<AlignedBytes<Align16>>::new_zeroed_aligned(len);
+    bytes.len()
+}
+
+fn main() {
+    black_box(canary_arc(black_box(1)));
+    black_box(canary_rc(black_box(2)));
+    black_box(canary_arc_slice(black_box(3), black_box(4)));
+    black_box(canary_rc_slice(black_box(5), black_box(6)));
+    black_box(canary_arc_str());
+    black_box(canary_rc_str());
+    black_box(canary_aligned(black_box(7)));
+}
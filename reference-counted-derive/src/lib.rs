@@ -0,0 +1,332 @@
+//! The derive macro behind the `derive` feature of the `reference-counted` crate. See the
+//! documentation of `reference_counted::ReferenceCounted` for how to use it.
+//!
+//! The input is parsed by hand rather than with `syn`, since only a tiny subset of Rust syntax
+//! (a tuple struct with a single field) is accepted.
+extern crate proc_macro;
+
+use proc_macro::{Delimiter, Spacing, TokenStream, TokenTree};
+
+/// Implements `ReferenceCounted`, `IntoMut` and `SmartPointer` (and the traits these require)
+/// for a tuple struct with a single field, by delegating to that field. The field must be a
+/// pointer type whose first generic type argument is the pointee, such as `Arc<T>`.
+///
+/// The `IntoMut::MutablePointer` of a struct `Name` is a generated struct `UniqueName` with
+/// the same visibility, which wraps the `MutablePointer` of the field.
+#[proc_macro_derive(ReferenceCounted)]
+pub fn derive_reference_counted(input: TokenStream) -> TokenStream {
+    let output = match Newtype::parse(input) {
+        Ok(newtype) => newtype.expand(),
+        Err(msg) => format!("compile_error!({:?});", msg),
+    };
+    output.parse().unwrap()
+}
+
+/// The parts of the struct definition that the generated code needs, as source text.
+struct Newtype {
+    vis: String,
+    name: String,
+    // The generic parameters with bounds, without defaults.
+    impl_generics: String,
+    // The names of the generic parameters.
+    type_generics: String,
+    // The predicates of the where clause, each followed by a comma.
+    predicates: String,
+    field: String,
+    // The type the field points to.
+    target: String,
+}
+
+impl Newtype {
+    fn parse(input: TokenStream) -> Result<Newtype, String> {
+        let tokens: Vec<TokenTree> = input.into_iter().collect();
+        let mut i = 0;
+
+        // Outer attributes, including doc comments.
+        while is_punct(tokens.get(i), '#') {
+            i += 2;
+        }
+
+        let mut vis = Vec::new();
+        if is_ident(tokens.get(i), "pub") {
+            vis.push(tokens[i].clone());
+            i += 1;
+            if let Some(TokenTree::Group(group)) = tokens.get(i) {
+                if group.delimiter() == Delimiter::Parenthesis {
+                    vis.push(tokens[i].clone());
+                    i += 1;
+                }
+            }
+        }
+
+        if !is_ident(tokens.get(i), "struct") {
+            return Err(ONLY_NEWTYPES.into());
+        }
+        i += 1;
+
+        let name = match tokens.get(i) {
+            Some(TokenTree::Ident(name)) => name.to_string(),
+            _ => return Err(ONLY_NEWTYPES.into()),
+        };
+        i += 1;
+
+        let mut params = Vec::new();
+        if is_punct(tokens.get(i), '<') {
+            i += 1;
+            let start = i;
+            let mut depth = 0;
+            while i < tokens.len() {
+                if is_open_angle(&tokens, i) {
+                    depth += 1;
+                } else if is_close_angle(&tokens, i) {
+                    if depth == 0 {
+                        break;
+                    }
+                    depth -= 1;
+                }
+                i += 1;
+            }
+            params = split_commas(&tokens[start..i]);
+            i += 1;
+        }
+
+        let (field, target) = match tokens.get(i) {
+            Some(TokenTree::Group(group)) if group.delimiter() == Delimiter::Parenthesis => {
+                let fields: Vec<TokenTree> = group.stream().into_iter().collect();
+                match split_commas(&fields)[..] {
+                    [field] => {
+                        let field = strip_field_prefix(field);
+                        (to_source(field), to_source(pointee(field).ok_or(NO_POINTEE)?))
+                    }
+                    _ => return Err(ONLY_NEWTYPES.into()),
+                }
+            }
+            _ => return Err(ONLY_NEWTYPES.into()),
+        };
+        i += 1;
+
+        let mut predicates = String::new();
+        if is_ident(tokens.get(i), "where") {
+            for predicate in split_commas(&tokens[i + 1..tokens.len() - 1]) {
+                predicates.push_str(&to_source(predicate));
+                predicates.push_str(", ");
+            }
+        }
+
+        let mut impl_generics = Vec::new();
+        let mut type_generics = Vec::new();
+        for param in params {
+            let without_default = match param.iter().position(|t| is_punct(Some(t), '=')) {
+                Some(end) => &param[..end],
+                None => param,
+            };
+            impl_generics.push(to_source(without_default));
+            type_generics.push(param_name(param));
+        }
+
+        Ok(Newtype {
+            vis: to_source(&vis),
+            name,
+            impl_generics: angle_brackets(&impl_generics),
+            type_generics: angle_brackets(&type_generics),
+            predicates,
+            field,
+            target,
+        })
+    }
+
+    fn expand(&self) -> String {
+        let Newtype { vis, name, impl_generics: ig, type_generics: tg, predicates, field, target } =
+            self;
+        let unique = format!("Unique{}", name);
+        let rc = "::reference_counted";
+        let sp = "::reference_counted::__private::smart_pointer";
+        let mutable = format!("<{} as {}::IntoMut<{}>>::MutablePointer", field, sp, target);
+        let wh = format!("where {} {}: {}::ReferenceCounted<{}>,", predicates, field, rc, target);
+
+        let mut out = String::new();
+
+        // The unique wrapper.
+        out += &format!(
+            "/// The unique counterpart of `{name}`, generated by `#[derive(ReferenceCounted)]`.\n\
+             {vis} struct {unique}{ig}({mutable}) {wh};",
+            name = name, vis = vis, unique = unique, ig = ig, mutable = mutable, wh = wh,
+        );
+
+        // The traits that both structs implement the same way.
+        for ty in &[name, &unique] {
+            out += &format!(
+                "impl{ig} ::core::ops::Deref for {ty}{tg} {wh} {{
+                    type Target = {target};
+                    fn deref(&self) -> &{target} {{ &*self.0 }}
+                }}
+                impl{ig} ::core::convert::AsRef<{target}> for {ty}{tg} {wh} {{
+                    fn as_ref(&self) -> &{target} {{ &*self.0 }}
+                }}
+                impl{ig} ::core::borrow::Borrow<{target}> for {ty}{tg} {wh} {{
+                    fn borrow(&self) -> &{target} {{ &*self.0 }}
+                }}
+                impl{ig} ::core::fmt::Pointer for {ty}{tg} {wh} {{
+                    fn fmt(&self, f: &mut ::core::fmt::Formatter<'_>) -> ::core::fmt::Result {{
+                        ::core::fmt::Pointer::fmt(&self.0, f)
+                    }}
+                }}
+                impl{ig} {sp}::SmartPointer<{target}> for {ty}{tg} {wh} {{
+                    fn new(value: {target}) -> Self where {target}: ::core::marker::Sized {{
+                        {ty}({sp}::SmartPointer::new(value))
+                    }}
+                    fn try_unwrap(this: Self) -> ::core::result::Result<{target}, Self>
+                    where
+                        {target}: ::core::marker::Sized,
+                    {{
+                        {sp}::SmartPointer::try_unwrap(this.0).map_err({ty})
+                    }}
+                }}",
+                ig = ig, tg = tg, ty = ty, wh = wh, target = target, sp = sp,
+            );
+        }
+
+        out += &format!(
+            "impl{ig} ::core::clone::Clone for {name}{tg} {wh} {{
+                fn clone(&self) -> Self {{ {name}(::core::clone::Clone::clone(&self.0)) }}
+            }}
+            impl{ig} {sp}::IntoMut<{target}> for {name}{tg} {wh} {{
+                type MutablePointer = {unique}{tg};
+                fn can_make_mut(this: &Self) -> bool {{
+                    <{field} as {sp}::IntoMut<{target}>>::can_make_mut(&this.0)
+                }}
+                unsafe fn into_mut_unchecked(this: Self) -> {unique}{tg} {{
+                    {unique}(<{field} as {sp}::IntoMut<{target}>>::into_mut_unchecked(this.0))
+                }}
+                unsafe fn get_mut_unchecked(this: &Self) -> &mut {target} {{
+                    <{field} as {sp}::IntoMut<{target}>>::get_mut_unchecked(&this.0)
+                }}
+            }}
+            impl{ig} {rc}::ReferenceCounted<{target}> for {name}{tg} {wh} {{
+                fn reference_count(this: &Self) -> ::core::num::NonZeroUsize {{
+                    <{field} as {rc}::ReferenceCounted<{target}>>::reference_count(&this.0)
+                }}
+            }}
+            impl{ig} ::core::ops::DerefMut for {unique}{tg} {wh} {{
+                fn deref_mut(&mut self) -> &mut {target} {{ &mut *self.0 }}
+            }}
+            impl{ig} ::core::convert::AsMut<{target}> for {unique}{tg} {wh} {{
+                fn as_mut(&mut self) -> &mut {target} {{ &mut *self.0 }}
+            }}
+            impl{ig} ::core::borrow::BorrowMut<{target}> for {unique}{tg} {wh} {{
+                fn borrow_mut(&mut self) -> &mut {target} {{ &mut *self.0 }}
+            }}
+            impl{ig} ::core::fmt::Debug for {unique}{tg} {wh} {mutable}: ::core::fmt::Debug {{
+                fn fmt(&self, f: &mut ::core::fmt::Formatter<'_>) -> ::core::fmt::Result {{
+                    ::core::fmt::Debug::fmt(&self.0, f)
+                }}
+            }}
+            impl{ig} {sp}::SmartPointerMut<{target}> for {unique}{tg} {wh} {{}}
+            impl{ig} ::core::convert::From<{unique}{tg}> for {name}{tg} {wh} {{
+                fn from(unique: {unique}{tg}) -> Self {{ {name}(::core::convert::Into::into(unique.0)) }}
+            }}",
+            ig = ig, tg = tg, name = name, unique = unique, wh = wh, target = target, sp = sp,
+            rc = rc, field = field, mutable = mutable,
+        );
+
+        out
+    }
+}
+
+const ONLY_NEWTYPES: &str =
+    "`#[derive(ReferenceCounted)]` only supports tuple structs with exactly one field";
+
+const NO_POINTEE: &str =
+    "`#[derive(ReferenceCounted)]` requires the field to be a pointer like `Arc<T>`, whose first \
+     generic argument is the type it points to";
+
+fn is_punct(token: Option<&TokenTree>, c: char) -> bool {
+    matches!(token, Some(TokenTree::Punct(p)) if p.as_char() == c)
+}
+
+fn is_ident(token: Option<&TokenTree>, s: &str) -> bool {
+    matches!(token, Some(TokenTree::Ident(i)) if i.to_string() == s)
+}
+
+fn is_open_angle(tokens: &[TokenTree], i: usize) -> bool {
+    is_punct(tokens.get(i), '<')
+}
+
+/// Whether `tokens[i]` closes an angle bracket, as opposed to being part of `->` or `=>`.
+fn is_close_angle(tokens: &[TokenTree], i: usize) -> bool {
+    if !is_punct(tokens.get(i), '>') {
+        return false;
+    }
+    match i.checked_sub(1).and_then(|j| tokens.get(j)) {
+        Some(TokenTree::Punct(p)) => {
+            !(p.spacing() == Spacing::Joint && (p.as_char() == '-' || p.as_char() == '='))
+        }
+        _ => true,
+    }
+}
+
+/// Splits at the commas that are not nested in angle brackets, dropping empty trailing parts.
+fn split_commas(tokens: &[TokenTree]) -> Vec<&[TokenTree]> {
+    let mut parts = Vec::new();
+    let mut depth = 0usize;
+    let mut start = 0;
+    for i in 0..tokens.len() {
+        if is_open_angle(tokens, i) {
+            depth += 1;
+        } else if is_close_angle(tokens, i) {
+            depth = depth.saturating_sub(1);
+        } else if depth == 0 && is_punct(tokens.get(i), ',') {
+            parts.push(&tokens[start..i]);
+            start = i + 1;
+        }
+    }
+    if start < tokens.len() {
+        parts.push(&tokens[start..]);
+    }
+    parts
+}
+
+/// Gets the first generic type argument of a path type like `Arc<T>` or `crate::Rc<T>`.
+fn pointee(ty: &[TokenTree]) -> Option<&[TokenTree]> {
+    let start = ty.iter().position(|t| is_punct(Some(t), '<'))? + 1;
+    let end = (start..ty.len()).rev().find(|&i| is_close_angle(ty, i))?;
+    split_commas(&ty[start..end]).into_iter().find(|arg| !is_punct(arg.first(), '\''))
+}
+
+/// Removes attributes and the visibility from a field.
+fn strip_field_prefix(mut field: &[TokenTree]) -> &[TokenTree] {
+    while is_punct(field.first(), '#') {
+        field = &field[2..];
+    }
+    if is_ident(field.first(), "pub") {
+        field = &field[1..];
+        if let Some(TokenTree::Group(group)) = field.first() {
+            if group.delimiter() == Delimiter::Parenthesis {
+                field = &field[1..];
+            }
+        }
+    }
+    field
+}
+
+/// Gets the name of a generic parameter: a lifetime, a type, or a const.
+fn param_name(param: &[TokenTree]) -> String {
+    match param {
+        [TokenTree::Punct(p), name, ..] if p.as_char() == '\'' => format!("'{}", name),
+        [TokenTree::Ident(c), name, ..] if c.to_string() == "const" => name.to_string(),
+        [name, ..] => name.to_string(),
+        [] => String::new(),
+    }
+}
+
+fn angle_brackets(parts: &[String]) -> String {
+    if parts.is_empty() {
+        String::new()
+    } else {
+        format!("<{}>", parts.join(", "))
+    }
+}
+
+fn to_source(tokens: &[TokenTree]) -> String {
+    tokens.iter().cloned().collect::<TokenStream>().to_string()
+}
@@ -0,0 +1,323 @@
+use base::alloc::Layout;
+use base::fmt;
+use base::ops::{Deref, DerefMut};
+use base::ptr::{self, NonNull};
+
+use smart_pointer::IntoMut;
+
+use crate::arc::{Arc, ArcInner, UniqueArc};
+use crate::capacity_overflow;
+
+/// A byte slice whose start is aligned to (at least) the alignment of `A`.
+///
+/// `A` only determines the alignment, no values of type `A` are stored. The crate provides the
+/// marker types `Align2` up to `Align4096`, but any sized type can be used.
+///
+/// The alignment is part of the type rather than a runtime value, so that dropping the last
+/// pointer to an `Arc<AlignedBytes<A>>` deallocates with the very layout the allocation was
+/// created with. `Arc::<[u8]>::new_zeroed_aligned` takes the alignment at runtime instead, but
+/// has to record the layout of each of its allocations until it is freed.
+///
+/// ```
+/// use reference_counted::{Align64, AlignedBytes, Arc};
+///
+/// let buf = Arc::<AlignedBytes<Align64>>::new_zeroed_aligned(100);
+/// assert_eq!(buf.as_ptr() as usize % 64, 0);
+/// assert_eq!(buf.len(), 100);
+/// ```
+#[repr(C)]
+pub struct AlignedBytes<A> {
+    _align: [A; 0],
+    bytes: [u8],
+}
+
+impl<A> AlignedBytes<A> {
+    /// Extracts the bytes as a slice.
+    pub fn as_slice(&self) -> &[u8] {
+        &self.bytes
+    }
+
+    /// Extracts the bytes as a mutable slice.
+    pub fn as_mut_slice(&mut self) -> &mut [u8] {
+        &mut self.bytes
+    }
+}
+
+impl<A> Deref for AlignedBytes<A> {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        &self.bytes
+    }
+}
+
+impl<A> DerefMut for AlignedBytes<A> {
+    fn deref_mut(&mut self) -> &mut [u8] {
+        &mut self.bytes
+    }
+}
+
+impl<A> AsRef<[u8]> for AlignedBytes<A> {
+    fn as_ref(&self) -> &[u8] {
+        &self.bytes
+    }
+}
+
+impl<A> AsMut<[u8]> for AlignedBytes<A> {
+    fn as_mut(&mut self) -> &mut [u8] {
+        &mut self.bytes
+    }
+}
+
+impl<A> fmt::Debug for AlignedBytes<A> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Debug::fmt(&self.bytes, f)
+    }
+}
+
+impl<A> PartialEq for AlignedBytes<A> {
+    fn eq(&self, other: &Self) -> bool {
+        self.bytes == other.bytes
+    }
+}
+
+impl<A> Eq for AlignedBytes<A> {}
+
+impl<A> Arc<AlignedBytes<A>> {
+    /// Constructs a new reference-counted buffer of `len` zero bytes, whose start is aligned to
+    /// the alignment of `A`.
+    pub fn new_zeroed_aligned(len: usize) -> UniqueArc<AlignedBytes<A>> {
+        let bytes = Layout::array::<u8>(len).unwrap_or_else(|_| capacity_overflow());
+        let layout = Layout::new::<[A; 0]>()
+            .extend(bytes)
+            .unwrap_or_else(|_| capacity_overflow())
+            .0
+            .pad_to_align();
+
+        unsafe {
+            let inner = Arc::allocate_for_layout(layout, |mem| {
+                ptr::slice_from_raw_parts_mut(mem, len) as *mut ArcInner<AlignedBytes<A>>
+            });
+            let bytes = ptr::addr_of_mut!((*inner).data.bytes) as *mut u8;
+            ptr::write_bytes(bytes, 0, len);
+
+            // The allocation was just created, so this is the only reference to it.
+            Arc::into_mut_unchecked(Arc::from_inner(NonNull::new_unchecked(inner)))
+        }
+    }
+}
+
+#[cfg(all(feature = "std", not(feature = "panic-free")))]
+impl Arc<[u8]> {
+    /// Constructs a new reference-counted slice of `len` zero bytes, whose start is aligned to
+    /// `align`.
+    ///
+    /// The data of an `Arc<[u8]>` directly follows the reference count, which is aligned to
+    /// (typically) a `usize`. For larger alignments, the counts are placed at an offset into an
+    /// allocation of the requested alignment, and the offset and layout of the allocation are
+    /// recorded in a global table until it is freed.
+    ///
+    /// ```
+    /// use reference_counted::Arc;
+    ///
+    /// let buf = Arc::<[u8]>::new_zeroed_aligned(100, 64);
+    /// assert_eq!(buf.as_ptr() as usize % 64, 0);
+    /// assert_eq!(buf.len(), 100);
+    /// ```
+    ///
+    /// # Panics
+    ///
+    /// Panics if `align` is not a power of two, or if the size of the allocation overflows.
+    pub fn new_zeroed_aligned(len: usize, align: usize) -> UniqueArc<[u8]> {
+        core::assert!(align.is_power_of_two(), "`align` is not a power of two");
+        let header = Layout::new::<ArcInner<()>>();
+        let bytes = Layout::array::<u8>(len).unwrap_or_else(|_| capacity_overflow());
+
+        unsafe {
+            let inner = if align <= header.align() {
+                // The data follows the counts at an offset that is a multiple of their alignment.
+                Arc::allocate_for_layout(bytes, |mem| {
+                    ptr::slice_from_raw_parts_mut(mem, len) as *mut ArcInner<[u8]>
+                })
+            } else {
+                let offset = (align - header.size() % align) % align;
+                let size = (offset + header.size())
+                    .checked_add(len)
+                    .unwrap_or_else(|| capacity_overflow());
+                let layout = Layout::from_size_align(size, align)
+                    .unwrap_or_else(|_| capacity_overflow())
+                    .pad_to_align();
+
+                let inner = Arc::allocate_with_layout(layout, |mem| {
+                    ptr::slice_from_raw_parts_mut(mem.add(offset), len) as *mut ArcInner<[u8]>
+                });
+                over_aligned::insert(inner as *const u8 as usize, offset, layout);
+                inner
+            };
+            ptr::write_bytes(ptr::addr_of_mut!((*inner).data) as *mut u8, 0, len);
+
+            // The allocation was just created, so this is the only reference to it.
+            Arc::into_mut_unchecked(Arc::from_inner(NonNull::new_unchecked(inner)))
+        }
+    }
+}
+
+/// The offsets and layouts of the allocations of `Arc::<[u8]>::new_zeroed_aligned`, keyed by
+/// the address of their `ArcInner`.
+///
+/// Keeping them in a global table rather than in the allocations means that other `Arc`s do not
+/// pay for this in memory. Freeing an allocation only checks the table while it is not empty.
+#[cfg(all(feature = "std", not(feature = "panic-free")))]
+pub(crate) mod over_aligned {
+    use base::alloc::Layout;
+    use base::collections::BTreeMap;
+    use base::sync::atomic::{AtomicUsize, Ordering::Relaxed};
+    use base::sync::{Mutex, MutexGuard};
+
+    // The number of entries in the table. An allocation is inserted before any pointer to it
+    // exists, and it is freed only after all accesses through other pointers, so the thread that
+    // frees it always sees the insertion.
+    static LEN: AtomicUsize = AtomicUsize::new(0);
+    static TABLE: Mutex<BTreeMap<usize, (usize, Layout)>> = Mutex::new(BTreeMap::new());
+
+    fn table() -> MutexGuard<'static, BTreeMap<usize, (usize, Layout)>> {
+        // The table is consistent even if a thread panicked while it was locked.
+        TABLE.lock().unwrap_or_else(|err| err.into_inner())
+    }
+
+    pub(crate) fn insert(addr: usize, offset: usize, layout: Layout) {
+        table().insert(addr, (offset, layout));
+        LEN.fetch_add(1, Relaxed);
+    }
+
+    pub(crate) fn contains(addr: usize) -> bool {
+        LEN.load(Relaxed) != 0 && table().contains_key(&addr)
+    }
+
+    /// Removes the allocation at `addr` from the table, and returns the offset of its `ArcInner`
+    /// and its layout if it was over-aligned.
+    pub(crate) fn remove(addr: usize) -> Option<(usize, Layout)> {
+        if LEN.load(Relaxed) == 0 {
+            return None;
+        }
+
+        let entry = table().remove(&addr);
+        if entry.is_some() {
+            LEN.fetch_sub(1, Relaxed);
+        }
+        entry
+    }
+}
+
+macro_rules! alignments {
+    ($($name:ident = $align:literal),*) => {
+        $(
+            #[doc = concat!("A zero-sized type with an alignment of ", stringify!($align), ".")]
+            #[repr(align($align))]
+            #[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Default)]
+            pub struct $name;
+        )*
+    };
+}
+
+alignments!(
+    Align2 = 2,
+    Align4 = 4,
+    Align8 = 8,
+    Align16 = 16,
+    Align32 = 32,
+    Align64 = 64,
+    Align128 = 128,
+    Align256 = 256,
+    Align512 = 512,
+    Align1024 = 1024,
+    Align2048 = 2048,
+    Align4096 = 4096
+);
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+
+    use super::*;
+    use std::vec::Vec;
+
+    fn check<A>() {
+        let align = base::mem::align_of::<A>();
+        for len in [0, 1, 7, 64, 1000] {
+            let mut unique = Arc::<AlignedBytes<A>>::new_zeroed_aligned(len);
+            assert_eq!(unique.as_ptr() as usize % align, 0);
+            assert_eq!(unique.len(), len);
+            assert!(unique.iter().all(|b| *b == 0));
+            unique.iter_mut().enumerate().for_each(|(i, b)| *b = i as u8);
+
+            // Dropping both pointers has to free the allocation with the layout it was
+            // created with, which Miri checks.
+            let shared: Arc<AlignedBytes<A>> = unique.into();
+            let other = shared.clone();
+            assert_eq!(other.as_ptr() as usize % align, 0);
+            assert_eq!(other.as_slice(), (0..len).map(|i| i as u8).collect::<Vec<_>>());
+        }
+    }
+
+    #[test]
+    fn aligned_and_zeroed() {
+        check::<u8>();
+        check::<Align2>();
+        check::<Align16>();
+        check::<Align32>();
+        check::<Align64>();
+        check::<Align4096>();
+    }
+
+    #[cfg(all(feature = "std", not(feature = "panic-free")))]
+    #[test]
+    fn runtime_alignments() {
+        for align in [1, 2, 8, 16, 32, 64, 4096] {
+            for len in [0, 1, 7, 64, 1000] {
+                let mut unique = Arc::<[u8]>::new_zeroed_aligned(len, align);
+                assert_eq!(unique.as_ptr() as usize % align, 0);
+                assert_eq!(unique.len(), len);
+                assert!(unique.iter().all(|b| *b == 0));
+                unique.iter_mut().enumerate().for_each(|(i, b)| *b = i as u8);
+
+                // Dropping both pointers has to free the allocation with the layout it was
+                // created with, which Miri checks.
+                let shared: Arc<[u8]> = unique.into();
+                let other = shared.clone();
+                assert_eq!(other.as_ptr() as usize % align, 0);
+                assert_eq!(&other[..], (0..len).map(|i| i as u8).collect::<Vec<_>>());
+            }
+        }
+    }
+
+    #[cfg(all(feature = "std", not(feature = "panic-free")))]
+    #[test]
+    fn over_aligned_slices_round_trip_through_raw_pointers() {
+        let shared: Arc<[u8]> = Arc::<[u8]>::new_zeroed_aligned(3, 128).into();
+        let raw = Arc::into_raw(shared);
+        assert_eq!(raw as *const u8 as usize % 128, 0);
+
+        let shared = unsafe { Arc::from_raw(raw) };
+        assert_eq!(&shared[..], [0, 0, 0]);
+    }
+
+    #[cfg(all(feature = "std", not(feature = "panic-free")))]
+    #[test]
+    fn appending_to_over_aligned_strings_copies() {
+        let bytes: Arc<[u8]> = Arc::<[u8]>::new_zeroed_aligned(2, 64).into();
+        // Zero bytes are valid utf-8.
+        let mut s: Arc<str> = unsafe { Arc::from_raw(Arc::into_raw(bytes) as *const str) };
+        s += "ab";
+        assert_eq!(&*s, "\0\0ab");
+    }
+
+    #[cfg(all(feature = "std", feature = "weak", not(feature = "panic-free")))]
+    #[test]
+    fn weaks_free_over_aligned_allocations() {
+        let shared: Arc<[u8]> = Arc::<[u8]>::new_zeroed_aligned(10, 64).into();
+        let weak = Arc::downgrade(&shared);
+        drop(shared);
+        assert!(weak.upgrade().is_none());
+    }
+}
@@ -1,17 +1,44 @@
 // This code is adapted from the rust standard library Arc.
 
+use base::alloc::{alloc, dealloc, handle_alloc_error, realloc, Layout};
+use base::any::Any;
 use base::borrow;
 use base::cmp::Ordering;
 use base::convert::{From, AsMut};
 use base::fmt;
+#[cfg(feature = "weak")]
+use base::hint;
+#[cfg(feature = "std")]
+use base::io;
 use base::hash::{Hash, Hasher};
+use base::iter::FusedIterator;
 use base::marker::{PhantomData, Unpin};
-use base::mem;
+#[cfg(feature = "unstable")]
+use base::marker::Tuple;
+use base::mem::{self, ManuallyDrop, MaybeUninit};
 use base::num::NonZeroUsize;
-use base::ops::{Deref, DerefMut};
+use base::ops::{Add, AddAssign, Deref, DerefMut};
+#[cfg(all(feature = "async", not(feature = "panic-free")))]
+use base::future::Future;
+#[cfg(any(
+    feature = "futures",
+    feature = "tokio-io",
+    feature = "futures-io",
+    all(feature = "async", not(feature = "panic-free")),
+))]
+use base::pin::Pin;
 use base::ptr::{self, NonNull};
+#[cfg(feature = "rayon")]
+use base::slice;
 use base::sync::atomic;
 use base::sync::atomic::Ordering::{Acquire, Relaxed, Release, SeqCst};
+#[cfg(any(
+    feature = "futures",
+    feature = "tokio-io",
+    feature = "futures-io",
+    all(feature = "async", not(feature = "panic-free")),
+))]
+use base::task::{Context, Poll};
 
 use base::borrow::BorrowMut;
 
@@ -19,7 +46,55 @@ use base::prelude::v1::*;
 
 use smart_pointer::{SmartPointer, IntoMut, SmartPointerMut};
 
-use crate::ReferenceCounted;
+use crate::{
+    capacity_overflow, iterator_too_short, refcount_overflow, str_to_array, FromOwned,
+    ReferenceCounted, SameLayout,
+};
+#[cfg(feature = "weak")]
+use crate::{Downgradable, WeakPointer, WeaklyReferenceCounted};
+#[cfg(all(feature = "std", not(feature = "panic-free")))]
+use crate::aligned::over_aligned;
+
+#[cfg(feature = "futures")]
+use futures_core::{FusedStream, Stream};
+#[cfg(feature = "futures")]
+use futures_sink::Sink;
+
+#[cfg(feature = "std")]
+use base::sync::Arc as StdArc;
+
+#[cfg(feature = "rayon")]
+use rayon::iter::plumbing::{Consumer, Folder, Reducer, UnindexedConsumer};
+#[cfg(feature = "rayon")]
+use rayon::iter::{FromParallelIterator, IntoParallelIterator, ParallelIterator};
+
+#[cfg(feature = "zerocopy")]
+use zerocopy::{FromBytes, Immutable, IntoBytes};
+
+#[cfg(any(feature = "bincode", feature = "borsh"))]
+use base::convert::TryFrom;
+#[cfg(any(feature = "bincode", feature = "borsh"))]
+use base::str;
+#[cfg(feature = "borsh")]
+use borsh::{io as borsh_io, BorshDeserialize, BorshSerialize};
+
+#[cfg(feature = "schemars")]
+use base::borrow::Cow;
+#[cfg(feature = "schemars")]
+use schemars::{JsonSchema, Schema, SchemaGenerator};
+
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Deserializer};
+#[cfg(all(feature = "serde", feature = "weak"))]
+use serde::{Serialize, Serializer};
+
+#[cfg(feature = "bincode")]
+use bincode::{
+    de::{read::Reader, BorrowDecoder, Decoder},
+    enc::Encoder,
+    error::{DecodeError, EncodeError},
+    BorrowDecode, Decode, Encode,
+};
 
 /// A soft limit on the amount of references that may be made to an `Arc`.
 ///
@@ -27,6 +102,36 @@ use crate::ReferenceCounted;
 /// necessarily) at _exactly_ `MAX_REFCOUNT + 1` references.
 const MAX_REFCOUNT: usize = (isize::MAX) as usize;
 
+/// The reference counts of an `ArcInner` that lives in static memory. Such counts are never
+/// modified, and the `ArcInner` is never freed.
+///
+/// Strong counts of heap allocations can not reach this value, since `clone` panics above
+/// `MAX_REFCOUNT`. Their weak count is temporarily set to it while `IntoMut::can_make_mut`
+/// checks the strong count, so only the strong count tells static allocations apart.
+const STATIC_REFCOUNT: usize = usize::MAX;
+
+/// The allocation shared by all `Arc`s of zero-sized types, see `is_static_zst`.
+static ZST_INNER: ArcInner<()> = ArcInner {
+    strong: atomic::AtomicUsize::new(STATIC_REFCOUNT),
+    #[cfg(feature = "weak")]
+    weak: atomic::AtomicUsize::new(STATIC_REFCOUNT),
+    #[cfg(feature = "allocation-id")]
+    id: 0,
+    data: (),
+};
+
+/// Whether `Arc::new` points to `ZST_INNER` instead of allocating. `ArcInner<T>` then has the
+/// same layout as `ArcInner<()>`, and forgetting a value is the same as dropping it.
+///
+/// Never the case with the `weak` feature: a `WeakArc` has to observe when the `Arc`s it was
+/// created from are gone, which a count shared by all of them can not tell.
+fn is_static_zst<T>() -> bool {
+    mem::size_of::<T>() == 0
+        && mem::align_of::<T>() <= mem::align_of::<ArcInner<()>>()
+        && !mem::needs_drop::<T>()
+        && !cfg!(feature = "weak")
+}
+
 macro_rules! acquire {
     ($x:expr) => {
         atomic::fence(Acquire)
@@ -34,6 +139,20 @@ macro_rules! acquire {
 }
 
 /// A thread-safe reference-counted pointer.
+///
+/// Unless the `weak` feature is enabled, `Arc::new` does not allocate for zero-sized types that
+/// have no drop glue and an alignment of at most that of `usize`. All such `Arc`s point to a
+/// single static allocation whose reference count is never modified, just like those created by
+/// `Arc::from_static`. In particular, their `ReferenceCounted::reference_count` is always
+/// `usize::MAX`, and `ptr_eq`-style address comparisons consider all of them equal. Since their
+/// values have neither bytes nor drop glue, `try_unwrap` still returns the value, and
+/// `IntoMut::into_mut` moves it into a `UniqueArc` of its own. This holds for every clone, so
+/// zero-sized values whose uniqueness matters (e.g. tokens) belong in a `UniqueArc`:
+/// `UniqueArc::new` always allocates, so that unique pointers never share an allocation.
+///
+/// With the `weak` feature, `Arc::downgrade` creates a `WeakArc`, which does not keep the value
+/// alive. The value is dropped when the last `Arc` is, and the allocation is freed once no
+/// `WeakArc` remains either.
 pub struct Arc<T: ?Sized> {
     ptr: NonNull<ArcInner<T>>,
     phantom: PhantomData<ArcInner<T>>,
@@ -43,14 +162,31 @@ unsafe impl<T: ?Sized + Sync + Send> Send for Arc<T> {}
 unsafe impl<T: ?Sized + Sync + Send> Sync for Arc<T> {}
 
 impl<T: ?Sized> Arc<T> {
-    fn from_inner(ptr: NonNull<ArcInner<T>>) -> Self {
+    pub(crate) const fn from_inner(ptr: NonNull<ArcInner<T>>) -> Self {
         Self { ptr, phantom: PhantomData }
     }
+
+    /// Consumes the `Arc` without decrementing the reference count, returning the pointer to
+    /// its `ArcInner`. Use `Arc::from_inner` to reclaim it.
+    pub(crate) fn into_inner(this: Self) -> NonNull<ArcInner<T>> {
+        let ptr = this.ptr;
+        mem::forget(this);
+        ptr
+    }
 }
 
-struct ArcInner<T: ?Sized> {
+// `repr(C)` so that the offset of `data` can be computed from the layout of the header alone,
+// which is needed for allocating unsized values and for getting from a pointer to the value
+// back to the header (see `data_offset_val`).
+#[repr(C)]
+pub(crate) struct ArcInner<T: ?Sized> {
     strong: atomic::AtomicUsize,
-    data: T,
+    // The number of `WeakArc`s, plus one that is held by all `Arc`s together.
+    #[cfg(feature = "weak")]
+    weak: atomic::AtomicUsize,
+    #[cfg(feature = "allocation-id")]
+    id: u64,
+    pub(crate) data: T,
 }
 
 impl<T: ?Sized> Arc<T> {
@@ -75,488 +211,4760 @@ impl<T: ?Sized> Arc<T> {
         // Destroy the data at this time, even though we may not free the box
         // allocation itself (there may still be weak pointers lying around).
         unsafe { ptr::drop_in_place(Self::get_mut_unchecked(self)) };
+
+        unsafe { release_allocation(self.ptr) };
     }
 
     fn ptr(&self) -> *mut ArcInner<T> {
         self.ptr.as_ptr()
     }
 
-    fn ref_count(&self) -> usize {
-        self.inner().strong.load(SeqCst)
+    /// Whether this points to `ZST_INNER`, see `is_static_zst`.
+    fn is_shared_zst(&self) -> bool {
+        ptr::eq(self.ptr() as *const u8, &ZST_INNER as *const ArcInner<()> as *const u8)
     }
-}
 
-impl<T: ?Sized> Clone for Arc<T> {
-    /// Makes a clone of the `Arc` pointer.
+    /// Allocates an `ArcInner<T>` with sufficient space for a possibly-unsized inner value
+    /// where the value has the layout provided, and initializes the reference counts to one.
     ///
-    /// This creates another pointer to the same allocation, increasing the reference count.
-    #[inline]
-    fn clone(&self) -> Arc<T> {
-        // Using a relaxed ordering is alright here, as knowledge of the
-        // original reference prevents other threads from erroneously deleting
-        // the object.
-        //
-        // As explained in the [Boost documentation][1], Increasing the
-        // reference counter can always be done with memory_order_relaxed: New
-        // references to an object can only be formed from an existing
-        // reference, and passing an existing reference from one thread to
-        // another must already provide any required synchronization.
-        //
-        // [1]: (www.boost.org/doc/libs/1_55_0/doc/html/atomic/usage_examples.html)
-        let old_size = self.inner().strong.fetch_add(1, Relaxed);
-
-        // However we need to guard against massive refcounts in case someone
-        // is `mem::forget`ing Arcs. If we don't do this the count can overflow
-        // and users will use-after free. We racily saturate to `isize::MAX` on
-        // the assumption that there aren't ~2 billion threads incrementing
-        // the reference count at once. This branch will never be taken in
-        // any realistic program.
-        //
-        // We abort because such a program is incredibly degenerate, and we
-        // don't care to support it.
-        if old_size > MAX_REFCOUNT {
-            panic!();
-        }
-
-        Self::from_inner(self.ptr)
+    /// The function `mem_to_arcinner` is called with the pointer to the start of the allocation
+    /// and must return back a (potentially fat) pointer for the `ArcInner<T>`.
+    pub(crate) unsafe fn allocate_for_layout(
+        value_layout: Layout,
+        mem_to_arcinner: impl FnOnce(*mut u8) -> *mut ArcInner<T>,
+    ) -> *mut ArcInner<T> {
+        let layout = arcinner_layout_for_value_layout(value_layout);
+        unsafe { Arc::allocate_with_layout(layout, mem_to_arcinner) }
     }
-}
 
-impl<T: ?Sized> Drop for Arc<T> {
-    /// Drops the `Arc`.
-    ///
-    /// This will decrement the reference count.
-    ///
-    /// # Examples
-    ///
-    /// ```
-    /// use std::sync::Arc;
-    ///
-    /// struct Foo;
-    ///
-    /// impl Drop for Foo {
-    ///     fn drop(&mut self) {
-    ///         println!("dropped!");
-    ///     }
-    /// }
-    ///
-    /// let foo  = Arc::new(Foo);
-    /// let foo2 = Arc::clone(&foo);
-    ///
-    /// drop(foo);    // Doesn't print anything
-    /// drop(foo2);   // Prints "dropped!"
-    /// ```
-    #[inline]
-    fn drop(&mut self) {
-        // Because `fetch_sub` is already atomic, we do not need to synchronize
-        // with other threads unless we are going to delete the object. This
-        // same logic applies to the below `fetch_sub` to the `weak` count.
-        if self.inner().strong.fetch_sub(1, Release) != 1 {
-            return;
+    /// Allocates memory with the given layout, which has to fit an `ArcInner<T>` at the pointer
+    /// returned by `mem_to_arcinner`, and initializes the reference counts to one.
+    pub(crate) unsafe fn allocate_with_layout(
+        layout: Layout,
+        mem_to_arcinner: impl FnOnce(*mut u8) -> *mut ArcInner<T>,
+    ) -> *mut ArcInner<T> {
+        let mem = unsafe { alloc(layout) };
+        if mem.is_null() {
+            handle_alloc_error(layout);
         }
 
-        // This fence is needed to prevent reordering of use of the data and
-        // deletion of the data.  Because it is marked `Release`, the decreasing
-        // of the reference count synchronizes with this `Acquire` fence. This
-        // means that use of the data happens before decreasing the reference
-        // count, which happens before this fence, which happens before the
-        // deletion of the data.
-        //
-        // As explained in the [Boost documentation][1],
-        //
-        // > It is important to enforce any possible access to the object in one
-        // > thread (through an existing reference) to *happen before* deleting
-        // > the object in a different thread. This is achieved by a "release"
-        // > operation after dropping a reference (any access to the object
-        // > through this reference must obviously happened before), and an
-        // > "acquire" operation before deleting the object.
-        //
-        // In particular, while the contents of an Arc are usually immutable, it's
-        // possible to have interior writes to something like a Mutex<T>. Since a
-        // Mutex is not acquired when it is deleted, we can't rely on its
-        // synchronization logic to make writes in thread A visible to a destructor
-        // running in thread B.
-        //
-        // Also note that the Acquire fence here could probably be replaced with an
-        // Acquire load, which could improve performance in highly-contended
-        // situations. See [2].
-        //
-        // [1]: (www.boost.org/doc/libs/1_55_0/doc/html/atomic/usage_examples.html)
-        // [2]: (https://github.com/rust-lang/rust/pull/41714)
-        acquire!(self.inner().strong);
-
-        unsafe {
-            self.drop_slow();
-        }
+        let inner = mem_to_arcinner(mem);
+        unsafe { ptr::addr_of_mut!((*inner).strong).write(atomic::AtomicUsize::new(1)) };
+        #[cfg(feature = "weak")]
+        unsafe { ptr::addr_of_mut!((*inner).weak).write(atomic::AtomicUsize::new(1)) };
+        #[cfg(feature = "allocation-id")]
+        unsafe { ptr::addr_of_mut!((*inner).id).write(crate::next_allocation_id()) };
+        inner
     }
 }
 
-impl<T: ?Sized> Deref for Arc<T> {
-    type Target = T;
-
-    #[inline]
-    fn deref(&self) -> &T {
-        &self.inner().data
-    }
+/// Calculate the layout of an `ArcInner<T>` whose value has the given layout.
+fn arcinner_layout_for_value_layout(value_layout: Layout) -> Layout {
+    // Calculate layout using the given value layout.
+    // Previously, layout was calculated on the expression
+    // `&*(ptr as *const ArcInner<T>)`, but this created a misaligned
+    // reference (see rust-lang/rust#54908).
+    let layout = Layout::new::<ArcInner<()>>().extend(value_layout);
+    layout.unwrap_or_else(|_| capacity_overflow()).0.pad_to_align()
 }
 
-impl<T: ?Sized> borrow::Borrow<T> for Arc<T> {
-    fn borrow(&self) -> &T {
-        &**self
-    }
+/// Calculate the offset of `value` in its `ArcInner`, for sized and unsized values alike.
+fn data_offset_val<T: ?Sized>(value: &T) -> usize {
+    // The value follows the header, padded to its alignment, as in
+    // `arcinner_layout_for_value_layout`. Only the alignment matters, which the pointer metadata
+    // determines for unsized values.
+    let value_layout = Layout::from_size_align(0, mem::align_of_val(value)).unwrap();
+    Layout::new::<ArcInner<()>>().extend(value_layout).unwrap().1
 }
 
-impl<T: ?Sized> AsRef<T> for Arc<T> {
-    fn as_ref(&self) -> &T {
-        &**self
-    }
-}
+/// Gives up the allocation of the `Arc`s once its value has been dropped or moved out. This frees
+/// it unless it is static or, with the `weak` feature, there still are `WeakArc`s.
+unsafe fn release_allocation<T: ?Sized>(ptr: NonNull<ArcInner<T>>) {
+    // Drop the weak reference collectively held by all strong references.
+    #[cfg(feature = "weak")]
+    drop(WeakArc { ptr });
 
-impl<T: ?Sized + fmt::Display> fmt::Display for Arc<T> {
-    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        fmt::Display::fmt(&**self, f)
+    // Pointers to static memory are not counted, and the memory is never freed.
+    #[cfg(not(feature = "weak"))]
+    unsafe {
+        if (*ptr.as_ptr()).strong.load(Relaxed) != STATIC_REFCOUNT {
+            deallocate(ptr);
+        }
     }
 }
 
-impl<T: ?Sized + fmt::Debug> fmt::Debug for Arc<T> {
-    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        fmt::Debug::fmt(&**self, f)
+/// Frees the allocation of an `ArcInner` with the layout it was created with.
+unsafe fn deallocate<T: ?Sized>(ptr: NonNull<ArcInner<T>>) {
+    // The allocations of `Arc::<[u8]>::new_zeroed_aligned` may start before the `ArcInner`, and
+    // have a larger alignment than the value.
+    #[cfg(all(feature = "std", not(feature = "panic-free")))]
+    if let Some((offset, layout)) = over_aligned::remove(ptr.as_ptr() as *const u8 as usize) {
+        unsafe { dealloc((ptr.as_ptr() as *mut u8).sub(offset), layout) };
+        return;
     }
+
+    // `Layout::for_value` only uses the size and alignment of the value, which are known from its
+    // type and the pointer metadata even after it has been dropped.
+    unsafe { dealloc(ptr.as_ptr() as *mut u8, Layout::for_value(ptr.as_ref())) };
 }
 
-impl<T: ?Sized> fmt::Pointer for Arc<T> {
-    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        fmt::Pointer::fmt(&(&**self as *const T), f)
-    }
+/// Sets the data pointer of a `?Sized` raw pointer, keeping its metadata.
+///
+/// For a slice or trait object, the data pointer is the first field of the fat pointer.
+unsafe fn set_data_ptr<T: ?Sized, U>(mut ptr: *mut T, data: *mut U) -> *mut T {
+    unsafe { ptr::write(&mut ptr as *mut _ as *mut *mut u8, data as *mut u8) };
+    ptr
 }
 
-impl<T: ?Sized> SmartPointer<T> for Arc<T> {
-    fn new(data: T) -> Arc<T> where T: Sized {
-        let x: Box<_> = Box::new(ArcInner {
-            strong: atomic::AtomicUsize::new(1),
-            data,
-        });
-        Self::from_inner(Box::leak(x).into())
+impl<T> Arc<[T]> {
+    /// Allocates an `ArcInner<[T]>` with the given length.
+    unsafe fn allocate_for_slice(len: usize) -> *mut ArcInner<[T]> {
+        unsafe {
+            let layout = Layout::array::<T>(len).unwrap_or_else(|_| capacity_overflow());
+            Self::allocate_for_layout(layout, |mem| {
+                ptr::slice_from_raw_parts_mut(mem as *mut T, len) as *mut ArcInner<[T]>
+            })
+        }
     }
 
-    fn try_unwrap(this: Self) -> Result<T, Self> where T: Sized {
-        if this.inner().strong.compare_exchange(1, 0, Relaxed, Relaxed).is_err() {
-            return Err(this);
+    /// Constructs an `Arc<[T]>` from an iterator that yields `len` items.
+    ///
+    /// Panics if the iterator yields fewer items, and ignores any items past the first `len`.
+    fn from_iter_exact(iter: impl Iterator<Item = T>, len: usize) -> Arc<[T]> {
+        // Panic guard while producing the elements. In the event of a panic, elements that
+        // have been written into the new `ArcInner` will be dropped, then the memory freed.
+        struct Guard<T> {
+            mem: *mut u8,
+            elems: *mut T,
+            layout: Layout,
+            n_elems: usize,
         }
 
-        acquire!(this.inner().strong);
+        impl<T> Drop for Guard<T> {
+            fn drop(&mut self) {
+                unsafe {
+                    let slice = ptr::slice_from_raw_parts_mut(self.elems, self.n_elems);
+                    ptr::drop_in_place(slice);
 
-        unsafe {
-            let elem = ptr::read(&this.ptr.as_ref().data);
-            mem::forget(this);
-            Ok(elem)
+                    dealloc(self.mem, self.layout);
+                }
+            }
         }
-    }
-}
 
-pub struct UniqueArc<T: ?Sized>(Arc<T>);
+        unsafe {
+            let ptr = Self::allocate_for_slice(len);
 
-unsafe impl<T: ?Sized + Sync + Send> Send for UniqueArc<T> {}
-unsafe impl<T: ?Sized + Sync + Send> Sync for UniqueArc<T> {}
+            let value_layout = Layout::array::<T>(len).unwrap_or_else(|_| capacity_overflow());
+            let mut guard = Guard {
+                mem: ptr as *mut u8,
+                elems: ptr::addr_of_mut!((*ptr).data) as *mut T,
+                layout: arcinner_layout_for_value_layout(value_layout),
+                n_elems: 0,
+            };
 
-impl<T: ?Sized> Deref for UniqueArc<T> {
-    type Target = T;
+            for item in iter.take(len) {
+                ptr::write(guard.elems.add(guard.n_elems), item);
+                guard.n_elems += 1;
+            }
 
-    #[inline]
-    fn deref(&self) -> &T {
-        self.0.deref()
+            if guard.n_elems != len {
+                iterator_too_short();
+            }
+
+            // All clear. Forget the guard so it doesn't free the new ArcInner.
+            mem::forget(guard);
+
+            Self::from_inner(NonNull::new_unchecked(ptr))
+        }
+    }
+
+    /// Constructs a new reference-counted slice with uninitialized contents.
+    ///
+    /// The returned pointer is unique, so the contents can be initialized in place, before
+    /// converting it into a `UniqueArc<[T]>` via `UniqueArc::assume_init`.
+    pub fn new_uninit_slice(len: usize) -> UniqueArc<[MaybeUninit<T>]> {
+        unsafe {
+            let ptr = Arc::<[MaybeUninit<T>]>::allocate_for_slice(len);
+            UniqueArc(Arc::from_inner(NonNull::new_unchecked(ptr)))
+        }
     }
 }
 
-impl<T: ?Sized> borrow::Borrow<T> for UniqueArc<T> {
-    fn borrow(&self) -> &T {
-        self.0.borrow()
+impl<T> Arc<T> {
+    /// Reinterprets the pointer as pointing to a `U`, without touching the allocation.
+    ///
+    /// `T` and `U` must have the same size and alignment, so that the value is found at the same
+    /// place and the allocation is freed with the layout it was created with. This is checked at
+    /// compile time:
+    ///
+    /// ```compile_fail
+    /// use reference_counted::Arc;
+    /// use smart_pointer::SmartPointer;
+    ///
+    /// let wide = unsafe { Arc::cast::<u64>(Arc::new(0_u32)) };
+    /// ```
+    ///
+    /// # Safety
+    ///
+    /// - The value must be a valid `U`.
+    /// - Other pointers to the allocation continue to access the value as a `T`, so accessing
+    ///   it as a `T` and as a `U` at the same time must be sound.
+    /// - Whichever pointer is dropped last drops the value, either as a `T` or as a `U`, so
+    ///   both must be correct. This holds for example if `U` is a `#[repr(transparent)]`
+    ///   wrapper around `T` that does not implement `Drop`.
+    pub unsafe fn cast<U>(this: Self) -> Arc<U> {
+        let () = SameLayout::<T, U>::ASSERT;
+        let ptr = Arc::into_inner(this);
+        Arc::from_inner(ptr.cast::<ArcInner<U>>())
     }
 }
 
-impl<T: ?Sized> AsRef<T> for UniqueArc<T> {
-    fn as_ref(&self) -> &T {
-        self.0.as_ref()
+impl<T> Arc<[T]> {
+    /// Reinterprets the pointer as pointing to a slice of `U`s of the same length, without
+    /// touching the allocation.
+    ///
+    /// # Safety
+    ///
+    /// The requirements of `Arc::cast` apply to the element types `T` and `U`, whose layouts
+    /// are checked at compile time as well.
+    pub unsafe fn cast_slice<U>(this: Self) -> Arc<[U]> {
+        let () = SameLayout::<T, U>::ASSERT;
+        let len = this.len();
+        let ptr = Arc::into_inner(this);
+        let ptr = ptr::slice_from_raw_parts_mut(ptr.as_ptr() as *mut U, len) as *mut ArcInner<[U]>;
+        Arc::from_inner(unsafe { NonNull::new_unchecked(ptr) })
     }
 }
 
-impl<T: ?Sized + fmt::Display> fmt::Display for UniqueArc<T> {
-    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        self.0.fmt(f)
+impl<T: Copy> Arc<[T]> {
+    /// Allocates a reference-counted slice and copies the items of `src` into it.
+    ///
+    /// This is equivalent to `Arc::from(src)`, but copies all items at once rather than cloning
+    /// them one by one.
+    pub fn copy_from_slice(src: &[T]) -> Arc<[T]> {
+        unsafe {
+            let ptr = Arc::allocate_for_slice(src.len());
+            ptr::copy_nonoverlapping(
+                src.as_ptr(),
+                ptr::addr_of_mut!((*ptr).data) as *mut T,
+                src.len(),
+            );
+            Self::from_inner(NonNull::new_unchecked(ptr))
+        }
     }
 }
 
-impl<T: ?Sized + fmt::Debug> fmt::Debug for UniqueArc<T> {
-    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        self.0.fmt(f)
+impl<T: Clone> Arc<[T]> {
+    /// Allocates a reference-counted slice of `n` copies of `elem`, cloning it `n - 1` times.
+    ///
+    /// This is the counterpart of `vec![elem; n]`, and what `arc![elem; n]` expands to.
+    pub fn from_elem(elem: T, n: usize) -> Arc<[T]> {
+        // The last item is `elem` itself. For `n == 0`, it is dropped along with the iterator.
+        let mut elem = Some(elem);
+        let items = (1..=n).filter_map(move |i| if i < n { elem.clone() } else { elem.take() });
+        Arc::from_iter_exact(items, n)
     }
 }
 
-impl<T: ?Sized> fmt::Pointer for UniqueArc<T> {
-    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        self.0.fmt(f)
+impl<T: Clone> Arc<Vec<T>> {
+    /// Converts an `Arc<Vec<T>>` into an `Arc<[T]>` that stores the elements directly in the
+    /// reference-counted allocation, saving a pointer indirection and the excess capacity.
+    ///
+    /// If this is the only reference to the vector, its elements are moved into the new
+    /// allocation, otherwise they are cloned. The buffer of the vector can not be reused even
+    /// if it has no excess capacity, since it has no room for the reference count.
+    pub fn flatten(self) -> Arc<[T]> {
+        match Arc::try_unwrap(self) {
+            Ok(v) => Arc::from(v),
+            Err(this) => Arc::from(&this[..]),
+        }
     }
 }
 
-impl<T: ?Sized> SmartPointer<T> for UniqueArc<T> {
-    fn new(data: T) -> Self where T: Sized {
-        UniqueArc(Arc::new(data))
+impl Arc<String> {
+    /// Converts an `Arc<String>` into an `Arc<str>` that stores the string directly in the
+    /// reference-counted allocation, saving a pointer indirection and the excess capacity.
+    ///
+    /// If this is the only reference to the string, its contents are moved into the new
+    /// allocation, otherwise they are copied. The buffer of the string can not be reused even
+    /// if it has no excess capacity, since it has no room for the reference count.
+    pub fn flatten(self) -> Arc<str> {
+        match Arc::try_unwrap(self) {
+            Ok(s) => Arc::from(s),
+            Err(this) => Arc::from(&this[..]),
+        }
     }
+}
 
-    fn try_unwrap(this: Self) -> Result<T, Self> where T: Sized {
-        let this = this.0;
+#[cfg(feature = "std")]
+impl Arc<[u8]> {
+    /// Reads exactly `len` bytes from `reader` into a new reference-counted slice.
+    ///
+    /// The slice is allocated once, up front, and filled via `Read::read_exact`. If the reader
+    /// fails or runs out of bytes before the slice is full, the allocation is freed and the
+    /// error is returned.
+    pub fn from_reader<R: ?Sized + io::Read>(reader: &mut R, len: usize) -> io::Result<Arc<[u8]>> {
+        let mut uninit = Arc::new_uninit_slice(len);
+        // `Read` implementations may inspect the buffer, so it has to be initialized.
+        let mut buf = unsafe {
+            ptr::write_bytes(uninit.as_mut_ptr(), 0, len);
+            uninit.assume_init()
+        };
 
-        acquire!(this.inner().strong);
+        reader.read_exact(&mut buf)?;
+        Ok(buf.into())
+    }
 
-        unsafe {
-            let elem = ptr::read(&this.ptr.as_ref().data);
-            mem::forget(this);
-            Ok(elem)
-        }
+    /// Reads all bytes from `reader` until EOF into a new reference-counted slice.
+    ///
+    /// Since the length is not known in advance, the bytes are read into a vector first and
+    /// then moved into an exact-size allocation.
+    pub fn from_reader_to_end<R: ?Sized + io::Read>(reader: &mut R) -> io::Result<Arc<[u8]>> {
+        let mut buf = Vec::new();
+        reader.read_to_end(&mut buf)?;
+        Ok(Arc::from(buf))
     }
 }
 
+#[cfg(feature = "zerocopy")]
+impl Arc<[u8]> {
+    /// Reinterprets the bytes as a slice of `T`s, reusing the allocation.
+    ///
+    /// This requires the number of bytes to be a multiple of the size of `T`, and the alignment
+    /// of `T` to be at most that of the reference count (typically `usize`), so that the
+    /// `T`s start at the same offset within the allocation as the bytes. Zero-sized types are
+    /// not supported. If any of these conditions is violated, the original `Arc` is returned.
+    ///
+    /// Other pointers to the same allocation can continue to access it as bytes, since neither
+    /// kind of pointer allows mutation while the allocation is shared.
+    pub fn into_arc_of<T: FromBytes + Immutable>(this: Self) -> Result<Arc<[T]>, Arc<[u8]>> {
+        let size = mem::size_of::<T>();
+        if size == 0
+            || this.len() % size != 0
+            || mem::align_of::<T>() > mem::align_of::<ArcInner<()>>()
+        {
+            return Err(this);
+        }
 
-impl<T: ?Sized> DerefMut for UniqueArc<T> {
-    fn deref_mut(&mut self) -> &mut T {
-        // We know this to be uniquely owned
-        unsafe { &mut (*self.0.ptr()).data }
+        let len = this.len() / size;
+        let ptr = Arc::into_inner(this).as_ptr() as *mut T;
+        // Since the alignment of `T` is at most that of the header, the data starts at the same
+        // offset, and the layouts of the two allocations are the same.
+        let ptr = ptr::slice_from_raw_parts_mut(ptr, len) as *mut ArcInner<[T]>;
+        Ok(Arc::from_inner(unsafe { NonNull::new_unchecked(ptr) }))
     }
 }
 
-impl<T: ?Sized> BorrowMut<T> for UniqueArc<T> {
-    fn borrow_mut(&mut self) -> &mut T {
-        &mut **self
+#[cfg(feature = "zerocopy")]
+impl<T: ?Sized + IntoBytes + Immutable> Arc<T> {
+    /// Gets the bytes of the inner value.
+    pub fn as_bytes(this: &Self) -> &[u8] {
+        (**this).as_bytes()
     }
 }
 
-impl<T: ?Sized> AsMut<T> for UniqueArc<T> {
-    fn as_mut(&mut self) -> &mut T {
-        &mut **self
+impl<T: ?Sized> Arc<T>
+where
+    for<'a> Arc<T>: From<&'a T>,
+{
+    /// Makes a mutable reference into the given `Arc`, e.g. an `Arc<[T]>` or an `Arc<str>`.
+    ///
+    /// If there are other `Arc` pointers to the same allocation, the value is copied into a new
+    /// allocation with `From<&T>` first, so that this pointer becomes unique. Otherwise, the
+    /// existing allocation is handed out without any copying.
+    pub fn make_mut(this: &mut Self) -> &mut T {
+        if !Arc::can_make_mut(this) {
+            *this = Arc::from(&**this);
+        }
+
+        // We either had the only reference, or just created a new one.
+        unsafe { Arc::get_mut_unchecked(this) }
     }
 }
 
-impl<T: ?Sized> SmartPointerMut<T> for UniqueArc<T> {}
+impl Arc<str> {
+    /// Appends `s` to the string. If this is the only reference to the allocation, it is grown
+    /// in place (which may still move it), otherwise both strings are copied into a new
+    /// allocation of the combined length.
+    fn append(this: &mut Self, s: &str) {
+        if s.is_empty() {
+            return;
+        }
+        let len = this.len();
+        let new_len = len.checked_add(s.len()).unwrap_or_else(|| capacity_overflow());
+        let unique = Arc::can_make_mut(this);
+        // `realloc` keeps the alignment of the layout, so over-aligned allocations are copied.
+        #[cfg(all(feature = "std", not(feature = "panic-free")))]
+        let unique = unique && !over_aligned::contains(this.ptr() as *const u8 as usize);
 
-impl<T: ?Sized> Into<Arc<T>> for UniqueArc<T> {
-    fn into(self) -> Arc<T> {
-        self.0
+        unsafe {
+            let inner = if unique {
+                let old_layout = arcinner_layout_for_value_layout(Layout::for_value(&**this));
+                let new_value_layout =
+                    Layout::array::<u8>(new_len).unwrap_or_else(|_| capacity_overflow());
+                let new_layout = arcinner_layout_for_value_layout(new_value_layout);
+                let mem = realloc(this.ptr() as *mut u8, old_layout, new_layout.size());
+                if mem.is_null() {
+                    handle_alloc_error(new_layout);
+                }
+                ptr::slice_from_raw_parts_mut(mem, new_len) as *mut ArcInner<[u8]>
+            } else {
+                let inner = Arc::<[u8]>::allocate_for_slice(new_len);
+                let data = ptr::addr_of_mut!((*inner).data) as *mut u8;
+                ptr::copy_nonoverlapping(this.as_ptr(), data, len);
+                inner
+            };
+            let data = ptr::addr_of_mut!((*inner).data) as *mut u8;
+            ptr::copy_nonoverlapping(s.as_ptr(), data.add(len), s.len());
+
+            // The bytes are valid utf-8, and `ArcInner<str>` has the same layout as
+            // `ArcInner<[u8]>`.
+            let new = Arc::from_inner(NonNull::new_unchecked(inner as *mut ArcInner<str>));
+            if unique {
+                // The old allocation has been reallocated, so it must not be released.
+                ptr::write(this, new);
+            } else {
+                *this = new;
+            }
+        }
     }
 }
 
-impl<T: ?Sized> IntoMut<T> for Arc<T> {
-    type MutablePointer = UniqueArc<T>;
+impl Add<&str> for Arc<str> {
+    type Output = Arc<str>;
 
-    fn can_make_mut(this: &Self) -> bool {
-        this.ref_count() == 1
+    /// Concatenates the strings, reusing the allocation of `self` if it is the only reference
+    /// to it.
+    fn add(mut self, rhs: &str) -> Arc<str> {
+        self += rhs;
+        self
     }
+}
 
-    unsafe fn into_mut_unchecked(this: Self) -> Self::MutablePointer {
-        UniqueArc(this)
-    }
+impl Add<Arc<str>> for Arc<str> {
+    type Output = Arc<str>;
 
-    /// Obtain a mutable reference to the wrapped value without performing runtime checks for
-    /// upholding any invariants.
-    ///
-    /// Safety: Calling this is safe if and only if `can_make_mut` returns true.
-    unsafe fn get_mut_unchecked(this: &Self) -> &mut T {
-        // We are careful to *not* create a reference covering the "count" fields, as
-        // this would alias with concurrent access to the reference counts (e.g. by `Weak`).
-        unsafe { &mut (*this.ptr.as_ptr()).data }
+    /// Concatenates the strings, reusing the allocation of `self` if it is the only reference
+    /// to it.
+    fn add(self, rhs: Arc<str>) -> Arc<str> {
+        self + &*rhs
     }
 }
 
-impl<T: ?Sized> ReferenceCounted<T> for Arc<T> {
-    fn reference_count(this: &Self) -> NonZeroUsize {
-        unsafe { NonZeroUsize::new_unchecked(this.inner().strong.load(SeqCst)) }
+impl AddAssign<&str> for Arc<str> {
+    /// Appends `rhs`, growing the allocation in place if this is the only reference to it, and
+    /// copying both strings into a new allocation otherwise.
+    fn add_assign(&mut self, rhs: &str) {
+        Arc::append(self, rhs);
     }
 }
 
-impl<T: Default> Default for Arc<T> {
-    /// Creates a new `Arc<T>`, with the `Default` value for `T`.
-    ///
-    /// # Examples
-    ///
-    /// ```
-    /// use std::sync::Arc;
+impl Arc<str> {
+    /// Constructs a reference-counted string from the `Display` output of a value.
     ///
-    /// let x: Arc<i32> = Default::default();
-    /// assert_eq!(*x, 0);
-    /// ```
-    fn default() -> Arc<T> {
-        Arc::new(Default::default())
+    /// The value is formatted into a `String` first, whose contents are then moved into an
+    /// exact-size allocation. Formatting twice (once to measure the length, once to write into
+    /// the final allocation) would avoid that intermediate buffer, but `Display`
+    /// implementations are not guaranteed to produce the same output both times.
+    pub fn from_display<D: ?Sized + fmt::Display>(value: &D) -> Arc<str> {
+        Arc::from(value.to_string())
     }
 }
 
-impl<T: Default> Default for UniqueArc<T> {
-    /// Creates a new `UniqueArc<T>`, with the `Default` value for `T`.
-    fn default() -> UniqueArc<T> {
-        UniqueArc::new(Default::default())
-    }
+/// Creates an `Arc<str>` using interpolation of runtime expressions, like `format!` does for
+/// `String`.
+#[macro_export]
+macro_rules! format_arc {
+    ($($arg:tt)*) => {
+        $crate::Arc::<str>::from_display(&format_args!($($arg)*))
+    };
 }
 
-impl<T: ?Sized + PartialEq> PartialEq for Arc<T> {
-    /// Equality for two `Arc`s.
-    ///
-    /// Two `Arc`s are equal if their inner values are equal, even if they are
-    /// stored in different allocation. This implementation does not check for
-    /// pointer equality.
-    #[inline]
-    fn eq(&self, other: &Arc<T>) -> bool {
-        (**self).eq(&**other)
+/// The reference-counted allocation of an `Arc`, placed in static memory instead of on the heap.
+///
+/// An `Arc` obtained via `Arc::from_static` does not keep count of its references: cloning and
+/// dropping it do not modify the allocation, which is never freed. Its
+/// `ReferenceCounted::reference_count` is always `usize::MAX`, so it can never be converted into
+/// a `UniqueArc` (or unwrapped).
+///
+/// ```
+/// use reference_counted::{Arc, StaticArcInner};
+///
+/// static ANSWER: StaticArcInner<u32> = StaticArcInner::new(42);
+///
+/// let a = Arc::from_static(&ANSWER);
+/// assert_eq!(*a.clone(), 42);
+/// ```
+///
+/// Since both `StaticArcInner::new` and `Arc::from_static` are `const`, an `Arc` can itself be
+/// placed in a `static`, without any initialization at runtime:
+///
+/// ```
+/// use reference_counted::{Arc, StaticArcInner};
+///
+/// static DEFAULT_INNER: StaticArcInner<u32> = StaticArcInner::new(42);
+/// static DEFAULT: Arc<u32> = Arc::from_static(&DEFAULT_INNER);
+///
+/// assert_eq!(*DEFAULT.clone(), 42);
+/// ```
+#[repr(transparent)]
+pub struct StaticArcInner<T: ?Sized>(ArcInner<T>);
+
+impl<T> StaticArcInner<T> {
+    /// Creates an allocation for `Arc::from_static` that contains the given value.
+    pub const fn new(data: T) -> Self {
+        StaticArcInner(ArcInner {
+            strong: atomic::AtomicUsize::new(STATIC_REFCOUNT),
+            #[cfg(feature = "weak")]
+            weak: atomic::AtomicUsize::new(STATIC_REFCOUNT),
+            #[cfg(feature = "allocation-id")]
+            id: 0,
+            data,
+        })
     }
+}
 
-    /// Inequality for two `Arc`s.
+impl<T: ?Sized> Arc<T> {
+    /// Creates an `Arc` that points to a static allocation, without allocating. This can be used
+    /// in const contexts.
     ///
-    /// Two `Arc`s are unequal if their inner values are unequal. This implementation does not
-    /// check for pointer equality.
-    #[inline]
-    fn ne(&self, other: &Arc<T>) -> bool {
-        (**self).ne(&**other)
+    /// Cloning and dropping the resulting pointers do not touch any reference count.
+    pub const fn from_static(inner: &'static StaticArcInner<T>) -> Arc<T> {
+        // The data is never accessed mutably, since `ReferenceCounted::reference_count` is
+        // never one for static allocations.
+        let ptr = &inner.0 as *const ArcInner<T> as *mut ArcInner<T>;
+        Self::from_inner(unsafe { NonNull::new_unchecked(ptr) })
     }
 }
 
-impl<T: ?Sized + Eq> Eq for Arc<T> {}
+/// A static allocation for an `Arc<str>`, used by the `arc_str` macro. Since it can only be
+/// constructed from a `str`, its bytes are guaranteed to be valid utf-8.
+#[doc(hidden)]
+pub struct StaticArcStr<const N: usize>(StaticArcInner<[u8; N]>);
 
-impl<T: ?Sized + PartialEq> PartialEq for UniqueArc<T> {
-    /// Equality for two `UniqueArc`s.
-    ///
-    /// Two `UniqueArc`s are equal if their inner values are equal, even if they are
-    /// stored in different allocation. This implementation does not check for
-    /// pointer equality.
-    #[inline]
-    fn eq(&self, other: &UniqueArc<T>) -> bool {
-        (**self).eq(&**other)
+impl<const N: usize> StaticArcStr<N> {
+    #[doc(hidden)]
+    pub const fn new(s: &str) -> Self {
+        StaticArcStr(StaticArcInner::new(str_to_array(s)))
     }
+}
 
-    /// Inequality for two `Arc`s.
-    ///
-    /// Two `Arc`s are unequal if their inner values are unequal. This implementation does not
-    /// check for pointer equality.
-    #[inline]
-    fn ne(&self, other: &UniqueArc<T>) -> bool {
-        (**self).ne(&**other)
+impl Arc<str> {
+    #[doc(hidden)]
+    pub fn from_static_str<const N: usize>(inner: &'static StaticArcStr<N>) -> Arc<str> {
+        let arc: Arc<[u8]> = Arc::from_static(&inner.0);
+        // The bytes are valid utf-8, and `ArcInner<str>` has the same layout as
+        // `ArcInner<[u8]>`.
+        let ptr = arc.ptr() as *mut ArcInner<str>;
+        mem::forget(arc);
+        unsafe { Self::from_inner(NonNull::new_unchecked(ptr)) }
     }
 }
 
-impl<T: ?Sized + Eq> Eq for UniqueArc<T> {}
+/// Creates an `Arc<[T]>` containing the arguments, like `vec!` but in a single
+/// reference-counted allocation of the exact size.
+///
+/// Unlike `arc_slice!`, the elements are evaluated at runtime.
+///
+/// ```
+/// use reference_counted::{arc, Arc};
+///
+/// let xs: Arc<[i32]> = arc![1, 2, 3];
+/// assert_eq!(&*xs, &[1, 2, 3]);
+///
+/// let zeros: Arc<[u8]> = arc![0; 1024];
+/// assert_eq!(zeros.len(), 1024);
+/// ```
+#[macro_export]
+macro_rules! arc {
+    () => {
+        $crate::Arc::<[_]>::from([])
+    };
+    ($elem:expr; $n:expr) => {
+        $crate::Arc::<[_]>::from_elem($elem, $n)
+    };
+    ($($x:expr),+ $(,)?) => {
+        $crate::Arc::<[_]>::from([$($x),+])
+    };
+}
 
-impl<T: ?Sized + PartialOrd> PartialOrd for Arc<T> {
-    /// Partial comparison for two `Arc`s.
-    ///
-    /// The two are compared by calling `partial_cmp()` on their inner values.
-    fn partial_cmp(&self, other: &Arc<T>) -> Option<Ordering> {
-        (**self).partial_cmp(&**other)
-    }
+/// Creates an `Arc<str>` from a string literal (or any constant string expression) without
+/// allocating, by placing it in a `StaticArcInner`.
+///
+/// ```
+/// use reference_counted::{arc_str, Arc};
+///
+/// let greeting: Arc<str> = arc_str!("hello");
+/// assert_eq!(&*greeting, "hello");
+/// ```
+#[macro_export]
+macro_rules! arc_str {
+    ($s:expr) => {{
+        static INNER: $crate::StaticArcStr<{ $s.len() }> = $crate::StaticArcStr::new($s);
+        $crate::Arc::<str>::from_static_str(&INNER)
+    }};
+}
 
-    /// Less-than comparison for two `Arc`s.
+/// Creates an `Arc<[T]>` from constant expressions without allocating, by placing them in a
+/// `StaticArcInner`.
+///
+/// Since the type of a static can not be inferred, the element type has to be given before
+/// the elements.
+///
+/// ```
+/// use reference_counted::{arc_slice, Arc};
+///
+/// let primes: Arc<[u8]> = arc_slice![u8 => 2, 3, 5, 7];
+/// assert_eq!(&*primes, &[2, 3, 5, 7]);
+/// ```
+#[macro_export]
+macro_rules! arc_slice {
+    ($t:ty => $($x:expr),* $(,)?) => {{
+        static INNER: $crate::StaticArcInner<[$t; <[&str]>::len(&[$(stringify!($x)),*])]> =
+            $crate::StaticArcInner::new([$($x),*]);
+        $crate::Arc::<[$t]>::from_static(&INNER)
+    }};
+}
+
+#[cfg(feature = "allocation-id")]
+impl<T: ?Sized> Arc<T> {
+    /// Gets the id of the allocation, which is assigned from a global counter when it is
+    /// created and hence never reused while the process runs. All clones share the id, and it
+    /// is kept when converting to and from a `UniqueArc`.
     ///
-    /// The two are compared by calling `<` on their inner values.
-    fn lt(&self, other: &Arc<T>) -> bool {
-        *(*self) < *(*other)
+    /// Static allocations (e.g. from `Arc::from_static`) all have the id zero.
+    ///
+    /// The id is stored in every allocation, adding eight bytes (plus padding) of overhead
+    /// when the `allocation-id` feature is enabled.
+    pub fn allocation_id(this: &Self) -> u64 {
+        this.inner().id
     }
+}
 
-    /// 'Less than or equal to' comparison for two `Arc`s.
-    ///
-    /// The two are compared by calling `<=` on their inner values.
-    fn le(&self, other: &Arc<T>) -> bool {
-        *(*self) <= *(*other)
+#[cfg(feature = "allocation-id")]
+impl<T: ?Sized> UniqueArc<T> {
+    /// Gets the id of the allocation, see `Arc::allocation_id`.
+    pub fn allocation_id(this: &Self) -> u64 {
+        Arc::allocation_id(&this.0)
     }
+}
 
-    /// Greater-than comparison for two `Arc`s.
+impl<T: ?Sized> Clone for Arc<T> {
+    /// Makes a clone of the `Arc` pointer.
     ///
-    /// The two are compared by calling `>` on their inner values.
-    fn gt(&self, other: &Arc<T>) -> bool {
-        *(*self) > *(*other)
+    /// This creates another pointer to the same allocation, increasing the reference count.
+    #[inline]
+    fn clone(&self) -> Arc<T> {
+        // Pointers to static memory are not counted.
+        if self.inner().strong.load(Relaxed) == STATIC_REFCOUNT {
+            return Self::from_inner(self.ptr);
+        }
+
+        // Using a relaxed ordering is alright here, as knowledge of the
+        // original reference prevents other threads from erroneously deleting
+        // the object.
+        //
+        // As explained in the [Boost documentation][1], Increasing the
+        // reference counter can always be done with memory_order_relaxed: New
+        // references to an object can only be formed from an existing
+        // reference, and passing an existing reference from one thread to
+        // another must already provide any required synchronization.
+        //
+        // [1]: (www.boost.org/doc/libs/1_55_0/doc/html/atomic/usage_examples.html)
+        let old_size = self.inner().strong.fetch_add(1, Relaxed);
+
+        // However we need to guard against massive refcounts in case someone
+        // is `mem::forget`ing Arcs. If we don't do this the count can overflow
+        // and users will use-after free. We racily saturate to `isize::MAX` on
+        // the assumption that there aren't ~2 billion threads incrementing
+        // the reference count at once. This branch will never be taken in
+        // any realistic program.
+        //
+        // We abort because such a program is incredibly degenerate, and we
+        // don't care to support it.
+        if old_size > MAX_REFCOUNT {
+            refcount_overflow();
+        }
+
+        Self::from_inner(self.ptr)
     }
+}
 
-    /// 'Greater than or equal to' comparison for two `Arc`s.
+impl<T: ?Sized> Arc<T> {
+    /// Makes `n` clones of the `Arc` pointer, increasing the reference count only once.
     ///
-    /// The two are compared by calling `>=` on their inner values.
-    fn ge(&self, other: &Arc<T>) -> bool {
-        *(*self) >= *(*other)
+    /// The clones are produced by the returned iterator. Those that have not been produced by
+    /// the time it is dropped are released again, with a single update of the reference count.
+    pub fn clone_many(this: &Self, n: usize) -> ArcClones<T> {
+        // Pointers to static memory are not counted.
+        if this.inner().strong.load(Relaxed) != STATIC_REFCOUNT && n > 0 {
+            if n > MAX_REFCOUNT {
+                refcount_overflow();
+            }
+
+            // See `clone` for the choice of ordering and the overflow check, which here
+            // applies to the whole batch.
+            let old_size = this.inner().strong.fetch_add(n, Relaxed);
+            if old_size > MAX_REFCOUNT - n {
+                // A bogus `n` can overflow the count without any pointers having been leaked, so
+                // give the references back before panicking.
+                this.inner().strong.fetch_sub(n, Relaxed);
+                refcount_overflow();
+            }
+        }
+
+        ArcClones { ptr: this.ptr, remaining: n }
     }
 }
 
-impl<T: ?Sized + PartialOrd> PartialOrd for UniqueArc<T> {
-    /// Partial comparison for two `UniqueArc`s.
-    ///
-    /// The two are compared by calling `partial_cmp()` on their inner values.
-    fn partial_cmp(&self, other: &UniqueArc<T>) -> Option<Ordering> {
-        (**self).partial_cmp(&**other)
+/// An iterator over clones of an `Arc`, whose reference counts have been acquired in advance.
+///
+/// Created by `Arc::clone_many`.
+pub struct ArcClones<T: ?Sized> {
+    ptr: NonNull<ArcInner<T>>,
+    remaining: usize,
+}
+
+unsafe impl<T: ?Sized + Sync + Send> Send for ArcClones<T> {}
+unsafe impl<T: ?Sized + Sync + Send> Sync for ArcClones<T> {}
+
+impl<T: ?Sized> Iterator for ArcClones<T> {
+    type Item = Arc<T>;
+
+    fn next(&mut self) -> Option<Arc<T>> {
+        if self.remaining == 0 {
+            None
+        } else {
+            self.remaining -= 1;
+            Some(Arc::from_inner(self.ptr))
+        }
     }
 
-    /// Less-than comparison for two `UniqueArc`s.
-    ///
-    /// The two are compared by calling `<` on their inner values.
-    fn lt(&self, other: &UniqueArc<T>) -> bool {
-        *(*self) < *(*other)
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.remaining, Some(self.remaining))
     }
+}
 
-    /// 'Less than or equal to' comparison for two `UniqueArc`s.
-    ///
-    /// The two are compared by calling `<=` on their inner values.
-    fn le(&self, other: &UniqueArc<T>) -> bool {
-        *(*self) <= *(*other)
+impl<T: ?Sized> ExactSizeIterator for ArcClones<T> {}
+
+impl<T: ?Sized> FusedIterator for ArcClones<T> {}
+
+impl<T: ?Sized> Drop for ArcClones<T> {
+    fn drop(&mut self) {
+        if self.remaining == 0 {
+            return;
+        }
+
+        let last = Arc::from_inner(self.ptr);
+        if last.inner().strong.load(Relaxed) != STATIC_REFCOUNT {
+            // Release all but one of the remaining references. This can not drop the count to
+            // zero, since `last` still holds a reference.
+            last.inner().strong.fetch_sub(self.remaining - 1, Release);
+        }
+        // Dropping the last one goes through the regular path, since the original `Arc` may
+        // be gone already.
+        drop(last);
     }
+}
 
-    /// Greater-than comparison for two `UniqueArc`s.
+#[cfg(feature = "weak")]
+impl<T: ?Sized> Arc<T> {
+    /// Creates a `WeakArc` to the allocation of this `Arc`.
     ///
-    /// The two are compared by calling `>` on their inner values.
-    fn gt(&self, other: &UniqueArc<T>) -> bool {
-        *(*self) > *(*other)
+    /// `WeakArc`s to static allocations (see `Arc::from_static`) can always be upgraded.
+    pub fn downgrade(this: &Self) -> WeakArc<T> {
+        // Pointers to static memory are not counted.
+        if this.inner().strong.load(Relaxed) == STATIC_REFCOUNT {
+            return WeakArc { ptr: this.ptr };
+        }
+
+        let weak = &this.inner().weak;
+        let mut cur = weak.load(Relaxed);
+        loop {
+            // The weak count is locked while `can_make_mut` checks the strong count, spin until
+            // it is released.
+            if cur == usize::MAX {
+                hint::spin_loop();
+                cur = weak.load(Relaxed);
+                continue;
+            }
+
+            // See `Clone` for the overflow check.
+            if cur > MAX_REFCOUNT {
+                refcount_overflow();
+            }
+
+            // Acquire synchronizes with the release write in `can_make_mut`, so that its read of
+            // the strong count happens before the increment.
+            match weak.compare_exchange_weak(cur, cur + 1, Acquire, Relaxed) {
+                Ok(_) => return WeakArc { ptr: this.ptr },
+                Err(old) => cur = old,
+            }
+        }
     }
 
-    /// 'Greater than or equal to' comparison for two `UniqueArc`s.
+    /// Returns the number of `WeakArc`s to the allocation of this `Arc`. As in `std`, this
+    /// does not include the weak reference that all `Arc`s hold together.
     ///
-    /// The two are compared by calling `>=` on their inner values.
-    fn ge(&self, other: &UniqueArc<T>) -> bool {
-        *(*self) >= *(*other)
+    /// Like `ReferenceCounted::reference_count`, this returns `usize::MAX` for static
+    /// allocations (see `Arc::from_static`).
+    pub fn weak_count(this: &Self) -> usize {
+        let inner = this.inner();
+        if inner.strong.load(SeqCst) == STATIC_REFCOUNT {
+            return STATIC_REFCOUNT;
+        }
+
+        // `can_make_mut` only locks the weak count while there are no `WeakArc`s.
+        match inner.weak.load(SeqCst) {
+            usize::MAX => 0,
+            weak => weak - 1,
+        }
     }
 }
 
-impl<T: ?Sized + Ord> Ord for Arc<T> {
-    /// Comparison for two `Arc`s.
+#[cfg(feature = "weak")]
+impl<T: ?Sized> UniqueArc<T> {
+    /// Creates a `WeakArc` to the allocation of this `UniqueArc`, e.g. to set up reference
+    /// cycles while the value can still be mutated.
     ///
-    /// The two are compared by calling `cmp()` on their inner values.
-    fn cmp(&self, other: &Arc<T>) -> Ordering {
-        (**self).cmp(&**other)
+    /// The `WeakArc` can not be upgraded while the value is uniquely owned, only once the
+    /// `UniqueArc` has been converted into an `Arc`. If the `UniqueArc` is dropped or unwrapped
+    /// instead, it can never be upgraded.
+    pub fn downgrade(this: &Self) -> WeakArc<T> {
+        // A `UniqueArc` never points to a static allocation, see `UniqueArc::new`.
+        let inner = this.0.inner();
+
+        // A strong count of zero makes `WeakArc::upgrade` fail until the conversion into an
+        // `Arc` sets it to one. No other thread accesses the strong count in the meantime, since
+        // there are no other `Arc`s and the `WeakArc`s only read it.
+        inner.strong.store(0, Relaxed);
+
+        // Clones of earlier `WeakArc`s may be incremented concurrently, see `WeakArc::clone`.
+        let old_size = inner.weak.fetch_add(1, Relaxed);
+        if old_size > MAX_REFCOUNT {
+            refcount_overflow();
+        }
+
+        WeakArc { ptr: this.0.ptr }
     }
 }
 
-impl<T: ?Sized + Ord> Ord for UniqueArc<T> {
-    /// Comparison for two `UniqueArc`s.
+#[cfg(feature = "weak")]
+impl<T> Arc<T> {
+    /// Constructs an `Arc` to the value returned by `f`, which gets a `WeakArc` to the
+    /// allocation that the value is placed in. This allows the value to hold a pointer to itself.
     ///
-    /// The two are compared by calling `cmp()` on their inner values.
-    fn cmp(&self, other: &UniqueArc<T>) -> Ordering {
-        (**self).cmp(&**other)
-    }
-}
+    /// Calling `WeakArc::upgrade` fails until `f` has returned, since the value does not exist
+    /// yet. If `f` panics, the allocation is freed once all `WeakArc`s to it are dropped.
+    ///
+    /// ```
+    /// use std::sync::Mutex;
+    /// use reference_counted::{Arc, WeakArc};
+    ///
+    /// struct Observer {
+    ///     name: &'static str,
+    ///     this: WeakArc<Observer>,
+    /// }
+    ///
+    /// impl Observer {
+    ///     fn subscribe(&self, subject: &Subject) {
+    ///         subject.observers.lock().unwrap().push(self.this.clone());
+    ///     }
+    /// }
+    ///
+    /// struct Subject {
+    ///     observers: Mutex<Vec<WeakArc<Observer>>>,
+    /// }
+    ///
+    /// impl Subject {
+    ///     fn notify(&self) -> Vec<&'static str> {
+    ///         let observers = self.observers.lock().unwrap();
+    ///         observers.iter().filter_map(|o| o.upgrade()).map(|o| o.name).collect()
+    ///     }
+    /// }
+    ///
+    /// let subject = Subject { observers: Mutex::new(Vec::new()) };
+    /// let a = Arc::new_cyclic(|this| {
+    ///     assert!(this.upgrade().is_none());
+    ///     Observer { name: "a", this: this.clone() }
+    /// });
+    /// let b = Arc::new_cyclic(|this| Observer { name: "b", this: this.clone() });
+    /// a.subscribe(&subject);
+    /// b.subscribe(&subject);
+    ///
+    /// drop(b);
+    /// assert_eq!(subject.notify(), ["a"]);
+    /// ```
+    pub fn new_cyclic<F: FnOnce(&WeakArc<T>) -> T>(f: F) -> Arc<T> {
+        // This allocates even for zero-sized types, since the strong count has to start at zero
+        // while `f` runs.
+        let ptr = unsafe {
+            Self::allocate_for_layout(Layout::new::<T>(), |mem| mem as *mut ArcInner<T>)
+        };
 
-impl<T> From<T> for Arc<T> {
-    fn from(t: T) -> Self {
-        Arc::new(t)
-    }
-}
+        // The strong count starts at zero so that upgrading fails while there is no value. The
+        // `WeakArc` holds the weak reference of all `Arc`s, so it frees the allocation without
+        // touching the value if `f` panics.
+        unsafe { (*ptr).strong.store(0, Relaxed) };
+        let weak = WeakArc { ptr: unsafe { NonNull::new_unchecked(ptr) } };
 
-impl<T> From<T> for UniqueArc<T> {
-    fn from(t: T) -> Self {
-        UniqueArc::new(t)
-    }
-}
+        let data = f(&weak);
 
-impl<T: ?Sized + Hash> Hash for Arc<T> {
-    fn hash<H: Hasher>(&self, state: &mut H) {
-        (**self).hash(state)
+        unsafe {
+            ptr::addr_of_mut!((*ptr).data).write(data);
+            // Release so that `WeakArc::upgrade` on another thread sees the written value.
+            (*ptr).strong.store(1, Release);
+        }
+        mem::forget(weak);
+        Self::from_inner(unsafe { NonNull::new_unchecked(ptr) })
     }
-}
 
-impl<T: ?Sized + Hash> Hash for UniqueArc<T> {
-    fn hash<H: Hasher>(&self, state: &mut H) {
-        (**self).hash(state)
+    /// Returns the value if this is the only `Arc` to its allocation, together with the number
+    /// of `WeakArc`s that can not be upgraded anymore because of it. Returns the `Arc` otherwise.
+    ///
+    /// The `WeakArc`s are counted right after the value has been taken out. Other threads may
+    /// still clone or drop them, so the number is only exact if they do not.
+    pub fn try_unwrap_disassociate(this: Self) -> Result<(T, usize), Arc<T>> {
+        if this.inner().strong.compare_exchange(1, 0, Relaxed, Relaxed).is_err() {
+            return Err(this);
+        }
+
+        acquire!(this.inner().strong);
+
+        // The weak count is not locked, since `can_make_mut` needs another `Arc` for that. It
+        // includes the weak reference of all `Arc`s, which `release_allocation` gives up.
+        let orphaned = this.inner().weak.load(Acquire) - 1;
+        unsafe {
+            let elem = ptr::read(&this.ptr.as_ref().data);
+            release_allocation(Arc::into_inner(this));
+            Ok((elem, orphaned))
+        }
     }
-}
 
-impl<T: ?Sized> Unpin for Arc<T> {}
+    /// Returns the value if this is the only `Arc` to its allocation and there are no
+    /// `WeakArc`s to it, e.g. because a remaining `WeakArc` would indicate a logic error.
+    /// Returns the `Arc` otherwise.
+    pub fn try_unwrap_strict(this: Self) -> Result<T, Arc<T>> {
+        // Lock the weak count as in `can_make_mut`, so that no `WeakArc` can be created from
+        // another `Arc` while the strong count is checked. Static allocations (see
+        // `Arc::from_static`) never have a weak count of one.
+        if this.inner().weak.compare_exchange(1, usize::MAX, Acquire, Relaxed).is_err() {
+            return Err(this);
+        }
 
-impl<T: ?Sized> Unpin for UniqueArc<T> {}
+        // Acquire to synchronize with the decrements of the strong count in `drop`.
+        let unique = this.inner().strong.compare_exchange(1, 0, Acquire, Relaxed).is_ok();
+        this.inner().weak.store(1, Release);
+        if !unique {
+            return Err(this);
+        }
+
+        unsafe {
+            let elem = ptr::read(&this.ptr.as_ref().data);
+            release_allocation(Arc::into_inner(this));
+            Ok(elem)
+        }
+    }
+}
+
+/// A weak pointer to the allocation of an `Arc`, created by `Arc::downgrade` or
+/// `Arc::new_cyclic`, or a weak pointer to no allocation at all, created by `WeakArc::new`.
+///
+/// A `WeakArc` does not keep the value alive, only the allocation, so that reference cycles
+/// through `WeakArc`s do not leak. `WeakArc::upgrade` returns an `Arc` to the value if there
+/// still is one.
+#[cfg(feature = "weak")]
+pub struct WeakArc<T: ?Sized> {
+    // `DANGLING` for pointers created by `WeakArc::new`. Such pointers have no counts, and must
+    // never be dereferenced.
+    ptr: NonNull<ArcInner<T>>,
+}
+
+#[cfg(feature = "weak")]
+unsafe impl<T: ?Sized + Sync + Send> Send for WeakArc<T> {}
+#[cfg(feature = "weak")]
+unsafe impl<T: ?Sized + Sync + Send> Sync for WeakArc<T> {}
+
+/// The address of the `ArcInner` of a `WeakArc` created by `WeakArc::new`. No allocation can
+/// start there, since an `ArcInner` is aligned at least as much as a `usize`.
+#[cfg(feature = "weak")]
+const DANGLING: usize = usize::MAX;
+
+/// The address of the value that `WeakArc::as_ptr` returns for a `WeakArc` created by
+/// `WeakArc::new`. It is not null, and a multiple of every possible alignment.
+#[cfg(feature = "weak")]
+const DANGLING_DATA: usize = 1 << (usize::BITS - 1);
+
+#[cfg(feature = "weak")]
+impl<T> WeakArc<T> {
+    /// Creates a `WeakArc` that does not point to any allocation, so that upgrading it always
+    /// fails.
+    ///
+    /// Neither this nor cloning or dropping the result allocates or frees memory.
+    pub const fn new() -> WeakArc<T> {
+        // Offsetting a null pointer rather than casting an integer makes it clear that the
+        // pointer has no provenance, and may never be dereferenced.
+        let ptr = ptr::null_mut::<u8>().wrapping_add(DANGLING).cast::<ArcInner<T>>();
+        WeakArc { ptr: unsafe { NonNull::new_unchecked(ptr) } }
+    }
+}
+
+#[cfg(feature = "weak")]
+impl<T: ?Sized> WeakArc<T> {
+    fn is_dangling(&self) -> bool {
+        self.ptr.as_ptr() as *mut u8 as usize == DANGLING
+    }
+
+    // The counts are accessed without creating a reference to the whole `ArcInner`, since the
+    // value might have been dropped already. They must not be accessed for dangling pointers.
+    fn strong(&self) -> &atomic::AtomicUsize {
+        unsafe { &*ptr::addr_of!((*self.ptr.as_ptr()).strong) }
+    }
+
+    fn weak(&self) -> &atomic::AtomicUsize {
+        unsafe { &*ptr::addr_of!((*self.ptr.as_ptr()).weak) }
+    }
+
+    /// Attempts to create an `Arc` to the value, returning `None` if the value has been
+    /// dropped already.
+    pub fn upgrade(&self) -> Option<Arc<T>> {
+        if self.is_dangling() {
+            return None;
+        }
+
+        let strong = self.strong();
+        let mut cur = strong.load(Relaxed);
+        loop {
+            // Pointers to static memory are not counted.
+            if cur == STATIC_REFCOUNT {
+                return Some(Arc::from_inner(self.ptr));
+            }
+
+            // Once the strong count has reached zero, the value is gone for good.
+            if cur == 0 {
+                return None;
+            }
+
+            // See `Arc::clone` for the overflow check.
+            if cur > MAX_REFCOUNT {
+                refcount_overflow();
+            }
+
+            // Relaxed is fine for the failure case, since there are no expectations about the
+            // new state. Acquire is needed for the success case to synchronize with whoever
+            // initialized the value that the new `Arc` gives access to.
+            match strong.compare_exchange_weak(cur, cur + 1, Acquire, Relaxed) {
+                Ok(_) => return Some(Arc::from_inner(self.ptr)),
+                Err(old) => cur = old,
+            }
+        }
+    }
+
+    /// Returns the number of `Arc`s to the allocation, which is zero if the value has been
+    /// dropped already or if this was created by `WeakArc::new`.
+    ///
+    /// Like `ReferenceCounted::reference_count`, this returns `usize::MAX` for static
+    /// allocations (see `Arc::from_static`).
+    pub fn strong_count(&self) -> usize {
+        if self.is_dangling() {
+            return 0;
+        }
+        self.strong().load(SeqCst)
+    }
+
+    /// Returns a pointer to the value, which may have been dropped already. The pointer is
+    /// valid only while there are `Arc`s to the allocation.
+    ///
+    /// For `WeakArc`s created by `WeakArc::new`, the pointer is dangling: it is aligned and not
+    /// null, but must not be dereferenced.
+    pub fn as_ptr(&self) -> *const T {
+        if self.is_dangling() {
+            let data = ptr::null_mut::<u8>().wrapping_add(DANGLING_DATA);
+            return unsafe { set_data_ptr(self.ptr.as_ptr() as *mut T, data) };
+        }
+        // This does not create a reference, since the value might not be valid anymore.
+        unsafe { ptr::addr_of!((*self.ptr.as_ptr()).data) }
+    }
+
+    /// Returns `true` if the two `WeakArc`s point to the same allocation, or if neither points to
+    /// an allocation (see `WeakArc::new`). Pointer metadata is ignored.
+    pub fn ptr_eq(&self, other: &WeakArc<T>) -> bool {
+        self.ptr.as_ptr() as *mut u8 == other.ptr.as_ptr() as *mut u8
+    }
+}
+
+#[cfg(feature = "weak")]
+impl<T: ?Sized> Clone for WeakArc<T> {
+    /// Makes another `WeakArc` to the same allocation.
+    fn clone(&self) -> WeakArc<T> {
+        // Pointers to static memory and dangling pointers are not counted.
+        if self.is_dangling() || self.strong().load(Relaxed) == STATIC_REFCOUNT {
+            return WeakArc { ptr: self.ptr };
+        }
+
+        // See `Arc::clone` for why this can be relaxed. The weak count is not locked by
+        // `can_make_mut`, since it only locks it while there are no `WeakArc`s.
+        let old_size = self.weak().fetch_add(1, Relaxed);
+        if old_size > MAX_REFCOUNT {
+            refcount_overflow();
+        }
+
+        WeakArc { ptr: self.ptr }
+    }
+}
+
+#[cfg(feature = "weak")]
+impl<T: ?Sized> Drop for WeakArc<T> {
+    /// Drops the `WeakArc`, freeing the allocation if this was the last reference of any kind.
+    fn drop(&mut self) {
+        // Pointers to static memory and dangling pointers are not counted, and there is nothing
+        // to free.
+        if self.is_dangling() || self.strong().load(Relaxed) == STATIC_REFCOUNT {
+            return;
+        }
+
+        // As for the strong count in `Arc::drop`, all accesses to the allocation through other
+        // references have to happen before it is freed.
+        if self.weak().fetch_sub(1, Release) == 1 {
+            acquire!(self.weak());
+            unsafe { deallocate(self.ptr) };
+        }
+    }
+}
+
+#[cfg(feature = "weak")]
+impl<T> Default for WeakArc<T> {
+    /// Creates a `WeakArc` that does not point to any allocation, see `WeakArc::new`.
+    fn default() -> WeakArc<T> {
+        WeakArc::new()
+    }
+}
+
+#[cfg(feature = "weak")]
+impl<T: ?Sized> fmt::Debug for WeakArc<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("(WeakArc)")
+    }
+}
+
+/// Serializes the value as `Some` if the `WeakArc` can be upgraded, and as `None` otherwise, like
+/// serde does for `std::sync::Weak`. See `serde_weak` for alternatives.
+#[cfg(all(feature = "serde", feature = "weak"))]
+impl<T: ?Sized + Serialize> Serialize for WeakArc<T> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        crate::serde_weak::as_option::serialize(self, serializer)
+    }
+}
+
+/// Deserializes an optional value and discards it, resulting in a `WeakArc` that does not point
+/// to any allocation. Links between values must be re-established after deserialization.
+#[cfg(all(feature = "serde", feature = "weak"))]
+impl<'de, T: Deserialize<'de>> Deserialize<'de> for WeakArc<T> {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        crate::serde_weak::as_option::deserialize(deserializer)
+    }
+}
+
+impl<T: ?Sized> Arc<T> {
+    /// Consumes the `Arc` without releasing its reference, and returns a pointer to the value.
+    /// Use `Arc::from_raw` to turn the pointer back into an `Arc`, or the allocation is leaked.
+    pub fn into_raw(this: Self) -> *const T {
+        let ptr = Self::as_ptr(&this);
+        mem::forget(this);
+        ptr
+    }
+
+    /// Returns a pointer to the value, without touching the reference count. The pointer is valid
+    /// as long as there are `Arc`s to the allocation.
+    pub fn as_ptr(this: &Self) -> *const T {
+        // This does not create a reference, so the pointer keeps the provenance of the whole
+        // allocation, which `Arc::from_raw` needs.
+        unsafe { ptr::addr_of!((*this.ptr.as_ptr()).data) }
+    }
+
+    /// Reconstructs an `Arc` from a pointer returned by `Arc::into_raw`, taking over the
+    /// reference that `into_raw` has kept. The reference count is not touched.
+    ///
+    /// # Safety
+    ///
+    /// `ptr` must have been returned by `Arc::into_raw` on an `Arc<T>`, and each such pointer
+    /// must be reconstructed at most once. Since the reference kept by `into_raw` keeps the
+    /// value alive, the value is still valid when this is called.
+    pub unsafe fn from_raw(ptr: *const T) -> Self {
+        // The value is still alive, so its alignment can be read through a reference.
+        let offset = data_offset_val(unsafe { &*ptr });
+        let inner = unsafe { set_data_ptr(ptr as *mut T, (ptr as *mut u8).sub(offset)) };
+        Self::from_inner(unsafe { NonNull::new_unchecked(inner as *mut ArcInner<T>) })
+    }
+
+    /// Increments the reference count of the allocation behind a pointer returned by
+    /// `Arc::into_raw`, as if an `Arc` to it had been cloned and leaked.
+    ///
+    /// # Safety
+    ///
+    /// `ptr` must have been returned by `Arc::into_raw` on an `Arc<T>`, and the reference kept by
+    /// it must not have been released yet.
+    pub unsafe fn increment_strong_count(ptr: *const T) {
+        // `Clone` takes care of static allocations and of the overflow check. Neither `Arc` is
+        // dropped, so the reference held by `ptr` stays where it is.
+        let arc = ManuallyDrop::new(unsafe { Arc::from_raw(ptr) });
+        let _clone: ManuallyDrop<Arc<T>> = arc.clone();
+    }
+
+    /// Decrements the reference count of the allocation behind a pointer returned by
+    /// `Arc::into_raw`, as if one of the `Arc`s to it had been dropped. If this releases the
+    /// last reference, the value is dropped and the allocation freed.
+    ///
+    /// # Safety
+    ///
+    /// `ptr` must have been returned by `Arc::into_raw` on an `Arc<T>`, and there must be a
+    /// reference for this call to release, kept by `into_raw` or by `increment_strong_count`.
+    /// `ptr` must not be used again if that was the last reference.
+    pub unsafe fn decrement_strong_count(ptr: *const T) {
+        drop(unsafe { Arc::from_raw(ptr) });
+    }
+
+    /// Borrows the `Arc` as an `ArcBorrow`, without touching the reference count.
+    pub fn borrow_arc(this: &Self) -> ArcBorrow<'_, T> {
+        ArcBorrow::from_inner(this.ptr)
+    }
+}
+
+impl<T: ?Sized + 'static> Arc<T> {
+    /// Consumes the `Arc` without releasing its reference, and returns a reference to the value
+    /// that lives forever, together with a token that can turn the leaked reference back into
+    /// an `Arc`.
+    ///
+    /// Until the token is reclaimed, the leaked reference stays counted, so the allocation is not
+    /// freed and the pointers to it can not become unique. Dropping the token without reclaiming
+    /// it makes the leak permanent.
+    pub fn leak_with_token(this: Self) -> (&'static T, LeakToken<T>) {
+        let ptr = Arc::into_inner(this);
+        (unsafe { &(*ptr.as_ptr()).data }, LeakToken { ptr })
+    }
+}
+
+/// The right to reclaim a reference leaked by `Arc::leak_with_token`.
+///
+/// Since the token is neither `Copy` nor `Clone`, the reference can be reclaimed at most once.
+pub struct LeakToken<T: ?Sized> {
+    ptr: NonNull<ArcInner<T>>,
+}
+
+unsafe impl<T: ?Sized + Sync + Send> Send for LeakToken<T> {}
+unsafe impl<T: ?Sized + Sync + Send> Sync for LeakToken<T> {}
+
+impl<T: ?Sized> LeakToken<T> {
+    /// Turns the leaked reference back into an `Arc`.
+    ///
+    /// # Safety
+    ///
+    /// The `&'static T` returned together with this token, and all references derived from it,
+    /// must not be used anymore.
+    pub unsafe fn reclaim(self) -> Arc<T> {
+        Arc::from_inner(self.ptr)
+    }
+}
+
+impl<T: ?Sized> fmt::Debug for LeakToken<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("LeakToken").finish_non_exhaustive()
+    }
+}
+
+#[cfg(feature = "std")]
+impl<T: ?Sized + Send + 'static> Arc<T> {
+    /// Drops the `Arc`, but if it is the last reference, the value is dropped and deallocated
+    /// on a background thread instead of the current one, e.g. because its destructor is
+    /// expensive or blocks.
+    ///
+    /// If other references remain, this only decrements the reference count, just like
+    /// dropping the `Arc`. The background thread is spawned when this first hands a value
+    /// to it, and is shared by all `Arc`s. Values are dropped in the order in which they were
+    /// handed over.
+    ///
+    /// Dropping in the background is best-effort: the background thread is never joined, so
+    /// values that are still queued when the process exits (e.g. when `main` returns) are
+    /// not dropped at all.
+    pub fn drop_in_background(this: Self) {
+        let this = ManuallyDrop::new(this);
+        // Pointers to static memory are not counted, and the memory is never freed.
+        if this.inner().strong.load(Relaxed) == STATIC_REFCOUNT {
+            return;
+        }
+
+        // See `Drop` for the orderings.
+        let old_size = this.inner().strong.fetch_sub(1, Release);
+        if old_size != 1 {
+            #[cfg(all(feature = "async", not(feature = "panic-free")))]
+            {
+                if old_size == 2 {
+                    unique_waiters::notify(this.ptr() as *const u8 as usize);
+                }
+            }
+
+            return;
+        }
+
+        acquire!(this.inner().strong);
+        // The strong count stays at zero, so that `WeakArc`s can not be upgraded anymore.
+        crate::drop_queue::background().push(Box::new(Reaped(this.ptr)));
+    }
+}
+
+// An allocation whose strong count has reached zero, on its way to the background thread,
+// which drops it like the last `Arc` would.
+#[cfg(feature = "std")]
+struct Reaped<T: ?Sized>(NonNull<ArcInner<T>>);
+
+// Unlike for `Arc`, `T: Sync` is not needed, since nothing else can access the value.
+#[cfg(feature = "std")]
+unsafe impl<T: ?Sized + Send> Send for Reaped<T> {}
+
+#[cfg(feature = "std")]
+impl<T: ?Sized> Drop for Reaped<T> {
+    fn drop(&mut self) {
+        let mut arc = ManuallyDrop::new(Arc::from_inner(self.0));
+        unsafe { arc.drop_slow() };
+    }
+}
+
+impl Arc<dyn Any> {
+    /// Attempts to convert the pointer into one to the concrete type `T`, reusing the
+    /// allocation. Returns the pointer unchanged if the value is of a different type.
+    ///
+    /// Whether the resulting `Arc<T>` is `Send` and `Sync` depends on `T` alone.
+    pub fn downcast<T: Any>(this: Self) -> Result<Arc<T>, Self> {
+        if this.is::<T>() {
+            // The allocation holds a `T`, so the layout of `ArcInner<T>` applies.
+            Ok(Arc::from_inner(Arc::into_inner(this).cast::<ArcInner<T>>()))
+        } else {
+            Err(this)
+        }
+    }
+}
+
+impl Arc<dyn Any + Send + Sync> {
+    /// Attempts to convert the pointer into one to the concrete type `T`, reusing the
+    /// allocation. Returns the pointer unchanged if the value is of a different type.
+    ///
+    /// Whether the resulting `Arc<T>` is `Send` and `Sync` depends on `T` alone.
+    pub fn downcast<T: Any>(this: Self) -> Result<Arc<T>, Self> {
+        if this.is::<T>() {
+            // The allocation holds a `T`, so the layout of `ArcInner<T>` applies.
+            Ok(Arc::from_inner(Arc::into_inner(this).cast::<ArcInner<T>>()))
+        } else {
+            Err(this)
+        }
+    }
+}
+
+impl UniqueArc<dyn Any> {
+    /// Attempts to convert the pointer into one to the concrete type `T`, reusing the
+    /// allocation and keeping it unique. Returns the pointer unchanged if the value is of a
+    /// different type.
+    pub fn downcast<T: Any>(this: Self) -> Result<UniqueArc<T>, Self> {
+        Arc::<dyn Any>::downcast(this.0).map(UniqueArc).map_err(UniqueArc)
+    }
+}
+
+impl UniqueArc<dyn Any + Send + Sync> {
+    /// Attempts to convert the pointer into one to the concrete type `T`, reusing the
+    /// allocation and keeping it unique. Returns the pointer unchanged if the value is of a
+    /// different type.
+    pub fn downcast<T: Any>(this: Self) -> Result<UniqueArc<T>, Self> {
+        Arc::<dyn Any + Send + Sync>::downcast(this.0).map(UniqueArc).map_err(UniqueArc)
+    }
+}
+
+#[cfg(not(feature = "panic-free"))]
+impl<T: ?Sized> Arc<T> {
+    /// Converts the pointer into one to a `U` that `f` borrows from the value, reusing the
+    /// allocation and the reference count.
+    ///
+    /// This is mostly useful for converting a trait object into one of a supertrait, e.g.
+    /// `Arc::upcast(sub, |value| value as &dyn Super)`, see also `upcast_arc!`. On compilers
+    /// without trait upcasting coercion, `f` can call a method of the subtrait that returns
+    /// `&dyn Super`.
+    ///
+    /// Panics if the reference returned by `f` does not cover exactly the value, i.e. if it
+    /// has a different address, size or alignment.
+    pub fn upcast<U: ?Sized>(this: Self, f: impl FnOnce(&T) -> &U) -> Arc<U> {
+        let target = {
+            let value = &*this;
+            let target = f(value);
+            // `assert!` with a message expands to a bare `panic!` in this edition, which is
+            // ambiguous with the prelude glob import.
+            if !(ptr::eq(value as *const T as *const u8, target as *const U as *const u8)
+                && Layout::for_value(value) == Layout::for_value(target))
+            {
+                core::panic!("the upcast reference must cover exactly the value");
+            }
+            target as *const U as *mut U
+        };
+        let ptr = Arc::into_inner(this).as_ptr();
+        // The value is a valid `U` at the same place, and dropping it as a `U` deallocates it
+        // with the same layout.
+        let ptr = unsafe { set_data_ptr(target as *mut ArcInner<U>, ptr as *mut u8) };
+        Arc::from_inner(unsafe { NonNull::new_unchecked(ptr) })
+    }
+}
+
+#[cfg(not(feature = "panic-free"))]
+impl<T: ?Sized> UniqueArc<T> {
+    /// Converts the pointer into one to a `U` that `f` borrows from the value, keeping it
+    /// unique. See `Arc::upcast`.
+    pub fn upcast<U: ?Sized>(this: Self, f: impl FnOnce(&T) -> &U) -> UniqueArc<U> {
+        UniqueArc(Arc::upcast(this.0, f))
+    }
+}
+
+/// Converts an `Arc<dyn Sub>` into an `Arc<dyn Super>`, where `Super` is a supertrait of
+/// `Sub`, reusing the allocation and the reference count.
+///
+/// This expands to a call of `Arc::upcast` and relies on trait upcasting coercion.
+///
+/// ```
+/// use reference_counted::{upcast_arc, Arc};
+///
+/// trait Named {
+///     fn name(&self) -> &str;
+/// }
+///
+/// trait Greeter: Named {
+///     fn greet(&self) -> String {
+///         format!("hello, {}", self.name())
+///     }
+/// }
+///
+/// struct World;
+///
+/// impl Named for World {
+///     fn name(&self) -> &str {
+///         "world"
+///     }
+/// }
+///
+/// impl Greeter for World {}
+///
+/// let greeter: Arc<dyn Greeter> = Arc::from(Box::new(World) as Box<dyn Greeter>);
+/// let named = upcast_arc!(greeter, dyn Greeter => dyn Named);
+/// assert_eq!(named.name(), "world");
+/// ```
+#[cfg(not(feature = "panic-free"))]
+#[macro_export]
+macro_rules! upcast_arc {
+    ($arc:expr, $sub:ty => $sup:ty) => {
+        $crate::Arc::<$sub>::upcast::<$sup>($arc, |value| value)
+    };
+}
+
+impl<T: ?Sized> Drop for Arc<T> {
+    /// Drops the `Arc`.
+    ///
+    /// This will decrement the reference count.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use std::sync::Arc;
+    ///
+    /// struct Foo;
+    ///
+    /// impl Drop for Foo {
+    ///     fn drop(&mut self) {
+    ///         println!("dropped!");
+    ///     }
+    /// }
+    ///
+    /// let foo  = Arc::new(Foo);
+    /// let foo2 = Arc::clone(&foo);
+    ///
+    /// drop(foo);    // Doesn't print anything
+    /// drop(foo2);   // Prints "dropped!"
+    /// ```
+    #[inline]
+    fn drop(&mut self) {
+        let strong = self.inner().strong.load(Relaxed);
+        // Pointers to static memory are not counted, and the memory is never freed.
+        if strong == STATIC_REFCOUNT {
+            return;
+        }
+
+        // Only the `Arc` of a `UniqueArc` that has been passed to `UniqueArc::downgrade` has a
+        // strong count of zero. It is the only `Arc`, so the value is dropped right away.
+        #[cfg(feature = "weak")]
+        if strong == 0 {
+            unsafe { self.drop_slow() };
+            return;
+        }
+
+        // Because `fetch_sub` is already atomic, we do not need to synchronize
+        // with other threads unless we are going to delete the object. This
+        // same logic applies to the below `fetch_sub` to the `weak` count.
+        let old_size = self.inner().strong.fetch_sub(1, Release);
+        if old_size != 1 {
+            // The remaining reference might be awaiting uniqueness.
+            #[cfg(all(feature = "async", not(feature = "panic-free")))]
+            {
+                if old_size == 2 {
+                    unique_waiters::notify(self.ptr() as *const u8 as usize);
+                }
+            }
+
+            return;
+        }
+
+        // This fence is needed to prevent reordering of use of the data and
+        // deletion of the data.  Because it is marked `Release`, the decreasing
+        // of the reference count synchronizes with this `Acquire` fence. This
+        // means that use of the data happens before decreasing the reference
+        // count, which happens before this fence, which happens before the
+        // deletion of the data.
+        //
+        // As explained in the [Boost documentation][1],
+        //
+        // > It is important to enforce any possible access to the object in one
+        // > thread (through an existing reference) to *happen before* deleting
+        // > the object in a different thread. This is achieved by a "release"
+        // > operation after dropping a reference (any access to the object
+        // > through this reference must obviously happened before), and an
+        // > "acquire" operation before deleting the object.
+        //
+        // In particular, while the contents of an Arc are usually immutable, it's
+        // possible to have interior writes to something like a Mutex<T>. Since a
+        // Mutex is not acquired when it is deleted, we can't rely on its
+        // synchronization logic to make writes in thread A visible to a destructor
+        // running in thread B.
+        //
+        // Also note that the Acquire fence here could probably be replaced with an
+        // Acquire load, which could improve performance in highly-contended
+        // situations. See [2].
+        //
+        // [1]: (www.boost.org/doc/libs/1_55_0/doc/html/atomic/usage_examples.html)
+        // [2]: (https://github.com/rust-lang/rust/pull/41714)
+        acquire!(self.inner().strong);
+
+        unsafe {
+            self.drop_slow();
+        }
+    }
+}
+
+impl<T: ?Sized> Deref for Arc<T> {
+    type Target = T;
+
+    #[inline]
+    fn deref(&self) -> &T {
+        &self.inner().data
+    }
+}
+
+impl<T: ?Sized> borrow::Borrow<T> for Arc<T> {
+    fn borrow(&self) -> &T {
+        &**self
+    }
+}
+
+impl<T: ?Sized> AsRef<T> for Arc<T> {
+    fn as_ref(&self) -> &T {
+        &**self
+    }
+}
+
+impl<T: ?Sized + fmt::Display> fmt::Display for Arc<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Display::fmt(&**self, f)
+    }
+}
+
+impl<T: ?Sized + fmt::Debug> fmt::Debug for Arc<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Debug::fmt(&**self, f)
+    }
+}
+
+impl<T: ?Sized> fmt::Pointer for Arc<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Pointer::fmt(&(&**self as *const T), f)
+    }
+}
+
+impl<T> Arc<T> {
+    /// Moves `data` into a new heap allocation, even if it is a zero-sized type.
+    fn new_allocated(data: T) -> Arc<T> {
+        let x: Box<_> = Box::new(ArcInner {
+            strong: atomic::AtomicUsize::new(1),
+            #[cfg(feature = "weak")]
+            weak: atomic::AtomicUsize::new(1),
+            #[cfg(feature = "allocation-id")]
+            id: crate::next_allocation_id(),
+            data,
+        });
+        Self::from_inner(Box::leak(x).into())
+    }
+}
+
+impl<T: ?Sized> SmartPointer<T> for Arc<T> {
+    fn new(data: T) -> Arc<T> where T: Sized {
+        if is_static_zst::<T>() {
+            mem::forget(data);
+            let ptr = &ZST_INNER as *const ArcInner<()> as *mut ArcInner<T>;
+            return Self::from_inner(unsafe { NonNull::new_unchecked(ptr) });
+        }
+
+        Arc::new_allocated(data)
+    }
+
+    fn try_unwrap(this: Self) -> Result<T, Self> where T: Sized {
+        // The value has neither bytes nor drop glue, see `is_static_zst`.
+        if this.is_shared_zst() {
+            return Ok(unsafe { ptr::read(NonNull::<T>::dangling().as_ptr()) });
+        }
+
+        if this.inner().strong.compare_exchange(1, 0, Relaxed, Relaxed).is_err() {
+            return Err(this);
+        }
+
+        acquire!(this.inner().strong);
+
+        unsafe {
+            let elem = ptr::read(&this.ptr.as_ref().data);
+            release_allocation(Arc::into_inner(this));
+            Ok(elem)
+        }
+    }
+}
+
+/// A borrowed `Arc`, which can be copied freely and be turned into an owned `Arc` if needed.
+///
+/// This is like an `&'a Arc<T>`, but needs only a single indirection to reach the value, and
+/// can be created from a pointer to the value itself (see `ArcBorrow::from_raw`).
+pub struct ArcBorrow<'a, T: ?Sized> {
+    ptr: NonNull<ArcInner<T>>,
+    phantom: PhantomData<&'a T>,
+}
+
+unsafe impl<'a, T: ?Sized + Sync + Send> Send for ArcBorrow<'a, T> {}
+unsafe impl<'a, T: ?Sized + Sync + Send> Sync for ArcBorrow<'a, T> {}
+
+impl<'a, T: ?Sized> ArcBorrow<'a, T> {
+    pub(crate) fn from_inner(ptr: NonNull<ArcInner<T>>) -> Self {
+        ArcBorrow { ptr, phantom: PhantomData }
+    }
+
+    /// Creates an `ArcBorrow` from a pointer to the value of an `Arc`, without touching the
+    /// reference count.
+    ///
+    /// # Safety
+    ///
+    /// `ptr` must have been returned by `Arc::as_ptr` or `Arc::into_raw` on an `Arc<T>`, and some
+    /// `Arc` to the allocation must stay alive for `'a`.
+    pub unsafe fn from_raw(ptr: *const T) -> Self {
+        // As in `Arc::from_raw`, the pointer keeps the provenance of the whole allocation.
+        let offset = data_offset_val(unsafe { &*ptr });
+        let inner = unsafe { set_data_ptr(ptr as *mut T, (ptr as *mut u8).sub(offset)) };
+        let ptr = inner as *mut ArcInner<T>;
+        ArcBorrow { ptr: unsafe { NonNull::new_unchecked(ptr) }, phantom: PhantomData }
+    }
+
+    /// Creates an owned `Arc` pointing to the same allocation, increasing the reference count.
+    pub fn to_arc(this: Self) -> Arc<T> {
+        let arc = ManuallyDrop::new(Arc::from_inner(this.ptr));
+        Arc::clone(&arc)
+    }
+
+    /// Gets a reference to the value that lives for `'a`, rather than only as long as `this`.
+    pub fn get(this: Self) -> &'a T {
+        unsafe { &(*this.ptr.as_ptr()).data }
+    }
+}
+
+impl<'a, T: ?Sized> Clone for ArcBorrow<'a, T> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<'a, T: ?Sized> Copy for ArcBorrow<'a, T> {}
+
+impl<'a, T: ?Sized> Deref for ArcBorrow<'a, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        ArcBorrow::get(*self)
+    }
+}
+
+impl<'a, T: ?Sized> borrow::Borrow<T> for ArcBorrow<'a, T> {
+    fn borrow(&self) -> &T {
+        self
+    }
+}
+
+impl<'a, T: ?Sized> AsRef<T> for ArcBorrow<'a, T> {
+    fn as_ref(&self) -> &T {
+        self
+    }
+}
+
+impl<'a, T: ?Sized + fmt::Display> fmt::Display for ArcBorrow<'a, T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Display::fmt(&**self, f)
+    }
+}
+
+impl<'a, T: ?Sized + fmt::Debug> fmt::Debug for ArcBorrow<'a, T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Debug::fmt(&**self, f)
+    }
+}
+
+impl<'a, T: ?Sized> fmt::Pointer for ArcBorrow<'a, T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Pointer::fmt(&(&**self as *const T), f)
+    }
+}
+
+/// An `Arc` that is known to be the only pointer to its allocation, which allows mutating the
+/// value. Converting it into an `Arc` is free.
+///
+/// Unlike `Arc::new`, `UniqueArc::new` always allocates, even for zero-sized types (see
+/// `Arc`): a `UniqueArc` never points to a static allocation, so two of them never share an
+/// allocation, and the `WeakArc`s of `UniqueArc::downgrade` can not be upgraded early.
+pub struct UniqueArc<T: ?Sized>(Arc<T>);
+
+unsafe impl<T: ?Sized + Sync + Send> Send for UniqueArc<T> {}
+unsafe impl<T: ?Sized + Sync + Send> Sync for UniqueArc<T> {}
+
+impl<T: ?Sized> Deref for UniqueArc<T> {
+    type Target = T;
+
+    #[inline]
+    fn deref(&self) -> &T {
+        self.0.deref()
+    }
+}
+
+impl<T: ?Sized> borrow::Borrow<T> for UniqueArc<T> {
+    fn borrow(&self) -> &T {
+        self.0.borrow()
+    }
+}
+
+impl<T: ?Sized> AsRef<T> for UniqueArc<T> {
+    fn as_ref(&self) -> &T {
+        self.0.as_ref()
+    }
+}
+
+impl<T: ?Sized + fmt::Display> fmt::Display for UniqueArc<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+impl<T: ?Sized + fmt::Debug> fmt::Debug for UniqueArc<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+impl<T: ?Sized> fmt::Pointer for UniqueArc<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+impl<T: ?Sized> SmartPointer<T> for UniqueArc<T> {
+    fn new(data: T) -> Self where T: Sized {
+        UniqueArc(Arc::new_allocated(data))
+    }
+
+    fn try_unwrap(this: Self) -> Result<T, Self> where T: Sized {
+        let this = this.0;
+
+        acquire!(this.inner().strong);
+
+        unsafe {
+            let elem = ptr::read(&this.ptr.as_ref().data);
+            // The allocation is kept if there are `WeakArc`s, which can not be upgraded though,
+            // see `UniqueArc::downgrade`.
+            release_allocation(Arc::into_inner(this));
+            Ok(elem)
+        }
+    }
+}
+
+
+impl<T> UniqueArc<[MaybeUninit<T>]> {
+    /// Converts to `UniqueArc<[T]>`, reusing the allocation.
+    ///
+    /// # Safety
+    ///
+    /// As with `MaybeUninit::assume_init`, it is up to the caller to guarantee that all items
+    /// really are in an initialized state. Calling this when the content is not yet fully
+    /// initialized causes immediate undefined behavior.
+    pub unsafe fn assume_init(self) -> UniqueArc<[T]> {
+        let ptr = self.0.ptr() as *mut ArcInner<[T]>;
+        mem::forget(self);
+        unsafe { UniqueArc(Arc::from_inner(NonNull::new_unchecked(ptr))) }
+    }
+}
+
+impl<T> UniqueArc<T> {
+    /// Transforms the inner value by applying `f` to it.
+    ///
+    /// If `T` and `U` have the same layout, i.e., `Layout::new::<T>() == Layout::new::<U>()`,
+    /// the result is written back into the allocation of `self`. Otherwise, the old allocation
+    /// is freed and the result is moved into a new one.
+    ///
+    /// Should `f` panic, the allocation is freed without dropping its contents, which have been
+    /// moved into `f` already.
+    pub fn map<U, F: FnOnce(T) -> U>(self, f: F) -> UniqueArc<U> {
+        // Gives up the allocation without dropping the (moved-out) value.
+        struct Guard<T> {
+            ptr: NonNull<ArcInner<T>>,
+        }
+
+        impl<T> Drop for Guard<T> {
+            fn drop(&mut self) {
+                unsafe { release_allocation(self.ptr) };
+            }
+        }
+
+        let guard = Guard { ptr: Arc::into_inner(self.0) };
+        let value = unsafe { ptr::read(ptr::addr_of!((*guard.ptr.as_ptr()).data)) };
+        let mapped = f(value);
+
+        // An allocation that `WeakArc<T>`s point to can not be reused. Without `WeakArc`s, the
+        // weak count can not change concurrently. A `UniqueArc` never points to a static
+        // allocation, see `UniqueArc::new`.
+        #[cfg(feature = "weak")]
+        let reusable = unsafe { &*ptr::addr_of!((*guard.ptr.as_ptr()).weak) }.load(Relaxed) == 1;
+        #[cfg(not(feature = "weak"))]
+        let reusable = true;
+        if Layout::new::<T>() == Layout::new::<U>() && reusable {
+            // Both `ArcInner`s are `repr(C)`, so their layouts are the same as well. The
+            // counts carry over.
+            let ptr = guard.ptr.as_ptr() as *mut ArcInner<U>;
+            mem::forget(guard);
+            unsafe {
+                ptr::write(ptr::addr_of_mut!((*ptr).data), mapped);
+                UniqueArc(Arc::from_inner(NonNull::new_unchecked(ptr)))
+            }
+        } else {
+            drop(guard);
+            UniqueArc::new(mapped)
+        }
+    }
+}
+
+impl<T> UniqueArc<T> {
+    /// Reinterprets the pointer as pointing to a `U`, without touching the allocation.
+    ///
+    /// # Safety
+    ///
+    /// The requirements of `Arc::cast` apply.
+    pub unsafe fn cast<U>(this: Self) -> UniqueArc<U> {
+        UniqueArc(unsafe { Arc::cast(this.0) })
+    }
+}
+
+impl<T> UniqueArc<[T]> {
+    /// Reinterprets the pointer as pointing to a slice of `U`s of the same length, without
+    /// touching the allocation.
+    ///
+    /// # Safety
+    ///
+    /// The requirements of `Arc::cast` apply to the element types `T` and `U`.
+    pub unsafe fn cast_slice<U>(this: Self) -> UniqueArc<[U]> {
+        UniqueArc(unsafe { Arc::cast_slice(this.0) })
+    }
+}
+
+impl<T: ?Sized> DerefMut for UniqueArc<T> {
+    fn deref_mut(&mut self) -> &mut T {
+        // We know this to be uniquely owned
+        unsafe { &mut (*self.0.ptr()).data }
+    }
+}
+
+impl<T: ?Sized> BorrowMut<T> for UniqueArc<T> {
+    fn borrow_mut(&mut self) -> &mut T {
+        &mut **self
+    }
+}
+
+impl<T: ?Sized> AsMut<T> for UniqueArc<T> {
+    fn as_mut(&mut self) -> &mut T {
+        &mut **self
+    }
+}
+
+impl<T: ?Sized> SmartPointerMut<T> for UniqueArc<T> {}
+
+impl<T: ?Sized> Into<Arc<T>> for UniqueArc<T> {
+    fn into(self) -> Arc<T> {
+        let arc = self.0;
+        // Allow upgrading the `WeakArc`s created by `UniqueArc::downgrade`. Release synchronizes
+        // with the acquire in `WeakArc::upgrade`, so that the value is visible through the
+        // upgraded `Arc`s.
+        #[cfg(feature = "weak")]
+        if arc.inner().strong.load(Relaxed) == 0 {
+            arc.inner().strong.store(1, Release);
+        }
+        arc
+    }
+}
+
+impl<T: ?Sized> IntoMut<T> for Arc<T> {
+    type MutablePointer = UniqueArc<T>;
+
+    /// Returns `true` if this is the only `Arc` to its allocation and there are no `WeakArc`s,
+    /// which could otherwise be upgraded while the value is being mutated.
+    #[cfg(feature = "weak")]
+    fn can_make_mut(this: &Self) -> bool {
+        // Lock the weak count if there are no `WeakArc`s, so that none can be created from
+        // another `Arc` (see `Arc::downgrade`) while the strong count is checked. Acquire
+        // synchronizes with the release in the `Drop` of `WeakArc`.
+        if this.inner().weak.compare_exchange(1, usize::MAX, Acquire, Relaxed).is_err() {
+            return false;
+        }
+
+        // This needs to be `Acquire` to synchronize with the decrement of the strong count in
+        // `drop`, the only access that happens when any but the last reference is dropped.
+        let unique = this.inner().strong.load(Acquire) == 1;
+
+        // The release write synchronizes with the read in `downgrade`, so that the above read of
+        // the strong count happens before any new `WeakArc` is created.
+        this.inner().weak.store(1, Release);
+        unique
+    }
+
+    /// Returns `true` if this is the only `Arc` to its allocation.
+    #[cfg(not(feature = "weak"))]
+    fn can_make_mut(this: &Self) -> bool {
+        // This needs to be `Acquire` to synchronize with the decrement of the strong count in
+        // `drop`, the only access that happens when any but the last reference is dropped.
+        this.inner().strong.load(Acquire) == 1
+    }
+
+    unsafe fn into_mut_unchecked(this: Self) -> Self::MutablePointer {
+        UniqueArc(this)
+    }
+
+    /// Converts into a `UniqueArc` if `can_make_mut` returns `true`.
+    ///
+    /// `Arc`s of zero-sized types that share a static allocation (see `Arc`) are converted into a
+    /// `UniqueArc` to a new allocation instead, since their values have neither bytes nor drop
+    /// glue.
+    fn into_mut(this: Self) -> Result<UniqueArc<T>, Self> {
+        if this.is_shared_zst() {
+            // The value has no bytes to move. Upcasts keep the pointer metadata of unsized
+            // values, which `set_data_ptr` carries over.
+            let ptr = unsafe {
+                Self::allocate_for_layout(Layout::for_value(&*this), |mem| {
+                    set_data_ptr(this.ptr(), mem)
+                })
+            };
+            return Ok(UniqueArc(Arc::from_inner(unsafe { NonNull::new_unchecked(ptr) })));
+        }
+
+        if Self::can_make_mut(&this) {
+            Ok(UniqueArc(this))
+        } else {
+            Err(this)
+        }
+    }
+
+    /// Obtain a mutable reference to the wrapped value without performing runtime checks for
+    /// upholding any invariants.
+    ///
+    /// Safety: Calling this is safe if and only if `can_make_mut` returns true.
+    unsafe fn get_mut_unchecked(this: &Self) -> &mut T {
+        // We are careful to *not* create a reference covering the "count" fields, as
+        // this would alias with concurrent access to the reference counts (e.g. by `Weak`).
+        unsafe { &mut (*this.ptr.as_ptr()).data }
+    }
+}
+
+impl<T: ?Sized> ReferenceCounted<T> for Arc<T> {
+    fn reference_count(this: &Self) -> NonZeroUsize {
+        unsafe { NonZeroUsize::new_unchecked(this.inner().strong.load(SeqCst)) }
+    }
+}
+
+#[cfg(feature = "weak")]
+impl<T: ?Sized> WeakPointer<T> for WeakArc<T> {
+    type Strong = Arc<T>;
+
+    fn upgrade(&self) -> Option<Arc<T>> {
+        WeakArc::upgrade(self)
+    }
+}
+
+#[cfg(feature = "weak")]
+impl<T: ?Sized> Downgradable<T> for Arc<T> {
+    type Weak = WeakArc<T>;
+
+    fn downgrade(this: &Self) -> WeakArc<T> {
+        Arc::downgrade(this)
+    }
+}
+
+#[cfg(feature = "weak")]
+impl<T: ?Sized> WeaklyReferenceCounted<T> for Arc<T> {
+    fn weak_count(this: &Self) -> usize {
+        Arc::weak_count(this)
+    }
+}
+
+impl<T: Default> Default for Arc<T> {
+    /// Creates a new `Arc<T>`, with the `Default` value for `T`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use std::sync::Arc;
+    ///
+    /// let x: Arc<i32> = Default::default();
+    /// assert_eq!(*x, 0);
+    /// ```
+    fn default() -> Arc<T> {
+        Arc::new(Default::default())
+    }
+}
+
+impl<T: Default> Default for UniqueArc<T> {
+    /// Creates a new `UniqueArc<T>`, with the `Default` value for `T`.
+    fn default() -> UniqueArc<T> {
+        UniqueArc::new(Default::default())
+    }
+}
+
+impl<T: ?Sized + PartialEq<U>, U: ?Sized> PartialEq<Arc<U>> for Arc<T> {
+    /// Equality for two `Arc`s.
+    ///
+    /// Two `Arc`s are equal if their inner values are equal, even if they are
+    /// stored in different allocation. This implementation does not check for
+    /// pointer equality.
+    #[inline]
+    fn eq(&self, other: &Arc<U>) -> bool {
+        (**self).eq(&**other)
+    }
+
+    /// Inequality for two `Arc`s.
+    ///
+    /// Two `Arc`s are unequal if their inner values are unequal. This implementation does not
+    /// check for pointer equality.
+    #[inline]
+    fn ne(&self, other: &Arc<U>) -> bool {
+        (**self).ne(&**other)
+    }
+}
+
+impl<T: ?Sized + Eq> Eq for Arc<T> {}
+
+impl<T: ?Sized + PartialEq<U>, U: ?Sized> PartialEq<UniqueArc<U>> for UniqueArc<T> {
+    /// Equality for two `UniqueArc`s.
+    ///
+    /// Two `UniqueArc`s are equal if their inner values are equal, even if they are
+    /// stored in different allocation. This implementation does not check for
+    /// pointer equality.
+    #[inline]
+    fn eq(&self, other: &UniqueArc<U>) -> bool {
+        (**self).eq(&**other)
+    }
+
+    /// Inequality for two `Arc`s.
+    ///
+    /// Two `Arc`s are unequal if their inner values are unequal. This implementation does not
+    /// check for pointer equality.
+    #[inline]
+    fn ne(&self, other: &UniqueArc<U>) -> bool {
+        (**self).ne(&**other)
+    }
+}
+
+impl<T: ?Sized + Eq> Eq for UniqueArc<T> {}
+
+impl<T: ?Sized + PartialEq<U>, U: ?Sized> PartialEq<UniqueArc<U>> for Arc<T> {
+    /// Equality for an `Arc` and a `UniqueArc`.
+    ///
+    /// They are equal if their inner values are equal.
+    #[inline]
+    fn eq(&self, other: &UniqueArc<U>) -> bool {
+        (**self).eq(&**other)
+    }
+}
+
+impl<T: ?Sized + PartialEq<U>, U: ?Sized> PartialEq<Arc<U>> for UniqueArc<T> {
+    /// Equality for a `UniqueArc` and an `Arc`.
+    ///
+    /// They are equal if their inner values are equal.
+    #[inline]
+    fn eq(&self, other: &Arc<U>) -> bool {
+        (**self).eq(&**other)
+    }
+}
+
+// Comparisons of `Arc<str>` and `Arc<[T]>` with other string and slice types, in both
+// directions, so that e.g. `assert_eq!(value, "expected")` works.
+macro_rules! impl_slice_eq {
+    ([$($vars:tt)*] $lhs:ty, $rhs:ty) => {
+        impl<$($vars)*> PartialEq<$rhs> for $lhs {
+            #[inline]
+            fn eq(&self, other: &$rhs) -> bool {
+                self[..] == other[..]
+            }
+        }
+    };
+}
+
+impl_slice_eq! { [] Arc<str>, str }
+impl_slice_eq! { [] str, Arc<str> }
+impl_slice_eq! { ['a] Arc<str>, &'a str }
+impl_slice_eq! { ['a] &'a str, Arc<str> }
+impl_slice_eq! { [] Arc<str>, String }
+impl_slice_eq! { [] String, Arc<str> }
+impl_slice_eq! { [T: PartialEq<U>, U] Arc<[T]>, [U] }
+impl_slice_eq! { [T: PartialEq<U>, U] [T], Arc<[U]> }
+impl_slice_eq! { ['a, T: PartialEq<U>, U] Arc<[T]>, &'a [U] }
+impl_slice_eq! { ['a, T: PartialEq<U>, U] &'a [T], Arc<[U]> }
+impl_slice_eq! { [T: PartialEq<U>, U] Arc<[T]>, Vec<U> }
+impl_slice_eq! { [T: PartialEq<U>, U] Vec<T>, Arc<[U]> }
+
+impl<T: ?Sized + PartialOrd<U>, U: ?Sized> PartialOrd<Arc<U>> for Arc<T> {
+    /// Partial comparison for two `Arc`s.
+    ///
+    /// The two are compared by calling `partial_cmp()` on their inner values.
+    fn partial_cmp(&self, other: &Arc<U>) -> Option<Ordering> {
+        (**self).partial_cmp(&**other)
+    }
+
+    /// Less-than comparison for two `Arc`s.
+    ///
+    /// The two are compared by calling `<` on their inner values.
+    fn lt(&self, other: &Arc<U>) -> bool {
+        *(*self) < *(*other)
+    }
+
+    /// 'Less than or equal to' comparison for two `Arc`s.
+    ///
+    /// The two are compared by calling `<=` on their inner values.
+    fn le(&self, other: &Arc<U>) -> bool {
+        *(*self) <= *(*other)
+    }
+
+    /// Greater-than comparison for two `Arc`s.
+    ///
+    /// The two are compared by calling `>` on their inner values.
+    fn gt(&self, other: &Arc<U>) -> bool {
+        *(*self) > *(*other)
+    }
+
+    /// 'Greater than or equal to' comparison for two `Arc`s.
+    ///
+    /// The two are compared by calling `>=` on their inner values.
+    fn ge(&self, other: &Arc<U>) -> bool {
+        *(*self) >= *(*other)
+    }
+}
+
+impl<T: ?Sized + PartialOrd<U>, U: ?Sized> PartialOrd<UniqueArc<U>> for UniqueArc<T> {
+    /// Partial comparison for two `UniqueArc`s.
+    ///
+    /// The two are compared by calling `partial_cmp()` on their inner values.
+    fn partial_cmp(&self, other: &UniqueArc<U>) -> Option<Ordering> {
+        (**self).partial_cmp(&**other)
+    }
+
+    /// Less-than comparison for two `UniqueArc`s.
+    ///
+    /// The two are compared by calling `<` on their inner values.
+    fn lt(&self, other: &UniqueArc<U>) -> bool {
+        *(*self) < *(*other)
+    }
+
+    /// 'Less than or equal to' comparison for two `UniqueArc`s.
+    ///
+    /// The two are compared by calling `<=` on their inner values.
+    fn le(&self, other: &UniqueArc<U>) -> bool {
+        *(*self) <= *(*other)
+    }
+
+    /// Greater-than comparison for two `UniqueArc`s.
+    ///
+    /// The two are compared by calling `>` on their inner values.
+    fn gt(&self, other: &UniqueArc<U>) -> bool {
+        *(*self) > *(*other)
+    }
+
+    /// 'Greater than or equal to' comparison for two `UniqueArc`s.
+    ///
+    /// The two are compared by calling `>=` on their inner values.
+    fn ge(&self, other: &UniqueArc<U>) -> bool {
+        *(*self) >= *(*other)
+    }
+}
+
+impl<T: ?Sized + PartialOrd<U>, U: ?Sized> PartialOrd<UniqueArc<U>> for Arc<T> {
+    /// Partial comparison for an `Arc` and a `UniqueArc`.
+    ///
+    /// The two are compared by calling `partial_cmp()` on their inner values.
+    fn partial_cmp(&self, other: &UniqueArc<U>) -> Option<Ordering> {
+        (**self).partial_cmp(&**other)
+    }
+}
+
+impl<T: ?Sized + PartialOrd<U>, U: ?Sized> PartialOrd<Arc<U>> for UniqueArc<T> {
+    /// Partial comparison for a `UniqueArc` and an `Arc`.
+    ///
+    /// The two are compared by calling `partial_cmp()` on their inner values.
+    fn partial_cmp(&self, other: &Arc<U>) -> Option<Ordering> {
+        (**self).partial_cmp(&**other)
+    }
+}
+
+impl<T: ?Sized + Ord> Ord for Arc<T> {
+    /// Comparison for two `Arc`s.
+    ///
+    /// The two are compared by calling `cmp()` on their inner values.
+    fn cmp(&self, other: &Arc<T>) -> Ordering {
+        (**self).cmp(&**other)
+    }
+}
+
+impl<T: ?Sized + Ord> Ord for UniqueArc<T> {
+    /// Comparison for two `UniqueArc`s.
+    ///
+    /// The two are compared by calling `cmp()` on their inner values.
+    fn cmp(&self, other: &UniqueArc<T>) -> Ordering {
+        (**self).cmp(&**other)
+    }
+}
+
+impl<T> From<T> for Arc<T> {
+    fn from(t: T) -> Self {
+        Arc::new(t)
+    }
+}
+
+impl<T> From<T> for UniqueArc<T> {
+    fn from(t: T) -> Self {
+        UniqueArc::new(t)
+    }
+}
+
+impl<T: ?Sized> From<Box<T>> for Arc<T> {
+    /// Moves the value out of the box into a new reference-counted allocation.
+    fn from(v: Box<T>) -> Arc<T> {
+        unsafe {
+            let value_layout = Layout::for_value(&*v);
+            let box_ptr = Box::into_raw(v);
+
+            let ptr = Arc::allocate_for_layout(value_layout, |mem| {
+                set_data_ptr(box_ptr as *mut ArcInner<T>, mem)
+            });
+            ptr::copy_nonoverlapping(
+                box_ptr as *const u8,
+                ptr::addr_of_mut!((*ptr).data) as *mut u8,
+                value_layout.size(),
+            );
+
+            // Free the box allocation without dropping its contents.
+            drop(Box::from_raw(box_ptr as *mut ManuallyDrop<T>));
+
+            Self::from_inner(NonNull::new_unchecked(ptr))
+        }
+    }
+}
+
+impl<T: Clone> From<&[T]> for Arc<[T]> {
+    /// Allocates a reference-counted slice and fills it by cloning `v`'s items.
+    fn from(v: &[T]) -> Arc<[T]> {
+        Arc::from_iter_exact(v.iter().cloned(), v.len())
+    }
+}
+
+impl<T, const N: usize> From<[T; N]> for Arc<[T]> {
+    /// Allocates a reference-counted slice and moves the items of `v` into it.
+    fn from(v: [T; N]) -> Arc<[T]> {
+        let v = ManuallyDrop::new(v);
+        unsafe {
+            let ptr = Arc::allocate_for_slice(N);
+            ptr::copy_nonoverlapping(v.as_ptr(), ptr::addr_of_mut!((*ptr).data) as *mut T, N);
+            Self::from_inner(NonNull::new_unchecked(ptr))
+        }
+    }
+}
+
+impl<T> From<Vec<T>> for Arc<[T]> {
+    /// Allocates a reference-counted slice and moves `v`'s items into it.
+    fn from(mut v: Vec<T>) -> Arc<[T]> {
+        unsafe {
+            let len = v.len();
+            let ptr = Arc::allocate_for_slice(len);
+            ptr::copy_nonoverlapping(v.as_ptr(), ptr::addr_of_mut!((*ptr).data) as *mut T, len);
+
+            // Allow the vector to free its memory, but not destroy its contents.
+            v.set_len(0);
+
+            Self::from_inner(NonNull::new_unchecked(ptr))
+        }
+    }
+}
+
+impl From<&str> for Arc<str> {
+    /// Allocates a reference-counted string slice and copies `v` into it.
+    fn from(v: &str) -> Arc<str> {
+        let arc = Arc::<[u8]>::copy_from_slice(v.as_bytes());
+        // The bytes are valid utf-8, and `ArcInner<str>` has the same layout as
+        // `ArcInner<[u8]>`.
+        let ptr = arc.ptr() as *mut ArcInner<str>;
+        mem::forget(arc);
+        unsafe { Self::from_inner(NonNull::new_unchecked(ptr)) }
+    }
+}
+
+impl From<String> for Arc<str> {
+    /// Allocates a reference-counted string slice and moves the contents of `v` into it.
+    fn from(v: String) -> Arc<str> {
+        let arc = Arc::<[u8]>::from(v.into_bytes());
+        // The bytes are valid utf-8, and `ArcInner<str>` has the same layout as
+        // `ArcInner<[u8]>`.
+        let ptr = arc.ptr() as *mut ArcInner<str>;
+        mem::forget(arc);
+        unsafe { Self::from_inner(NonNull::new_unchecked(ptr)) }
+    }
+}
+
+impl<T: ?Sized> FromOwned<Box<T>> for Arc<T> {
+    fn from_owned(src: Box<T>) -> Self {
+        Arc::from(src)
+    }
+}
+
+impl<T> FromOwned<Vec<T>> for Arc<[T]> {
+    fn from_owned(src: Vec<T>) -> Self {
+        Arc::from(src)
+    }
+}
+
+impl FromOwned<String> for Arc<str> {
+    fn from_owned(src: String) -> Self {
+        Arc::from(src)
+    }
+}
+
+#[cfg(feature = "std")]
+impl<T> Arc<T> {
+    /// Converts a `std::sync::Arc` into an `Arc` without cloning the value, succeeding only if it
+    /// is the only reference to its allocation. Returns the `std::sync::Arc` unchanged otherwise.
+    ///
+    /// The value is moved into a new allocation, since the two pointer types store their
+    /// reference counts differently.
+    pub fn from_std_unique(std: StdArc<T>) -> Result<Arc<T>, StdArc<T>> {
+        StdArc::try_unwrap(std).map(Arc::new)
+    }
+}
+
+#[cfg(feature = "std")]
+impl<T: Clone> From<StdArc<T>> for Arc<T> {
+    /// Converts a `std::sync::Arc` into an `Arc` with an equal value.
+    ///
+    /// The allocation can never be reused, since the two pointer types store their reference
+    /// counts differently. The value is moved into a new allocation if the `std::sync::Arc` was the
+    /// only reference to its allocation, or cloned otherwise.
+    fn from(std: StdArc<T>) -> Arc<T> {
+        StdArc::try_unwrap(std).map_or_else(|std| Arc::new((*std).clone()), Arc::new)
+    }
+}
+
+#[cfg(feature = "std")]
+impl<T: Clone> From<Arc<T>> for StdArc<T> {
+    /// Converts an `Arc` into a `std::sync::Arc` with an equal value.
+    ///
+    /// The allocation can never be reused, since the two pointer types store their reference
+    /// counts differently. The value is moved into a new allocation if the `Arc` was the only
+    /// reference to its allocation, or cloned otherwise.
+    fn from(this: Arc<T>) -> StdArc<T> {
+        Arc::try_unwrap(this).map_or_else(|this| StdArc::new((*this).clone()), StdArc::new)
+    }
+}
+
+impl<T: ?Sized + Hash> Hash for Arc<T> {
+    /// Hashes the inner value, so an `Arc` and a `UniqueArc` with equal inner values have equal
+    /// hashes, consistent with the equality between the two types.
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        (**self).hash(state)
+    }
+}
+
+impl<T: ?Sized + Hash> Hash for UniqueArc<T> {
+    /// Hashes the inner value, so an `Arc` and a `UniqueArc` with equal inner values have equal
+    /// hashes, consistent with the equality between the two types.
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        (**self).hash(state)
+    }
+}
+
+impl<T: ?Sized> Unpin for Arc<T> {}
+
+impl<T: ?Sized> Unpin for UniqueArc<T> {}
+
+#[cfg(all(feature = "async", not(feature = "panic-free")))]
+impl<T> Arc<T> {
+    /// Returns a future that resolves to the inner value once `this` is the only reference to
+    /// its allocation.
+    ///
+    /// The future is woken by the drop of the second-to-last reference. It never resolves if
+    /// some other reference to the same allocation is never dropped, for example because it is
+    /// awaiting uniqueness as well, or if the `Arc` points to static memory (see
+    /// `Arc::from_static`). `Arc`s of zero-sized types that share a static allocation (see `Arc`)
+    /// resolve right away.
+    pub fn into_inner_async(this: Self) -> IntoInnerAsync<T> {
+        IntoInnerAsync { arc: Some(this), registered: false }
+    }
+}
+
+/// The future returned by `Arc::into_inner_async`.
+#[cfg(all(feature = "async", not(feature = "panic-free")))]
+#[must_use = "futures do nothing unless you `.await` or poll them"]
+pub struct IntoInnerAsync<T> {
+    arc: Option<Arc<T>>,
+    // Whether a waker for this allocation is stored in the `unique_waiters` table.
+    registered: bool,
+}
+
+#[cfg(all(feature = "async", not(feature = "panic-free")))]
+impl<T> IntoInnerAsync<T> {
+    fn addr(arc: &Arc<T>) -> usize {
+        arc.ptr() as *const u8 as usize
+    }
+}
+
+#[cfg(all(feature = "async", not(feature = "panic-free")))]
+impl<T> Future for IntoInnerAsync<T> {
+    type Output = T;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<T> {
+        let this = &mut *self;
+        let arc = this.arc.as_ref().expect("`IntoInnerAsync` polled after completion");
+
+        // `Arc::try_unwrap` returns the value of an `Arc` to `ZST_INNER` right away.
+        if !arc.is_shared_zst() && arc.inner().strong.load(Acquire) != 1 {
+            unique_waiters::register(IntoInnerAsync::addr(arc), cx.waker());
+            this.registered = true;
+
+            // Check again, the last other reference might have been dropped before the waker
+            // was registered.
+            if arc.inner().strong.load(SeqCst) != 1 {
+                return Poll::Pending;
+            }
+        }
+
+        let arc = this.arc.take().unwrap();
+        if this.registered {
+            unique_waiters::unregister(IntoInnerAsync::addr(&arc));
+            this.registered = false;
+        }
+
+        match Arc::try_unwrap(arc) {
+            Ok(value) => Poll::Ready(value),
+            // A `WeakArc` was upgraded since the check, wait for that reference to go away too.
+            Err(arc) => {
+                this.arc = Some(arc);
+                Pin::new(this).poll(cx)
+            }
+        }
+    }
+}
+
+#[cfg(all(feature = "async", not(feature = "panic-free")))]
+impl<T> Drop for IntoInnerAsync<T> {
+    fn drop(&mut self) {
+        if self.registered {
+            if let Some(arc) = &self.arc {
+                unique_waiters::unregister(IntoInnerAsync::addr(arc));
+            }
+        }
+    }
+}
+
+/// The wakers of all pending `IntoInnerAsync` futures, keyed by the address of their
+/// allocation.
+///
+/// Keeping them in a global table rather than in the allocations means that `Arc`s do not pay
+/// for this in memory. Dropping an `Arc` only checks the table if the reference count drops to
+/// one while any future is waiting.
+#[cfg(all(feature = "async", not(feature = "panic-free")))]
+mod unique_waiters {
+    use base::mem;
+    use base::sync::atomic::{self, AtomicUsize, Ordering::SeqCst};
+    use base::sync::{Mutex, MutexGuard};
+    use base::task::Waker;
+
+    use base::prelude::v1::*;
+
+    static WAITERS: AtomicUsize = AtomicUsize::new(0);
+    static TABLE: Mutex<Vec<(usize, Waker)>> = Mutex::new(Vec::new());
+
+    fn table() -> MutexGuard<'static, Vec<(usize, Waker)>> {
+        // The table is consistent even if a waker panicked while it was locked.
+        TABLE.lock().unwrap_or_else(|err| err.into_inner())
+    }
+
+    // Replaced and removed wakers are only dropped after the table has been unlocked, since
+    // dropping a waker may drop an `Arc`, which locks the table in `notify`.
+
+    pub(super) fn register(addr: usize, waker: &Waker) {
+        let mut table = table();
+        let _old = match table.iter_mut().find(|(a, _)| *a == addr) {
+            Some((_, w)) if !w.will_wake(waker) => Some(mem::replace(w, waker.clone())),
+            Some(_) => None,
+            None => {
+                table.push((addr, waker.clone()));
+                WAITERS.fetch_add(1, SeqCst);
+                None
+            }
+        };
+        drop(table);
+    }
+
+    pub(super) fn unregister(addr: usize) {
+        let mut table = table();
+        let _removed = table.iter().position(|(a, _)| *a == addr).map(|i| {
+            WAITERS.fetch_sub(1, SeqCst);
+            table.swap_remove(i)
+        });
+        drop(table);
+    }
+
+    /// Called after the reference count of the allocation at `addr` has dropped to one.
+    pub(super) fn notify(addr: usize) {
+        // Pairs with the registration and subsequent load of the count in `poll`, so that
+        // either the future sees the decremented count, or this sees the registration.
+        atomic::fence(SeqCst);
+        if WAITERS.load(SeqCst) == 0 {
+            return;
+        }
+
+        let waker = table().iter().find(|(a, _)| *a == addr).map(|(_, w)| w.clone());
+        if let Some(waker) = waker {
+            waker.wake();
+        }
+    }
+}
+
+// Shared pointers can only hand out shared references, so they forward `Fn` only.
+
+#[cfg(feature = "unstable")]
+impl<Args: Tuple, F: ?Sized + Fn<Args>> FnOnce<Args> for Arc<F> {
+    type Output = F::Output;
+
+    extern "rust-call" fn call_once(self, args: Args) -> F::Output {
+        (*self).call(args)
+    }
+}
+
+#[cfg(feature = "unstable")]
+impl<Args: Tuple, F: ?Sized + Fn<Args>> FnMut<Args> for Arc<F> {
+    extern "rust-call" fn call_mut(&mut self, args: Args) -> F::Output {
+        (**self).call(args)
+    }
+}
+
+#[cfg(feature = "unstable")]
+impl<Args: Tuple, F: ?Sized + Fn<Args>> Fn<Args> for Arc<F> {
+    extern "rust-call" fn call(&self, args: Args) -> F::Output {
+        (**self).call(args)
+    }
+}
+
+#[cfg(feature = "unstable")]
+impl<Args: Tuple, F: ?Sized + FnMut<Args>> FnOnce<Args> for UniqueArc<F> {
+    type Output = F::Output;
+
+    extern "rust-call" fn call_once(mut self, args: Args) -> F::Output {
+        (*self).call_mut(args)
+    }
+}
+
+#[cfg(feature = "unstable")]
+impl<Args: Tuple, F: ?Sized + FnMut<Args>> FnMut<Args> for UniqueArc<F> {
+    extern "rust-call" fn call_mut(&mut self, args: Args) -> F::Output {
+        (**self).call_mut(args)
+    }
+}
+
+// `Pin<UniqueArc<_>>` is covered by the blanket impls for pinned pointers of the futures and tokio
+// crates.
+
+#[cfg(feature = "futures")]
+impl<S: ?Sized + Stream + Unpin> Stream for UniqueArc<S> {
+    type Item = S::Item;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        Pin::new(&mut **self).poll_next(cx)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (**self).size_hint()
+    }
+}
+
+#[cfg(feature = "futures")]
+impl<S: ?Sized + FusedStream + Unpin> FusedStream for UniqueArc<S> {
+    fn is_terminated(&self) -> bool {
+        (**self).is_terminated()
+    }
+}
+
+#[cfg(feature = "futures")]
+impl<S: ?Sized + Sink<Item> + Unpin, Item> Sink<Item> for UniqueArc<S> {
+    type Error = S::Error;
+
+    fn poll_ready(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        Pin::new(&mut **self).poll_ready(cx)
+    }
+
+    fn start_send(mut self: Pin<&mut Self>, item: Item) -> Result<(), Self::Error> {
+        Pin::new(&mut **self).start_send(item)
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        Pin::new(&mut **self).poll_flush(cx)
+    }
+
+    fn poll_close(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        Pin::new(&mut **self).poll_close(cx)
+    }
+}
+
+#[cfg(feature = "tokio-io")]
+impl<T: ?Sized + tokio::io::AsyncRead + Unpin> tokio::io::AsyncRead for UniqueArc<T> {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut tokio::io::ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        Pin::new(&mut **self).poll_read(cx, buf)
+    }
+}
+
+#[cfg(feature = "tokio-io")]
+impl<T: ?Sized + tokio::io::AsyncWrite + Unpin> tokio::io::AsyncWrite for UniqueArc<T> {
+    fn poll_write(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        Pin::new(&mut **self).poll_write(cx, buf)
+    }
+
+    fn poll_write_vectored(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        bufs: &[io::IoSlice<'_>],
+    ) -> Poll<io::Result<usize>> {
+        Pin::new(&mut **self).poll_write_vectored(cx, bufs)
+    }
+
+    fn is_write_vectored(&self) -> bool {
+        (**self).is_write_vectored()
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut **self).poll_flush(cx)
+    }
+
+    fn poll_shutdown(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut **self).poll_shutdown(cx)
+    }
+}
+
+#[cfg(feature = "futures-io")]
+impl<T: ?Sized + futures_io::AsyncRead + Unpin> futures_io::AsyncRead for UniqueArc<T> {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut [u8],
+    ) -> Poll<io::Result<usize>> {
+        Pin::new(&mut **self).poll_read(cx, buf)
+    }
+
+    fn poll_read_vectored(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        bufs: &mut [io::IoSliceMut<'_>],
+    ) -> Poll<io::Result<usize>> {
+        Pin::new(&mut **self).poll_read_vectored(cx, bufs)
+    }
+}
+
+#[cfg(feature = "futures-io")]
+impl<T: ?Sized + futures_io::AsyncWrite + Unpin> futures_io::AsyncWrite for UniqueArc<T> {
+    fn poll_write(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        Pin::new(&mut **self).poll_write(cx, buf)
+    }
+
+    fn poll_write_vectored(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        bufs: &[io::IoSlice<'_>],
+    ) -> Poll<io::Result<usize>> {
+        Pin::new(&mut **self).poll_write_vectored(cx, bufs)
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut **self).poll_flush(cx)
+    }
+
+    fn poll_close(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut **self).poll_close(cx)
+    }
+}
+
+#[cfg(feature = "rayon")]
+impl<T: Send> FromParallelIterator<T> for Arc<[T]> {
+    /// Collects the items into a new reference-counted slice.
+    ///
+    /// If the number of items is known up front, as for all `IndexedParallelIterator`s, the
+    /// items are written into the new allocation in place. Otherwise, they are collected into a
+    /// vector first, and then moved into a new allocation.
+    ///
+    /// Panics if the iterator yields fewer items than it announced, and ignores any items past
+    /// those.
+    fn from_par_iter<I: IntoParallelIterator<Item = T>>(par_iter: I) -> Self {
+        let par_iter = par_iter.into_par_iter();
+        let len = match par_iter.opt_len() {
+            Some(len) => len,
+            None => return Arc::from(Vec::from_par_iter(par_iter)),
+        };
+
+        let mut uninit = Arc::new_uninit_slice(len);
+        let guard = par_iter.drive_unindexed(SliceConsumer { slots: &mut uninit });
+        if guard.len != len {
+            iterator_too_short();
+        }
+        mem::forget(guard);
+        unsafe { uninit.assume_init() }.into()
+    }
+}
+
+#[cfg(feature = "rayon")]
+impl<T: Send> Arc<[T]> {
+    /// Constructs a reference-counted slice of the given length, whose items are computed by
+    /// calling `f` with their index in parallel on the rayon thread pool.
+    ///
+    /// The items are written into the new allocation in place. If `f` panics, all items that
+    /// have been computed are dropped, the allocation is freed, and the panic is propagated.
+    pub fn from_par_fn<F: Fn(usize) -> T + Sync>(len: usize, f: F) -> Arc<[T]> {
+        let mut uninit = Arc::new_uninit_slice(len);
+        let min_len = (len / (rayon::current_num_threads() * 4)).max(1);
+        mem::forget(par_fill(&mut uninit, 0, min_len, &f));
+        unsafe { uninit.assume_init() }.into()
+    }
+}
+
+/// An initialized range of items that is dropped with the guard unless the guard is forgotten.
+#[cfg(feature = "rayon")]
+struct InitGuard<T> {
+    start: *mut T,
+    len: usize,
+}
+
+#[cfg(feature = "rayon")]
+unsafe impl<T: Send> Send for InitGuard<T> {}
+
+#[cfg(feature = "rayon")]
+impl<T> InitGuard<T> {
+    /// Merges the guard with the guard of the range that follows it. Should the ranges not be
+    /// adjacent, because this one is shorter than its part of the slice, the items of `right`
+    /// are dropped instead.
+    fn merge(self, right: InitGuard<T>) -> InitGuard<T> {
+        if unsafe { self.start.add(self.len) } != right.start {
+            return self;
+        }
+        let merged = InitGuard { start: self.start, len: self.len + right.len };
+        mem::forget(self);
+        mem::forget(right);
+        merged
+    }
+}
+
+#[cfg(feature = "rayon")]
+impl<T> Drop for InitGuard<T> {
+    fn drop(&mut self) {
+        unsafe { ptr::drop_in_place(ptr::slice_from_raw_parts_mut(self.start, self.len)) };
+    }
+}
+
+/// A rayon consumer that writes the items into consecutive slots, for the indexed iterators
+/// whose items are split at known positions.
+#[cfg(feature = "rayon")]
+struct SliceConsumer<'a, T> {
+    slots: &'a mut [MaybeUninit<T>],
+}
+
+#[cfg(feature = "rayon")]
+struct SliceFolder<'a, T> {
+    slots: slice::IterMut<'a, MaybeUninit<T>>,
+    guard: InitGuard<T>,
+}
+
+#[cfg(feature = "rayon")]
+struct MergeGuards;
+
+#[cfg(feature = "rayon")]
+impl<'a, T: Send> Consumer<T> for SliceConsumer<'a, T> {
+    type Folder = SliceFolder<'a, T>;
+    type Reducer = MergeGuards;
+    type Result = InitGuard<T>;
+
+    fn split_at(self, index: usize) -> (Self, Self, MergeGuards) {
+        let (left, right) = self.slots.split_at_mut(index);
+        (SliceConsumer { slots: left }, SliceConsumer { slots: right }, MergeGuards)
+    }
+
+    fn into_folder(self) -> SliceFolder<'a, T> {
+        let guard = InitGuard { start: self.slots.as_mut_ptr() as *mut T, len: 0 };
+        SliceFolder { slots: self.slots.iter_mut(), guard }
+    }
+
+    fn full(&self) -> bool {
+        false
+    }
+}
+
+#[cfg(feature = "rayon")]
+impl<T: Send> UnindexedConsumer<T> for SliceConsumer<'_, T> {
+    // Iterators that announce their length split the consumer at known positions instead.
+    fn split_off_left(&self) -> Self {
+        unreachable!("SliceConsumer is only driven by iterators of known length")
+    }
+
+    fn to_reducer(&self) -> MergeGuards {
+        MergeGuards
+    }
+}
+
+#[cfg(feature = "rayon")]
+impl<T> Folder<T> for SliceFolder<'_, T> {
+    type Result = InitGuard<T>;
+
+    fn consume(mut self, item: T) -> Self {
+        if let Some(slot) = self.slots.next() {
+            slot.write(item);
+            self.guard.len += 1;
+        }
+        self
+    }
+
+    fn complete(self) -> InitGuard<T> {
+        self.guard
+    }
+
+    fn full(&self) -> bool {
+        self.slots.len() == 0
+    }
+}
+
+#[cfg(feature = "rayon")]
+impl<T> Reducer<InitGuard<T>> for MergeGuards {
+    fn reduce(self, left: InitGuard<T>, right: InitGuard<T>) -> InitGuard<T> {
+        left.merge(right)
+    }
+}
+
+/// Initialize `slice[i]` to `f(offset + i)`, recursively splitting the work into rayon tasks
+/// until they have at most `min_len` items.
+///
+/// Should `f` panic, the guards of all completed tasks are dropped while unwinding out of
+/// `rayon::join`, which drops exactly those items that have been initialized.
+#[cfg(feature = "rayon")]
+fn par_fill<T: Send, F: Fn(usize) -> T + Sync>(
+    slice: &mut [MaybeUninit<T>],
+    offset: usize,
+    min_len: usize,
+    f: &F,
+) -> InitGuard<T> {
+    if slice.len() <= min_len {
+        let mut guard = InitGuard { start: slice.as_mut_ptr() as *mut T, len: 0 };
+        for (i, slot) in slice.iter_mut().enumerate() {
+            unsafe { ptr::write(slot.as_mut_ptr(), f(offset + i)) };
+            guard.len += 1;
+        }
+        guard
+    } else {
+        let mid = slice.len() / 2;
+        let (left, right) = slice.split_at_mut(mid);
+        let (left, right) = rayon::join(
+            || par_fill(left, offset, min_len, f),
+            || par_fill(right, offset + mid, min_len, f),
+        );
+        left.merge(right)
+    }
+}
+
+#[cfg(feature = "bincode")]
+impl<T: ?Sized + Encode> Encode for Arc<T> {
+    fn encode<E: Encoder>(&self, encoder: &mut E) -> Result<(), EncodeError> {
+        T::encode(self, encoder)
+    }
+}
+
+#[cfg(feature = "bincode")]
+impl<T: ?Sized + Encode> Encode for UniqueArc<T> {
+    fn encode<E: Encoder>(&self, encoder: &mut E) -> Result<(), EncodeError> {
+        T::encode(self, encoder)
+    }
+}
+
+#[cfg(feature = "bincode")]
+impl<C, T: Decode<C>> Decode<C> for Arc<T> {
+    fn decode<D: Decoder<Context = C>>(decoder: &mut D) -> Result<Self, DecodeError> {
+        Ok(Arc::new(T::decode(decoder)?))
+    }
+}
+
+#[cfg(feature = "bincode")]
+impl<C, T: Decode<C>> Decode<C> for UniqueArc<T> {
+    fn decode<D: Decoder<Context = C>>(decoder: &mut D) -> Result<Self, DecodeError> {
+        Ok(UniqueArc::new(T::decode(decoder)?))
+    }
+}
+
+#[cfg(feature = "bincode")]
+impl<'de, C, T: BorrowDecode<'de, C>> BorrowDecode<'de, C> for Arc<T> {
+    fn borrow_decode<D: BorrowDecoder<'de, Context = C>>(
+        decoder: &mut D,
+    ) -> Result<Self, DecodeError> {
+        Ok(Arc::new(T::borrow_decode(decoder)?))
+    }
+}
+
+#[cfg(feature = "bincode")]
+impl<'de, C, T: BorrowDecode<'de, C>> BorrowDecode<'de, C> for UniqueArc<T> {
+    fn borrow_decode<D: BorrowDecoder<'de, Context = C>>(
+        decoder: &mut D,
+    ) -> Result<Self, DecodeError> {
+        Ok(UniqueArc::new(T::borrow_decode(decoder)?))
+    }
+}
+
+#[cfg(feature = "bincode")]
+impl<C> Decode<C> for Arc<[u8]> {
+    /// Decodes the bytes directly into a reference-counted slice of the right size, rather than
+    /// into a `Vec<u8>` that is then copied.
+    fn decode<D: Decoder<Context = C>>(decoder: &mut D) -> Result<Self, DecodeError> {
+        let len = u64::decode(decoder)?;
+        let len = usize::try_from(len).map_err(|_| DecodeError::OutsideUsizeRange(len))?;
+        decoder.claim_container_read::<u8>(len)?;
+
+        let mut uninit = Arc::new_uninit_slice(len);
+        // `Reader` implementations may inspect the buffer, so it has to be initialized.
+        let mut buf = unsafe {
+            ptr::write_bytes(uninit.as_mut_ptr(), 0, len);
+            uninit.assume_init()
+        };
+
+        decoder.reader().read(&mut buf)?;
+        Ok(buf.into())
+    }
+}
+
+#[cfg(feature = "bincode")]
+impl<'de, C> BorrowDecode<'de, C> for Arc<[u8]> {
+    fn borrow_decode<D: BorrowDecoder<'de, Context = C>>(
+        decoder: &mut D,
+    ) -> Result<Self, DecodeError> {
+        Decode::decode(decoder)
+    }
+}
+
+#[cfg(feature = "bincode")]
+impl<C> Decode<C> for Arc<str> {
+    /// Decodes the string directly into a reference-counted string slice of the right size,
+    /// rather than into a `String` that is then copied.
+    fn decode<D: Decoder<Context = C>>(decoder: &mut D) -> Result<Self, DecodeError> {
+        let bytes = Arc::<[u8]>::decode(decoder)?;
+        if let Err(inner) = str::from_utf8(&bytes) {
+            return Err(DecodeError::Utf8 { inner });
+        }
+
+        // The bytes are valid utf-8, and `ArcInner<str>` has the same layout as
+        // `ArcInner<[u8]>`.
+        let ptr = Arc::into_inner(bytes).as_ptr() as *mut ArcInner<str>;
+        Ok(Arc::from_inner(unsafe { NonNull::new_unchecked(ptr) }))
+    }
+}
+
+#[cfg(feature = "bincode")]
+impl<'de, C> BorrowDecode<'de, C> for Arc<str> {
+    fn borrow_decode<D: BorrowDecoder<'de, Context = C>>(
+        decoder: &mut D,
+    ) -> Result<Self, DecodeError> {
+        Decode::decode(decoder)
+    }
+}
+
+#[cfg(feature = "schemars")]
+impl<T: ?Sized + JsonSchema> JsonSchema for Arc<T> {
+    fn inline_schema() -> bool {
+        T::inline_schema()
+    }
+
+    fn schema_name() -> Cow<'static, str> {
+        T::schema_name()
+    }
+
+    fn schema_id() -> Cow<'static, str> {
+        T::schema_id()
+    }
+
+    fn json_schema(generator: &mut SchemaGenerator) -> Schema {
+        T::json_schema(generator)
+    }
+}
+
+#[cfg(feature = "schemars")]
+impl<T: ?Sized + JsonSchema> JsonSchema for UniqueArc<T> {
+    fn inline_schema() -> bool {
+        T::inline_schema()
+    }
+
+    fn schema_name() -> Cow<'static, str> {
+        T::schema_name()
+    }
+
+    fn schema_id() -> Cow<'static, str> {
+        T::schema_id()
+    }
+
+    fn json_schema(generator: &mut SchemaGenerator) -> Schema {
+        T::json_schema(generator)
+    }
+}
+
+#[cfg(feature = "borsh")]
+impl<T: ?Sized + BorshSerialize> BorshSerialize for Arc<T> {
+    fn serialize<W: borsh_io::Write>(&self, writer: &mut W) -> borsh_io::Result<()> {
+        (**self).serialize(writer)
+    }
+}
+
+#[cfg(feature = "borsh")]
+impl<T: ?Sized + BorshSerialize> BorshSerialize for UniqueArc<T> {
+    fn serialize<W: borsh_io::Write>(&self, writer: &mut W) -> borsh_io::Result<()> {
+        (**self).serialize(writer)
+    }
+}
+
+#[cfg(feature = "borsh")]
+impl<T: BorshDeserialize> BorshDeserialize for Arc<T> {
+    fn deserialize_reader<R: borsh_io::Read>(reader: &mut R) -> borsh_io::Result<Self> {
+        Ok(Arc::new(T::deserialize_reader(reader)?))
+    }
+}
+
+#[cfg(feature = "borsh")]
+impl<T: BorshDeserialize> BorshDeserialize for UniqueArc<T> {
+    fn deserialize_reader<R: borsh_io::Read>(reader: &mut R) -> borsh_io::Result<Self> {
+        Ok(UniqueArc::new(T::deserialize_reader(reader)?))
+    }
+}
+
+/// The maximal number of bytes that are allocated for a borsh-encoded slice before its items
+/// have actually been read, so that a bogus length can not trigger a huge allocation.
+#[cfg(feature = "borsh")]
+const BORSH_MAX_PREALLOCATION: usize = 4096;
+
+#[cfg(feature = "borsh")]
+impl<T> Arc<[T]> {
+    /// Constructs a slice of `len` items produced by `f`, stopping at the first error.
+    fn try_from_fn<E>(len: usize, mut f: impl FnMut() -> Result<T, E>) -> Result<Arc<[T]>, E> {
+        // Drops the items that have been initialized already, should `f` fail or panic.
+        struct Guard<'a, T> {
+            slice: &'a mut [MaybeUninit<T>],
+            len: usize,
+        }
+
+        impl<'a, T> Drop for Guard<'a, T> {
+            fn drop(&mut self) {
+                unsafe {
+                    ptr::drop_in_place(ptr::slice_from_raw_parts_mut(
+                        self.slice.as_mut_ptr() as *mut T,
+                        self.len,
+                    ));
+                }
+            }
+        }
+
+        let mut uninit = Arc::new_uninit_slice(len);
+        let mut guard = Guard { slice: &mut uninit, len: 0 };
+        while guard.len < len {
+            let item = f()?;
+            guard.slice[guard.len] = MaybeUninit::new(item);
+            guard.len += 1;
+        }
+        mem::forget(guard);
+
+        Ok(unsafe { uninit.assume_init() }.into())
+    }
+}
+
+#[cfg(feature = "borsh")]
+impl<T: BorshDeserialize> BorshDeserialize for Arc<[T]> {
+    /// Deserializes the items directly into a reference-counted slice of the right size, unless
+    /// the claimed length would take more than 4096 bytes. Larger slices are read into a
+    /// vector that only grows as items actually arrive, and then moved into the slice.
+    fn deserialize_reader<R: borsh_io::Read>(reader: &mut R) -> borsh_io::Result<Self> {
+        let len = u32::deserialize_reader(reader)?;
+        let len = usize::try_from(len).map_err(|_| {
+            borsh_io::Error::new(borsh_io::ErrorKind::InvalidData, "length overflows usize")
+        })?;
+
+        if len == 0 {
+            return Ok(Arc::from(Vec::new()));
+        }
+        if mem::size_of::<T>() == 0 {
+            // Same as borsh, since otherwise a short input could claim a huge length.
+            return Err(borsh_io::Error::new(
+                borsh_io::ErrorKind::InvalidData,
+                "collections of zero-sized types are not allowed",
+            ));
+        }
+
+        if len <= BORSH_MAX_PREALLOCATION / mem::size_of::<T>() {
+            Arc::try_from_fn(len, || T::deserialize_reader(reader))
+        } else {
+            let mut items = Vec::with_capacity(BORSH_MAX_PREALLOCATION / mem::size_of::<T>());
+            for _ in 0..len {
+                items.push(T::deserialize_reader(reader)?);
+            }
+            Ok(Arc::from(items))
+        }
+    }
+}
+
+#[cfg(feature = "borsh")]
+impl BorshDeserialize for Arc<str> {
+    /// Deserializes the string like an `Arc<[u8]>`, then checks that it is valid utf-8.
+    fn deserialize_reader<R: borsh_io::Read>(reader: &mut R) -> borsh_io::Result<Self> {
+        let bytes = Arc::<[u8]>::deserialize_reader(reader)?;
+        if str::from_utf8(&bytes).is_err() {
+            return Err(borsh_io::Error::new(borsh_io::ErrorKind::InvalidData, "invalid utf-8"));
+        }
+
+        // The bytes are valid utf-8, and `ArcInner<str>` has the same layout as
+        // `ArcInner<[u8]>`.
+        let ptr = Arc::into_inner(bytes).as_ptr() as *mut ArcInner<str>;
+        Ok(Arc::from_inner(unsafe { NonNull::new_unchecked(ptr) }))
+    }
+}
+
+/// Deserializes a new value for `this`, reusing its allocation if possible.
+///
+/// If `this` is unique, the value is deserialized in place via `Deserialize::deserialize_in_place`,
+/// which can reuse the memory the old value owns (e.g. the buffers of nested vectors). Otherwise,
+/// a fresh `Arc` replaces `this`, and all other pointers keep seeing the old value.
+#[cfg(feature = "serde")]
+pub fn arc_deserialize_in_place<'de, T, D>(
+    this: &mut Arc<T>,
+    deserializer: D,
+) -> Result<(), D::Error>
+where
+    T: Deserialize<'de>,
+    D: Deserializer<'de>,
+{
+    if Arc::can_make_mut(this) {
+        T::deserialize_in_place(deserializer, unsafe { Arc::get_mut_unchecked(this) })
+    } else {
+        *this = Arc::new(T::deserialize(deserializer)?);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+
+    use super::*;
+    use crate::test_util::allocations;
+    use base::marker::PhantomData;
+
+    #[cfg(not(feature = "weak"))]
+    #[test]
+    fn zero_sized_arcs_do_not_allocate() {
+        let before = allocations();
+        let a = Arc::new(());
+        let b = a.clone();
+        let c: Arc<PhantomData<String>> = Arc::new(PhantomData);
+        drop(a);
+        assert_eq!(allocations(), before);
+
+        assert_eq!(Arc::reference_count(&b).get(), usize::MAX);
+        assert!(!Arc::can_make_mut(&b));
+        assert_eq!(Arc::try_unwrap(b.clone()), Ok(()));
+        assert_eq!(Arc::try_unwrap(b), Ok(()));
+        assert_eq!(allocations(), before);
+
+        // The unique pointer gets an allocation of its own.
+        let mut c = Arc::into_mut(c).unwrap();
+        assert_eq!(allocations(), before + 1);
+        let _: &mut PhantomData<String> = &mut c;
+        let c: Arc<PhantomData<String>> = c.into();
+        assert_eq!(Arc::reference_count(&c).get(), 1);
+    }
+
+    #[cfg(all(not(feature = "weak"), not(feature = "panic-free")))]
+    #[test]
+    fn upcast_zero_sized_arcs_convert_into_unique_ones() {
+        let any: Arc<dyn Any> = Arc::upcast(Arc::new(()), |unit| unit as &dyn Any);
+        let unique = Arc::into_mut(any).unwrap();
+        assert_eq!(mem::size_of_val(&*unique), 0);
+        assert!(UniqueArc::<dyn Any>::downcast::<()>(unique).is_ok());
+    }
+
+    #[cfg(feature = "weak")]
+    #[test]
+    fn zero_sized_arcs_allocate_with_weak() {
+        let before = allocations();
+        let token = Arc::new(());
+        assert_eq!(allocations(), before + 1);
+        assert_eq!(Arc::reference_count(&token).get(), 1);
+
+        // The token witnesses the liveness of its clones.
+        let witness = Arc::downgrade(&token);
+        let clone = token.clone();
+        assert_eq!(witness.strong_count(), 2);
+        drop(token);
+        assert!(witness.upgrade().is_some());
+        drop(clone);
+        assert!(witness.upgrade().is_none());
+
+        assert_eq!(Arc::try_unwrap(Arc::new(PhantomData::<String>)), Ok(PhantomData));
+    }
+
+    #[test]
+    fn zero_sized_types_with_drop_glue_allocate() {
+        struct Noisy;
+        impl Drop for Noisy {
+            fn drop(&mut self) {}
+        }
+
+        let before = allocations();
+        let a = Arc::new(Noisy);
+        assert_eq!(allocations(), before + 1);
+        assert!(Arc::try_unwrap(a).is_ok());
+    }
+
+    #[test]
+    fn unique_zero_sized_arcs_do_not_share_allocations() {
+        let mut a = UniqueArc::new(());
+        let b = UniqueArc::new(());
+        assert!(!ptr::eq(&*a as *const (), &*b as *const ()));
+        let _: &mut () = &mut a;
+
+        let a: Arc<()> = a.into();
+        assert_eq!(Arc::reference_count(&a).get(), 1);
+        assert!(Arc::can_make_mut(&a));
+        assert_eq!(UniqueArc::try_unwrap(b).ok(), Some(()));
+    }
+
+    // These mostly check that the heterogeneous impls do not break type inference.
+    #[test]
+    fn comparisons_between_same_types() {
+        let a = Arc::new(1);
+        assert_eq!(a, Arc::new(1));
+        assert!(a < Arc::new(2));
+        assert_eq!(a.partial_cmp(&Arc::new(0)), Some(Ordering::Greater));
+        let bytes: Arc<Vec<u8>> = Arc::new(Vec::new());
+        assert_eq!(bytes, Arc::new(Vec::<u8>::new()));
+        assert_ne!(Arc::new("a"), Arc::new("b"));
+
+        let u = UniqueArc::new(1);
+        assert_eq!(u, UniqueArc::new(1));
+        assert!(u < UniqueArc::new(2));
+        assert_eq!(a, u);
+        assert_eq!(u, a);
+        assert!(u <= a);
+    }
+
+    #[test]
+    fn comparisons_between_different_types() {
+        let string = Arc::new(String::from("abc"));
+        let str: Arc<str> = Arc::from("abc");
+        assert!(string == str);
+        assert!(str != Arc::new(String::from("abd")));
+
+        let unique = UniqueArc::new(String::from("abc"));
+        assert!(unique == str);
+        assert!(str == unique);
+
+        let vec = Arc::new(std::vec![1, 2]);
+        let slice: Arc<[i32]> = Arc::from(&[1, 2][..]);
+        assert!(vec == slice);
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn comparisons_between_paths() {
+        use std::path::{Path, PathBuf};
+
+        let buf = Arc::new(PathBuf::from("/a/b"));
+        let path: Arc<Path> = Arc::from(PathBuf::from("/a/b").into_boxed_path());
+        assert!(buf == path);
+        assert!(path != Arc::new(PathBuf::from("/a/c")));
+    }
+
+    #[cfg(feature = "weak")]
+    #[test]
+    fn weak_of_unique_zero_sized_arc_waits_for_sharing() {
+        let unique = UniqueArc::new(());
+        let weak = UniqueArc::downgrade(&unique);
+        assert!(weak.upgrade().is_none());
+
+        let shared: Arc<()> = unique.into();
+        assert!(weak.upgrade().is_some());
+        drop(shared);
+        assert!(weak.upgrade().is_none());
+    }
+
+    #[cfg(feature = "weak")]
+    #[test]
+    fn weaks_do_not_upgrade_after_the_last_arc() {
+        let a = Arc::new(String::from("value"));
+        let b = a.clone();
+        let weak = Arc::downgrade(&a);
+        assert_eq!(Arc::reference_count(&a).get(), 2);
+        assert_eq!(Arc::weak_count(&a), 1);
+
+        drop(a);
+        assert_eq!(*weak.upgrade().unwrap(), "value");
+        drop(b);
+        assert!(weak.upgrade().is_none());
+        assert_eq!(weak.strong_count(), 0);
+        assert!(weak.clone().upgrade().is_none());
+    }
+
+    #[cfg(feature = "weak")]
+    #[test]
+    fn unwrapping_reports_or_rejects_weaks() {
+        for weaks in [0, 1, 5] {
+            let arc = Arc::new(String::from("value"));
+            let held: Vec<_> = (0..weaks).map(|_| Arc::downgrade(&arc)).collect();
+            let (value, orphaned) = Arc::try_unwrap_disassociate(arc).unwrap();
+            assert_eq!((value.as_str(), orphaned), ("value", weaks));
+            assert!(held.iter().all(|weak| weak.upgrade().is_none()));
+
+            let arc = Arc::new(String::from("value"));
+            let held: Vec<_> = (0..weaks).map(|_| Arc::downgrade(&arc)).collect();
+            match Arc::try_unwrap_strict(arc) {
+                Ok(value) => {
+                    assert_eq!(weaks, 0);
+                    assert_eq!(value, "value");
+                }
+                Err(arc) => {
+                    assert_eq!(Arc::weak_count(&arc), weaks);
+                    assert!(Arc::ptr_eq(&held[0].upgrade().unwrap(), &arc));
+                }
+            }
+        }
+
+        // Neither unwraps a value that is still shared.
+        let a = Arc::new(1);
+        let b = a.clone();
+        let a = Arc::try_unwrap_disassociate(a).unwrap_err();
+        let a = Arc::try_unwrap_strict(a).unwrap_err();
+        assert_eq!(Arc::reference_count(&a).get(), 2);
+        drop(b);
+        assert_eq!(Arc::try_unwrap_strict(a).ok(), Some(1));
+
+        let a = Arc::from_static(&STATIC_INNER);
+        let a = Arc::try_unwrap_disassociate(a).unwrap_err();
+        assert!(Arc::try_unwrap_strict(a).is_err());
+    }
+
+    #[cfg(feature = "weak")]
+    #[test]
+    fn cyclic_zero_sized_arcs_allocate() {
+        let before = allocations();
+        let mut inner = None;
+        let arc = Arc::new_cyclic(|weak: &WeakArc<()>| {
+            assert!(weak.upgrade().is_none());
+            inner = Some(weak.clone());
+        });
+        assert_eq!(allocations(), before + 1);
+        assert_eq!(Arc::reference_count(&arc).get(), 1);
+        assert!(Arc::ptr_eq(&inner.unwrap().upgrade().unwrap(), &arc));
+    }
+
+    #[cfg(feature = "weak")]
+    #[test]
+    fn weaks_compare_by_allocation() {
+        let a = Arc::new(String::from("a"));
+        let b = Arc::new(String::from("a"));
+        let weak_a = Arc::downgrade(&a);
+        assert!(weak_a.ptr_eq(&Arc::downgrade(&a)));
+        assert!(weak_a.ptr_eq(&weak_a.clone()));
+        assert!(!weak_a.ptr_eq(&Arc::downgrade(&b)));
+        assert!(!weak_a.ptr_eq(&WeakArc::new()));
+        assert!(WeakArc::<String>::new().ptr_eq(&WeakArc::new()));
+        assert_eq!(weak_a.as_ptr(), Arc::as_ptr(&a));
+
+        // The pointers stay comparable after the value is gone.
+        let ptr = Arc::as_ptr(&a);
+        drop(a);
+        assert_eq!(weak_a.as_ptr(), ptr);
+        assert!(weak_a.ptr_eq(&weak_a.clone()));
+        assert!(!weak_a.ptr_eq(&Arc::downgrade(&b)));
+
+        let slice: Arc<[u8]> = Arc::from(&[1, 2, 3][..]);
+        let weak = Arc::downgrade(&slice);
+        assert_eq!(weak.as_ptr(), Arc::as_ptr(&slice));
+        assert_eq!(unsafe { &*weak.as_ptr() }, &[1, 2, 3]);
+    }
+
+    #[cfg(feature = "weak")]
+    #[test]
+    fn dangling_weaks_never_allocate_or_upgrade() {
+        #[repr(align(64))]
+        struct Aligned;
+
+        let before = allocations();
+        let weak = WeakArc::<String>::new();
+        let clone = weak.clone();
+        assert!(weak.upgrade().is_none());
+        assert!(clone.upgrade().is_none());
+        assert_eq!(weak.strong_count(), 0);
+        assert!(weak.ptr_eq(&clone));
+        assert!(WeakArc::<String>::default().ptr_eq(&weak));
+        drop((weak, clone));
+        assert_eq!(allocations(), before);
+
+        let ptr = WeakArc::<u64>::new().as_ptr();
+        assert!(!ptr.is_null());
+        assert_eq!(ptr as usize % mem::align_of::<u64>(), 0);
+        assert_eq!(WeakArc::<Aligned>::new().as_ptr() as usize % 64, 0);
+    }
+
+    #[cfg(feature = "weak")]
+    #[test]
+    fn upgrades_race_with_the_last_drop() {
+        use std::sync::atomic::AtomicUsize;
+
+        static DROPS: AtomicUsize = AtomicUsize::new(0);
+
+        struct Counted(u64);
+
+        impl Drop for Counted {
+            fn drop(&mut self) {
+                DROPS.fetch_add(1, Relaxed);
+            }
+        }
+
+        let rounds = if cfg!(miri) { 10 } else { 1000 };
+        for round in 0..rounds {
+            let arc = Arc::new(Counted(round));
+            let weak = Arc::downgrade(&arc);
+            std::thread::scope(|scope| {
+                scope.spawn(move || drop(arc));
+                let weak = weak.clone();
+                scope.spawn(move || {
+                    // Whenever upgrading succeeds, the value is still intact.
+                    while let Some(arc) = weak.upgrade() {
+                        assert_eq!(arc.0, round);
+                    }
+                });
+            });
+            assert!(weak.upgrade().is_none());
+            assert_eq!(DROPS.load(Relaxed), round as usize + 1);
+        }
+    }
+
+    #[cfg(feature = "weak")]
+    #[test]
+    fn weaks_of_unique_arcs_upgrade_once_shared() {
+        let mut unique = UniqueArc::new(String::from("draft"));
+        let weak = UniqueArc::downgrade(&unique);
+        assert!(weak.upgrade().is_none());
+        assert_eq!(weak.strong_count(), 0);
+        unique.push_str(" done");
+        assert!(weak.clone().upgrade().is_none());
+
+        let shared: Arc<String> = unique.into();
+        assert_eq!(*weak.upgrade().unwrap(), "draft done");
+        assert_eq!(Arc::reference_count(&shared).get(), 1);
+        assert_eq!(Arc::weak_count(&shared), 1);
+        drop(shared);
+        assert!(weak.upgrade().is_none());
+
+        // Dropping the unique pointer without sharing it invalidates its weak pointers too.
+        let unique = UniqueArc::new(String::from("discarded"));
+        let weak = UniqueArc::downgrade(&unique);
+        drop(unique);
+        assert!(weak.upgrade().is_none());
+    }
+
+    #[test]
+    fn headers_only_hold_the_enabled_counts() {
+        use crate::test_util::deallocations;
+
+        // The header holds a second count only with the `weak` feature.
+        if !cfg!(feature = "allocation-id") {
+            let counts = if cfg!(feature = "weak") { 2 } else { 1 };
+            let header = counts * mem::size_of::<usize>();
+            assert_eq!(mem::offset_of!(ArcInner<u8>, data), header);
+            assert_eq!(mem::size_of::<ArcInner<usize>>(), header + mem::size_of::<usize>());
+        }
+
+        // Without any weak pointers, the last `Arc` frees the allocation right away.
+        let before = deallocations();
+        drop(Arc::new(1_u64));
+        assert_eq!(deallocations(), before + 1);
+    }
+
+    #[test]
+    fn raw_pointers_round_trip() {
+        let arc = Arc::new(String::from("abc"));
+        let ptr = Arc::into_raw(arc.clone());
+        assert_eq!(ptr, Arc::as_ptr(&arc));
+        assert_eq!(unsafe { &*ptr }, "abc");
+        assert_eq!(Arc::reference_count(&arc).get(), 2);
+        let back = unsafe { Arc::from_raw(ptr) };
+        assert!(Arc::ptr_eq(&arc, &back));
+        assert_eq!(Arc::reference_count(&arc).get(), 2);
+        drop(back);
+        assert_eq!(Arc::reference_count(&arc).get(), 1);
+
+        // The value starts further into the allocation than the header ends.
+        #[repr(align(64))]
+        struct Aligned(u8);
+        let arc = Arc::new(Aligned(7));
+        let ptr = Arc::into_raw(arc.clone());
+        assert_eq!(ptr as usize % 64, 0);
+        assert_eq!(Arc::reference_count(&arc).get(), 2);
+        let back = unsafe { Arc::from_raw(ptr) };
+        assert!(Arc::ptr_eq(&arc, &back));
+        assert_eq!(back.0, 7);
+        assert_eq!(Arc::reference_count(&arc).get(), 2);
+        drop(back);
+        assert_eq!(Arc::reference_count(&arc).get(), 1);
+    }
+
+    #[test]
+    fn raw_counts_follow_retains_and_releases() {
+        use crate::test_util::deallocations;
+
+        // A C library that retains the pointer for each of its callbacks and releases them one by
+        // one, with the last release happening after all `Arc`s are gone.
+        let arc = Arc::new(String::from("shared"));
+        let ptr = Arc::into_raw(arc.clone());
+        for retained in 1..=5 {
+            unsafe { Arc::increment_strong_count(ptr) };
+            assert_eq!(Arc::reference_count(&arc).get(), 2 + retained);
+        }
+        for retained in (0..5).rev() {
+            unsafe { Arc::decrement_strong_count(ptr) };
+            assert_eq!(Arc::reference_count(&arc).get(), 2 + retained);
+        }
+        drop(arc);
+        assert_eq!(unsafe { &*ptr }, "shared");
+
+        // Frees both the string and the allocation of the `Arc`.
+        let before = deallocations();
+        unsafe { Arc::decrement_strong_count(ptr) };
+        assert_eq!(deallocations(), before + 2);
+    }
+
+    #[test]
+    fn data_offsets_match_the_layout() {
+        use base::any::Any;
+
+        #[repr(align(64))]
+        struct Aligned(u8);
+
+        assert_eq!(data_offset_val(&1_u8), mem::offset_of!(ArcInner<u8>, data));
+        assert_eq!(data_offset_val(&1_u64), mem::offset_of!(ArcInner<u64>, data));
+        assert_eq!(data_offset_val(&Aligned(1)), mem::offset_of!(ArcInner<Aligned>, data));
+        assert_eq!(data_offset_val(&Aligned(1)) % 64, 0);
+
+        // The offsets of unsized values lead back from the value to the header.
+        fn header_of<T: ?Sized>(ptr: &Arc<T>) -> *const u8 {
+            Arc::as_ptr(ptr).cast::<u8>().wrapping_sub(data_offset_val(&**ptr))
+        }
+        let slice: Arc<[u64]> = Arc::from(&[1, 2, 3][..]);
+        assert_eq!(data_offset_val(&*slice), mem::offset_of!(ArcInner<[u64; 3]>, data));
+        assert_eq!(header_of(&slice), slice.ptr.as_ptr().cast::<u8>());
+        let text: Arc<str> = Arc::from("abc");
+        assert_eq!(data_offset_val(&*text), mem::offset_of!(ArcInner<u8>, data));
+        assert_eq!(header_of(&text), text.ptr.as_ptr().cast::<u8>());
+        let dynamic: Arc<dyn Any> = Arc::from(Box::new(Aligned(1)) as Box<dyn Any>);
+        assert_eq!(data_offset_val(&*dynamic), mem::offset_of!(ArcInner<Aligned>, data));
+        assert_eq!(header_of(&dynamic), dynamic.ptr.as_ptr().cast::<u8>());
+        assert_eq!(dynamic.downcast_ref::<Aligned>().unwrap().0, 1);
+    }
+
+    #[test]
+    fn borrows_share_the_allocation() {
+        let a = Arc::new(std::string::String::from("abc"));
+        let b = Arc::borrow_arc(&a);
+        let copy = b;
+        assert_eq!(&*copy, "abc");
+        assert_eq!(Arc::reference_count(&a).get(), 1);
+
+        let owned = ArcBorrow::to_arc(b);
+        assert!(Arc::ptr_eq(&a, &owned));
+        assert_eq!(Arc::reference_count(&a).get(), 2);
+        drop(owned);
+        assert_eq!(Arc::reference_count(&a).get(), 1);
+    }
+
+    #[test]
+    fn borrows_from_unsized_pointers() {
+        let a: Arc<[u64]> = Arc::from(&[1, 2, 3][..]);
+        let b = unsafe { ArcBorrow::from_raw(Arc::as_ptr(&a)) };
+        assert_eq!(ArcBorrow::get(b), &[1, 2, 3]);
+        assert!(Arc::ptr_eq(&a, &ArcBorrow::to_arc(b)));
+
+        let s: Arc<str> = Arc::from("xyz");
+        let b = unsafe { ArcBorrow::from_raw(Arc::as_ptr(&s)) };
+        assert!(Arc::ptr_eq(&s, &ArcBorrow::to_arc(b)));
+        assert_eq!(Arc::reference_count(&s).get(), 1);
+    }
+
+    static STATIC_INNER: StaticArcInner<[u32; 2]> = StaticArcInner::new([1, 2]);
+    static STATIC: Arc<[u32; 2]> = Arc::from_static(&STATIC_INNER);
+    const CONST: Arc<[u32; 2]> = Arc::from_static(&STATIC_INNER);
+
+    #[test]
+    fn static_arcs_need_no_setup() {
+        let barrier = std::sync::Barrier::new(2);
+        std::thread::scope(|scope| {
+            for _ in 0..2 {
+                scope.spawn(|| {
+                    barrier.wait();
+                    let before = allocations();
+                    let clones = [(); 100].map(|_| STATIC.clone());
+                    let constant = CONST;
+                    assert!(clones.iter().all(|clone| Arc::ptr_eq(clone, &constant)));
+                    assert_eq!(*constant, [1, 2]);
+                    drop((clones, constant));
+                    assert_eq!(allocations(), before);
+                });
+            }
+        });
+        assert_eq!(Arc::reference_count(&STATIC).get(), usize::MAX);
+        assert!(!Arc::can_make_mut(&CONST));
+    }
+
+    #[test]
+    fn copy_from_slice_keeps_alignment() {
+        let src: std::vec::Vec<u64> = (0..1000).map(|i| i * 0x0101_0101_0101).collect();
+        let copy = Arc::copy_from_slice(&src);
+        assert_eq!(&*copy, &src[..]);
+        assert_eq!(copy.as_ptr() as usize % mem::align_of::<u64>(), 0);
+        assert_eq!(copy, Arc::<[u64]>::from(&src[..]));
+
+        #[derive(Clone, Copy, Debug, PartialEq)]
+        #[repr(align(32))]
+        struct Aligned(u8);
+        let src = [Aligned(1), Aligned(2), Aligned(3)];
+        let copy = Arc::copy_from_slice(&src);
+        assert_eq!(&*copy, &src[..]);
+        assert_eq!(copy.as_ptr() as usize % 32, 0);
+
+        let empty = Arc::<[u64]>::copy_from_slice(&[]);
+        assert!(empty.is_empty());
+    }
+
+    #[cfg(feature = "serde")]
+    fn numbers(numbers: [u32; 3]) -> serde::de::value::SeqDeserializer<
+        base::array::IntoIter<u32, 3>,
+        serde::de::value::Error,
+    > {
+        serde::de::value::SeqDeserializer::new(IntoIterator::into_iter(numbers))
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn deserialize_in_place_reuses_unique_allocations() {
+        let mut arc = Arc::new([1, 2, 3]);
+        let ptr = Arc::as_ptr(&arc);
+        arc_deserialize_in_place(&mut arc, numbers([4, 5, 6])).unwrap();
+        assert_eq!(*arc, [4, 5, 6]);
+        assert_eq!(Arc::as_ptr(&arc), ptr);
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn deserialize_in_place_replaces_shared_arcs() {
+        let mut arc = Arc::new([1, 2, 3]);
+        let old = arc.clone();
+        arc_deserialize_in_place(&mut arc, numbers([4, 5, 6])).unwrap();
+        assert_eq!(*arc, [4, 5, 6]);
+        assert_eq!(*old, [1, 2, 3]);
+        assert!(!Arc::ptr_eq(&arc, &old));
+        assert_eq!(Arc::reference_count(&arc).get(), 1);
+        assert_eq!(Arc::reference_count(&old).get(), 1);
+
+        // A failed deserialization leaves the shared value in place.
+        let mut shared = old.clone();
+        let short = serde::de::value::SeqDeserializer::<_, serde::de::value::Error>::new(
+            IntoIterator::into_iter([7_u32]),
+        );
+        assert!(arc_deserialize_in_place(&mut shared, short).is_err());
+        assert!(Arc::ptr_eq(&shared, &old));
+    }
+
+    #[cfg(feature = "bincode")]
+    #[test]
+    fn bincode_round_trips_nested_values() {
+        type Nested = (Arc<str>, Arc<[u8]>, Option<Arc<(u32, Arc<str>)>>, UniqueArc<[u16; 2]>);
+
+        let config = bincode::config::standard();
+        let mut buf = [0; 64];
+        let value: Nested = (
+            Arc::from("häuschen"),
+            Arc::from(&[1_u8, 2, 3][..]),
+            Some(Arc::new((7, Arc::from("")))),
+            UniqueArc::new([4, 5]),
+        );
+        let len = bincode::encode_into_slice(&value, &mut buf, config).unwrap();
+
+        // The pointers are encoded exactly like the values they point to.
+        let mut plain = [0; 64];
+        let plain_len = bincode::encode_into_slice(
+            ("häuschen", &[1_u8, 2, 3][..], Some((7_u32, "")), [4_u16, 5]),
+            &mut plain,
+            config,
+        )
+        .unwrap();
+        assert_eq!(buf[..len], plain[..plain_len]);
+
+        let (decoded, read): (Nested, usize) =
+            bincode::decode_from_slice(&buf[..len], config).unwrap();
+        assert_eq!(read, len);
+        assert_eq!(&*decoded.0, "häuschen");
+        assert_eq!(&*decoded.1, &[1, 2, 3]);
+        let inner = decoded.2.unwrap();
+        assert_eq!(inner.0, 7);
+        assert_eq!(&*inner.1, "");
+        assert_eq!(*decoded.3, [4, 5]);
+
+        let (borrowed, _): (Nested, usize) =
+            bincode::borrow_decode_from_slice(&buf[..len], config).unwrap();
+        assert_eq!(&*borrowed.0, "häuschen");
+        assert_eq!(&*borrowed.2.unwrap().1, "");
+    }
+
+    #[cfg(feature = "bincode")]
+    #[test]
+    fn bincode_rejects_invalid_strings() {
+        use crate::test_util::deallocations;
+
+        let config = bincode::config::standard();
+        let mut buf = [0; 8];
+        let len = bincode::encode_into_slice(&[0xff_u8, 0xfe][..], &mut buf, config).unwrap();
+
+        let before = (allocations(), deallocations());
+        let result: Result<(Arc<str>, usize), _> = bincode::decode_from_slice(&buf[..len], config);
+        assert!(matches!(result, Err(DecodeError::Utf8 { .. })));
+        // The bytes that were read are freed again.
+        assert_eq!((allocations(), deallocations()), (before.0 + 1, before.1 + 1));
+
+        // So is the buffer for a string that is cut short.
+        let before = (allocations(), deallocations());
+        let result: Result<(Arc<str>, usize), _> = bincode::decode_from_slice(&buf[..2], config);
+        assert!(matches!(result, Err(DecodeError::UnexpectedEnd { .. })));
+        assert_eq!(allocations() - before.0, deallocations() - before.1);
+    }
+
+    #[cfg(feature = "schemars")]
+    #[test]
+    fn schemas_match_those_of_owned_values() {
+        mod shared {
+            use super::*;
+
+            #[allow(dead_code)]
+            #[derive(JsonSchema)]
+            pub struct Message {
+                pub text: Arc<str>,
+                pub codes: Arc<[u32]>,
+                pub reply: Option<UniqueArc<Message>>,
+            }
+        }
+
+        mod owned {
+            use super::*;
+
+            #[allow(dead_code)]
+            #[derive(JsonSchema)]
+            pub struct Message {
+                pub text: String,
+                pub codes: Vec<u32>,
+                pub reply: Option<Box<Message>>,
+            }
+        }
+
+        assert_eq!(
+            schemars::schema_for!(shared::Message),
+            schemars::schema_for!(owned::Message)
+        );
+        assert_eq!(
+            schemars::schema_for!(Arc<[Arc<str>]>),
+            schemars::schema_for!(Vec<String>)
+        );
+    }
+
+    #[cfg(feature = "borsh")]
+    #[test]
+    fn borsh_round_trips_nested_values() {
+        type Nested = (Arc<str>, Arc<[u16]>, Option<Arc<(u8, Arc<str>)>>, UniqueArc<[u8; 2]>);
+
+        let value: Nested = (
+            Arc::from("häuschen"),
+            Arc::from(&[1_u16, 2, 3][..]),
+            Some(Arc::new((7, Arc::from("")))),
+            UniqueArc::new([4, 5]),
+        );
+        let bytes = borsh::to_vec(&value).unwrap();
+        // The pointers are encoded exactly like the values they point to.
+        let plain = ("häuschen", &[1_u16, 2, 3][..], Some((7_u8, "")), [4_u8, 5]);
+        assert_eq!(bytes, borsh::to_vec(&plain).unwrap());
+
+        let decoded: Nested = borsh::from_slice(&bytes).unwrap();
+        assert_eq!(&*decoded.0, "häuschen");
+        assert_eq!(&*decoded.1, &[1, 2, 3]);
+        let inner = decoded.2.unwrap();
+        assert_eq!(inner.0, 7);
+        assert_eq!(&*inner.1, "");
+        assert_eq!(*decoded.3, [4, 5]);
+
+        let empty: Arc<[Arc<str>]> = borsh::from_slice(&[0, 0, 0, 0]).unwrap();
+        assert!(empty.is_empty());
+        assert!(borsh::from_slice::<Arc<str>>(&[2, 0, 0, 0, 0xff, 0xfe]).is_err());
+        assert!(borsh::from_slice::<Arc<[()]>>(&[1, 0, 0, 0]).is_err());
+    }
+
+    #[cfg(feature = "borsh")]
+    #[test]
+    fn borsh_rejects_huge_lengths_gracefully() {
+        use crate::test_util::deallocations;
+
+        // Claims `u32::MAX` items, but only three follow.
+        let mut bytes = std::vec![0xff; 4];
+        for item in ["a", "b", "c"].iter() {
+            bytes.extend_from_slice(&borsh::to_vec(*item).unwrap());
+        }
+
+        let before = (allocations(), deallocations());
+        assert!(borsh::from_slice::<Arc<[String]>>(&bytes).is_err());
+        // The items that were read are dropped again.
+        assert_eq!(allocations() - before.0, deallocations() - before.1);
+
+        let before = (allocations(), deallocations());
+        assert!(borsh::from_slice::<Arc<str>>(&[0xff, 0xff, 0xff, 0xff, b'a']).is_err());
+        assert!(borsh::from_slice::<Arc<[u64]>>(&[0xff, 0xff, 0xff, 0xff, 1, 2]).is_err());
+        assert_eq!(allocations() - before.0, deallocations() - before.1);
+
+        // Short slices are read into a slice of the claimed size right away, which drops the
+        // items that were read as well.
+        bytes[..4].copy_from_slice(&[4, 0, 0, 0]);
+        let before = (allocations(), deallocations());
+        assert!(borsh::from_slice::<Arc<[String]>>(&bytes[..9]).is_err());
+        assert_eq!(allocations() - before.0, deallocations() - before.1);
+    }
+
+    #[test]
+    fn arc_macro_forms() {
+        let empty: Arc<[String]> = crate::arc![];
+        assert!(empty.is_empty());
+
+        let before = allocations();
+        let words = crate::arc![String::from("a"), String::from("b"),];
+        let repeated = crate::arc![String::from("ab"); 3];
+        // One for each string, and one for each slice.
+        assert_eq!(allocations() - before, 7);
+        assert_eq!(&*words, &[String::from("a"), String::from("b")]);
+        assert_eq!(&*repeated, &[String::from("ab"), String::from("ab"), String::from("ab")]);
+
+        let none: Arc<[String]> = crate::arc![String::from("dropped"); 0];
+        assert!(none.is_empty());
+        let single = crate::arc![1,];
+        assert_eq!(&*single, &[1]);
+    }
+
+    #[cfg(feature = "rayon")]
+    #[test]
+    fn parallel_collection_matches_sequential_construction() {
+        use rayon::prelude::*;
+
+        let expected: Vec<String> = (0..1_000).map(|i| i.to_string()).collect();
+        let expected: Arc<[String]> = Arc::from(expected);
+        // Indexed iterators are written in place, the others go through a vector.
+        let indexed: Arc<[String]> = (0..1_000).into_par_iter().map(|i| i.to_string()).collect();
+        let chained: Arc<[String]> =
+            (0..500).into_par_iter().chain(500..1_000).map(|i| i.to_string()).collect();
+        let filtered: Arc<[String]> = (0..2_000)
+            .into_par_iter()
+            .filter(|i| i % 2 == 0)
+            .map(|i| (i / 2).to_string())
+            .collect();
+        let from_fn = Arc::from_par_fn(1_000, |i| i.to_string());
+        assert_eq!(indexed, expected);
+        assert_eq!(chained, expected);
+        assert_eq!(filtered, expected);
+        assert_eq!(from_fn, expected);
+
+        let empty: Arc<[String]> = Vec::<String>::new().into_par_iter().collect();
+        assert!(empty.is_empty());
+        assert!(Arc::<[String]>::from_par_fn(0, |_| unreachable!()).is_empty());
+    }
+
+    #[cfg(feature = "rayon")]
+    #[test]
+    fn panics_during_parallel_construction_drop_the_computed_items() {
+        use rayon::prelude::*;
+        use std::panic;
+        use std::sync::atomic::AtomicUsize;
+
+        static CREATED: AtomicUsize = AtomicUsize::new(0);
+        static DROPPED: AtomicUsize = AtomicUsize::new(0);
+
+        struct Counted;
+
+        impl Drop for Counted {
+            fn drop(&mut self) {
+                DROPPED.fetch_add(1, Relaxed);
+            }
+        }
+
+        fn item(i: usize) -> Counted {
+            if i == 777 {
+                std::panic!("item failed");
+            }
+            CREATED.fetch_add(1, Relaxed);
+            Counted
+        }
+
+        assert!(panic::catch_unwind(|| Arc::from_par_fn(1_000, item)).is_err());
+        assert_eq!(CREATED.load(Relaxed), DROPPED.load(Relaxed));
+
+        let collected = panic::catch_unwind(|| {
+            (0..1_000).into_par_iter().map(item).collect::<Arc<[Counted]>>()
+        });
+        assert!(collected.is_err());
+        assert_eq!(CREATED.load(Relaxed), DROPPED.load(Relaxed));
+        assert!(CREATED.load(Relaxed) > 0);
+    }
+
+    #[test]
+    fn casts_between_transparent_newtypes() {
+        #[repr(transparent)]
+        struct RawId(u32);
+
+        #[repr(transparent)]
+        struct Id(RawId);
+
+        #[repr(transparent)]
+        struct Name(String);
+
+        let raw = Arc::new(RawId(7));
+        let shared = raw.clone();
+        let id: Arc<Id> = unsafe { Arc::cast(raw) };
+        assert_eq!((id.0).0, 7);
+        assert_eq!(Arc::reference_count(&id).get(), 2);
+        drop(shared);
+
+        // The strings are dropped through whichever pointer comes last.
+        let name = Arc::new(String::from("name"));
+        let cast: Arc<Name> = unsafe { Arc::cast(name.clone()) };
+        drop(name);
+        assert_eq!(cast.0, "name");
+
+        let raw: Arc<[RawId]> = Arc::from(vec![RawId(1), RawId(2), RawId(3)]);
+        let ptr = Arc::as_ptr(&raw) as *const u8;
+        let ids: Arc<[Id]> = unsafe { Arc::cast_slice(raw) };
+        assert_eq!(ids.len(), 3);
+        assert_eq!(ids.iter().map(|id| (id.0).0).collect::<Vec<_>>(), [1, 2, 3]);
+        assert_eq!(Arc::as_ptr(&ids) as *const u8, ptr);
+
+        let names: Arc<[String]> = Arc::from(vec![String::from("a"), String::from("b")]);
+        let names = Arc::into_mut(names).ok().unwrap();
+        let mut names: UniqueArc<[Name]> = unsafe { UniqueArc::cast_slice(names) };
+        names[1].0.push('c');
+        let name = Arc::into_mut(Arc::new(String::new())).ok().unwrap();
+        let mut name: UniqueArc<Name> = unsafe { UniqueArc::cast(name) };
+        // `name.0` would be the field of the `UniqueArc`.
+        (*name).0.push_str(&names[1].0);
+        assert_eq!((*name).0, "bc");
+    }
+
+    #[test]
+    fn mapping_reuses_same_layout_allocations() {
+        use crate::test_util::deallocations;
+
+        let unique = UniqueArc::new(String::from("bytes"));
+        let address = &*unique as *const String as usize;
+        let (allocs, deallocs) = (allocations(), deallocations());
+        let bytes = unique.map(String::into_bytes);
+        assert_eq!(&*bytes as *const Vec<u8> as usize, address);
+        assert_eq!(*bytes, b"bytes");
+        assert_eq!((allocations(), deallocations()), (allocs, deallocs));
+
+        // A different layout needs a new allocation.
+        let wide = UniqueArc::new(7_u32).map(u64::from);
+        let (allocs, deallocs) = (allocations(), deallocations());
+        let narrow = wide.map(|n| n as u8);
+        assert_eq!(*narrow, 7);
+        assert_eq!((allocations(), deallocations()), (allocs + 1, deallocs + 1));
+    }
+
+    #[test]
+    fn panicking_maps_drop_the_value_once() {
+        use std::cell::Cell;
+        use std::panic::{self, AssertUnwindSafe};
+
+        struct Counted<'a>(&'a Cell<usize>);
+
+        impl Drop for Counted<'_> {
+            fn drop(&mut self) {
+                self.0.set(self.0.get() + 1);
+            }
+        }
+
+        let drops = Cell::new(0);
+        let unique = UniqueArc::new(Counted(&drops));
+        let result = panic::catch_unwind(AssertUnwindSafe(|| {
+            unique.map(|value| -> Counted<'_> {
+                let _value = value;
+                std::panic!("map failed");
+            })
+        }));
+        // Unwinding allocates as well, so it is left to Miri to check that the allocation has
+        // been freed.
+        assert!(result.is_err());
+        assert_eq!(drops.get(), 1);
+    }
+
+    #[cfg(feature = "weak")]
+    #[test]
+    fn mapping_does_not_reuse_weakly_referenced_allocations() {
+        let unique = UniqueArc::new(1_u64);
+        let weak = UniqueArc::downgrade(&unique);
+        let address = &*unique as *const u64 as usize;
+
+        let mapped = unique.map(|n| n as i64 + 1);
+        // The `WeakArc` keeps the old allocation, which never contains an `i64`.
+        assert_ne!(&*mapped as *const i64 as usize, address);
+        assert_eq!(*mapped, 2);
+        assert!(weak.upgrade().is_none());
+        let shared: Arc<i64> = mapped.into();
+        assert_eq!(Arc::weak_count(&shared), 0);
+    }
+
+    #[cfg(feature = "unstable")]
+    #[test]
+    fn pointers_to_closures_are_callable() {
+        fn call_once<F: FnOnce(u32) -> u32>(f: F) -> u32 {
+            f(5)
+        }
+
+        let offset = 10;
+        let add = Arc::new(move |x: u32| x + offset);
+        assert_eq!(add(1), 11);
+        let mapped: Vec<u32> = (0..3).map(add.clone()).collect();
+        assert_eq!(mapped, [10, 11, 12]);
+        // Calling a pointer by value only consumes that pointer.
+        assert_eq!(call_once(add.clone()), 15);
+        assert_eq!(Arc::reference_count(&add).get(), 1);
+
+        let len: Box<dyn Fn(&str) -> usize> = Box::new(str::len);
+        let len: Arc<dyn Fn(&str) -> usize> = Arc::from(len);
+        assert_eq!(len("häuschen"), 9);
+        assert_eq!(len(""), 0);
+
+        // Unique pointers hand out mutable references, so they forward `FnMut` as well.
+        let mut calls = 0;
+        let mut count = UniqueArc::new(|x: u32| {
+            calls += 1;
+            x + calls
+        });
+        assert_eq!(count(0), 1);
+        assert_eq!(count(0), 2);
+        assert_eq!(call_once(count), 8);
+        assert_eq!(calls, 3);
+    }
+
+    #[test]
+    fn clone_many_releases_the_clones_left_over() {
+        use crate::test_util::deallocations;
+
+        let a = Arc::new(String::from("a"));
+        let mut clones = Arc::clone_many(&a, 5);
+        assert_eq!(Arc::reference_count(&a).get(), 6);
+        assert_eq!(clones.len(), 5);
+        let first = clones.next().unwrap();
+        assert!(Arc::ptr_eq(&first, &a));
+        assert_eq!(clones.len(), 4);
+        // Dropping the iterator releases the four clones it has not produced.
+        drop(clones);
+        assert_eq!(Arc::reference_count(&a).get(), 2);
+        drop(first);
+        assert_eq!(Arc::reference_count(&a).get(), 1);
+
+        let all: Vec<Arc<String>> = Arc::clone_many(&a, 3).collect();
+        assert_eq!(Arc::reference_count(&a).get(), 4);
+        drop(all);
+        assert_eq!(Arc::clone_many(&a, 0).count(), 0);
+        assert_eq!(Arc::reference_count(&a).get(), 1);
+
+        // The iterator may outlive the original pointer, and then frees the allocation.
+        let clones = Arc::clone_many(&a, 3);
+        drop(a);
+        let before = deallocations();
+        drop(clones);
+        assert_eq!(deallocations(), before + 2);
+
+        // Pointers to static memory are not counted.
+        static INNER: StaticArcInner<u32> = StaticArcInner::new(7);
+        let shared = Arc::from_static(&INNER);
+        let mut clones = Arc::clone_many(&shared, 3);
+        assert_eq!(*clones.next().unwrap(), 7);
+        drop(clones);
+        assert_eq!(Arc::reference_count(&shared).get(), usize::MAX);
+    }
+
+    #[cfg(not(feature = "panic-free"))]
+    #[test]
+    fn clone_many_checks_for_overflow() {
+        use std::panic::{self, AssertUnwindSafe};
+
+        let a = Arc::new(1);
+        let clone_many = |n| panic::catch_unwind(AssertUnwindSafe(|| drop(Arc::clone_many(&a, n))));
+        assert!(clone_many(usize::MAX).is_err());
+        assert!(clone_many(MAX_REFCOUNT).is_err());
+        // The failed batches did not touch the count.
+        assert_eq!(Arc::reference_count(&a).get(), 1);
+
+        // The largest batch that fits.
+        let clones = Arc::clone_many(&a, MAX_REFCOUNT - 1);
+        assert_eq!(Arc::reference_count(&a).get(), MAX_REFCOUNT);
+        drop(clones);
+        assert_eq!(Arc::reference_count(&a).get(), 1);
+    }
+
+    #[test]
+    fn leaked_references_can_be_reclaimed() {
+        use crate::test_util::deallocations;
+
+        let a = Arc::new(String::from("leaked"));
+        let (value, token) = Arc::leak_with_token(a.clone());
+        assert_eq!(value, "leaked");
+        assert!(ptr::eq(value, &*a));
+        // The leaked reference is still counted.
+        assert_eq!(Arc::reference_count(&a).get(), 2);
+        assert!(!Arc::can_make_mut(&a));
+
+        let reclaimed = unsafe { token.reclaim() };
+        assert!(Arc::ptr_eq(&reclaimed, &a));
+        assert_eq!(Arc::reference_count(&a).get(), 2);
+        drop(reclaimed);
+        assert_eq!(Arc::reference_count(&a).get(), 1);
+        assert!(Arc::can_make_mut(&a));
+
+        // Reclaiming the last reference frees the allocation once it is dropped.
+        let before = deallocations();
+        let (_, token) = Arc::leak_with_token(a);
+        assert_eq!(deallocations(), before);
+        drop(unsafe { token.reclaim() });
+        assert_eq!(deallocations(), before + 2);
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn drop_in_background_drops_once_on_another_thread() {
+        use std::sync::mpsc::{self, Sender};
+        use std::thread::{self, ThreadId};
+        use std::time::Duration;
+
+        struct Reported(Sender<ThreadId>);
+
+        impl Drop for Reported {
+            fn drop(&mut self) {
+                self.0.send(thread::current().id()).unwrap();
+            }
+        }
+
+        let (sender, receiver) = mpsc::channel();
+        let a = Arc::new(Reported(sender));
+        let b = a.clone();
+
+        // Releasing a reference that is not the last one only decrements the count.
+        Arc::drop_in_background(a);
+        assert_eq!(Arc::reference_count(&b).get(), 1);
+        assert!(receiver.try_recv().is_err());
+
+        Arc::drop_in_background(b);
+        let dropped_on = receiver.recv_timeout(Duration::from_secs(10)).unwrap();
+        assert_ne!(dropped_on, thread::current().id());
+        // The sender is gone with the value, so there is no second drop to wait for.
+        assert_eq!(receiver.recv(), Err(mpsc::RecvError));
+
+        // Values are handed to the same thread every time.
+        let (sender, receiver) = mpsc::channel();
+        Arc::drop_in_background(Arc::new(Reported(sender)));
+        assert_eq!(receiver.recv_timeout(Duration::from_secs(10)).unwrap(), dropped_on);
+    }
+
+    #[test]
+    fn downcasting_reuses_the_allocation() {
+        let any: Arc<dyn Any> = Arc::from(Box::new(String::from("any")) as Box<dyn Any>);
+        let ptr = Arc::as_ptr(&any) as *const u8;
+        // A failed downcast hands back the original pointer.
+        let any = Arc::<dyn Any>::downcast::<u32>(any).unwrap_err();
+        assert_eq!(Arc::as_ptr(&any) as *const u8, ptr);
+        let other = any.clone();
+        let string = Arc::<dyn Any>::downcast::<String>(any).unwrap();
+        assert_eq!(*string, "any");
+        assert_eq!(Arc::as_ptr(&string) as *const u8, ptr);
+        assert_eq!(Arc::reference_count(&string).get(), 2);
+        drop(other);
+
+        let number: Box<dyn Any + Send + Sync> = Box::new(7_u64);
+        let number = Arc::<dyn Any + Send + Sync>::downcast::<u32>(Arc::from(number)).unwrap_err();
+        assert_eq!(*Arc::<dyn Any + Send + Sync>::downcast::<u64>(number).unwrap(), 7);
+
+        let number: Box<dyn Any + Send + Sync> = Box::new(7_u64);
+        let unique = Arc::into_mut(Arc::from(number)).unwrap();
+        let mut number = UniqueArc::<dyn Any + Send + Sync>::downcast::<u64>(unique).unwrap();
+        *number += 1;
+        assert_eq!(*number, 8);
+        // Downcasting keeps unique pointers unique, so the value can be mutated.
+        let bytes: Box<dyn Any> = Box::new(std::vec![1_u8]);
+        let unique = Arc::into_mut(Arc::from(bytes)).unwrap();
+        let unique = UniqueArc::<dyn Any>::downcast::<Vec<u16>>(unique).unwrap_err();
+        let mut bytes = UniqueArc::<dyn Any>::downcast::<Vec<u8>>(unique).unwrap();
+        bytes.push(2);
+        let bytes: Arc<Vec<u8>> = bytes.into();
+        assert_eq!(*bytes, [1, 2]);
+        assert_eq!(Arc::reference_count(&bytes).get(), 1);
+    }
+
+    #[test]
+    fn adding_strings_reuses_unique_allocations() {
+        use crate::test_util::deallocations;
+
+        let mut s: Arc<str> = Arc::from("grüß");
+        let before = (allocations(), deallocations());
+        s += ", ";
+        let mut s = s + "gott";
+        // Each append is a single reallocation.
+        assert_eq!((allocations(), deallocations()), (before.0 + 2, before.1 + 2));
+        assert_eq!(&*s, "grüß, gott");
+        s += "";
+        assert_eq!(allocations(), before.0 + 2);
+
+        // Shared strings are copied, and the other pointers keep the old value.
+        let shared = s.clone();
+        let bang: Arc<str> = Arc::from("!");
+        let before = (allocations(), deallocations());
+        let s = s + bang;
+        // The copy is the only allocation, `bang` the only one freed.
+        assert_eq!((allocations(), deallocations()), (before.0 + 1, before.1 + 1));
+        assert_eq!(&*s, "grüß, gott!");
+        assert_eq!(&*shared, "grüß, gott");
+        assert_eq!(Arc::reference_count(&shared).get(), 1);
+        assert_eq!(Arc::reference_count(&s).get(), 1);
+
+        // Static strings are not counted, so they are always copied.
+        let mut greeting = crate::arc_str!("hello");
+        greeting += "";
+        assert_eq!(Arc::reference_count(&greeting).get(), usize::MAX);
+        let before = allocations();
+        greeting += " world";
+        assert_eq!(allocations(), before + 1);
+        assert_eq!(&*greeting, "hello world");
+        assert_eq!(Arc::reference_count(&greeting).get(), 1);
+        assert_eq!(&*(crate::arc_str!("") + crate::arc_str!("static")), "static");
+    }
+
+    #[test]
+    fn adding_strings_allocates_less_than_formatting() {
+        let words = ["reference", "-", "counted", " ", "strings"];
+
+        let before = allocations();
+        let mut added: Arc<str> = Arc::from(words[0]);
+        for word in &words[1..] {
+            added += word;
+        }
+        let adding = allocations() - before;
+
+        let before = allocations();
+        let mut formatted: Arc<str> = Arc::from(words[0]);
+        for word in &words[1..] {
+            formatted = Arc::from(std::format!("{}{}", formatted, word));
+        }
+        let formatting = allocations() - before;
+
+        assert_eq!(added, formatted);
+        // One allocation up front, and one reallocation per appended word.
+        assert_eq!(adding, words.len());
+        // On top of the first allocation, a `String` and a copy of it per appended word.
+        assert!(formatting > 2 * (words.len() - 1));
+    }
+
+    #[cfg(feature = "allocation-id")]
+    #[test]
+    fn allocation_ids_follow_the_allocation() {
+        let a = Arc::new(String::from("a"));
+        let b = Arc::new(String::from("a"));
+        let id = Arc::allocation_id(&a);
+        assert_ne!(id, 0);
+        assert_ne!(Arc::allocation_id(&b), id);
+        assert_eq!(Arc::allocation_id(&a.clone()), id);
+
+        // Converting to and from the unique pointer keeps the allocation, and hence the id.
+        let mut unique = Arc::into_mut(a).unwrap();
+        assert_eq!(UniqueArc::allocation_id(&unique), id);
+        unique.push('!');
+        let a: Arc<String> = unique.into();
+        assert_eq!(Arc::allocation_id(&a), id);
+
+        // Copying a shared value makes a new allocation with a fresh id.
+        let s: Arc<str> = Arc::from("s");
+        let mut copy = s.clone();
+        Arc::make_mut(&mut copy).make_ascii_uppercase();
+        let ids = [id, Arc::allocation_id(&b), Arc::allocation_id(&s), Arc::allocation_id(&copy)];
+        assert!((1..ids.len()).all(|i| !ids[..i].contains(&ids[i])));
+        assert_eq!(Arc::allocation_id(&a), id);
+
+        static INNER: StaticArcInner<u32> = StaticArcInner::new(7);
+        assert_eq!(Arc::allocation_id(&Arc::from_static(&INNER)), 0);
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn std_conversions_clone_only_shared_values() {
+        use std::sync::atomic::AtomicUsize;
+
+        #[derive(Debug)]
+        struct Counted<'a>(u32, &'a AtomicUsize);
+
+        impl Clone for Counted<'_> {
+            fn clone(&self) -> Self {
+                self.1.fetch_add(1, Relaxed);
+                Counted(self.0, self.1)
+            }
+        }
+
+        let clones = AtomicUsize::new(0);
+        let moved = Arc::from_std_unique(StdArc::new(Counted(1, &clones))).unwrap();
+        assert_eq!(moved.0, 1);
+        let moved: Arc<Counted> = Arc::from(StdArc::new(Counted(2, &clones)));
+        assert_eq!(moved.0, 2);
+        assert_eq!(clones.load(Relaxed), 0);
+
+        // A shared `std::sync::Arc` is handed back unchanged, or cloned by `From`.
+        let std = StdArc::new(Counted(3, &clones));
+        let other = std.clone();
+        let std = Arc::from_std_unique(std).unwrap_err();
+        assert!(StdArc::ptr_eq(&std, &other));
+        assert_eq!(clones.load(Relaxed), 0);
+        let cloned: Arc<Counted> = Arc::from(std);
+        assert_eq!(cloned.0, 3);
+        assert_eq!(clones.load(Relaxed), 1);
+        assert_eq!(StdArc::strong_count(&other), 1);
+
+        // The same holds the other way around.
+        let back: StdArc<Counted> = StdArc::from(cloned);
+        assert_eq!(back.0, 3);
+        assert_eq!(clones.load(Relaxed), 1);
+        let shared = Arc::new(Counted(4, &clones));
+        let other = shared.clone();
+        let back: StdArc<Counted> = StdArc::from(shared);
+        assert_eq!(back.0, 4);
+        assert_eq!(clones.load(Relaxed), 2);
+        assert_eq!(Arc::reference_count(&other).get(), 1);
+    }
+
+    #[cfg(feature = "futures")]
+    #[test]
+    fn unique_streams_and_sinks_forward_to_their_value() {
+        use futures::channel::mpsc;
+        use futures::executor::block_on;
+        use futures::{stream, SinkExt, StreamExt};
+
+        let mut numbers = UniqueArc::new(stream::iter(1..=5));
+        assert_eq!(numbers.size_hint(), (5, Some(5)));
+        let first: Vec<i32> = block_on(numbers.by_ref().take(2).collect());
+        assert_eq!(first, [1, 2]);
+        let rest: Vec<i32> = block_on(numbers.collect());
+        assert_eq!(rest, [3, 4, 5]);
+
+        let (sender, receiver) = mpsc::unbounded();
+        let mut sender = UniqueArc::new(sender);
+        block_on(async {
+            sender.send("a").await.unwrap();
+            sender.send("b").await.unwrap();
+            sender.close().await.unwrap();
+        });
+        // The unique pointer can be frozen once it has been driven.
+        let sender: Arc<mpsc::UnboundedSender<&str>> = sender.into();
+        assert!(sender.is_closed());
+        let received: Vec<&str> = block_on(receiver.collect());
+        assert_eq!(received, ["a", "b"]);
+    }
+
+    #[cfg(feature = "tokio-io")]
+    #[test]
+    fn unique_tokio_streams_copy_bytes() {
+        use futures::executor::block_on;
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+        let (client, server) = tokio::io::duplex(4);
+        let mut client = UniqueArc::new(client);
+        let mut server = UniqueArc::new(server);
+        block_on(async {
+            // The buffer is smaller than the message, so the copy has to wait for the reader.
+            let (written, read) = futures::join!(
+                async {
+                    let written = tokio::io::copy(&mut &b"ping pong"[..], &mut client).await;
+                    client.shutdown().await.unwrap();
+                    written
+                },
+                async {
+                    let mut read = Vec::new();
+                    server.read_to_end(&mut read).await.map(|_| read)
+                },
+            );
+            assert_eq!(written.unwrap(), 9);
+            assert_eq!(read.unwrap(), b"ping pong");
+
+            server.write_all(b"back").await.unwrap();
+            server.shutdown().await.unwrap();
+            let mut copied = Vec::new();
+            assert_eq!(tokio::io::copy(&mut client, &mut copied).await.unwrap(), 4);
+            assert_eq!(copied, b"back");
+        });
+    }
+
+    #[cfg(feature = "futures-io")]
+    #[test]
+    fn unique_futures_io_streams_copy_bytes() {
+        use futures::executor::block_on;
+        use futures::io::{AsyncReadExt, AsyncWriteExt, Cursor};
+
+        let mut reader = UniqueArc::new(Cursor::new(b"bytes".to_vec()));
+        let mut writer = UniqueArc::new(Cursor::new(Vec::new()));
+        block_on(async {
+            assert_eq!(futures::io::copy(&mut reader, &mut writer).await.unwrap(), 5);
+            writer.close().await.unwrap();
+            let mut rest = Vec::new();
+            assert_eq!(reader.read_to_end(&mut rest).await.unwrap(), 0);
+        });
+        let writer: Arc<Cursor<Vec<u8>>> = writer.into();
+        assert_eq!(writer.get_ref(), b"bytes");
+    }
+
+    #[test]
+    fn formatting_into_strings() {
+        // Pads and truncates by characters, like `str` does.
+        struct Padded(&'static str);
+
+        impl fmt::Display for Padded {
+            fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                f.pad(self.0)
+            }
+        }
+
+        let s = Arc::from_display(&Padded("grüße ✓"));
+        assert_eq!(&*s, "grüße ✓");
+        assert_eq!(s.len(), "grüße ✓".len());
+        assert_eq!(&*format_arc!("[{:>9}]", Padded("日本")), "[       日本]");
+        assert_eq!(&*format_arc!("[{:^6.3}]", Padded("grüße")), "[ grü  ]");
+        assert_eq!(&*format_arc!("{}-{:03}", 'ß', 7), "ß-007");
+
+        assert_eq!(&*Arc::from_display(&Padded("")), "");
+        assert_eq!(&*format_arc!(""), "");
+        assert_eq!(&*format_arc!("{}", ""), "");
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn reading_into_slices() {
+        use crate::test_util::deallocations;
+        use std::io::{self, Cursor, Read};
+
+        let mut cursor = Cursor::new(b"header body".to_vec());
+        let header = Arc::<[u8]>::from_reader(&mut cursor, 6).unwrap();
+        assert_eq!(&*header, b"header");
+        let rest = Arc::<[u8]>::from_reader_to_end(&mut cursor).unwrap();
+        assert_eq!(&*rest, b" body");
+        assert!(Arc::<[u8]>::from_reader_to_end(&mut cursor).unwrap().is_empty());
+        assert!(Arc::<[u8]>::from_reader(&mut Cursor::new(b""), 0).unwrap().is_empty());
+
+        // A short read frees the allocation, and consumes what the reader had.
+        let mut short = Cursor::new(b"abc".to_vec());
+        let before = (allocations(), deallocations());
+        let err = Arc::<[u8]>::from_reader(&mut short, 4).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::UnexpectedEof);
+        assert_eq!((allocations(), deallocations()), (before.0 + 1, before.1 + 1));
+        assert_eq!(short.position(), 3);
+
+        // Interruptions are retried, other errors are passed on.
+        struct Flaky(u32);
+
+        impl Read for Flaky {
+            fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+                self.0 += 1;
+                match self.0 {
+                    1 => Err(io::ErrorKind::Interrupted.into()),
+                    2 => {
+                        buf[0] = b'x';
+                        Ok(1)
+                    }
+                    _ => Err(io::ErrorKind::BrokenPipe.into()),
+                }
+            }
+        }
+
+        assert_eq!(&*Arc::<[u8]>::from_reader(&mut Flaky(0), 1).unwrap(), b"x");
+        let err = Arc::<[u8]>::from_reader(&mut Flaky(0), 2).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::BrokenPipe);
+        let err = Arc::<[u8]>::from_reader_to_end(&mut Flaky(1)).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::BrokenPipe);
+    }
+
+    #[cfg(feature = "zerocopy")]
+    #[test]
+    fn bytes_reinterpret_as_items_in_place() {
+        let bytes: Arc<[u8]> = Arc::from(&[1, 0, 0, 0, 2, 0, 0, 0][..]);
+        let other = bytes.clone();
+        let words = Arc::into_arc_of::<u32>(bytes).unwrap();
+        assert_eq!(&*words, [u32::from_ne_bytes([1, 0, 0, 0]), u32::from_ne_bytes([2, 0, 0, 0])]);
+        // Both pointers share the allocation.
+        assert_eq!(words.as_ptr() as *const u8, other.as_ptr());
+        assert_eq!(Arc::reference_count(&other).get(), 2);
+        assert_eq!(Arc::as_bytes(&words), &*other);
+
+        let triples = Arc::into_arc_of::<[u8; 3]>(Arc::from(&b"abcdef"[..])).unwrap();
+        assert_eq!(&*triples, [*b"abc", *b"def"]);
+        assert!(Arc::into_arc_of::<u64>(Arc::from(&[][..])).unwrap().is_empty());
+
+        // Otherwise, the bytes are handed back.
+        let odd = Arc::into_arc_of::<u32>(Arc::from(&[7_u8; 7][..])).unwrap_err();
+        assert_eq!(&*odd, [7; 7]);
+        assert!(Arc::into_arc_of::<()>(Arc::from(&[][..])).is_err());
+        // Items that are aligned more strictly than the header would start at another offset.
+        let wide = Arc::into_arc_of::<u128>(Arc::from(&[0_u8; 16][..]));
+        assert_eq!(wide.is_ok(), mem::align_of::<u128>() <= mem::align_of::<ArcInner<()>>());
+    }
+
+    #[cfg(all(feature = "zerocopy", feature = "std", not(feature = "panic-free")))]
+    #[test]
+    fn over_aligned_bytes_reinterpret_as_items() {
+        let bytes: Arc<[u8]> = Arc::<[u8]>::new_zeroed_aligned(16, 64).into();
+        let words = Arc::into_arc_of::<u64>(bytes).unwrap();
+        assert_eq!(&*words, [0, 0]);
+        assert_eq!(words.as_ptr() as usize % 64, 0);
+    }
+
+    #[test]
+    fn make_mut_copies_shared_slices_and_strings() {
+        let mut slice: Arc<[u32]> = Arc::from(&[1, 2, 3][..]);
+        let sibling = slice.clone();
+        Arc::make_mut(&mut slice)[0] = 10;
+        assert_eq!(&*slice, [10, 2, 3]);
+        assert_eq!(&*sibling, [1, 2, 3]);
+        assert!(!Arc::ptr_eq(&slice, &sibling));
+        assert_eq!(Arc::reference_count(&sibling).get(), 1);
+
+        let mut s: Arc<str> = Arc::from("grüße");
+        let sibling = s.clone();
+        Arc::make_mut(&mut s).make_ascii_uppercase();
+        assert_eq!(&*s, "GRüßE");
+        assert_eq!(&*sibling, "grüße");
+        assert_eq!(Arc::reference_count(&sibling).get(), 1);
+    }
+
+    #[test]
+    fn make_mut_keeps_unique_slices_and_strings_in_place() {
+        let mut slice: Arc<[String]> = Arc::from(std::vec![String::from("a")]);
+        let address = slice.as_ptr();
+        Arc::make_mut(&mut slice)[0].push('b');
+        assert_eq!(slice.as_ptr(), address);
+        assert_eq!(&*slice, ["ab"]);
+
+        let mut s: Arc<str> = Arc::from("abc");
+        let address = s.as_ptr();
+        Arc::make_mut(&mut s).make_ascii_uppercase();
+        assert_eq!(s.as_ptr(), address);
+        assert_eq!(&*s, "ABC");
+    }
+
+    #[cfg(feature = "weak")]
+    #[test]
+    fn make_mut_copies_weakly_referenced_slices() {
+        let mut slice: Arc<[u8]> = Arc::from(&b"abc"[..]);
+        let weak = Arc::downgrade(&slice);
+        Arc::make_mut(&mut slice)[0] = b'x';
+        assert_eq!(&*slice, b"xbc");
+        // The old allocation lost its only `Arc`, so the `WeakArc` can not be upgraded anymore.
+        assert!(weak.upgrade().is_none());
+        assert_eq!(Arc::weak_count(&slice), 0);
+    }
+
+    /// Whether a task of `run_current_thread` has been woken since it was last polled.
+    #[cfg(all(feature = "async", not(feature = "panic-free")))]
+    struct Woken(std::sync::atomic::AtomicBool);
+
+    #[cfg(all(feature = "async", not(feature = "panic-free")))]
+    impl crate::Wake for Woken {
+        fn wake(this: Arc<Self>) {
+            this.0.store(true, Relaxed);
+        }
+    }
+
+    /// A task of `run_current_thread`, and how often it has been polled.
+    #[cfg(all(feature = "async", not(feature = "panic-free")))]
+    struct Spawned<'a> {
+        future: Pin<Box<dyn Future<Output = ()> + 'a>>,
+        woken: Arc<Woken>,
+        polls: usize,
+        done: bool,
+    }
+
+    /// Polls the tasks on the current thread whenever they have been woken, in the order in
+    /// which they are given, until all of them have completed. Returns how often each task has
+    /// been polled.
+    #[cfg(all(feature = "async", not(feature = "panic-free")))]
+    fn run_current_thread(tasks: Vec<Pin<Box<dyn Future<Output = ()> + '_>>>) -> Vec<usize> {
+        let mut tasks: Vec<_> = tasks
+            .into_iter()
+            .map(|future| {
+                let woken = Arc::new(Woken(std::sync::atomic::AtomicBool::new(true)));
+                Spawned { future, woken, polls: 0, done: false }
+            })
+            .collect();
+
+        while tasks.iter().any(|task| !task.done) {
+            let mut progress = false;
+            for task in tasks.iter_mut().filter(|task| !task.done) {
+                if task.woken.0.swap(false, Relaxed) {
+                    progress = true;
+                    task.polls += 1;
+                    let waker = crate::waker_from_arc(task.woken.clone());
+                    let mut cx = Context::from_waker(&waker);
+                    task.done = task.future.as_mut().poll(&mut cx).is_ready();
+                }
+            }
+            // Otherwise the remaining tasks are never woken.
+            assert!(progress);
+        }
+        tasks.iter().map(|task| task.polls).collect()
+    }
+
+    /// Returns `Poll::Pending` once, after waking its task.
+    #[cfg(all(feature = "async", not(feature = "panic-free")))]
+    struct YieldNow(bool);
+
+    #[cfg(all(feature = "async", not(feature = "panic-free")))]
+    impl Future for YieldNow {
+        type Output = ();
+
+        fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<()> {
+            if self.0 {
+                return Poll::Ready(());
+            }
+            self.0 = true;
+            cx.waker().wake_by_ref();
+            Poll::Pending
+        }
+    }
+
+    #[cfg(all(feature = "async", not(feature = "panic-free")))]
+    #[test]
+    fn into_inner_async_waits_for_the_other_tasks() {
+        use std::cell::RefCell;
+
+        let log = RefCell::new(Vec::new());
+        let value = Arc::new(String::from("shared"));
+        let clones = std::vec![value.clone(), value.clone()];
+
+        let mut tasks: Vec<Pin<Box<dyn Future<Output = ()> + '_>>> = Vec::new();
+        tasks.push(Box::pin(async {
+            let value = Arc::into_inner_async(value).await;
+            log.borrow_mut().push(value);
+        }));
+        for (i, clone) in clones.into_iter().enumerate() {
+            let log = &log;
+            tasks.push(Box::pin(async move {
+                // The clones are dropped in different rounds.
+                for _ in 0..=i {
+                    YieldNow(false).await;
+                }
+                log.borrow_mut().push(std::format!("dropped {}", clone));
+                drop(clone);
+            }));
+        }
+
+        // Only the drop of the second-to-last reference wakes the waiting task.
+        assert_eq!(run_current_thread(tasks), [2, 2, 3]);
+        assert_eq!(*log.borrow(), ["dropped shared", "dropped shared", "shared"]);
+    }
+
+    #[cfg(all(feature = "async", not(feature = "panic-free")))]
+    #[test]
+    fn into_inner_async_resolves_unique_arcs_right_away() {
+        let unique = Arc::new(7);
+        let mut got = None;
+        let polls = run_current_thread(std::vec![Box::pin(async {
+            got = Some(Arc::into_inner_async(unique).await);
+        })]);
+        assert_eq!(polls, [1]);
+        assert_eq!(got, Some(7));
+
+        // Without `WeakArc`s, zero-sized values share a static allocation, so they are not
+        // waited for.
+        #[cfg(not(feature = "weak"))]
+        {
+            let zst = Arc::new(());
+            let _other = zst.clone();
+            let polls = run_current_thread(std::vec![Box::pin(async {
+                Arc::into_inner_async(zst).await;
+            })]);
+            assert_eq!(polls, [1]);
+        }
+    }
+
+    #[cfg(all(feature = "async", not(feature = "panic-free")))]
+    #[test]
+    fn dropped_into_inner_async_futures_are_not_woken() {
+        let woken = Arc::new(Woken(std::sync::atomic::AtomicBool::new(false)));
+        let waker = crate::waker_from_arc(woken.clone());
+        let value = Arc::new(1);
+        let other = value.clone();
+
+        let mut future = Box::pin(Arc::into_inner_async(value));
+        assert!(future.as_mut().poll(&mut Context::from_waker(&waker)).is_pending());
+        // Removing the last other reference to `woken` from the table drops its count to one,
+        // which checks the table for waiters.
+        drop(waker);
+        drop(future);
+        assert!(!woken.0.load(Relaxed));
+
+        // The future has given up its reference, so the other one is unique now.
+        assert_eq!(Arc::try_unwrap(other), Ok(1));
+        assert!(!woken.0.load(Relaxed));
+    }
+}
@@ -0,0 +1,208 @@
+use base::borrow;
+use base::cmp::Ordering;
+use base::fmt;
+use base::hash::{Hash, Hasher};
+use base::ops::Deref;
+
+use crate::arc::Arc;
+
+/// A value that is either borrowed or shared through an `Arc`.
+///
+/// Unlike with `Cow`, the owned variant is an `Arc<T>`, so that `ArcCow` is cheap to clone in
+/// both variants, and so that converting it into an owned value allocates at most once. This
+/// makes it a suitable parameter type for functions that only sometimes retain their argument.
+///
+/// Comparisons and hashing consider only the value, regardless of the variant, and `ArcCow`s
+/// can be compared with plain values (`T` or `&T`) as well.
+pub enum ArcCow<'a, T: ?Sized> {
+    /// A borrowed value.
+    Borrowed(&'a T),
+    /// A value shared through an `Arc`.
+    Shared(Arc<T>),
+}
+
+impl<'a, T: ?Sized> ArcCow<'a, T> {
+    /// Returns `true` if this is the `Borrowed` variant.
+    pub fn is_borrowed(&self) -> bool {
+        matches!(self, ArcCow::Borrowed(_))
+    }
+
+    /// Returns `true` if this is the `Shared` variant.
+    pub fn is_shared(&self) -> bool {
+        !self.is_borrowed()
+    }
+
+    /// Converts into an `Arc`, allocating only if the value is borrowed.
+    pub fn into_shared(self) -> Arc<T>
+    where
+        Arc<T>: From<&'a T>,
+    {
+        match self {
+            ArcCow::Borrowed(value) => Arc::from(value),
+            ArcCow::Shared(arc) => arc,
+        }
+    }
+}
+
+impl<'a, T: ?Sized> Clone for ArcCow<'a, T> {
+    fn clone(&self) -> Self {
+        match self {
+            ArcCow::Borrowed(value) => ArcCow::Borrowed(value),
+            ArcCow::Shared(arc) => ArcCow::Shared(arc.clone()),
+        }
+    }
+}
+
+impl<'a, T: ?Sized> From<&'a T> for ArcCow<'a, T> {
+    fn from(value: &'a T) -> Self {
+        ArcCow::Borrowed(value)
+    }
+}
+
+impl<'a, T: ?Sized> From<Arc<T>> for ArcCow<'a, T> {
+    fn from(arc: Arc<T>) -> Self {
+        ArcCow::Shared(arc)
+    }
+}
+
+impl<'a, T: ?Sized> Deref for ArcCow<'a, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        match self {
+            ArcCow::Borrowed(value) => value,
+            ArcCow::Shared(arc) => arc,
+        }
+    }
+}
+
+impl<'a, T: ?Sized> borrow::Borrow<T> for ArcCow<'a, T> {
+    fn borrow(&self) -> &T {
+        self
+    }
+}
+
+impl<'a, T: ?Sized> AsRef<T> for ArcCow<'a, T> {
+    fn as_ref(&self) -> &T {
+        self
+    }
+}
+
+impl<'a, 'b, T: ?Sized + PartialEq> PartialEq<ArcCow<'b, T>> for ArcCow<'a, T> {
+    fn eq(&self, other: &ArcCow<'b, T>) -> bool {
+        **self == **other
+    }
+}
+
+impl<'a, T: ?Sized + PartialEq> PartialEq<T> for ArcCow<'a, T> {
+    fn eq(&self, other: &T) -> bool {
+        **self == *other
+    }
+}
+
+impl<'a, 'b, T: ?Sized + PartialEq> PartialEq<&'b T> for ArcCow<'a, T> {
+    fn eq(&self, other: &&'b T) -> bool {
+        **self == **other
+    }
+}
+
+impl<'a, T: ?Sized + Eq> Eq for ArcCow<'a, T> {}
+
+impl<'a, 'b, T: ?Sized + PartialOrd> PartialOrd<ArcCow<'b, T>> for ArcCow<'a, T> {
+    fn partial_cmp(&self, other: &ArcCow<'b, T>) -> Option<Ordering> {
+        (**self).partial_cmp(&**other)
+    }
+}
+
+impl<'a, T: ?Sized + Ord> Ord for ArcCow<'a, T> {
+    fn cmp(&self, other: &Self) -> Ordering {
+        (**self).cmp(&**other)
+    }
+}
+
+impl<'a, T: ?Sized + Hash> Hash for ArcCow<'a, T> {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        (**self).hash(state)
+    }
+}
+
+impl<'a, T: ?Sized + fmt::Display> fmt::Display for ArcCow<'a, T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Display::fmt(&**self, f)
+    }
+}
+
+impl<'a, T: ?Sized + fmt::Debug> fmt::Debug for ArcCow<'a, T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Debug::fmt(&**self, f)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+
+    use std::collections::hash_map::DefaultHasher;
+    use std::string::String;
+
+    use super::*;
+    use crate::test_util::allocations;
+    use crate::ReferenceCounted;
+    use smart_pointer::SmartPointer;
+
+    fn hash<T: ?Sized + Hash>(value: &T) -> u64 {
+        let mut hasher = DefaultHasher::new();
+        value.hash(&mut hasher);
+        hasher.finish()
+    }
+
+    #[test]
+    fn conversions_from_either_variant() {
+        let s = String::from("hello");
+        let borrowed = ArcCow::from(s.as_str());
+        assert!(borrowed.is_borrowed());
+        let p: Arc<str> = Arc::from("hello");
+        let shared = ArcCow::from(p.clone());
+        assert!(shared.is_shared());
+
+        // Cloning does not allocate in either variant.
+        let before = allocations();
+        let (borrowed, shared) = (borrowed.clone(), shared.clone());
+        assert_eq!(allocations(), before);
+        assert!(borrowed.is_borrowed());
+        assert_eq!(Arc::reference_count(&p).get(), 3);
+
+        // Only the borrowed variant allocates to become shared.
+        let from_shared = shared.into_shared();
+        assert!(Arc::ptr_eq(&from_shared, &p));
+        assert_eq!(allocations(), before);
+        let from_borrowed = borrowed.into_shared();
+        assert_eq!(allocations(), before + 1);
+        assert_eq!(&*from_borrowed, "hello");
+        assert!(!Arc::ptr_eq(&from_borrowed, &p));
+    }
+
+    #[test]
+    fn comparisons_ignore_the_variant() {
+        let shared: ArcCow<str> = ArcCow::from(Arc::from("b"));
+        let borrowed: ArcCow<str> = ArcCow::from("b");
+        assert_eq!(shared, borrowed);
+        assert_eq!(hash(&shared), hash(&borrowed));
+        assert_eq!(shared.cmp(&borrowed), Ordering::Equal);
+        let smaller: ArcCow<str> = ArcCow::from("a");
+        assert!(smaller < shared);
+        assert!(borrowed < ArcCow::<str>::Shared(Arc::from("c")));
+
+        // Either variant compares with plain values.
+        assert!(shared == "b");
+        assert!(borrowed == *"b");
+        assert!(shared != "c");
+        let number = ArcCow::Shared(Arc::new(3));
+        assert!(number == 3);
+        let three = &3;
+        assert!(ArcCow::Borrowed(&3) == three);
+        assert!(number != ArcCow::Borrowed(&4));
+
+        assert_eq!(std::format!("{} {:?}", borrowed, shared), "b \"b\"");
+    }
+}
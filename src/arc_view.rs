@@ -0,0 +1,3 @@
+use crate::arc::{Arc, UniqueArc};
+
+crate::view::shared_views!(Arc, UniqueArc, ArcStr, ArcSlice);
@@ -0,0 +1,363 @@
+use base::borrow;
+use base::boxed::Box;
+use base::cell::{Cell, UnsafeCell};
+use base::fmt;
+use base::mem::{self, MaybeUninit};
+use base::num::NonZeroUsize;
+use base::ops::Deref;
+use base::ptr::{self, NonNull};
+use base::vec::Vec;
+
+use crate::refcount_overflow;
+
+/// The number of slots that are allocated at once.
+const CHUNK_LEN: usize = 1024;
+
+/// Marks the end of the free list.
+const NO_SLOT: u32 = u32::MAX;
+
+/// A memory arena for values of type `T`, handing out reference-counted `ArenaRc` handles.
+///
+/// Slots are allocated in chunks of many values at a time, and a slot whose reference count
+/// drops to zero is put on a free list to be reused by the next call to `Arena::alloc`.
+///
+/// When the arena itself is dropped, all of its memory is freed at once, regardless of the
+/// reference counts. Since every handle borrows the arena, this only affects values whose
+/// handles have been leaked (e.g. via `mem::forget`); these values are dropped as well.
+pub struct Arena<T> {
+    chunks: UnsafeCell<Vec<NonNull<Slot<T>>>>,
+    // Head of the list of free slots, linked via `Slot::next_free`.
+    free: Cell<u32>,
+    // The number of slots that have been handed out at least once.
+    used: Cell<u32>,
+}
+
+struct Slot<T> {
+    strong: Cell<usize>,
+    next_free: Cell<u32>,
+    value: UnsafeCell<MaybeUninit<T>>,
+}
+
+unsafe impl<T: Send> Send for Arena<T> {}
+
+impl<T> Arena<T> {
+    /// Constructs a new, empty `Arena<T>`. Does not allocate until the first value is stored.
+    pub fn new() -> Self {
+        Arena {
+            chunks: UnsafeCell::new(Vec::new()),
+            free: Cell::new(NO_SLOT),
+            used: Cell::new(0),
+        }
+    }
+
+    /// Moves `value` into the arena, returning a handle with a reference count of one.
+    ///
+    /// Panics if the arena would need to hold more than `u32::MAX - 1` values at once.
+    pub fn alloc(&self, value: T) -> ArenaRc<'_, T> {
+        let index = match self.free.get() {
+            NO_SLOT => self.fresh_slot(),
+            index => {
+                self.free.set(self.slot(index).next_free.get());
+                index
+            }
+        };
+
+        let slot = self.slot(index);
+        unsafe { (*slot.value.get()).as_mut_ptr().write(value) };
+        slot.strong.set(1);
+        ArenaRc { arena: self, index }
+    }
+
+    /// Returns the index of a slot that has never been used before, allocating a new chunk if
+    /// necessary.
+    fn fresh_slot(&self) -> u32 {
+        let index = self.used.get();
+        if index == NO_SLOT {
+            panic!("arena is full");
+        }
+
+        if index as usize % CHUNK_LEN == 0 {
+            let chunk: Box<[Slot<T>]> = (0..CHUNK_LEN)
+                .map(|_| Slot {
+                    strong: Cell::new(0),
+                    next_free: Cell::new(NO_SLOT),
+                    value: UnsafeCell::new(MaybeUninit::uninit()),
+                })
+                .collect();
+            let chunk = Box::into_raw(chunk) as *mut Slot<T>;
+            // No references into the vector itself are held at this point, only references
+            // into the chunks, which do not move.
+            unsafe { (&mut *self.chunks.get()).push(NonNull::new_unchecked(chunk)) };
+        }
+
+        self.used.set(index + 1);
+        index
+    }
+
+    fn slot(&self, index: u32) -> &Slot<T> {
+        let index = index as usize;
+        unsafe {
+            let chunk = (&*self.chunks.get())[index / CHUNK_LEN];
+            &*chunk.as_ptr().add(index % CHUNK_LEN)
+        }
+    }
+}
+
+impl<T> Default for Arena<T> {
+    fn default() -> Self {
+        Arena::new()
+    }
+}
+
+impl<T> Drop for Arena<T> {
+    fn drop(&mut self) {
+        let used = self.used.get() as usize;
+        for (i, chunk) in self.chunks.get_mut().iter().enumerate() {
+            unsafe {
+                for j in 0..CHUNK_LEN.min(used - i * CHUNK_LEN) {
+                    let slot = &*chunk.as_ptr().add(j);
+                    if slot.strong.get() != 0 {
+                        ptr::drop_in_place((*slot.value.get()).as_mut_ptr());
+                    }
+                }
+
+                drop(Box::from_raw(ptr::slice_from_raw_parts_mut(chunk.as_ptr(), CHUNK_LEN)));
+            }
+        }
+    }
+}
+
+impl<T> fmt::Debug for Arena<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Arena").finish_non_exhaustive()
+    }
+}
+
+/// A reference-counted pointer to a value in an `Arena`.
+///
+/// A handle consists of a reference to the arena and the 32 bit index of its slot. Cloning
+/// increments the reference count of the slot, and once the last handle is dropped, the
+/// value is dropped and its slot is reused by later allocations.
+///
+/// `ArenaRc` does not implement `ReferenceCounted`, since that requires `SmartPointer` and
+/// `IntoMut`, and `SmartPointer::new` would have to allocate a value without access to an
+/// arena. The only way around this would be to leak a new arena (and its first chunk of
+/// slots) for every such value, which defeats the purpose of the arena. Generic code over
+/// `ReferenceCounted` can therefore not be used with `ArenaRc`. Instead, `ArenaRc` offers the
+/// functionality of the traits as associated functions of the same names:
+/// `ArenaRc::reference_count`, `ArenaRc::can_make_mut`, `ArenaRc::get_mut`,
+/// `ArenaRc::try_unwrap` and `ArenaRc::ptr_eq`.
+pub struct ArenaRc<'a, T> {
+    arena: &'a Arena<T>,
+    index: u32,
+}
+
+impl<'a, T> ArenaRc<'a, T> {
+    fn slot(&self) -> &'a Slot<T> {
+        self.arena.slot(self.index)
+    }
+
+    /// Gets the index of the slot this handle points to. Indices are unique among the values
+    /// that are alive in an arena at the same time.
+    pub fn index(this: &Self) -> u32 {
+        this.index
+    }
+
+    /// Gets the arena this handle points into.
+    pub fn arena(this: &Self) -> &'a Arena<T> {
+        this.arena
+    }
+
+    /// Gets the number of handles pointing to the same value.
+    pub fn reference_count(this: &Self) -> NonZeroUsize {
+        unsafe { NonZeroUsize::new_unchecked(this.slot().strong.get()) }
+    }
+
+    /// Returns `true` if no other handles point to the same value.
+    pub fn can_make_mut(this: &Self) -> bool {
+        this.slot().strong.get() == 1
+    }
+
+    /// Returns a mutable reference to the value if no other handles point to it.
+    pub fn get_mut(this: &mut Self) -> Option<&mut T> {
+        if ArenaRc::can_make_mut(this) {
+            Some(unsafe { &mut *(*this.slot().value.get()).as_mut_ptr() })
+        } else {
+            None
+        }
+    }
+
+    /// Returns the value if this is the only handle pointing to it. Returns the handle
+    /// unchanged otherwise.
+    pub fn try_unwrap(this: Self) -> Result<T, Self> {
+        if !ArenaRc::can_make_mut(&this) {
+            return Err(this);
+        }
+
+        let slot = this.slot();
+        let value = unsafe { (*slot.value.get()).as_ptr().read() };
+        slot.strong.set(0);
+        this.release();
+        Ok(value)
+    }
+
+    /// Returns `true` if the two handles point to the same value.
+    pub fn ptr_eq(this: &Self, other: &Self) -> bool {
+        ptr::eq(this.arena, other.arena) && this.index == other.index
+    }
+
+    /// Puts the (already emptied) slot onto the free list, without running `Drop`.
+    fn release(self) {
+        let arena = self.arena;
+        let index = self.index;
+        mem::forget(self);
+
+        arena.slot(index).next_free.set(arena.free.get());
+        arena.free.set(index);
+    }
+}
+
+impl<'a, T> Clone for ArenaRc<'a, T> {
+    fn clone(&self) -> Self {
+        let strong = self.slot().strong.get();
+        if strong == usize::MAX {
+            refcount_overflow();
+        }
+        self.slot().strong.set(strong + 1);
+        ArenaRc { arena: self.arena, index: self.index }
+    }
+}
+
+impl<'a, T> Drop for ArenaRc<'a, T> {
+    fn drop(&mut self) {
+        let slot = self.slot();
+        let strong = slot.strong.get() - 1;
+        slot.strong.set(strong);
+
+        if strong == 0 {
+            // Put the slot onto the free list only after dropping the value, so that the
+            // destructor can not reuse it while it is still being dropped.
+            unsafe { ptr::drop_in_place((*slot.value.get()).as_mut_ptr()) };
+            slot.next_free.set(self.arena.free.get());
+            self.arena.free.set(self.index);
+        }
+    }
+}
+
+impl<'a, T> Deref for ArenaRc<'a, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        unsafe { &*(*self.slot().value.get()).as_ptr() }
+    }
+}
+
+impl<'a, T> borrow::Borrow<T> for ArenaRc<'a, T> {
+    fn borrow(&self) -> &T {
+        self
+    }
+}
+
+impl<'a, T> AsRef<T> for ArenaRc<'a, T> {
+    fn as_ref(&self) -> &T {
+        self
+    }
+}
+
+impl<'a, T: fmt::Display> fmt::Display for ArenaRc<'a, T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Display::fmt(&**self, f)
+    }
+}
+
+impl<'a, T: fmt::Debug> fmt::Debug for ArenaRc<'a, T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Debug::fmt(&**self, f)
+    }
+}
+
+impl<'a, T> fmt::Pointer for ArenaRc<'a, T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Pointer::fmt(&(&**self as *const T), f)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+
+    use super::*;
+    use std::rc::Rc;
+
+    // Counts how often values of this type are dropped.
+    struct Tracked(Rc<Cell<usize>>);
+
+    impl Drop for Tracked {
+        fn drop(&mut self) {
+            self.0.set(self.0.get() + 1);
+        }
+    }
+
+    #[test]
+    fn clone_and_drop() {
+        let drops = Rc::new(Cell::new(0));
+        let arena = Arena::new();
+        let a = arena.alloc(Tracked(drops.clone()));
+        let b = a.clone();
+        assert_eq!(ArenaRc::reference_count(&a).get(), 2);
+        assert!(ArenaRc::ptr_eq(&a, &b));
+        assert!(!ArenaRc::can_make_mut(&a));
+
+        drop(a);
+        assert_eq!(drops.get(), 0);
+        assert!(ArenaRc::can_make_mut(&b));
+        drop(b);
+        assert_eq!(drops.get(), 1);
+    }
+
+    #[test]
+    fn slots_are_reused() {
+        let arena = Arena::new();
+        let a = arena.alloc(1);
+        let b = arena.alloc(2);
+        let index = ArenaRc::index(&a);
+        assert_ne!(index, ArenaRc::index(&b));
+
+        drop(a);
+        let c = arena.alloc(3);
+        assert_eq!(ArenaRc::index(&c), index);
+        assert_eq!((*b, *c), (2, 3));
+    }
+
+    #[test]
+    fn several_chunks() {
+        let arena = Arena::new();
+        let handles: Vec<_> = (0..3 * CHUNK_LEN).map(|i| arena.alloc(i)).collect();
+        assert!(handles.iter().enumerate().all(|(i, h)| **h == i));
+    }
+
+    #[test]
+    fn try_unwrap_and_get_mut() {
+        let arena = Arena::new();
+        let mut a = arena.alloc(1);
+        *ArenaRc::get_mut(&mut a).unwrap() += 1;
+
+        let b = a.clone();
+        assert!(ArenaRc::get_mut(&mut a).is_none());
+        let a = ArenaRc::try_unwrap(a).unwrap_err();
+        drop(b);
+        assert_eq!(ArenaRc::try_unwrap(a).ok(), Some(2));
+    }
+
+    #[test]
+    fn dropping_the_arena_drops_leaked_values() {
+        let drops = Rc::new(Cell::new(0));
+        let arena = Arena::new();
+        mem::forget(arena.alloc(Tracked(drops.clone())));
+        drop(arena.alloc(Tracked(drops.clone())));
+        assert_eq!(drops.get(), 1);
+
+        drop(arena);
+        assert_eq!(drops.get(), 2);
+    }
+}
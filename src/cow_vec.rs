@@ -0,0 +1,451 @@
+use base::alloc::Layout;
+use base::cmp::{self, Ordering};
+use base::fmt;
+use base::hash::{Hash, Hasher};
+use base::iter::FromIterator;
+use base::mem::{self, MaybeUninit};
+use base::ops::Deref;
+use base::ptr::{self, NonNull};
+use base::slice;
+
+use base::prelude::v1::*;
+
+use smart_pointer::IntoMut;
+
+use crate::arc::{Arc, ArcInner};
+
+/// A growable vector that is cheap to clone, and copies its contents only when a clone that
+/// shares them is mutated.
+///
+/// The elements live directly in a single reference-counted allocation, which may have room
+/// for more elements than are currently stored. Cloning a `CowVec` only increments the
+/// reference count, and all mutating methods first ensure that the allocation is not shared,
+/// copying the elements into a fresh allocation if necessary. Hence a clone is a snapshot that
+/// never observes later writes to the vector it was cloned from, or vice versa.
+pub struct CowVec<T> {
+    buf: Arc<CowBuf<T>>,
+}
+
+// The number of initialized elements is stored inside the shared allocation rather than in the
+// handles, so that whichever handle ends up dropping the allocation knows which elements to
+// drop. This is fine since all handles sharing an allocation agree on the length; any handle
+// that wants to change it has to become unique first.
+#[repr(C)]
+struct CowBuf<T> {
+    len: usize,
+    data: [MaybeUninit<T>],
+}
+
+impl<T> Drop for CowBuf<T> {
+    fn drop(&mut self) {
+        unsafe {
+            ptr::drop_in_place(ptr::slice_from_raw_parts_mut(
+                self.data.as_mut_ptr() as *mut T,
+                self.len,
+            ));
+        }
+    }
+}
+
+impl<T> CowBuf<T> {
+    fn capacity(&self) -> usize {
+        self.data.len()
+    }
+
+    fn as_ptr(&self) -> *const T {
+        self.data.as_ptr() as *const T
+    }
+
+    fn as_mut_ptr(&mut self) -> *mut T {
+        self.data.as_mut_ptr() as *mut T
+    }
+}
+
+/// Allocate an empty buffer with room for `cap` elements.
+fn allocate<T>(cap: usize) -> Arc<CowBuf<T>> {
+    let layout = Layout::new::<usize>()
+        .extend(Layout::array::<MaybeUninit<T>>(cap).unwrap())
+        .unwrap()
+        .0
+        .pad_to_align();
+
+    unsafe {
+        let inner = Arc::allocate_for_layout(layout, |mem| {
+            ptr::slice_from_raw_parts_mut(mem as *mut MaybeUninit<T>, cap)
+                as *mut ArcInner<CowBuf<T>>
+        });
+        ptr::addr_of_mut!((*inner).data.len).write(0);
+        Arc::from_inner(NonNull::new_unchecked(inner))
+    }
+}
+
+impl<T> CowVec<T> {
+    /// Constructs a new, empty `CowVec<T>`.
+    pub fn new() -> Self {
+        CowVec::with_capacity(0)
+    }
+
+    /// Constructs a new, empty `CowVec<T>` with room for at least `capacity` elements before
+    /// it needs to reallocate.
+    pub fn with_capacity(capacity: usize) -> Self {
+        CowVec { buf: allocate(capacity) }
+    }
+
+    /// Returns the number of elements in the vector.
+    pub fn len(&self) -> usize {
+        self.buf.len
+    }
+
+    /// Returns `true` if the vector contains no elements.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Returns the number of elements the vector can hold without reallocating, provided that
+    /// it is not shared.
+    pub fn capacity(&self) -> usize {
+        self.buf.capacity()
+    }
+
+    /// Extracts a slice containing the entire vector.
+    pub fn as_slice(&self) -> &[T] {
+        unsafe { slice::from_raw_parts(self.buf.as_ptr(), self.len()) }
+    }
+
+    /// Returns `true` if the two vectors share the same allocation, i.e., if one of them is an
+    /// unmodified clone of the other.
+    pub fn ptr_eq(this: &Self, other: &Self) -> bool {
+        let this_ptr = &*this.buf as *const CowBuf<T> as *const u8;
+        let other_ptr = &*other.buf as *const CowBuf<T> as *const u8;
+        ptr::eq(this_ptr, other_ptr)
+    }
+}
+
+impl<T: Clone> CowVec<T> {
+    /// Ensure that the buffer is not shared and has room for at least `min_capacity` elements,
+    /// then return mutable access to it.
+    fn make_unique(&mut self, min_capacity: usize) -> &mut CowBuf<T> {
+        let unique = Arc::can_make_mut(&self.buf);
+
+        if !unique || self.capacity() < min_capacity {
+            let capacity = if self.capacity() < min_capacity {
+                cmp::max(min_capacity, cmp::max(self.capacity() * 2, 4))
+            } else {
+                self.capacity()
+            };
+            let mut new_buf = allocate::<T>(capacity);
+
+            unsafe {
+                let new = IntoMut::get_mut_unchecked(&new_buf);
+
+                if unique {
+                    // Move the elements over, leaving the old buffer empty.
+                    let old = IntoMut::get_mut_unchecked(&self.buf);
+                    ptr::copy_nonoverlapping(old.as_ptr(), new.as_mut_ptr(), old.len);
+                    new.len = old.len;
+                    old.len = 0;
+                } else {
+                    // Clone the elements over. Should a clone panic, dropping `new_buf` drops
+                    // exactly those elements that have been written already.
+                    for item in self.as_slice() {
+                        ptr::write(new.as_mut_ptr().add(new.len), item.clone());
+                        new.len += 1;
+                    }
+                }
+            }
+
+            mem::swap(&mut self.buf, &mut new_buf);
+        }
+
+        unsafe { IntoMut::get_mut_unchecked(&self.buf) }
+    }
+
+    /// Appends an element to the back of the vector.
+    ///
+    /// Copies the elements into a new allocation if the vector is shared or out of capacity.
+    pub fn push(&mut self, value: T) {
+        let len = self.len();
+        let buf = self.make_unique(len + 1);
+        unsafe { ptr::write(buf.as_mut_ptr().add(len), value) };
+        buf.len += 1;
+    }
+
+    /// Removes the last element from the vector and returns it, or `None` if it is empty.
+    ///
+    /// Copies the elements into a new allocation if the vector is shared.
+    pub fn pop(&mut self) -> Option<T> {
+        if self.is_empty() {
+            return None;
+        }
+
+        let buf = self.make_unique(0);
+        buf.len -= 1;
+        Some(unsafe { ptr::read(buf.as_ptr().add(buf.len)) })
+    }
+
+    /// Shortens the vector, keeping the first `len` elements and dropping the rest. Has no
+    /// effect if `len` is greater than or equal to the current length.
+    ///
+    /// Copies the remaining elements into a new allocation if the vector is shared.
+    pub fn truncate(&mut self, len: usize) {
+        let old_len = self.len();
+        if len >= old_len {
+            return;
+        }
+
+        if !Arc::can_make_mut(&self.buf) {
+            // Only copy the elements that are kept.
+            let mut v = CowVec::with_capacity(self.capacity());
+            v.extend(self[..len].iter().cloned());
+            *self = v;
+            return;
+        }
+
+        let buf = self.make_unique(0);
+        unsafe {
+            let tail = ptr::slice_from_raw_parts_mut(buf.as_mut_ptr().add(len), old_len - len);
+            // Update the length first, so that a panicking destructor leaves the vector in a
+            // consistent state.
+            buf.len = len;
+            ptr::drop_in_place(tail);
+        }
+    }
+
+    /// Removes all elements from the vector.
+    ///
+    /// If the vector is shared, this releases the shared allocation instead of copying it.
+    pub fn clear(&mut self) {
+        if Arc::can_make_mut(&self.buf) {
+            self.truncate(0);
+        } else {
+            *self = CowVec::with_capacity(self.capacity());
+        }
+    }
+
+    /// Extracts a mutable slice of the entire vector.
+    ///
+    /// Copies the elements into a new allocation if the vector is shared.
+    pub fn as_mut_slice(&mut self) -> &mut [T] {
+        let buf = self.make_unique(0);
+        unsafe { slice::from_raw_parts_mut(buf.as_mut_ptr(), buf.len) }
+    }
+}
+
+impl<T> Clone for CowVec<T> {
+    /// Makes a clone of the `CowVec` that shares the allocation with the original, increasing
+    /// its reference count.
+    fn clone(&self) -> Self {
+        CowVec { buf: self.buf.clone() }
+    }
+}
+
+impl<T> Deref for CowVec<T> {
+    type Target = [T];
+
+    fn deref(&self) -> &[T] {
+        self.as_slice()
+    }
+}
+
+impl<T> AsRef<[T]> for CowVec<T> {
+    fn as_ref(&self) -> &[T] {
+        self.as_slice()
+    }
+}
+
+impl<T> Default for CowVec<T> {
+    fn default() -> Self {
+        CowVec::new()
+    }
+}
+
+impl<T> From<Vec<T>> for CowVec<T> {
+    fn from(mut v: Vec<T>) -> Self {
+        let buf = allocate::<T>(v.len());
+        unsafe {
+            let new = IntoMut::get_mut_unchecked(&buf);
+            ptr::copy_nonoverlapping(v.as_ptr(), new.as_mut_ptr(), v.len());
+            new.len = v.len();
+
+            // Allow the vector to free its memory, but not destroy its contents.
+            v.set_len(0);
+        }
+        CowVec { buf }
+    }
+}
+
+impl<T: Clone> Extend<T> for CowVec<T> {
+    fn extend<I: IntoIterator<Item = T>>(&mut self, iter: I) {
+        for item in iter {
+            self.push(item);
+        }
+    }
+}
+
+impl<T: Clone> FromIterator<T> for CowVec<T> {
+    fn from_iter<I: IntoIterator<Item = T>>(iter: I) -> Self {
+        let mut v = CowVec::new();
+        v.extend(iter);
+        v
+    }
+}
+
+impl<T: fmt::Debug> fmt::Debug for CowVec<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Debug::fmt(self.as_slice(), f)
+    }
+}
+
+impl<T: PartialEq> PartialEq for CowVec<T> {
+    fn eq(&self, other: &CowVec<T>) -> bool {
+        self.as_slice() == other.as_slice()
+    }
+}
+
+impl<T: Eq> Eq for CowVec<T> {}
+
+impl<T: PartialOrd> PartialOrd for CowVec<T> {
+    fn partial_cmp(&self, other: &CowVec<T>) -> Option<Ordering> {
+        self.as_slice().partial_cmp(other.as_slice())
+    }
+}
+
+impl<T: Ord> Ord for CowVec<T> {
+    fn cmp(&self, other: &CowVec<T>) -> Ordering {
+        self.as_slice().cmp(other.as_slice())
+    }
+}
+
+impl<T: Hash> Hash for CowVec<T> {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.as_slice().hash(state)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+
+    use std::cell::Cell;
+    use std::panic::{self, AssertUnwindSafe};
+
+    use super::*;
+
+    #[test]
+    fn snapshots_never_observe_later_writes() {
+        let mut v = CowVec::new();
+        let mut snapshots = Vec::new();
+        for i in 0..20 {
+            snapshots.push(v.clone());
+            v.push(i);
+        }
+        for (i, snapshot) in snapshots.iter().enumerate() {
+            assert_eq!(snapshot.as_slice(), &(0..i).collect::<Vec<_>>()[..]);
+        }
+
+        let before_pop = v.clone();
+        assert_eq!(v.pop(), Some(19));
+        let before_truncate = v.clone();
+        v.truncate(5);
+        let before_write = v.clone();
+        v.as_mut_slice()[0] = 100;
+        let before_clear = v.clone();
+        v.clear();
+
+        assert!(v.is_empty());
+        assert_eq!(before_pop.len(), 20);
+        assert_eq!(before_truncate.len(), 19);
+        assert_eq!(before_write.as_slice(), [0, 1, 2, 3, 4]);
+        assert_eq!(before_clear.as_slice(), [100, 1, 2, 3, 4]);
+        assert_eq!(snapshots[19].len(), 19);
+    }
+
+    #[test]
+    fn unique_vectors_mutate_in_place() {
+        let mut v = CowVec::with_capacity(8);
+        v.extend(0..8);
+        let ptr = v.as_ptr();
+
+        assert_eq!(v.pop(), Some(7));
+        v.truncate(4);
+        v.as_mut_slice()[0] = 10;
+        assert_eq!(v.as_slice(), [10, 1, 2, 3]);
+        assert_eq!(v.as_ptr(), ptr);
+
+        v.clear();
+        v.push(5);
+        assert_eq!(v.as_ptr(), ptr);
+        assert_eq!(v.capacity(), 8);
+
+        // Dropping the last clone makes the vector unique again.
+        let clone = v.clone();
+        drop(clone);
+        v.push(6);
+        assert_eq!(v.as_ptr(), ptr);
+    }
+
+    #[test]
+    fn full_vectors_grow() {
+        let mut v = CowVec::new();
+        assert_eq!(v.capacity(), 0);
+        v.push(0);
+        assert_eq!(v.capacity(), 4);
+        v.extend(1..5);
+        assert_eq!(v.capacity(), 8);
+        v.extend(5..8);
+        assert_eq!(v.as_slice(), &(0..8).collect::<Vec<_>>()[..]);
+
+        // Copying a shared vector keeps its capacity unless it is full.
+        let snapshot = v.clone();
+        v.push(8);
+        assert_eq!(v.capacity(), 16);
+        assert_eq!(snapshot.capacity(), 8);
+        let snapshot = v.clone();
+        v.pop();
+        assert_eq!(v.capacity(), 16);
+        assert_eq!(snapshot.len(), 9);
+    }
+
+    struct Tracked<'a> {
+        clones: &'a Cell<usize>,
+        drops: &'a Cell<usize>,
+        clone_limit: usize,
+    }
+
+    impl Clone for Tracked<'_> {
+        fn clone(&self) -> Self {
+            if self.clones.get() == self.clone_limit {
+                std::panic!("clone failed");
+            }
+            self.clones.set(self.clones.get() + 1);
+            Tracked { ..*self }
+        }
+    }
+
+    impl Drop for Tracked<'_> {
+        fn drop(&mut self) {
+            self.drops.set(self.drops.get() + 1);
+        }
+    }
+
+    #[test]
+    fn panicking_clones_drop_the_partial_copy() {
+        let clones = Cell::new(0);
+        let drops = Cell::new(0);
+        let tracked = || Tracked { clones: &clones, drops: &drops, clone_limit: 3 };
+
+        let mut v: CowVec<_> = (0..5).map(|_| tracked()).collect();
+        let snapshot = v.clone();
+        let result = panic::catch_unwind(AssertUnwindSafe(|| v.push(tracked())));
+        assert!(result.is_err());
+
+        // The three clones and the pushed element have been dropped, the originals are intact.
+        assert_eq!(clones.get(), 3);
+        assert_eq!(drops.get(), 4);
+        assert!(CowVec::ptr_eq(&v, &snapshot));
+        assert_eq!(v.len(), 5);
+
+        drop((v, snapshot));
+        assert_eq!(drops.get(), 9);
+    }
+}
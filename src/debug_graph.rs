@@ -0,0 +1,171 @@
+use base::cell::RefCell;
+use base::collections::BTreeMap;
+use base::fmt;
+use base::ops::Deref;
+
+use base::prelude::v1::*;
+
+/// A value that holds reference-counted pointers of type `P` to further values, which makes it
+/// a node in a graph of shared values.
+pub trait Children<P> {
+    /// Calls `visit` with each pointer to a child of this value, in a fixed order.
+    fn children(&self, visit: &mut dyn FnMut(&P));
+}
+
+/// A node of a graph that can be formatted by `DebugGraph`.
+pub trait DebugNode<P>: Children<P> {
+    /// Formats the value itself, without its children.
+    fn fmt_node(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result;
+}
+
+/// Formats the graph reachable from a reference-counted pointer without recursing forever on
+/// cycles, and without repeating values that are reachable on several paths.
+///
+/// Values are numbered in the order in which they are first reached, depth-first. The first
+/// time a value is reached, it is formatted as `#i`, followed by `DebugNode::fmt_node` and
+/// the list of its children if it has any. Every further time, only a back-reference `<#i>` is
+/// formatted. Allocations are told apart by their address.
+///
+/// The children of a value are cloned while it is formatted, so that `Children::children` can
+/// hand out pointers from behind a `RefCell`, as cyclic graphs usually require.
+///
+/// ```
+/// # #[cfg(feature = "arc")] {
+/// use std::fmt;
+/// use reference_counted::{Arc, Children, DebugGraph, DebugNode};
+/// use smart_pointer::SmartPointer;
+///
+/// struct Node {
+///     name: &'static str,
+///     children: Vec<Arc<Node>>,
+/// }
+///
+/// impl Children<Arc<Node>> for Node {
+///     fn children(&self, visit: &mut dyn FnMut(&Arc<Node>)) {
+///         self.children.iter().for_each(visit);
+///     }
+/// }
+///
+/// impl DebugNode<Arc<Node>> for Node {
+///     fn fmt_node(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+///         f.write_str(self.name)
+///     }
+/// }
+///
+/// let d = Arc::new(Node { name: "d", children: vec![] });
+/// let b = Arc::new(Node { name: "b", children: vec![d.clone()] });
+/// let c = Arc::new(Node { name: "c", children: vec![d] });
+/// let a = Arc::new(Node { name: "a", children: vec![b, c] });
+/// assert_eq!(format!("{:?}", DebugGraph::new(&a)), "#0 a [#1 b [#2 d], #3 c [<#2>]]");
+/// # }
+/// ```
+pub struct DebugGraph<'a, P> {
+    root: &'a P,
+}
+
+impl<'a, P> DebugGraph<'a, P> {
+    /// Creates a wrapper that formats the graph reachable from `root`.
+    pub fn new(root: &'a P) -> Self {
+        DebugGraph { root }
+    }
+}
+
+impl<'a, P> fmt::Debug for DebugGraph<'a, P>
+where
+    P: Deref + Clone,
+    P::Target: DebugNode<P>,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let visited = RefCell::new(BTreeMap::new());
+        fmt::Debug::fmt(&Entry { ptr: self.root, visited: &visited }, f)
+    }
+}
+
+// A pointer in the graph, together with the indices of the allocations reached so far.
+struct Entry<'a, 'v, P> {
+    ptr: &'a P,
+    visited: &'v RefCell<BTreeMap<usize, usize>>,
+}
+
+impl<'a, 'v, P> fmt::Debug for Entry<'a, 'v, P>
+where
+    P: Deref + Clone,
+    P::Target: DebugNode<P>,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let index = {
+            let mut visited = self.visited.borrow_mut();
+            let count = visited.len();
+            let address = &**self.ptr as *const P::Target as *const u8 as usize;
+            let index = *visited.entry(address).or_insert(count);
+            if index < count {
+                return write!(f, "<#{}>", index);
+            }
+            index
+        };
+
+        let value: &P::Target = self.ptr;
+        write!(f, "#{} ", index)?;
+        value.fmt_node(f)?;
+
+        let mut children = Vec::new();
+        value.children(&mut |child| children.push(child.clone()));
+        if children.is_empty() {
+            return Ok(());
+        }
+        f.write_str(" ")?;
+        f.debug_list()
+            .entries(children.iter().map(|ptr| Entry { ptr, visited: self.visited }))
+            .finish()
+    }
+}
+
+#[cfg(all(test, feature = "rc"))]
+mod tests {
+    extern crate std;
+
+    use std::format;
+    use std::vec;
+
+    use super::*;
+    use crate::rc::Rc;
+    use smart_pointer::SmartPointer;
+
+    struct Node {
+        name: &'static str,
+        children: RefCell<Vec<Rc<Node>>>,
+    }
+
+    impl Children<Rc<Node>> for Node {
+        fn children(&self, visit: &mut dyn FnMut(&Rc<Node>)) {
+            self.children.borrow().iter().for_each(visit);
+        }
+    }
+
+    impl DebugNode<Rc<Node>> for Node {
+        fn fmt_node(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            f.write_str(self.name)
+        }
+    }
+
+    fn node(name: &'static str) -> Rc<Node> {
+        Rc::new(Node { name, children: RefCell::new(vec![]) })
+    }
+
+    #[test]
+    fn cycles_are_formatted_once() {
+        let a = node("a");
+        let b = node("b");
+        a.children.borrow_mut().push(b.clone());
+        b.children.borrow_mut().push(a.clone());
+        assert_eq!(format!("{:?}", DebugGraph::new(&a)), "#0 a [#1 b [<#0>]]");
+        assert_eq!(format!("{:?}", DebugGraph::new(&b)), "#0 b [#1 a [<#0>]]");
+
+        // A node that is its own child.
+        a.children.borrow_mut().push(a.clone());
+        assert_eq!(format!("{:?}", DebugGraph::new(&a)), "#0 a [#1 b [<#0>], <#0>]");
+
+        // Break the cycles so that the nodes are dropped.
+        a.children.borrow_mut().clear();
+    }
+}
@@ -0,0 +1,247 @@
+use base::fmt;
+use base::mem;
+use base::sync::{Condvar, Mutex, MutexGuard, OnceLock};
+use base::thread::{self, JoinHandle};
+
+use base::prelude::v1::*;
+
+use smart_pointer::SmartPointer;
+
+use crate::arc::Arc;
+
+/// A queue of `Arc`s whose release is deferred, so that dropping the last reference to a large
+/// value does not have to happen on a latency-sensitive path.
+///
+/// `defer` only moves the handle into the queue. The handles are released by `drain`, at a point
+/// of the caller's choosing, or continuously by a background thread started with
+/// `spawn_worker`. Values whose destructors defer further values to the same queue are fine:
+/// these are released in the same `drain` call (or by the worker).
+///
+/// Within a drain, handles are released in the order in which they were deferred. Dropping the
+/// queue releases all handles that are still queued, on the background thread if there is one
+/// (which the drop waits for), and on the current thread otherwise.
+pub struct DropQueue {
+    shared: Arc<Shared>,
+    worker: Option<JoinHandle<()>>,
+}
+
+struct Shared {
+    state: Mutex<State>,
+    queued: Condvar,
+}
+
+struct State {
+    handles: Vec<Box<dyn Send>>,
+    closed: bool,
+}
+
+impl Shared {
+    fn state(&self) -> MutexGuard<'_, State> {
+        // The state is consistent even if a destructor panicked while it was locked.
+        self.state.lock().unwrap_or_else(|err| err.into_inner())
+    }
+
+    /// Releases queued handles until there are none left.
+    fn drain(&self) {
+        loop {
+            // Take the handles out first, so that their destructors can defer further handles.
+            let handles = mem::take(&mut self.state().handles);
+            if handles.is_empty() {
+                return;
+            }
+            drop(handles);
+        }
+    }
+}
+
+impl DropQueue {
+    /// Creates an empty queue without a background thread.
+    pub fn new() -> Self {
+        let state = State { handles: Vec::new(), closed: false };
+        DropQueue {
+            shared: Arc::new(Shared { state: Mutex::new(state), queued: Condvar::new() }),
+            worker: None,
+        }
+    }
+
+    /// Creates an empty queue whose handles are released by a new background thread.
+    pub fn spawn_worker() -> Self {
+        let mut queue = DropQueue::new();
+        let shared = queue.shared.clone();
+        queue.worker = Some(thread::spawn(move || {
+            let mut state = shared.state();
+            loop {
+                if !state.handles.is_empty() {
+                    let handles = mem::take(&mut state.handles);
+                    drop(state);
+                    drop(handles);
+                    state = shared.state();
+                } else if state.closed {
+                    return;
+                } else {
+                    state = shared.queued.wait(state).unwrap_or_else(|err| err.into_inner());
+                }
+            }
+        }));
+        queue
+    }
+
+    /// Moves `arc` into the queue, so that it is released later rather than now.
+    pub fn defer<T: ?Sized + Send + Sync + 'static>(&self, arc: Arc<T>) {
+        self.push(Box::new(arc));
+    }
+
+    pub(crate) fn push(&self, handle: Box<dyn Send>) {
+        self.shared.state().handles.push(handle);
+        if self.worker.is_some() {
+            self.shared.queued.notify_one();
+        }
+    }
+
+    /// Releases all queued handles on the current thread, including those deferred by the
+    /// destructors of the released values.
+    pub fn drain(&self) {
+        self.shared.drain();
+    }
+
+    /// Gets the number of handles in the queue.
+    pub fn len(&self) -> usize {
+        self.shared.state().handles.len()
+    }
+
+    /// Returns `true` if there are no handles in the queue.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+/// Gets the queue of `Arc::drop_in_background`, spawning its worker on first use.
+pub(crate) fn background() -> &'static DropQueue {
+    static BACKGROUND: OnceLock<DropQueue> = OnceLock::new();
+    BACKGROUND.get_or_init(DropQueue::spawn_worker)
+}
+
+impl Default for DropQueue {
+    fn default() -> Self {
+        DropQueue::new()
+    }
+}
+
+impl Drop for DropQueue {
+    fn drop(&mut self) {
+        match self.worker.take() {
+            Some(worker) => {
+                self.shared.state().closed = true;
+                self.shared.queued.notify_one();
+                // A panic of the worker has already been reported on its thread.
+                let _ = worker.join();
+            }
+            None => self.shared.drain(),
+        }
+    }
+}
+
+impl fmt::Debug for DropQueue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("DropQueue")
+            .field("len", &self.len())
+            .field("worker", &self.worker.is_some())
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+
+    use super::*;
+
+    type Log = Arc<Mutex<Vec<u32>>>;
+
+    /// Records its id in the log when dropped, after deferring `then` to the given queue.
+    struct Noisy {
+        id: u32,
+        log: Log,
+        then: Option<(Arc<DropQueue>, Arc<Noisy>)>,
+    }
+
+    impl Noisy {
+        fn new(id: u32, log: &Log) -> Arc<Noisy> {
+            Arc::new(Noisy { id, log: log.clone(), then: None })
+        }
+    }
+
+    impl Drop for Noisy {
+        fn drop(&mut self) {
+            if let Some((queue, next)) = self.then.take() {
+                queue.defer(next);
+            }
+            self.log.lock().unwrap().push(self.id);
+        }
+    }
+
+    fn log() -> Log {
+        Arc::new(Mutex::new(Vec::new()))
+    }
+
+    #[test]
+    fn values_are_released_in_order_when_drained() {
+        let log = log();
+        let queue = DropQueue::new();
+        for id in 0..3 {
+            queue.defer(Noisy::new(id, &log));
+        }
+        assert_eq!(queue.len(), 3);
+        assert!(log.lock().unwrap().is_empty());
+
+        queue.drain();
+        assert!(queue.is_empty());
+        assert_eq!(*log.lock().unwrap(), [0, 1, 2]);
+    }
+
+    #[test]
+    fn values_deferred_while_draining_are_drained_too() {
+        let log = log();
+        let queue = Arc::new(DropQueue::new());
+        let last = Noisy::new(2, &log);
+        let first = Arc::new(Noisy { id: 1, log: log.clone(), then: Some((queue.clone(), last)) });
+        queue.defer(first);
+        queue.defer(Noisy::new(0, &log));
+
+        queue.drain();
+        assert!(queue.is_empty());
+        assert_eq!(*log.lock().unwrap(), [1, 0, 2]);
+    }
+
+    #[test]
+    fn dropping_the_queue_releases_the_values() {
+        let log = log();
+        let queue = DropQueue::new();
+        queue.defer(Noisy::new(0, &log));
+        drop(queue);
+        assert_eq!(*log.lock().unwrap(), [0]);
+    }
+
+    #[test]
+    fn the_worker_drains_when_the_queue_is_dropped() {
+        let log = log();
+        let queue = DropQueue::spawn_worker();
+        for id in 0..100 {
+            queue.defer(Noisy::new(id, &log));
+        }
+        drop(queue);
+        assert_eq!(*log.lock().unwrap(), (0..100).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn kept_values_are_not_released() {
+        let log = log();
+        let kept = Noisy::new(0, &log);
+        let queue = DropQueue::new();
+        queue.defer(kept.clone());
+        queue.drain();
+        assert!(log.lock().unwrap().is_empty());
+        drop(kept);
+        assert_eq!(*log.lock().unwrap(), [0]);
+    }
+}
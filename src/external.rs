@@ -0,0 +1,411 @@
+use base::borrow::{self, BorrowMut};
+use base::fmt;
+use base::marker::PhantomData;
+use base::mem::{self, offset_of};
+use base::num::NonZeroUsize;
+use base::ops::{Deref, DerefMut};
+use base::ptr::{self, NonNull};
+use base::sync::atomic::{self, AtomicUsize, Ordering::{Acquire, Relaxed, Release}};
+use core::ffi::c_void;
+
+use base::prelude::v1::*;
+
+use smart_pointer::{IntoMut, SmartPointer, SmartPointerMut};
+
+use crate::{refcount_overflow, ReferenceCounted};
+
+/// The functions through which an `ExternalArc` manipulates the reference count of a foreign
+/// object, e.g. the retain and release functions of a C library, or `AddRef` and `Release` of a
+/// COM object.
+///
+/// All functions are called with the pointer the `ExternalArc` was created from.
+#[derive(Clone, Copy, Debug)]
+pub struct ExternalVtable {
+    /// Acquires an additional reference to the object.
+    pub retain: unsafe extern "C" fn(*const c_void),
+    /// Releases a reference to the object, which may destroy it.
+    pub release: unsafe extern "C" fn(*const c_void),
+    /// Returns the current number of references to the object, if the foreign side can tell.
+    pub count: Option<unsafe extern "C" fn(*const c_void) -> usize>,
+}
+
+/// A reference-counted pointer to an object whose count is managed outside of Rust, through the
+/// functions of an `ExternalVtable`.
+///
+/// Cloning calls `retain`, and dropping calls `release`. `ReferenceCounted::reference_count`
+/// uses the `count` function if there is one, and returns one otherwise (a lower bound of the
+/// true count).
+///
+/// Since Rust can not know whether foreign code holds further references to the object, an
+/// `ExternalArc` never hands out mutable access: `IntoMut::can_make_mut` always returns
+/// `false`, regardless of the reference count. Consequently, `try_unwrap` always fails too.
+///
+/// `SmartPointer::new` moves the value into an allocation with a reference count managed on
+/// the Rust side, so that generic code can create `ExternalArc`s as well.
+///
+/// Since nothing is known about the thread-safety of the foreign functions, an `ExternalArc` is
+/// neither `Send` nor `Sync`.
+pub struct ExternalArc<T> {
+    ptr: NonNull<T>,
+    vtable: &'static ExternalVtable,
+    phantom: PhantomData<T>,
+}
+
+impl<T> ExternalArc<T> {
+    /// Creates an `ExternalArc` that takes over one reference to the object at `ptr`.
+    ///
+    /// # Safety
+    ///
+    /// `ptr` must point to a valid `T` which stays valid and is not mutated for as long as
+    /// references acquired through `vtable` are held, and the caller must own one such
+    /// reference, which is released when the `ExternalArc` is dropped.
+    pub unsafe fn from_raw(ptr: NonNull<T>, vtable: &'static ExternalVtable) -> Self {
+        ExternalArc { ptr, vtable, phantom: PhantomData }
+    }
+
+    /// Consumes the `ExternalArc` without releasing its reference, and returns the pointer to
+    /// the object.
+    pub fn into_raw(this: Self) -> NonNull<T> {
+        let ptr = this.ptr;
+        mem::forget(this);
+        ptr
+    }
+
+    /// Gets the pointer to the object.
+    pub fn as_ptr(this: &Self) -> *const T {
+        this.ptr.as_ptr()
+    }
+
+    /// Gets the functions that manage the reference count of the object.
+    pub fn vtable(this: &Self) -> &'static ExternalVtable {
+        this.vtable
+    }
+
+    fn raw(&self) -> *const c_void {
+        self.ptr.as_ptr() as *const c_void
+    }
+}
+
+impl<T> Clone for ExternalArc<T> {
+    fn clone(&self) -> Self {
+        unsafe { (self.vtable.retain)(self.raw()) };
+        ExternalArc { ptr: self.ptr, vtable: self.vtable, phantom: PhantomData }
+    }
+}
+
+impl<T> Drop for ExternalArc<T> {
+    fn drop(&mut self) {
+        unsafe { (self.vtable.release)(self.raw()) };
+    }
+}
+
+impl<T> Deref for ExternalArc<T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        unsafe { self.ptr.as_ref() }
+    }
+}
+
+impl<T> borrow::Borrow<T> for ExternalArc<T> {
+    fn borrow(&self) -> &T {
+        self
+    }
+}
+
+impl<T> AsRef<T> for ExternalArc<T> {
+    fn as_ref(&self) -> &T {
+        self
+    }
+}
+
+impl<T: fmt::Debug> fmt::Debug for ExternalArc<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Debug::fmt(&**self, f)
+    }
+}
+
+impl<T> fmt::Pointer for ExternalArc<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Pointer::fmt(&self.ptr, f)
+    }
+}
+
+impl<T> SmartPointer<T> for ExternalArc<T> {
+    fn new(data: T) -> Self {
+        UniqueExternalArc::new(data).0
+    }
+
+    /// Always fails, see `IntoMut::can_make_mut`.
+    fn try_unwrap(this: Self) -> Result<T, Self> {
+        Err(this)
+    }
+}
+
+impl<T> IntoMut<T> for ExternalArc<T> {
+    type MutablePointer = UniqueExternalArc<T>;
+
+    fn can_make_mut(_this: &Self) -> bool {
+        false
+    }
+
+    /// Since `can_make_mut` never returns `true`, calling this is never safe.
+    unsafe fn into_mut_unchecked(_this: Self) -> Self::MutablePointer {
+        unreachable!("an ExternalArc is never known to be unique")
+    }
+
+    /// Since `can_make_mut` never returns `true`, calling this is never safe.
+    unsafe fn get_mut_unchecked(_this: &Self) -> &mut T {
+        unreachable!("an ExternalArc is never known to be unique")
+    }
+}
+
+impl<T> ReferenceCounted<T> for ExternalArc<T> {
+    /// Calls the `count` function of the vtable, or returns one if there is none. A count of
+    /// zero reported by the foreign side is also turned into one.
+    fn reference_count(this: &Self) -> NonZeroUsize {
+        let count = this.vtable.count.map_or(1, |count| unsafe { count(this.raw()) });
+        NonZeroUsize::new(count).unwrap_or(NonZeroUsize::MIN)
+    }
+}
+
+/// The `IntoMut::MutablePointer` of `ExternalArc`, a unique pointer to a value in an allocation
+/// managed on the Rust side.
+///
+/// It can only be created by `SmartPointer::new`, since foreign objects are never known to be
+/// unique.
+pub struct UniqueExternalArc<T>(ExternalArc<T>);
+
+impl<T> From<UniqueExternalArc<T>> for ExternalArc<T> {
+    fn from(unique: UniqueExternalArc<T>) -> Self {
+        unique.0
+    }
+}
+
+impl<T> Deref for UniqueExternalArc<T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.0
+    }
+}
+
+impl<T> DerefMut for UniqueExternalArc<T> {
+    fn deref_mut(&mut self) -> &mut T {
+        // This is the only reference to the allocation.
+        unsafe { self.0.ptr.as_mut() }
+    }
+}
+
+impl<T> borrow::Borrow<T> for UniqueExternalArc<T> {
+    fn borrow(&self) -> &T {
+        self
+    }
+}
+
+impl<T> BorrowMut<T> for UniqueExternalArc<T> {
+    fn borrow_mut(&mut self) -> &mut T {
+        self
+    }
+}
+
+impl<T> AsRef<T> for UniqueExternalArc<T> {
+    fn as_ref(&self) -> &T {
+        self
+    }
+}
+
+impl<T> AsMut<T> for UniqueExternalArc<T> {
+    fn as_mut(&mut self) -> &mut T {
+        self
+    }
+}
+
+impl<T: fmt::Debug> fmt::Debug for UniqueExternalArc<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Debug::fmt(&**self, f)
+    }
+}
+
+impl<T> fmt::Pointer for UniqueExternalArc<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Pointer::fmt(&self.0, f)
+    }
+}
+
+impl<T> SmartPointer<T> for UniqueExternalArc<T> {
+    fn new(data: T) -> Self {
+        let owned = Box::into_raw(Box::new(Owned { strong: AtomicUsize::new(1), data }));
+        let ptr = unsafe { NonNull::new_unchecked(ptr::addr_of_mut!((*owned).data)) };
+        let vtable = &OwnedVtable::<T>::VTABLE;
+        UniqueExternalArc(ExternalArc { ptr, vtable, phantom: PhantomData })
+    }
+
+    fn try_unwrap(this: Self) -> Result<T, Self> {
+        let ptr = ExternalArc::into_raw(this.0);
+        let owned = unsafe { Owned::<T>::from_data(ptr.as_ptr() as *const c_void) };
+        let owned = unsafe { Box::from_raw(owned) };
+        Ok(owned.data)
+    }
+}
+
+impl<T> SmartPointerMut<T> for UniqueExternalArc<T> {}
+
+/// The allocation of an `ExternalArc` created by `SmartPointer::new`.
+#[repr(C)]
+struct Owned<T> {
+    strong: AtomicUsize,
+    data: T,
+}
+
+impl<T> Owned<T> {
+    /// Gets the allocation from a pointer to its data, keeping the provenance of the pointer.
+    ///
+    /// # Safety
+    ///
+    /// `data` must point to the `data` field of an `Owned<T>`.
+    unsafe fn from_data(data: *const c_void) -> *mut Owned<T> {
+        unsafe { (data as *mut u8).sub(offset_of!(Owned<T>, data)).cast() }
+    }
+}
+
+struct OwnedVtable<T>(PhantomData<T>);
+
+impl<T> OwnedVtable<T> {
+    const VTABLE: ExternalVtable = ExternalVtable {
+        retain: owned_retain::<T>,
+        release: owned_release::<T>,
+        count: Some(owned_count::<T>),
+    };
+}
+
+unsafe extern "C" fn owned_retain<T>(data: *const c_void) {
+    // See `Arc::clone` for the ordering and the overflow check.
+    let old_size = unsafe { (*Owned::<T>::from_data(data)).strong.fetch_add(1, Relaxed) };
+    if old_size > isize::MAX as usize {
+        refcount_overflow();
+    }
+}
+
+unsafe extern "C" fn owned_release<T>(data: *const c_void) {
+    let owned = unsafe { Owned::<T>::from_data(data) };
+    // See `Arc::drop` for the orderings.
+    if unsafe { (*owned).strong.fetch_sub(1, Release) } == 1 {
+        atomic::fence(Acquire);
+        drop(unsafe { Box::from_raw(owned) });
+    }
+}
+
+unsafe extern "C" fn owned_count<T>(data: *const c_void) -> usize {
+    unsafe { (*Owned::<T>::from_data(data)).strong.load(Acquire) }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+
+    use super::*;
+
+    /// A foreign object that counts the calls of its vtable.
+    #[derive(Debug, Default)]
+    struct Mock {
+        refs: AtomicUsize,
+        retains: AtomicUsize,
+        releases: AtomicUsize,
+    }
+
+    unsafe fn mock<'a>(ptr: *const c_void) -> &'a Mock {
+        unsafe { &*(ptr as *const Mock) }
+    }
+
+    unsafe extern "C" fn mock_retain(ptr: *const c_void) {
+        let mock = unsafe { mock(ptr) };
+        mock.retains.fetch_add(1, Relaxed);
+        mock.refs.fetch_add(1, Relaxed);
+    }
+
+    unsafe extern "C" fn mock_release(ptr: *const c_void) {
+        let mock = unsafe { mock(ptr) };
+        mock.releases.fetch_add(1, Relaxed);
+        mock.refs.fetch_sub(1, Relaxed);
+    }
+
+    unsafe extern "C" fn mock_count(ptr: *const c_void) -> usize {
+        unsafe { mock(ptr) }.refs.load(Relaxed)
+    }
+
+    static COUNTED: ExternalVtable =
+        ExternalVtable { retain: mock_retain, release: mock_release, count: Some(mock_count) };
+    static UNCOUNTED: ExternalVtable =
+        ExternalVtable { retain: mock_retain, release: mock_release, count: None };
+
+    /// Hands one reference to `mock` to a new `ExternalArc`.
+    fn external(mock: &Mock, vtable: &'static ExternalVtable) -> ExternalArc<Mock> {
+        mock.refs.fetch_add(1, Relaxed);
+        unsafe { ExternalArc::from_raw(NonNull::from(mock), vtable) }
+    }
+
+    #[test]
+    fn clone_and_drop_call_the_vtable() {
+        let mock = Mock::default();
+        let a = external(&mock, &COUNTED);
+        let b = a.clone();
+        assert_eq!(mock.retains.load(Relaxed), 1);
+        assert_eq!(ExternalArc::reference_count(&a).get(), 2);
+        assert!(ptr::eq(&*b, &mock));
+
+        drop(a);
+        assert_eq!(mock.releases.load(Relaxed), 1);
+        assert_eq!(ExternalArc::reference_count(&b).get(), 1);
+        // Even a count of one does not make the pointer unique.
+        assert!(!ExternalArc::can_make_mut(&b));
+        let b = ExternalArc::try_unwrap(b).unwrap_err();
+
+        drop(b);
+        assert_eq!(mock.releases.load(Relaxed), 2);
+        assert_eq!(mock.refs.load(Relaxed), 0);
+    }
+
+    #[test]
+    fn missing_counts_are_reported_as_one() {
+        let mock = Mock::default();
+        let a = external(&mock, &UNCOUNTED);
+        let b = a.clone();
+        assert_eq!(ExternalArc::reference_count(&a).get(), 1);
+        drop((a, b));
+        assert_eq!(mock.releases.load(Relaxed), 2);
+    }
+
+    #[test]
+    fn raw_pointers_keep_their_reference() {
+        let mock = Mock::default();
+        let a = external(&mock, &COUNTED);
+        let ptr = ExternalArc::into_raw(a);
+        assert_eq!(mock.releases.load(Relaxed), 0);
+
+        let a = unsafe { ExternalArc::from_raw(ptr, &COUNTED) };
+        assert!(ptr::eq(ExternalArc::vtable(&a), &COUNTED));
+        assert_eq!(ExternalArc::as_ptr(&a), ptr.as_ptr() as *const Mock);
+        drop(a);
+        assert_eq!(mock.releases.load(Relaxed), 1);
+    }
+
+    #[test]
+    fn rust_side_allocations_count_and_unwrap() {
+        let a = ExternalArc::new(std::string::String::from("abc"));
+        let b = a.clone();
+        assert_eq!(ExternalArc::reference_count(&a).get(), 2);
+        assert!(ptr::eq(&*a, &*b));
+        drop(a);
+        assert_eq!(ExternalArc::reference_count(&b).get(), 1);
+        drop(b);
+
+        let mut unique = UniqueExternalArc::new(std::string::String::from("abc"));
+        unique.push('d');
+        let shared: ExternalArc<_> = unique.into();
+        assert_eq!(*shared, "abcd");
+
+        let unique = UniqueExternalArc::new(std::vec![1, 2]);
+        assert_eq!(UniqueExternalArc::try_unwrap(unique).unwrap(), [1, 2]);
+    }
+}
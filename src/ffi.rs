@@ -0,0 +1,169 @@
+/// Exports the reference counting of `Arc<$t>` to C, as three functions named after a prefix.
+///
+/// `export_arc_ffi!(MyType, prefix = "mytype");` defines the following functions, which take
+/// pointers returned by `Arc::into_raw`:
+///
+/// ```c
+/// // Acquires another reference, and returns `ptr` (or NULL, if `ptr` is NULL).
+/// const MyType *mytype_clone(const MyType *ptr);
+/// // Releases a reference, dropping the value if it was the last one. Does nothing for NULL.
+/// void mytype_release(const MyType *ptr);
+/// // Returns the current number of references, or 0 for NULL.
+/// uintptr_t mytype_ref_count(const MyType *ptr);
+/// ```
+///
+/// The functions are `extern "C"` functions over a pointer to the type, exported under exactly
+/// these names. cbindgen (with `parse.expand` enabled, so that it sees the expanded macro) emits
+/// the declarations above, and `MyType` as an opaque struct. Values are handed to C with
+/// `Arc::into_raw`, and taken back with `Arc::from_raw`. Static allocations report a count of
+/// `usize::MAX`.
+///
+/// Each prefix can only be exported once per program, since the functions are global symbols.
+///
+/// ```
+/// use reference_counted::{export_arc_ffi, Arc};
+/// use smart_pointer::SmartPointer;
+///
+/// pub struct Counter(u32);
+///
+/// export_arc_ffi!(Counter, prefix = "counter");
+///
+/// extern "C" {
+///     fn counter_clone(ptr: *const Counter) -> *const Counter;
+///     fn counter_release(ptr: *const Counter);
+///     fn counter_ref_count(ptr: *const Counter) -> usize;
+/// }
+///
+/// let ptr = Arc::into_raw(Arc::new(Counter(0)));
+/// unsafe {
+///     assert_eq!(counter_clone(ptr), ptr);
+///     assert_eq!(counter_ref_count(ptr), 2);
+///     counter_release(ptr);
+///     counter_release(ptr);
+/// }
+/// ```
+#[macro_export]
+macro_rules! export_arc_ffi {
+    ($t:ty, prefix = $prefix:literal $(,)?) => {
+        const _: () = {
+            /// # Safety
+            ///
+            /// `ptr` must be null, or a pointer returned by `Arc::into_raw` whose reference has
+            /// not been released yet.
+            #[export_name = concat!($prefix, "_clone")]
+            pub unsafe extern "C" fn clone(ptr: *const $t) -> *const $t {
+                if !ptr.is_null() {
+                    unsafe { $crate::Arc::<$t>::increment_strong_count(ptr) };
+                }
+                ptr
+            }
+
+            /// # Safety
+            ///
+            /// `ptr` must be null, or a pointer returned by `Arc::into_raw` whose reference has
+            /// not been released yet. If this releases the last reference, `ptr` must not be
+            /// used afterwards.
+            #[export_name = concat!($prefix, "_release")]
+            pub unsafe extern "C" fn release(ptr: *const $t) {
+                if !ptr.is_null() {
+                    unsafe { $crate::Arc::<$t>::decrement_strong_count(ptr) };
+                }
+            }
+
+            /// # Safety
+            ///
+            /// `ptr` must be null, or a pointer returned by `Arc::into_raw` whose reference has
+            /// not been released yet.
+            #[export_name = concat!($prefix, "_ref_count")]
+            pub unsafe extern "C" fn ref_count(ptr: *const $t) -> usize {
+                if ptr.is_null() {
+                    return 0;
+                }
+                // The reference stays with `ptr`.
+                let arc = unsafe { $crate::Arc::<$t>::from_raw(ptr) };
+                let arc = ::core::mem::ManuallyDrop::new(arc);
+                <$crate::Arc<$t> as $crate::ReferenceCounted<$t>>::reference_count(&arc).get()
+            }
+        };
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+
+    use crate::arc::{Arc, StaticArcInner};
+    use crate::ReferenceCounted;
+    use smart_pointer::SmartPointer;
+    use std::sync::atomic::{AtomicUsize, Ordering::Relaxed};
+
+    static DROPPED: AtomicUsize = AtomicUsize::new(0);
+
+    // `repr(C)` only keeps the declarations of the C side below free of warnings.
+    #[repr(C)]
+    struct Handle(u32);
+
+    impl Drop for Handle {
+        fn drop(&mut self) {
+            DROPPED.fetch_add(1, Relaxed);
+        }
+    }
+
+    export_arc_ffi!(Handle, prefix = "reference_counted_test_handle");
+    export_arc_ffi!(u64, prefix = "reference_counted_test_u64",);
+
+    // The C side of the functions.
+    extern "C" {
+        fn reference_counted_test_handle_clone(ptr: *const Handle) -> *const Handle;
+        fn reference_counted_test_handle_release(ptr: *const Handle);
+        fn reference_counted_test_handle_ref_count(ptr: *const Handle) -> usize;
+        fn reference_counted_test_u64_clone(ptr: *const u64) -> *const u64;
+        fn reference_counted_test_u64_release(ptr: *const u64);
+        fn reference_counted_test_u64_ref_count(ptr: *const u64) -> usize;
+    }
+
+    #[test]
+    fn c_handles_count_and_release() {
+        let arc = Arc::new(Handle(7));
+        let ptr = Arc::into_raw(arc.clone());
+        unsafe {
+            assert_eq!(reference_counted_test_handle_ref_count(ptr), 2);
+            let copy = reference_counted_test_handle_clone(ptr);
+            assert_eq!(copy, ptr);
+            assert_eq!(Arc::reference_count(&arc).get(), 3);
+            assert_eq!((*copy).0, 7);
+
+            reference_counted_test_handle_release(copy);
+            reference_counted_test_handle_release(ptr);
+            assert_eq!(reference_counted_test_handle_ref_count(Arc::as_ptr(&arc)), 1);
+        }
+        assert_eq!(DROPPED.load(Relaxed), 0);
+
+        // The C side can release the last reference, too.
+        let ptr = Arc::into_raw(arc);
+        unsafe { reference_counted_test_handle_release(ptr) };
+        assert_eq!(DROPPED.load(Relaxed), 1);
+    }
+
+    #[test]
+    fn null_pointers_are_ignored() {
+        unsafe {
+            assert!(reference_counted_test_u64_clone(base::ptr::null()).is_null());
+            reference_counted_test_u64_release(base::ptr::null());
+            assert_eq!(reference_counted_test_u64_ref_count(base::ptr::null()), 0);
+        }
+    }
+
+    #[test]
+    fn static_handles_are_not_counted() {
+        static INNER: StaticArcInner<u64> = StaticArcInner::new(3);
+        let ptr = Arc::into_raw(Arc::from_static(&INNER));
+        unsafe {
+            assert_eq!(reference_counted_test_u64_clone(ptr), ptr);
+            assert_eq!(reference_counted_test_u64_ref_count(ptr), usize::MAX);
+            reference_counted_test_u64_release(ptr);
+            reference_counted_test_u64_release(ptr);
+            assert_eq!(*ptr, 3);
+        }
+    }
+}
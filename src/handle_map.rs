@@ -0,0 +1,220 @@
+use base::convert::TryFrom;
+use base::fmt;
+
+use base::prelude::v1::*;
+
+use crate::arc::Arc;
+
+/// A small, copyable key of a value in a `HandleMap`, e.g. for passing to scripts or across an
+/// FFI boundary instead of a pointer.
+///
+/// A handle consists of the index of a slot, and the generation of that slot at the time of
+/// insertion. Removing a value increments the generation of its slot, so that stale handles are
+/// detected even after the slot has been reused. Generations wrap around after `u32::MAX`
+/// reuses of the same slot.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Handle {
+    index: u32,
+    generation: u32,
+}
+
+impl Handle {
+    /// Creates a handle from its parts, e.g. after it was passed through foreign code.
+    pub const fn from_parts(index: u32, generation: u32) -> Self {
+        Handle { index, generation }
+    }
+
+    /// Gets the index of the slot of the handle.
+    pub const fn index(self) -> u32 {
+        self.index
+    }
+
+    /// Gets the generation of the slot at the time the handle was created.
+    pub const fn generation(self) -> u32 {
+        self.generation
+    }
+}
+
+struct Slot<T: ?Sized> {
+    generation: u32,
+    value: Option<Arc<T>>,
+}
+
+/// A map that assigns `Handle`s to `Arc`s, keeping their values alive while they are inserted.
+///
+/// Internally, this is a slab: the slots of removed values are reused by later insertions.
+pub struct HandleMap<T: ?Sized> {
+    slots: Vec<Slot<T>>,
+    free: Vec<u32>,
+}
+
+impl<T: ?Sized> HandleMap<T> {
+    /// Creates an empty map.
+    pub fn new() -> Self {
+        HandleMap { slots: Vec::new(), free: Vec::new() }
+    }
+
+    /// Gets the number of values in the map.
+    pub fn len(&self) -> usize {
+        self.slots.len() - self.free.len()
+    }
+
+    /// Returns `true` if the map contains no values.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Inserts a value, returning the handle under which it can be accessed.
+    ///
+    /// Panics if the map would contain more than `u32::MAX` slots.
+    pub fn insert(&mut self, value: Arc<T>) -> Handle {
+        match self.free.pop() {
+            Some(index) => {
+                let slot = &mut self.slots[index as usize];
+                slot.value = Some(value);
+                Handle { index, generation: slot.generation }
+            }
+            None => {
+                let index = u32::try_from(self.slots.len()).expect("too many handles");
+                self.slots.push(Slot { generation: 0, value: Some(value) });
+                Handle { index, generation: 0 }
+            }
+        }
+    }
+
+    /// Gets a reference to the value of a handle, or `None` if it has been removed.
+    pub fn get_ref(&self, handle: Handle) -> Option<&Arc<T>> {
+        match self.slots.get(handle.index as usize) {
+            Some(slot) if slot.generation == handle.generation => slot.value.as_ref(),
+            _ => None,
+        }
+    }
+
+    /// Gets a clone of the value of a handle, or `None` if it has been removed.
+    pub fn get(&self, handle: Handle) -> Option<Arc<T>> {
+        self.get_ref(handle).cloned()
+    }
+
+    /// Returns `true` if the handle refers to a value in the map.
+    pub fn contains(&self, handle: Handle) -> bool {
+        self.get_ref(handle).is_some()
+    }
+
+    /// Removes the value of a handle from the map and returns it, or `None` if it has been
+    /// removed already. The handle, and all copies of it, become stale.
+    pub fn remove(&mut self, handle: Handle) -> Option<Arc<T>> {
+        let slot = self.slots.get_mut(handle.index as usize)?;
+        if slot.generation != handle.generation {
+            return None;
+        }
+
+        let value = slot.value.take()?;
+        slot.generation = slot.generation.wrapping_add(1);
+        self.free.push(handle.index);
+        Some(value)
+    }
+
+    /// Removes all values from the map. All handles become stale.
+    pub fn clear(&mut self) {
+        for (index, slot) in self.slots.iter_mut().enumerate() {
+            if slot.value.take().is_some() {
+                slot.generation = slot.generation.wrapping_add(1);
+                self.free.push(index as u32);
+            }
+        }
+    }
+
+    /// Iterates over the handles and values of the map, in the order of their slots.
+    pub fn iter(&self) -> impl Iterator<Item = (Handle, &Arc<T>)> + '_ {
+        self.slots.iter().enumerate().filter_map(|(index, slot)| {
+            let handle = Handle { index: index as u32, generation: slot.generation };
+            slot.value.as_ref().map(|value| (handle, value))
+        })
+    }
+}
+
+impl<T: ?Sized> Default for HandleMap<T> {
+    fn default() -> Self {
+        HandleMap::new()
+    }
+}
+
+impl<T: ?Sized + fmt::Debug> fmt::Debug for HandleMap<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_map().entries(self.iter()).finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+
+    use super::*;
+    use crate::ReferenceCounted;
+    use smart_pointer::SmartPointer;
+
+    #[test]
+    fn stale_handles_find_nothing() {
+        let mut map = HandleMap::new();
+        let a = map.insert(Arc::new(1));
+        assert_eq!(map.get(a).as_deref(), Some(&1));
+        assert_eq!(map.remove(a).as_deref(), Some(&1));
+
+        assert_eq!(map.get(a), None);
+        assert!(!map.contains(a));
+        assert_eq!(map.remove(a), None);
+        assert_eq!(map.get(Handle::from_parts(a.index(), a.generation() + 1)), None);
+        assert_eq!(map.get(Handle::from_parts(7, 0)), None);
+        assert!(map.is_empty());
+    }
+
+    #[test]
+    fn removed_slots_are_reused() {
+        let mut map = HandleMap::new();
+        let a = map.insert(Arc::new("a"));
+        let b = map.insert(Arc::new("b"));
+        map.remove(a);
+
+        let c = map.insert(Arc::new("c"));
+        assert_eq!(c.index(), a.index());
+        assert_ne!(c, a);
+        assert_eq!(map.get(a), None);
+        assert_eq!(map.get(c).as_deref(), Some(&"c"));
+        assert_eq!(map.get(b).as_deref(), Some(&"b"));
+        assert_eq!(map.len(), 2);
+
+        // Handles survive being taken apart and put back together.
+        let c = Handle::from_parts(c.index(), c.generation());
+        assert_eq!(map.get(c).as_deref(), Some(&"c"));
+    }
+
+    #[test]
+    fn values_stay_alive_while_inserted() {
+        let mut map = HandleMap::new();
+        let value = Arc::new(std::string::String::from("value"));
+        let handle = map.insert(value.clone());
+        assert_eq!(Arc::reference_count(&value).get(), 2);
+
+        let got = map.get(handle).unwrap();
+        assert!(Arc::ptr_eq(&got, &value));
+        assert_eq!(Arc::reference_count(&value).get(), 3);
+        drop(got);
+
+        map.clear();
+        assert_eq!(Arc::reference_count(&value).get(), 1);
+        assert_eq!(map.get(handle), None);
+        assert!(map.is_empty());
+    }
+
+    #[test]
+    fn iteration_visits_the_values_in_slot_order() {
+        let mut map = HandleMap::new();
+        let handles: std::vec::Vec<_> = (0..4).map(|i| map.insert(Arc::new(i))).collect();
+        map.remove(handles[1]);
+
+        let entries: std::vec::Vec<_> =
+            map.iter().map(|(handle, value)| (handle, **value)).collect();
+        assert_eq!(entries, [(handles[0], 0), (handles[2], 2), (handles[3], 3)]);
+        assert_eq!(std::format!("{:?}", HandleMap::<u8>::new()), "{}");
+    }
+}
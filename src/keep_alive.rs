@@ -0,0 +1,176 @@
+use base::boxed::Box;
+use base::fmt;
+use base::vec::Vec;
+
+use crate::arc::Arc;
+
+/// A bag of `Arc`s of arbitrary types, which keeps their values alive until the bag is dropped
+/// or cleared.
+///
+/// This is useful when an operation (e.g. an FFI call or a DMA transfer) needs some shared
+/// objects to stay alive until it completes, but nothing ever accesses them through the bag.
+/// Every handle is boxed individually, erasing its type.
+///
+/// Only handles that may be sent and shared across threads can be stored, so that the bag
+/// itself is `Send` and `Sync`. Use a `LocalKeepAlive` for other handles.
+#[derive(Default)]
+pub struct KeepAlive {
+    handles: Vec<Box<dyn Handle + Send + Sync>>,
+}
+
+// Implemented by everything, so that any handle can be boxed into a trait object.
+trait Handle {}
+
+impl<T: ?Sized> Handle for T {}
+
+impl KeepAlive {
+    /// Constructs a new, empty `KeepAlive`.
+    pub fn new() -> Self {
+        KeepAlive { handles: Vec::new() }
+    }
+
+    /// Keeps the value of `handle` alive for as long as the bag holds on to it.
+    pub fn push<T: ?Sized + Send + Sync + 'static>(&mut self, handle: Arc<T>) {
+        self.handles.push(Box::new(handle));
+    }
+
+    /// Releases all handles in the bag.
+    pub fn clear(&mut self) {
+        self.handles.clear();
+    }
+
+    /// Returns the number of handles in the bag.
+    pub fn len(&self) -> usize {
+        self.handles.len()
+    }
+
+    /// Returns `true` if the bag contains no handles.
+    pub fn is_empty(&self) -> bool {
+        self.handles.is_empty()
+    }
+}
+
+impl fmt::Debug for KeepAlive {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("KeepAlive").field("len", &self.len()).finish()
+    }
+}
+
+/// A bag of `Arc`s of arbitrary types, like `KeepAlive`, which also accepts handles that may
+/// not be sent or shared across threads.
+///
+/// In turn, the bag is neither `Send` nor `Sync`.
+#[derive(Default)]
+pub struct LocalKeepAlive {
+    handles: Vec<Box<dyn Handle>>,
+}
+
+impl LocalKeepAlive {
+    /// Constructs a new, empty `LocalKeepAlive`.
+    pub fn new() -> Self {
+        LocalKeepAlive { handles: Vec::new() }
+    }
+
+    /// Keeps the value of `handle` alive for as long as the bag holds on to it.
+    pub fn push<T: ?Sized + 'static>(&mut self, handle: Arc<T>) {
+        self.handles.push(Box::new(handle));
+    }
+
+    /// Releases all handles in the bag.
+    pub fn clear(&mut self) {
+        self.handles.clear();
+    }
+
+    /// Returns the number of handles in the bag.
+    pub fn len(&self) -> usize {
+        self.handles.len()
+    }
+
+    /// Returns `true` if the bag contains no handles.
+    pub fn is_empty(&self) -> bool {
+        self.handles.is_empty()
+    }
+}
+
+impl From<KeepAlive> for LocalKeepAlive {
+    fn from(bag: KeepAlive) -> Self {
+        LocalKeepAlive { handles: bag.handles.into_iter().map(|h| h as Box<dyn Handle>).collect() }
+    }
+}
+
+impl fmt::Debug for LocalKeepAlive {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("LocalKeepAlive").field("len", &self.len()).finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+
+    use super::*;
+    use base::cell::Cell;
+    use base::sync::atomic::{AtomicUsize, Ordering::SeqCst};
+    use smart_pointer::SmartPointer;
+
+    use crate::ReferenceCounted;
+
+    struct CountDrops(Arc<AtomicUsize>);
+
+    impl Drop for CountDrops {
+        fn drop(&mut self) {
+            self.0.fetch_add(1, SeqCst);
+        }
+    }
+
+    fn is_send_sync<T: Send + Sync>(_: &T) {}
+
+    #[test]
+    fn values_are_dropped_once_with_the_bag() {
+        let drops = Arc::new(AtomicUsize::new(0));
+        let mut bag = KeepAlive::new();
+        bag.push(Arc::new(CountDrops(drops.clone())));
+        let shared = Arc::new(CountDrops(drops.clone()));
+        bag.push(shared.clone());
+        bag.push(Arc::new(CountDrops(drops.clone())));
+        bag.push::<str>(Arc::from("unsized"));
+        is_send_sync(&bag);
+
+        drop(shared);
+        assert_eq!(bag.len(), 4);
+        assert_eq!(drops.load(SeqCst), 0);
+        drop(bag);
+        assert_eq!(drops.load(SeqCst), 3);
+    }
+
+    #[test]
+    fn clear_drops_the_values() {
+        let drops = Arc::new(AtomicUsize::new(0));
+        let mut bag = KeepAlive::new();
+        bag.push(Arc::new(CountDrops(drops.clone())));
+        bag.clear();
+        assert!(bag.is_empty());
+        assert_eq!(drops.load(SeqCst), 1);
+        drop(bag);
+        assert_eq!(drops.load(SeqCst), 1);
+    }
+
+    #[test]
+    fn local_bags_accept_thread_unsafe_handles() {
+        let drops = Arc::new(AtomicUsize::new(0));
+        let mut sync_bag = KeepAlive::new();
+        sync_bag.push(Arc::new(CountDrops(drops.clone())));
+
+        let mut bag = LocalKeepAlive::from(sync_bag);
+        let cell = Arc::new(Cell::new(1));
+        bag.push(cell.clone());
+        bag.push(Arc::new(CountDrops(drops.clone())));
+        cell.set(2);
+        assert_eq!(bag.len(), 3);
+        assert_eq!(Arc::reference_count(&cell).get(), 2);
+
+        drop(bag);
+        assert_eq!(Arc::reference_count(&cell).get(), 1);
+        assert_eq!(drops.load(SeqCst), 2);
+    }
+}
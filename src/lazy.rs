@@ -0,0 +1,249 @@
+use base::cell::UnsafeCell;
+use base::fmt;
+use base::mem::{self, ManuallyDrop};
+use base::ops::Deref;
+use base::ptr::{self, NonNull};
+use base::sync::atomic::Ordering::{Acquire, Relaxed, Release};
+use base::sync::atomic::{AtomicPtr, AtomicU8};
+
+use smart_pointer::SmartPointer;
+
+use crate::arc::{Arc, ArcInner};
+#[cfg(feature = "weak")]
+use crate::arc::WeakArc;
+
+const INCOMPLETE: u8 = 0;
+const RUNNING: u8 = 1;
+const COMPLETE: u8 = 2;
+const POISONED: u8 = 3;
+
+/// An `Arc` that is initialized on first access, suitable for use in statics.
+///
+/// The first call to `get` (or a dereference) runs the initializer, all further calls return
+/// clones of the same `Arc`. If several threads access an uninitialized `LazyArc` at the same
+/// time, one of them runs the initializer while the others wait for it. The `LazyArc` stores
+/// the pointer to the allocation directly, so obtaining an `Arc` from it does not involve
+/// any additional indirection.
+///
+/// If the initializer panics, the `LazyArc` is poisoned, and all further accesses panic.
+///
+/// ```
+/// use reference_counted::{Arc, LazyArc};
+///
+/// static GREETING: LazyArc<String> = LazyArc::new(|| "hello".to_string());
+///
+/// let a: Arc<String> = GREETING.get();
+/// assert_eq!(&*a, "hello");
+/// assert_eq!(&*GREETING, "hello");
+/// ```
+pub struct LazyArc<T, F = fn() -> T> {
+    state: AtomicU8,
+    // Non-null once the state is `COMPLETE`, and owns one strong reference from then on.
+    ptr: AtomicPtr<ArcInner<T>>,
+    // Taken by the thread that runs the initializer.
+    init: UnsafeCell<Option<F>>,
+}
+
+unsafe impl<T: Send + Sync, F: Send> Send for LazyArc<T, F> {}
+unsafe impl<T: Send + Sync, F: Send> Sync for LazyArc<T, F> {}
+
+impl<T, F> LazyArc<T, F> {
+    /// Creates a new `LazyArc` that calls `init` on first access to compute its value.
+    pub const fn new(init: F) -> Self {
+        LazyArc {
+            state: AtomicU8::new(INCOMPLETE),
+            ptr: AtomicPtr::new(ptr::null_mut()),
+            init: UnsafeCell::new(Some(init)),
+        }
+    }
+
+    /// Returns a clone of the `Arc` if it has been initialized already, without running the
+    /// initializer.
+    pub fn get_if_initialized(&self) -> Option<Arc<T>> {
+        NonNull::new(self.ptr.load(Acquire)).map(clone_arc)
+    }
+
+    /// Initializes the `LazyArc` with the given `Arc` instead of running the initializer.
+    ///
+    /// Fails and returns the `Arc` if the `LazyArc` has been initialized already, or if the
+    /// initializer is currently running.
+    pub fn set(&self, arc: Arc<T>) -> Result<(), Arc<T>> {
+        if self.state.compare_exchange(INCOMPLETE, RUNNING, Acquire, Relaxed).is_err() {
+            return Err(arc);
+        }
+
+        // We won the race, so nobody else accesses the initializer.
+        drop(unsafe { (*self.init.get()).take() });
+        self.complete(arc);
+        Ok(())
+    }
+
+    fn complete(&self, arc: Arc<T>) {
+        self.ptr.store(Arc::into_inner(arc).as_ptr(), Release);
+        self.state.store(COMPLETE, Release);
+    }
+}
+
+/// Create a new `Arc` for the reference owned by a `LazyArc`.
+fn clone_arc<T>(ptr: NonNull<ArcInner<T>>) -> Arc<T> {
+    // The `LazyArc` keeps its own reference, which must not be released here.
+    let arc = ManuallyDrop::new(Arc::from_inner(ptr));
+    Arc::clone(&arc)
+}
+
+impl<T, F: FnOnce() -> T> LazyArc<T, F> {
+    /// Returns a clone of the `Arc`, initializing it first if necessary.
+    pub fn get(&self) -> Arc<T> {
+        clone_arc(self.force())
+    }
+
+    /// Returns a `WeakArc` to the value, initializing it first if necessary. It can be upgraded
+    /// for as long as the `LazyArc` is alive.
+    #[cfg(feature = "weak")]
+    pub fn get_weak(&self) -> WeakArc<T> {
+        let arc = ManuallyDrop::new(Arc::from_inner(self.force()));
+        Arc::downgrade(&arc)
+    }
+
+    fn force(&self) -> NonNull<ArcInner<T>> {
+        loop {
+            if let Some(ptr) = NonNull::new(self.ptr.load(Acquire)) {
+                return ptr;
+            }
+
+            match self.state.compare_exchange(INCOMPLETE, RUNNING, Acquire, Acquire) {
+                Ok(_) => {
+                    // Poisons the `LazyArc` should the initializer panic.
+                    struct Guard<'a>(&'a AtomicU8);
+
+                    impl Drop for Guard<'_> {
+                        fn drop(&mut self) {
+                            self.0.store(POISONED, Release);
+                        }
+                    }
+
+                    // We won the race, so nobody else accesses the initializer.
+                    let init = unsafe { (*self.init.get()).take() }.unwrap();
+
+                    let guard = Guard(&self.state);
+                    let arc = Arc::new(init());
+                    mem::forget(guard);
+
+                    self.complete(arc);
+                }
+                Err(POISONED) => panic!("LazyArc instance has previously been poisoned"),
+                Err(_) => wait(),
+            }
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+fn wait() {
+    base::thread::yield_now();
+}
+
+#[cfg(not(feature = "std"))]
+fn wait() {
+    base::hint::spin_loop();
+}
+
+impl<T, F: FnOnce() -> T> Deref for LazyArc<T, F> {
+    type Target = T;
+
+    /// Dereferences to the value, initializing it first if necessary.
+    fn deref(&self) -> &T {
+        // The allocation lives as long as the `LazyArc`.
+        unsafe { &(*self.force().as_ptr()).data }
+    }
+}
+
+impl<T, F> Drop for LazyArc<T, F> {
+    fn drop(&mut self) {
+        if let Some(ptr) = NonNull::new(*self.ptr.get_mut()) {
+            drop(Arc::from_inner(ptr));
+        }
+    }
+}
+
+impl<T: fmt::Debug, F> fmt::Debug for LazyArc<T, F> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.get_if_initialized() {
+            Some(arc) => f.debug_tuple("LazyArc").field(&*arc).finish(),
+            None => f.write_str("LazyArc(<uninit>)"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+
+    use std::vec::Vec;
+
+    use super::*;
+    use crate::test_util::allocations;
+    use crate::ReferenceCounted;
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn concurrent_first_accesses_initialize_once() {
+        use std::sync::atomic::AtomicUsize;
+
+        let runs = AtomicUsize::new(0);
+        let lazy = LazyArc::new(|| {
+            runs.fetch_add(1, Relaxed);
+            // Give the other threads time to find the initializer running.
+            std::thread::sleep(std::time::Duration::from_millis(10));
+            42
+        });
+        let barrier = std::sync::Barrier::new(8);
+
+        let arcs: Vec<Arc<u32>> = std::thread::scope(|scope| {
+            let handles: Vec<_> = (0..8)
+                .map(|_| {
+                    scope.spawn(|| {
+                        barrier.wait();
+                        lazy.get()
+                    })
+                })
+                .collect();
+            handles.into_iter().map(|handle| handle.join().unwrap()).collect()
+        });
+
+        assert_eq!(runs.load(Relaxed), 1);
+        assert!(arcs.iter().all(|arc| Arc::ptr_eq(arc, &arcs[0]) && **arc == 42));
+        assert_eq!(Arc::reference_count(&arcs[0]).get(), 9);
+    }
+
+    #[test]
+    fn clones_share_one_allocation() {
+        let lazy: LazyArc<Vec<u32>> = LazyArc::new(|| Vec::with_capacity(4));
+        assert!(lazy.get_if_initialized().is_none());
+
+        let before = allocations();
+        let a = lazy.get();
+        assert_eq!(allocations(), before + 2);
+        let b = lazy.get();
+        assert_eq!(lazy.capacity(), 4);
+        assert_eq!(allocations(), before + 2);
+        assert!(Arc::ptr_eq(&a, &b));
+        assert!(Arc::ptr_eq(&a, &lazy.get_if_initialized().unwrap()));
+        assert!(lazy.set(Arc::new(Vec::new())).is_err());
+
+        drop(lazy);
+        assert_eq!(Arc::reference_count(&a).get(), 2);
+    }
+
+    #[cfg(feature = "weak")]
+    #[test]
+    fn weaks_upgrade_while_the_lazy_arc_lives() {
+        let lazy = LazyArc::new(|| 7);
+        let weak = lazy.get_weak();
+        assert_eq!(*weak.upgrade().unwrap(), 7);
+        assert!(Arc::ptr_eq(&weak.upgrade().unwrap(), &lazy.get()));
+
+        drop(lazy);
+        assert!(weak.upgrade().is_none());
+    }
+}
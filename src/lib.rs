@@ -1,20 +1,490 @@
 #![no_std]
 #![allow(unused_unsafe)]
 // #![cfg_attr(feature = "unstable", coerce_unsized, dispatch_from_dyn)]
+#![cfg_attr(feature = "unstable", feature(fn_traits, tuple_trait, unboxed_closures))]
 extern crate maybe_std as base;
 
+#[cfg(feature = "std")]
+use base::collections::HashMap;
+#[cfg(feature = "std")]
+use base::hash::Hash;
 use base::num::NonZeroUsize;
+#[cfg(not(feature = "panic-free"))]
+use base::ops::{Deref, DerefMut, Range};
+#[cfg(any(feature = "arc", feature = "rc", feature = "std"))]
+use base::vec::Vec;
 
 use smart_pointer::IntoMut;
 
+/// Implements `ReferenceCounted` for a tuple struct with a single field that implements it,
+/// along with `IntoMut`, `SmartPointer` and the traits these require. The `MutablePointer` is a
+/// generated struct whose name is that of the newtype prefixed with `Unique`.
+///
+/// The pointee is taken to be the first generic type argument of the field type, as in
+/// `Arc<T>`.
+///
+/// ```
+/// use reference_counted::{Arc, ReferenceCounted};
+/// use smart_pointer::SmartPointer;
+///
+/// #[derive(ReferenceCounted)]
+/// pub struct Config(Arc<String>); // also defines `pub struct UniqueConfig`
+///
+/// fn count<P: ReferenceCounted<String>>(p: &P) -> usize {
+///     P::reference_count(p).get()
+/// }
+///
+/// let config = Config(Arc::new(String::from("verbose")));
+/// let copy = config.clone();
+/// assert_eq!(count(&config), 2);
+/// assert_eq!(copy.as_str(), "verbose");
+/// ```
+///
+/// Anything but a tuple struct with a single field is rejected:
+///
+/// ```compile_fail
+/// # use reference_counted::{Arc, ReferenceCounted};
+/// #[derive(ReferenceCounted)]
+/// pub struct Config { data: Arc<String> }
+/// ```
+///
+/// ```compile_fail
+/// # use reference_counted::{Arc, ReferenceCounted};
+/// #[derive(ReferenceCounted)]
+/// pub struct Config(Arc<String>, Arc<String>);
+/// ```
+///
+/// ```compile_fail
+/// # use reference_counted::{Arc, ReferenceCounted};
+/// #[derive(ReferenceCounted)]
+/// pub enum Config { Data(Arc<String>) }
+/// ```
+///
+/// So is a field type without a generic argument to take the pointee from:
+///
+/// ```compile_fail
+/// # use reference_counted::ReferenceCounted;
+/// #[derive(ReferenceCounted)]
+/// pub struct Config(&'static String);
+/// ```
+#[cfg(feature = "derive")]
+pub use reference_counted_derive::ReferenceCounted;
+
+#[cfg(feature = "derive")]
+#[doc(hidden)]
+pub mod __private {
+    pub use smart_pointer;
+}
+
 /// A smart pointer that keeps track of how many pointers refer to the same allocation and
 /// exposes this information in its API.
 pub trait ReferenceCounted<T: ?Sized>: IntoMut<T> + Clone {
-    /// Get the number of owning pointers referring to the same allocation.
+    /// Get the number of owning pointers referring to the same allocation. Weak pointers, which
+    /// do not own the value, are not included.
     ///
     /// Implementations must fulfill that `ReferenceCounted::reference_count(this) == 1` implies
-    /// `IntoMut::con_make_mut(this) == true`.
+    /// `IntoMut::can_make_mut(this) == true`, unless there are weak pointers to the allocation.
     fn reference_count(this: &Self) -> NonZeroUsize;
+
+    /// Returns `true` if the two pointers refer to the same allocation.
+    ///
+    /// The default implementation compares the addresses of the values, ignoring any pointer
+    /// metadata.
+    fn same_allocation(this: &Self, other: &Self) -> bool {
+        allocation_address::<T, Self>(this) == allocation_address::<T, Self>(other)
+    }
+}
+
+/// A weak pointer to the allocation of a reference-counted pointer of type `Self::Strong`, which
+/// keeps the allocation but not the value alive.
+#[cfg(feature = "weak")]
+pub trait WeakPointer<T: ?Sized>: Clone {
+    /// The owning pointer type.
+    type Strong: ReferenceCounted<T>;
+
+    /// Attempts to create an owning pointer to the value, returning `None` if the value has been
+    /// dropped already.
+    fn upgrade(&self) -> Option<Self::Strong>;
+}
+
+/// A reference-counted pointer that can be downgraded to a weak pointer, which names the weak
+/// form of the pointer for generic code.
+///
+/// ```
+/// # #[cfg(all(feature = "arc", feature = "rc"))] {
+/// use std::collections::HashMap;
+/// use reference_counted::{Arc, Downgradable, Rc, WeakPointer};
+///
+/// // Hands out shared values by key, keeping only those alive that are still in use.
+/// struct Cache<P: Downgradable<String>> {
+///     entries: HashMap<u32, P::Weak>,
+/// }
+///
+/// impl<P: Downgradable<String>> Cache<P> {
+///     fn get(&mut self, key: u32) -> P {
+///         if let Some(value) = self.entries.get(&key).and_then(|weak| weak.upgrade()) {
+///             return value;
+///         }
+///         let value = P::new(key.to_string());
+///         self.entries.insert(key, P::downgrade(&value));
+///         value
+///     }
+/// }
+///
+/// fn check<P: Downgradable<String>>() {
+///     let mut cache = Cache::<P> { entries: HashMap::new() };
+///     let a = cache.get(1);
+///     assert!(P::same_allocation(&a, &cache.get(1)));
+///
+///     drop(a);
+///     assert!(cache.entries[&1].upgrade().is_none());
+///     assert_eq!(*cache.get(1), "1");
+/// }
+///
+/// check::<Arc<String>>();
+/// check::<Rc<String>>();
+/// # }
+/// ```
+#[cfg(feature = "weak")]
+pub trait Downgradable<T: ?Sized>: ReferenceCounted<T> {
+    /// The weak pointer type.
+    type Weak: WeakPointer<T, Strong = Self>;
+
+    /// Creates a weak pointer to the allocation of `this`.
+    fn downgrade(this: &Self) -> Self::Weak;
+}
+
+/// A reference-counted pointer that exposes how many weak pointers refer to its allocation.
+///
+/// Following `std`, `WeaklyReferenceCounted::weak_count` counts the weak pointers only, not the
+/// weak reference that the owning pointers may hold together. Generic code can thus track both
+/// kinds of pointers, e.g. to evict cache entries whose values are gone:
+///
+/// ```
+/// # #[cfg(all(feature = "arc", feature = "rc"))] {
+/// use reference_counted::{Arc, Rc, WeakPointer, WeaklyReferenceCounted};
+///
+/// fn evict<T, P: WeaklyReferenceCounted<T>>(entries: &mut Vec<P::Weak>) {
+///     entries.retain(|weak| weak.upgrade().is_some());
+/// }
+///
+/// fn check<P: WeaklyReferenceCounted<u8>>() {
+///     let kept = P::new(1);
+///     let dropped = P::new(2);
+///     assert_eq!(P::weak_count(&kept), 0);
+///
+///     let mut entries = vec![P::downgrade(&kept), P::downgrade(&dropped)];
+///     assert_eq!(P::weak_count(&kept), 1);
+///
+///     drop(dropped);
+///     evict::<u8, P>(&mut entries);
+///     assert_eq!(entries.len(), 1);
+///
+///     drop(entries);
+///     assert_eq!(P::weak_count(&kept), 0);
+/// }
+///
+/// check::<Arc<u8>>();
+/// check::<Rc<u8>>();
+/// # }
+/// ```
+#[cfg(feature = "weak")]
+pub trait WeaklyReferenceCounted<T: ?Sized>: Downgradable<T> {
+    /// Get the number of weak pointers referring to the same allocation.
+    fn weak_count(this: &Self) -> usize;
+}
+
+/// Construction of a pointer from an owned container of its pointee, e.g. of an `Arc<[T]>` from
+/// a `Vec<T>`, for generic code that can not name the pointer type.
+///
+/// `SmartPointer::new` only covers sized pointees. A single trait with the source as parameter
+/// lets one bound express exactly the conversion that is needed, and lets pointer types pick
+/// which sources they support. Implementations move the contents rather than cloning them.
+///
+/// ```
+/// # #[cfg(all(feature = "arc", feature = "rc"))] {
+/// use reference_counted::{Arc, FromOwned, Rc};
+///
+/// fn build<P: FromOwned<Vec<u8>>>(bytes: &[u8]) -> P {
+///     P::from_owned(bytes.to_vec())
+/// }
+///
+/// let a: Arc<[u8]> = build(b"abc");
+/// let r: Rc<[u8]> = build(b"abc");
+/// assert_eq!(&*a, &*r);
+/// # }
+/// ```
+pub trait FromOwned<Src>: Sized {
+    /// Creates a pointer to the contents of `src`.
+    fn from_owned(src: Src) -> Self;
+}
+
+/// A cheaply clonable pointer to a slice, which can be narrowed to a subslice without copying.
+///
+/// Implemented by `ArcSlice`, `RcSlice` and `StaticRef<[T]>`, so that e.g. zero-copy parsers can
+/// be written once for all kinds of backing storage.
+///
+/// ```
+/// # #[cfg(all(feature = "arc", feature = "rc"))] {
+/// use reference_counted::{Arc, ArcSlice, Rc, RcSlice, SharedSlice, StaticRef};
+///
+/// // Splits a buffer of frames, each prefixed by its length in a single byte.
+/// fn frames<S: SharedSlice<u8>>(mut buf: S) -> Vec<S> {
+///     let mut frames = Vec::new();
+///     while S::len(&buf) > 0 {
+///         let end = 1 + buf[0] as usize;
+///         let rest = end..S::len(&buf);
+///         frames.push(S::slice(buf.clone(), 1..end));
+///         buf = S::slice(buf, rest);
+///     }
+///     frames
+/// }
+///
+/// let bytes: &[u8] = &[2, 10, 11, 0, 1, 12];
+/// let expected: &[&[u8]] = &[&[10, 11], &[], &[12]];
+///
+/// let arc = frames(ArcSlice::from(Arc::from(bytes)));
+/// assert!(arc.iter().map(|f| &f[..]).eq(expected.iter().copied()));
+/// let rc = frames(RcSlice::from(Rc::from(bytes)));
+/// assert!(rc.iter().map(|f| &f[..]).eq(expected.iter().copied()));
+/// let st = frames(StaticRef::new_static(&[2u8, 10, 11, 0, 1, 12][..]));
+/// assert!(st.iter().map(|f| &f[..]).eq(expected.iter().copied()));
+/// # }
+/// ```
+#[cfg(not(feature = "panic-free"))]
+pub trait SharedSlice<T>: Clone + Deref<Target = [T]> {
+    /// The unique pointer returned by `try_into_unique`.
+    type Unique: DerefMut<Target = [T]>;
+
+    /// Gets the number of items in the slice.
+    fn len(this: &Self) -> usize {
+        Self::as_slice(this).len()
+    }
+
+    /// Gets the items of the slice.
+    fn as_slice(this: &Self) -> &[T] {
+        this
+    }
+
+    /// Narrows the pointer to the given subrange of its items, sharing the same allocation.
+    ///
+    /// Panics if the range is out of bounds or decreasing.
+    fn slice(this: Self, range: Range<usize>) -> Self;
+
+    /// Converts the pointer into a unique one, succeeding only if it is the only pointer to its
+    /// allocation and covers all of it. Returns the pointer unchanged otherwise.
+    fn try_into_unique(this: Self) -> Result<Self::Unique, Self>;
+}
+
+fn allocation_address<T: ?Sized, P: ReferenceCounted<T>>(p: &P) -> usize {
+    &**p as *const T as *const u8 as usize
+}
+
+/// Removes consecutive pointers to the same allocation from `pointers`, keeping the first one of
+/// each run.
+#[cfg(any(feature = "arc", feature = "rc"))]
+pub fn dedup_by_allocation<T: ?Sized, P: ReferenceCounted<T>>(pointers: &mut Vec<P>) {
+    pointers.dedup_by(|a, b| P::same_allocation(a, b));
+}
+
+/// Counts how many distinct allocations the given pointers refer to.
+///
+/// This collects and sorts the addresses of the allocations rather than hashing them, so that
+/// it does not depend on the standard library.
+#[cfg(any(feature = "arc", feature = "rc"))]
+pub fn count_unique_allocations<'a, T, P, I>(pointers: I) -> usize
+where
+    T: ?Sized,
+    P: ReferenceCounted<T> + 'a,
+    I: IntoIterator<Item = &'a P>,
+{
+    let mut addresses: Vec<usize> = pointers.into_iter().map(allocation_address::<T, P>).collect();
+    addresses.sort_unstable();
+    addresses.dedup();
+    addresses.len()
+}
+
+/// Splits `pointers` into those whose allocation is referred to by more than one of them, and
+/// those whose allocation occurs only once. Both parts keep the original order.
+#[cfg(any(feature = "arc", feature = "rc"))]
+pub fn partition_shared<T: ?Sized, P: ReferenceCounted<T>>(pointers: &[P]) -> (Vec<&P>, Vec<&P>) {
+    let mut addresses: Vec<usize> = pointers.iter().map(allocation_address::<T, P>).collect();
+    addresses.sort_unstable();
+
+    pointers.iter().partition(|p| {
+        let address = allocation_address::<T, P>(p);
+        let start = addresses.partition_point(|a| *a < address);
+        addresses.get(start + 1) == Some(&address)
+    })
+}
+
+/// The error returned by fallible constructors when the allocator fails to provide memory.
+#[cfg(any(feature = "arc", feature = "rc"))]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct AllocError;
+
+#[cfg(any(feature = "arc", feature = "rc"))]
+impl base::fmt::Display for AllocError {
+    fn fmt(&self, f: &mut base::fmt::Formatter<'_>) -> base::fmt::Result {
+        f.write_str("memory allocation failed")
+    }
+}
+
+#[cfg(any(feature = "arc", feature = "rc"))]
+impl core::error::Error for AllocError {}
+
+/// Called when incrementing a reference count would overflow it.
+///
+/// With the `panic-free` feature, this aborts the process via `std::process::abort`, so that
+/// cloning pointers involves no panicking code paths (and no formatting machinery). Otherwise,
+/// it panics.
+#[cfg(any(feature = "arc", feature = "rc"))]
+#[cold]
+#[inline(never)]
+pub(crate) fn refcount_overflow() -> ! {
+    #[cfg(feature = "panic-free")]
+    base::process::abort();
+    #[cfg(not(feature = "panic-free"))]
+    panic!();
+}
+
+/// Called when the size of an allocation would exceed `isize::MAX` bytes.
+///
+/// Like `refcount_overflow`, this aborts the process with the `panic-free` feature, and panics
+/// otherwise.
+#[cfg(any(feature = "arc", feature = "rc"))]
+#[cold]
+#[inline(never)]
+pub(crate) fn capacity_overflow() -> ! {
+    #[cfg(feature = "panic-free")]
+    base::process::abort();
+    #[cfg(not(feature = "panic-free"))]
+    core::panic!("capacity overflow");
+}
+
+/// Called when an iterator yields fewer items than the slice that is filled from it has room
+/// for, so that the slice can not be completed.
+///
+/// Like `refcount_overflow`, this aborts the process with the `panic-free` feature, and panics
+/// otherwise.
+#[cfg(any(feature = "arc", feature = "rc"))]
+#[cold]
+#[inline(never)]
+pub(crate) fn iterator_too_short() -> ! {
+    #[cfg(feature = "panic-free")]
+    base::process::abort();
+    #[cfg(not(feature = "panic-free"))]
+    core::panic!("iterator yielded fewer items than expected");
+}
+
+/// Checks at compile time that `T` and `U` have the same size and alignment, as required by the
+/// `cast` and `cast_slice` functions of the pointer types.
+///
+/// Evaluating `SameLayout::<T, U>::ASSERT` fails the build for any instantiation that violates
+/// this, instead of leaving it to a `debug_assert!` at run time.
+#[cfg(any(feature = "arc", feature = "rc"))]
+pub(crate) struct SameLayout<T, U>(base::marker::PhantomData<(T, U)>);
+
+#[cfg(any(feature = "arc", feature = "rc"))]
+impl<T, U> SameLayout<T, U> {
+    pub(crate) const ASSERT: () = core::assert!(
+        base::mem::size_of::<T>() == base::mem::size_of::<U>()
+            && base::mem::align_of::<T>() == base::mem::align_of::<U>(),
+        "the source and target types of a cast must have the same size and alignment"
+    );
+}
+
+/// Returns a fresh allocation id, see `Arc::allocation_id`. Ids start at one, zero is used for
+/// static allocations.
+#[cfg(all(feature = "allocation-id", any(feature = "arc", feature = "rc")))]
+pub(crate) fn next_allocation_id() -> u64 {
+    use base::sync::atomic::{AtomicU64, Ordering::Relaxed};
+
+    static NEXT_ID: AtomicU64 = AtomicU64::new(1);
+    NEXT_ID.fetch_add(1, Relaxed)
+}
+
+/// Converts `range` into the bounds of a subrange of `0..len`, panicking if it is out of
+/// bounds or decreasing.
+#[cfg(all(any(feature = "arc", feature = "rc"), not(feature = "panic-free")))]
+pub(crate) fn resolve_range(
+    range: impl base::ops::RangeBounds<usize>,
+    len: usize,
+) -> (usize, usize) {
+    use base::ops::Bound;
+
+    let start = match range.start_bound() {
+        Bound::Included(&start) => start,
+        Bound::Excluded(&start) => start.checked_add(1).expect("range start overflows"),
+        Bound::Unbounded => 0,
+    };
+    let end = match range.end_bound() {
+        Bound::Included(&end) => end.checked_add(1).expect("range end overflows"),
+        Bound::Excluded(&end) => end,
+        Bound::Unbounded => len,
+    };
+
+    if start > end {
+        panic!("range starts at {} but ends at {}", start, end);
+    }
+    if end > len {
+        panic!("range end {} out of range for length {}", end, len);
+    }
+    (start, end)
+}
+
+/// Copy the bytes of `s` (which must have length `N`) into an array.
+#[cfg(any(feature = "arc", feature = "rc"))]
+pub(crate) const fn str_to_array<const N: usize>(s: &str) -> [u8; N] {
+    let bytes = s.as_bytes();
+    assert!(bytes.len() == N);
+
+    let mut array = [0; N];
+    let mut i = 0;
+    while i < N {
+        array[i] = bytes[i];
+        i += 1;
+    }
+    array
+}
+
+/// Makes `candidate` share the allocation of `canonical` if their values are equal but live in
+/// different allocations. Returns whether `candidate` was replaced.
+///
+/// The old allocation of `candidate` is released like when dropping the pointer.
+pub fn unify<T: ?Sized + Eq, P: ReferenceCounted<T>>(canonical: &P, candidate: &mut P) -> bool {
+    if P::same_allocation(canonical, candidate) || **canonical != **candidate {
+        false
+    } else {
+        *candidate = canonical.clone();
+        true
+    }
+}
+
+/// Makes all pointers with equal values share a single allocation, namely that of the first of
+/// them. Returns the number of pointers that were replaced.
+#[cfg(feature = "std")]
+pub fn unify_all<T: ?Sized + Hash + Eq, P: ReferenceCounted<T>>(pointers: &mut [P]) -> usize {
+    let mut replacements = Vec::new();
+    {
+        let mut first: HashMap<&T, usize> = HashMap::new();
+        for (i, p) in pointers.iter().enumerate() {
+            match first.get(&**p) {
+                Some(&canonical) if !P::same_allocation(&pointers[canonical], p) => {
+                    replacements.push((i, canonical));
+                }
+                Some(_) => {}
+                None => {
+                    first.insert(&**p, i);
+                }
+            }
+        }
+    }
+
+    for &(i, canonical) in &replacements {
+        pointers[i] = pointers[canonical].clone();
+    }
+    replacements.len()
 }
 
 #[cfg(feature = "arc")]
@@ -22,7 +492,208 @@ mod arc;
 #[cfg(feature = "arc")]
 pub use arc::*;
 
-#[cfg(feature = "arc")]
+#[cfg(feature = "rc")]
 mod rc;
-#[cfg(feature = "arc")]
+#[cfg(feature = "rc")]
 pub use rc::*;
+
+#[cfg(all(feature = "arc", not(feature = "panic-free")))]
+mod cow_vec;
+#[cfg(all(feature = "arc", not(feature = "panic-free")))]
+pub use cow_vec::*;
+
+#[cfg(feature = "arc")]
+mod aligned;
+#[cfg(feature = "arc")]
+pub use aligned::*;
+
+#[cfg(all(feature = "arc", not(feature = "panic-free")))]
+mod lazy;
+#[cfg(all(feature = "arc", not(feature = "panic-free")))]
+pub use lazy::*;
+
+#[cfg(all(feature = "rc", not(feature = "panic-free")))]
+mod arena;
+#[cfg(all(feature = "rc", not(feature = "panic-free")))]
+pub use arena::*;
+
+#[cfg(all(feature = "testing", not(feature = "panic-free")))]
+mod testing;
+#[cfg(all(feature = "testing", not(feature = "panic-free")))]
+pub use testing::*;
+
+#[cfg(feature = "arc")]
+mod task;
+#[cfg(feature = "arc")]
+pub use task::*;
+
+#[cfg(feature = "arc")]
+mod keep_alive;
+#[cfg(feature = "arc")]
+pub use keep_alive::*;
+
+#[cfg(any(feature = "rc", feature = "arc"))]
+mod static_ref;
+#[cfg(any(feature = "rc", feature = "arc"))]
+pub use static_ref::*;
+
+#[cfg(all(any(feature = "arc", feature = "rc"), not(feature = "panic-free")))]
+mod view;
+
+#[cfg(all(feature = "arc", not(feature = "panic-free")))]
+mod arc_view;
+#[cfg(all(feature = "arc", not(feature = "panic-free")))]
+pub use arc_view::*;
+
+#[cfg(all(feature = "rc", not(feature = "panic-free")))]
+mod rc_view;
+#[cfg(all(feature = "rc", not(feature = "panic-free")))]
+pub use rc_view::*;
+
+#[cfg(feature = "arc")]
+mod external;
+#[cfg(feature = "arc")]
+pub use external::*;
+
+#[cfg(feature = "ffi")]
+mod ffi;
+
+#[cfg(all(feature = "arc", not(feature = "panic-free")))]
+mod handle_map;
+#[cfg(all(feature = "arc", not(feature = "panic-free")))]
+pub use handle_map::*;
+
+#[cfg(all(feature = "arc", feature = "std"))]
+mod drop_queue;
+#[cfg(all(feature = "arc", feature = "std"))]
+pub use drop_queue::*;
+
+#[cfg(all(feature = "arc", feature = "weak"))]
+mod shared_from_self;
+#[cfg(all(feature = "arc", feature = "weak"))]
+pub use shared_from_self::*;
+
+#[cfg(all(feature = "arc", feature = "weak"))]
+mod weak_list;
+#[cfg(all(feature = "arc", feature = "weak"))]
+pub use weak_list::*;
+
+#[cfg(all(feature = "arc", feature = "std", feature = "weak"))]
+mod weak_value_map;
+#[cfg(all(feature = "arc", feature = "std", feature = "weak"))]
+pub use weak_value_map::*;
+
+#[cfg(all(feature = "arc", feature = "std", feature = "weak"))]
+mod weak_key_map;
+#[cfg(all(feature = "arc", feature = "std", feature = "weak"))]
+pub use weak_key_map::*;
+
+#[cfg(all(feature = "arc", not(feature = "panic-free")))]
+mod tagged_arc;
+#[cfg(all(feature = "arc", not(feature = "panic-free")))]
+pub use tagged_arc::*;
+
+#[cfg(feature = "arc")]
+mod arc_cow;
+#[cfg(feature = "arc")]
+pub use arc_cow::*;
+
+#[cfg(feature = "rc")]
+mod rc_cow;
+#[cfg(feature = "rc")]
+pub use rc_cow::*;
+
+#[cfg(any(feature = "arc", feature = "rc"))]
+mod debug_graph;
+#[cfg(any(feature = "arc", feature = "rc"))]
+pub use debug_graph::*;
+
+#[cfg(any(feature = "threadsafe", feature = "rc"))]
+mod shared;
+#[cfg(any(feature = "threadsafe", feature = "rc"))]
+pub use shared::*;
+
+#[cfg(all(feature = "serde", feature = "weak", any(feature = "arc", feature = "rc")))]
+pub mod serde_weak;
+
+#[cfg(all(test, any(feature = "arc", feature = "rc")))]
+mod test_util;
+
+#[cfg(all(test, feature = "rc"))]
+mod tests {
+    extern crate std;
+
+    use super::*;
+    use base::string::String;
+    use smart_pointer::SmartPointer;
+
+    #[test]
+    fn allocation_identity_helpers() {
+        let a = Rc::new(1);
+        let b = Rc::new(1);
+        let c = Rc::new(2);
+        let pointers = std::vec![a.clone(), a.clone(), b.clone(), a.clone(), c.clone(), c.clone()];
+
+        assert_eq!(count_unique_allocations(&pointers), 3);
+        assert_eq!(count_unique_allocations::<i32, Rc<i32>, _>(&[]), 0);
+
+        let (shared, single) = partition_shared(&pointers);
+        assert_eq!(shared.len(), 5);
+        assert!(shared.iter().all(|p| !Rc::ptr_eq(p, &b)));
+        assert_eq!(single.len(), 1);
+        assert!(Rc::ptr_eq(single[0], &b));
+
+        let mut deduped = pointers.clone();
+        dedup_by_allocation(&mut deduped);
+        assert_eq!(deduped.len(), 4);
+        assert!(Rc::ptr_eq(&deduped[0], &a));
+        assert!(Rc::ptr_eq(&deduped[1], &b));
+        assert!(Rc::ptr_eq(&deduped[2], &a));
+        assert!(Rc::ptr_eq(&deduped[3], &c));
+    }
+
+    #[test]
+    fn allocation_identity_ignores_slice_metadata() {
+        let whole: Rc<[u8]> = Rc::from(&[1, 2, 3][..]);
+        let empty: Rc<[u8]> = Rc::from(&[][..]);
+        assert_eq!(count_unique_allocations(&[whole.clone(), whole, empty]), 2);
+    }
+
+    #[test]
+    fn unify_shares_equal_values() {
+        let canonical = Rc::new(String::from("a"));
+        let mut equal = Rc::new(String::from("a"));
+        let other = equal.clone();
+        let mut different = Rc::new(String::from("b"));
+
+        assert!(unify(&canonical, &mut equal));
+        assert!(Rc::same_allocation(&canonical, &equal));
+        assert_eq!(Rc::reference_count(&canonical).get(), 2);
+        // The old allocation lives on in `other`.
+        assert_eq!(Rc::reference_count(&other).get(), 1);
+        assert_eq!(*other, "a");
+
+        assert!(!unify(&canonical, &mut equal));
+        assert!(!unify(&canonical, &mut different));
+        assert!(!Rc::same_allocation(&canonical, &different));
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn unify_all_keeps_the_first_allocation() {
+        let first_a = Rc::new(1);
+        let mut pointers = std::vec![
+            first_a.clone(),
+            Rc::new(2),
+            Rc::new(1),
+            first_a.clone(),
+            Rc::new(1),
+            Rc::new(3),
+        ];
+        assert_eq!(unify_all(&mut pointers), 2);
+        assert!(pointers.iter().filter(|p| ***p == 1).all(|p| Rc::same_allocation(p, &first_a)));
+        assert_eq!(count_unique_allocations(&pointers), 3);
+        assert_eq!(Rc::reference_count(&first_a).get(), 5);
+        assert_eq!(unify_all(&mut pointers), 0);
+    }
+}
@@ -1,17 +1,29 @@
 // This code is adapted from the rust standard library Rc.
 
-use base::alloc::{dealloc, Layout};
-use base::borrow;
+use base::alloc::{alloc, dealloc, handle_alloc_error, realloc, Layout};
+use base::any::Any;
+use base::borrow::{self, Cow};
 use base::cell::Cell;
 use base::cmp::Ordering;
-use base::convert::{From, AsMut};
+use base::convert::{From, AsMut, Infallible};
 use base::fmt;
+#[cfg(any(feature = "tokio-io", feature = "futures-io"))]
+use base::io;
 use base::hash::{Hash, Hasher};
+use base::hint;
+use base::iter::{FromIterator, FusedIterator};
 use base::marker::{PhantomData, Unpin};
-use base::mem;
+#[cfg(feature = "unstable")]
+use base::marker::Tuple;
+use base::mem::{self, ManuallyDrop, MaybeUninit};
 use base::num::NonZeroUsize;
-use base::ops::{Deref, DerefMut};
+use base::ops::{Add, AddAssign, Deref, DerefMut};
+#[cfg(any(feature = "futures", feature = "tokio-io", feature = "futures-io"))]
+use base::pin::Pin;
 use base::ptr::{self, NonNull};
+use base::str::FromStr;
+#[cfg(any(feature = "futures", feature = "tokio-io", feature = "futures-io"))]
+use base::task::{Context, Poll};
 
 use base::borrow::BorrowMut;
 
@@ -19,21 +31,76 @@ use base::prelude::v1::*;
 
 use smart_pointer::{SmartPointer, IntoMut, SmartPointerMut};
 
-use crate::ReferenceCounted;
+use crate::{
+    capacity_overflow, iterator_too_short, refcount_overflow, str_to_array, AllocError,
+    FromOwned, ReferenceCounted, SameLayout,
+};
+#[cfg(feature = "weak")]
+use crate::{Downgradable, WeakPointer, WeaklyReferenceCounted};
+
+#[cfg(feature = "futures")]
+use futures_core::{FusedStream, Stream};
+#[cfg(feature = "futures")]
+use futures_sink::Sink;
+
+#[cfg(feature = "std")]
+use base::rc::Rc as StdRc;
+#[cfg(feature = "std")]
+use base::{ffi::{OsStr, OsString}, path::{Path, PathBuf}};
+
+use core::error::Error;
+use core::ffi::CStr;
+
+#[cfg(feature = "borsh")]
+use borsh::{io as borsh_io, BorshDeserialize, BorshSerialize};
+
+#[cfg(feature = "schemars")]
+use schemars::{JsonSchema, Schema, SchemaGenerator};
+
+#[cfg(all(feature = "serde", feature = "weak"))]
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+#[cfg(feature = "bincode")]
+use bincode::{
+    de::{BorrowDecoder, Decoder},
+    enc::Encoder,
+    error::{DecodeError, EncodeError},
+    BorrowDecode, Decode, Encode,
+};
+
+/// The reference counts of an `RcBox` that lives in static memory. Such counts are never
+/// modified, and the `RcBox` is never freed.
+///
+/// Counts of heap allocations can not reach this value, since `inc_strong` and `inc_weak` panic
+/// before.
+const STATIC_REFCOUNT: usize = usize::MAX;
 
 /// A non-thread-safe reference-counted pointer.
+///
+/// With the `weak` feature, `Rc::downgrade` creates a `WeakRc`, which does not keep the value
+/// alive. The value is dropped when the last `Rc` is, and the allocation is freed once no
+/// `WeakRc` remains either.
 pub struct Rc<T: ?Sized> {
     ptr: NonNull<RcBox<T>>,
     phantom: PhantomData<RcBox<T>>,
 }
 
+// `repr(C)` so that the offset of `data` can be computed from the layout of the header alone,
+// which is needed for allocating unsized values and for getting from a pointer to the value
+// back to the header (see `data_offset_val`).
+#[repr(C)]
 struct RcBox<T: ?Sized> {
     strong: Cell<usize>,
+    // The number of `WeakRc`s, plus one that is held by all `Rc`s together.
+    #[cfg(feature = "weak")]
+    weak: Cell<usize>,
+    #[cfg(feature = "allocation-id")]
+    id: u64,
     data: T,
 }
 
 impl<T: ?Sized> Rc<T> {
-    fn from_inner(ptr: NonNull<RcBox<T>>) -> Self {
+    const fn from_inner(ptr: NonNull<RcBox<T>>) -> Self {
         Self { ptr, phantom: PhantomData }
     }
 
@@ -52,23 +119,600 @@ impl<T: ?Sized> Rc<T> {
         self.inner().strong.get()
     }
 
+    /// Increments the reference count of a pointer to a heap allocation.
     #[inline]
     fn inc_strong(&self) {
         let strong = self.ref_count();
 
-        // We want to abort on overflow instead of dropping the value.
-        // The reference count will never be zero when this is called;
-        // nevertheless, we insert an abort here to hint LLVM at
-        // an otherwise missed optimization.
-        if strong == 0 || strong == usize::MAX {
-            panic!();
+        // `self` is a live `Rc` and hence holds one of the references it counts, so the count
+        // is not zero. The only allocations with a count of zero are those whose value has been
+        // moved out or is still being constructed by `new_cyclic`, and no `Rc` points to them.
+        // Telling the compiler lets it see that dropping a fresh clone never frees the value.
+        if strong == 0 {
+            unsafe { hint::unreachable_unchecked() };
+        }
+
+        // We want to panic on overflow instead of turning the allocation into a static one.
+        if strong == STATIC_REFCOUNT - 1 {
+            refcount_overflow();
         }
         self.inner().strong.set(strong + 1);
     }
 
+    /// Decrements the reference count of a pointer to a heap allocation, returning the new
+    /// count. A result of zero means that `self` was the last pointer to the allocation.
     #[inline]
-    fn dec_strong(&self) {
-        self.inner().strong.set(self.ref_count() - 1);
+    fn dec_strong(&self) -> usize {
+        let strong = self.ref_count() - 1;
+        self.inner().strong.set(strong);
+        strong
+    }
+
+    /// Allocates an `RcBox<T>` with sufficient space for a possibly-unsized inner value
+    /// where the value has the layout provided, and initializes the reference counts to one.
+    ///
+    /// The function `mem_to_rcbox` is called with the pointer to the start of the allocation
+    /// and must return back a (potentially fat) pointer for the `RcBox<T>`.
+    unsafe fn allocate_for_layout(
+        value_layout: Layout,
+        mem_to_rcbox: impl FnOnce(*mut u8) -> *mut RcBox<T>,
+    ) -> *mut RcBox<T> {
+        match unsafe { Self::try_allocate_for_layout(value_layout, mem_to_rcbox) } {
+            Ok(inner) => inner,
+            Err(AllocError) => handle_alloc_error(rcbox_layout_for_value_layout(value_layout)),
+        }
+    }
+
+    /// Like `allocate_for_layout`, but returns an error if the allocator fails instead of
+    /// calling `handle_alloc_error`.
+    unsafe fn try_allocate_for_layout(
+        value_layout: Layout,
+        mem_to_rcbox: impl FnOnce(*mut u8) -> *mut RcBox<T>,
+    ) -> Result<*mut RcBox<T>, AllocError> {
+        let layout = rcbox_layout_for_value_layout(value_layout);
+
+        let mem = unsafe { alloc(layout) };
+        if mem.is_null() {
+            return Err(AllocError);
+        }
+
+        let inner = mem_to_rcbox(mem);
+        unsafe { ptr::addr_of_mut!((*inner).strong).write(Cell::new(1)) };
+        #[cfg(feature = "weak")]
+        unsafe { ptr::addr_of_mut!((*inner).weak).write(Cell::new(1)) };
+        #[cfg(feature = "allocation-id")]
+        unsafe { ptr::addr_of_mut!((*inner).id).write(crate::next_allocation_id()) };
+        Ok(inner)
+    }
+}
+
+impl<T: ?Sized> Rc<T> {
+    /// Allocates an `RcBox<T>` and initializes its value with a bitwise copy of `v`.
+    ///
+    /// If `T` has a destructor, the caller must ensure that `v` is not dropped afterwards.
+    unsafe fn copy_from_value(v: &T) -> Rc<T> {
+        unsafe {
+            let value_layout = Layout::for_value(v);
+            let ptr = Rc::allocate_for_layout(value_layout, |mem| {
+                set_data_ptr(v as *const T as *mut RcBox<T>, mem)
+            });
+            ptr::copy_nonoverlapping(
+                v as *const T as *const u8,
+                ptr::addr_of_mut!((*ptr).data) as *mut u8,
+                value_layout.size(),
+            );
+            Self::from_inner(NonNull::new_unchecked(ptr))
+        }
+    }
+}
+
+/// Calculate the layout of an `RcBox<T>` whose value has the given layout.
+fn rcbox_layout_for_value_layout(value_layout: Layout) -> Layout {
+    // Calculate layout using the given value layout.
+    // Previously, layout was calculated on the expression
+    // `&*(ptr as *const RcBox<T>)`, but this created a misaligned
+    // reference (see rust-lang/rust#54908).
+    let layout = Layout::new::<RcBox<()>>().extend(value_layout);
+    layout.unwrap_or_else(|_| capacity_overflow()).0.pad_to_align()
+}
+
+/// Calculate the offset of `value` in its `RcBox`, for sized and unsized values alike.
+fn data_offset_val<T: ?Sized>(value: &T) -> usize {
+    // The value follows the header, padded to its alignment, as in
+    // `rcbox_layout_for_value_layout`. Only the alignment matters, which the pointer metadata
+    // determines for unsized values.
+    let value_layout = Layout::from_size_align(0, mem::align_of_val(value)).unwrap();
+    Layout::new::<RcBox<()>>().extend(value_layout).unwrap().1
+}
+
+/// Gives up the allocation of the `Rc`s once its value has been dropped or moved out. This frees
+/// it unless, with the `weak` feature, there still are `WeakRc`s.
+///
+/// Must not be called for static allocations.
+unsafe fn release_allocation<T: ?Sized>(ptr: NonNull<RcBox<T>>) {
+    // Drop the weak reference collectively held by all strong references.
+    #[cfg(feature = "weak")]
+    drop(WeakRc { ptr });
+
+    // The layout only depends on the size and alignment of the value, which are known from its
+    // type and the pointer metadata even after it has been dropped.
+    #[cfg(not(feature = "weak"))]
+    unsafe {
+        dealloc(ptr.as_ptr().cast(), Layout::for_value(ptr.as_ref()));
+    }
+}
+
+/// Sets the data pointer of a `?Sized` raw pointer, keeping its metadata.
+///
+/// For a slice or trait object, the data pointer is the first field of the fat pointer.
+unsafe fn set_data_ptr<T: ?Sized, U>(mut ptr: *mut T, data: *mut U) -> *mut T {
+    unsafe { ptr::write(&mut ptr as *mut _ as *mut *mut u8, data as *mut u8) };
+    ptr
+}
+
+impl<T> Rc<[T]> {
+    /// Allocates an `RcBox<[T]>` with the given length.
+    unsafe fn allocate_for_slice(len: usize) -> *mut RcBox<[T]> {
+        unsafe {
+            let layout = Layout::array::<T>(len).unwrap_or_else(|_| capacity_overflow());
+            Self::allocate_for_layout(layout, |mem| {
+                ptr::slice_from_raw_parts_mut(mem as *mut T, len) as *mut RcBox<[T]>
+            })
+        }
+    }
+
+    /// Constructs an `Rc<[T]>` from an iterator that yields `len` items.
+    ///
+    /// Panics if the iterator yields fewer items, and ignores any items past the first `len`.
+    fn from_iter_exact(iter: impl Iterator<Item = T>, len: usize) -> Rc<[T]> {
+        // Panic guard while producing the elements. In the event of a panic, elements that
+        // have been written into the new `RcBox` will be dropped, then the memory freed.
+        struct Guard<T> {
+            mem: *mut u8,
+            elems: *mut T,
+            layout: Layout,
+            n_elems: usize,
+        }
+
+        impl<T> Drop for Guard<T> {
+            fn drop(&mut self) {
+                unsafe {
+                    let slice = ptr::slice_from_raw_parts_mut(self.elems, self.n_elems);
+                    ptr::drop_in_place(slice);
+
+                    dealloc(self.mem, self.layout);
+                }
+            }
+        }
+
+        unsafe {
+            let ptr = Self::allocate_for_slice(len);
+
+            let value_layout = Layout::array::<T>(len).unwrap_or_else(|_| capacity_overflow());
+            let mut guard = Guard {
+                mem: ptr as *mut u8,
+                elems: ptr::addr_of_mut!((*ptr).data) as *mut T,
+                layout: rcbox_layout_for_value_layout(value_layout),
+                n_elems: 0,
+            };
+
+            for item in iter.take(len) {
+                ptr::write(guard.elems.add(guard.n_elems), item);
+                guard.n_elems += 1;
+            }
+
+            if guard.n_elems != len {
+                iterator_too_short();
+            }
+
+            // All clear. Forget the guard so it doesn't free the new RcBox.
+            mem::forget(guard);
+
+            Self::from_inner(NonNull::new_unchecked(ptr))
+        }
+    }
+}
+
+impl<T> Rc<T> {
+    /// Constructs a new `Rc<T>`, returning an error if the allocation fails.
+    ///
+    /// On failure, `value` is dropped and no memory is left allocated.
+    pub fn try_new(value: T) -> Result<Rc<T>, AllocError> {
+        unsafe {
+            let ptr = Rc::try_allocate_for_layout(Layout::new::<T>(), |mem| mem as *mut RcBox<T>)?;
+            ptr::addr_of_mut!((*ptr).data).write(value);
+            Ok(Self::from_inner(NonNull::new_unchecked(ptr)))
+        }
+    }
+
+    /// Constructs a new `Rc` with uninitialized contents.
+    ///
+    /// The returned pointer is unique, so the contents can be initialized in place, before
+    /// converting it into a `UniqueRc<T>` via `UniqueRc::assume_init`.
+    pub fn new_uninit() -> UniqueRc<MaybeUninit<T>> {
+        UniqueRc::new(MaybeUninit::uninit())
+    }
+
+    /// Constructs a new `Rc` with uninitialized contents, returning an error if the allocation
+    /// fails.
+    pub fn try_new_uninit() -> Result<UniqueRc<MaybeUninit<T>>, AllocError> {
+        Rc::try_new(MaybeUninit::uninit()).map(UniqueRc)
+    }
+}
+
+impl<T> Rc<T> {
+    /// Reinterprets the pointer as pointing to a `U`, without touching the allocation.
+    ///
+    /// `T` and `U` must have the same size and alignment, so that the value is found at the same
+    /// place and the allocation is freed with the layout it was created with. This is checked at
+    /// compile time:
+    ///
+    /// ```compile_fail
+    /// use reference_counted::Rc;
+    /// use smart_pointer::SmartPointer;
+    ///
+    /// let wide = unsafe { Rc::cast::<u64>(Rc::new(0_u32)) };
+    /// ```
+    ///
+    /// # Safety
+    ///
+    /// - The value must be a valid `U`.
+    /// - Other pointers to the allocation continue to access the value as a `T`, so accessing
+    ///   it as a `T` and as a `U` at the same time must be sound.
+    /// - Whichever pointer is dropped last drops the value, either as a `T` or as a `U`, so
+    ///   both must be correct. This holds for example if `U` is a `#[repr(transparent)]`
+    ///   wrapper around `T` that does not implement `Drop`.
+    pub unsafe fn cast<U>(this: Self) -> Rc<U> {
+        let () = SameLayout::<T, U>::ASSERT;
+        let ptr = this.ptr;
+        mem::forget(this);
+        Rc::from_inner(ptr.cast::<RcBox<U>>())
+    }
+}
+
+impl<T> Rc<[T]> {
+    /// Reinterprets the pointer as pointing to a slice of `U`s of the same length, without
+    /// touching the allocation.
+    ///
+    /// # Safety
+    ///
+    /// The requirements of `Rc::cast` apply to the element types `T` and `U`, whose layouts
+    /// are checked at compile time as well.
+    pub unsafe fn cast_slice<U>(this: Self) -> Rc<[U]> {
+        let () = SameLayout::<T, U>::ASSERT;
+        let len = this.len();
+        let ptr = this.ptr;
+        mem::forget(this);
+        let ptr = ptr::slice_from_raw_parts_mut(ptr.as_ptr() as *mut U, len) as *mut RcBox<[U]>;
+        Rc::from_inner(unsafe { NonNull::new_unchecked(ptr) })
+    }
+}
+
+impl<T: Copy> Rc<[T]> {
+    /// Allocates a reference-counted slice and copies the items of `src` into it.
+    ///
+    /// This is equivalent to `Rc::from(src)`, but copies all items at once rather than cloning
+    /// them one by one.
+    pub fn copy_from_slice(src: &[T]) -> Rc<[T]> {
+        unsafe {
+            let ptr = Rc::allocate_for_slice(src.len());
+            ptr::copy_nonoverlapping(
+                src.as_ptr(),
+                ptr::addr_of_mut!((*ptr).data) as *mut T,
+                src.len(),
+            );
+            Self::from_inner(NonNull::new_unchecked(ptr))
+        }
+    }
+}
+
+impl<T: Clone> Rc<[T]> {
+    /// Allocates a reference-counted slice of `n` copies of `elem`, cloning it `n - 1` times.
+    ///
+    /// This is the counterpart of `vec![elem; n]`, and what `rc![elem; n]` expands to.
+    pub fn from_elem(elem: T, n: usize) -> Rc<[T]> {
+        // The last item is `elem` itself. For `n == 0`, it is dropped along with the iterator.
+        let mut elem = Some(elem);
+        let items = (1..=n).filter_map(move |i| if i < n { elem.clone() } else { elem.take() });
+        Rc::from_iter_exact(items, n)
+    }
+}
+
+impl<T: ?Sized> Rc<T>
+where
+    for<'a> Rc<T>: From<&'a T>,
+{
+    /// Returns a mutable reference to the value, e.g. of an `Rc<[T]>` or an `Rc<str>`, first
+    /// copying it into a new allocation with `From<&T>` if other pointers to it exist.
+    pub fn make_mut(this: &mut Self) -> &mut T {
+        if !Rc::can_make_mut(this) {
+            *this = Rc::from(&**this);
+        }
+        unsafe { Rc::get_mut_unchecked(this) }
+    }
+}
+
+impl Rc<str> {
+    /// Appends `s` to the string. If this is the only reference to the allocation, it is grown
+    /// in place (which may still move it), otherwise both strings are copied into a new
+    /// allocation of the combined length.
+    fn append(this: &mut Self, s: &str) {
+        if s.is_empty() {
+            return;
+        }
+        let len = this.len();
+        let new_len = len.checked_add(s.len()).unwrap_or_else(|| capacity_overflow());
+        let unique = Rc::can_make_mut(this);
+
+        unsafe {
+            let inner = if unique {
+                let old_layout = rcbox_layout_for_value_layout(Layout::for_value(&**this));
+                let new_value_layout =
+                    Layout::array::<u8>(new_len).unwrap_or_else(|_| capacity_overflow());
+                let new_layout = rcbox_layout_for_value_layout(new_value_layout);
+                let mem = realloc(this.ptr() as *mut u8, old_layout, new_layout.size());
+                if mem.is_null() {
+                    handle_alloc_error(new_layout);
+                }
+                ptr::slice_from_raw_parts_mut(mem, new_len) as *mut RcBox<[u8]>
+            } else {
+                let inner = Rc::<[u8]>::allocate_for_slice(new_len);
+                let data = ptr::addr_of_mut!((*inner).data) as *mut u8;
+                ptr::copy_nonoverlapping(this.as_ptr(), data, len);
+                inner
+            };
+            let data = ptr::addr_of_mut!((*inner).data) as *mut u8;
+            ptr::copy_nonoverlapping(s.as_ptr(), data.add(len), s.len());
+
+            // The bytes are valid utf-8, and `RcBox<str>` has the same layout as `RcBox<[u8]>`.
+            let new = Rc::from_inner(NonNull::new_unchecked(inner as *mut RcBox<str>));
+            if unique {
+                // The old allocation has been reallocated, so it must not be released.
+                ptr::write(this, new);
+            } else {
+                *this = new;
+            }
+        }
+    }
+}
+
+impl Add<&str> for Rc<str> {
+    type Output = Rc<str>;
+
+    /// Concatenates the strings, reusing the allocation of `self` if it is the only reference
+    /// to it.
+    fn add(mut self, rhs: &str) -> Rc<str> {
+        self += rhs;
+        self
+    }
+}
+
+impl Add<Rc<str>> for Rc<str> {
+    type Output = Rc<str>;
+
+    /// Concatenates the strings, reusing the allocation of `self` if it is the only reference
+    /// to it.
+    fn add(self, rhs: Rc<str>) -> Rc<str> {
+        self + &*rhs
+    }
+}
+
+impl AddAssign<&str> for Rc<str> {
+    /// Appends `rhs`, growing the allocation in place if this is the only reference to it, and
+    /// copying both strings into a new allocation otherwise.
+    fn add_assign(&mut self, rhs: &str) {
+        Rc::append(self, rhs);
+    }
+}
+
+impl<T: Clone> Rc<Vec<T>> {
+    /// Converts an `Rc<Vec<T>>` into an `Rc<[T]>` that stores the elements directly in the
+    /// reference-counted allocation, saving a pointer indirection and the excess capacity.
+    ///
+    /// If this is the only reference to the vector, its elements are moved into the new
+    /// allocation, otherwise they are cloned. The buffer of the vector can not be reused even
+    /// if it has no excess capacity, since it has no room for the reference count.
+    pub fn flatten(self) -> Rc<[T]> {
+        match Rc::try_unwrap(self) {
+            Ok(v) => Rc::from(v),
+            Err(this) => Rc::from(&this[..]),
+        }
+    }
+}
+
+impl Rc<String> {
+    /// Converts an `Rc<String>` into an `Rc<str>` that stores the string directly in the
+    /// reference-counted allocation, saving a pointer indirection and the excess capacity.
+    ///
+    /// If this is the only reference to the string, its contents are moved into the new
+    /// allocation, otherwise they are copied. The buffer of the string can not be reused even
+    /// if it has no excess capacity, since it has no room for the reference count.
+    pub fn flatten(self) -> Rc<str> {
+        match Rc::try_unwrap(self) {
+            Ok(s) => Rc::from(s),
+            Err(this) => Rc::from(&this[..]),
+        }
+    }
+}
+
+impl Rc<str> {
+    /// Constructs a reference-counted string from the `Display` output of a value.
+    ///
+    /// The value is formatted into a `String` first, whose contents are then moved into an
+    /// exact-size allocation. Formatting twice (once to measure the length, once to write into
+    /// the final allocation) would avoid that intermediate buffer, but `Display`
+    /// implementations are not guaranteed to produce the same output both times.
+    pub fn from_display<D: ?Sized + fmt::Display>(value: &D) -> Rc<str> {
+        Rc::from(value.to_string())
+    }
+}
+
+/// Creates an `Rc<str>` using interpolation of runtime expressions, like `format!` does for
+/// `String`.
+#[macro_export]
+macro_rules! format_rc {
+    ($($arg:tt)*) => {
+        $crate::Rc::<str>::from_display(&format_args!($($arg)*))
+    };
+}
+
+/// The reference-counted allocation of an `Rc`, placed in static memory instead of on the heap.
+///
+/// An `Rc` obtained via `Rc::from_static` does not keep count of its references: cloning and
+/// dropping it do not modify the allocation, which is never freed. Its
+/// `ReferenceCounted::reference_count` is always `usize::MAX`, so it can never be converted into
+/// a `UniqueRc` (or unwrapped).
+///
+/// ```
+/// use reference_counted::{Rc, StaticRcInner};
+///
+/// static ANSWER: StaticRcInner<u32> = StaticRcInner::new(42);
+///
+/// let a = Rc::from_static(&ANSWER);
+/// assert_eq!(*a.clone(), 42);
+/// ```
+#[repr(transparent)]
+pub struct StaticRcInner<T: ?Sized>(RcBox<T>);
+
+// The count of a static allocation is only ever read, never written, so it may be shared
+// between threads. The `Rc`s pointing to it still can not be sent to other threads.
+unsafe impl<T: ?Sized + Sync> Sync for StaticRcInner<T> {}
+
+impl<T> StaticRcInner<T> {
+    /// Creates an allocation for `Rc::from_static` that contains the given value.
+    pub const fn new(data: T) -> Self {
+        StaticRcInner(RcBox {
+            strong: Cell::new(STATIC_REFCOUNT),
+            #[cfg(feature = "weak")]
+            weak: Cell::new(STATIC_REFCOUNT),
+            #[cfg(feature = "allocation-id")]
+            id: 0,
+            data,
+        })
+    }
+}
+
+impl<T: ?Sized> Rc<T> {
+    /// Creates an `Rc` that points to a static allocation, without allocating. This can be used
+    /// in const contexts.
+    ///
+    /// Cloning and dropping the resulting pointers do not touch any reference count.
+    pub const fn from_static(inner: &'static StaticRcInner<T>) -> Rc<T> {
+        // The data is never accessed mutably, since `ReferenceCounted::reference_count` is
+        // never one for static allocations.
+        let ptr = &inner.0 as *const RcBox<T> as *mut RcBox<T>;
+        Self::from_inner(unsafe { NonNull::new_unchecked(ptr) })
+    }
+}
+
+/// A static allocation for an `Rc<str>`, used by the `rc_str` macro. Since it can only be
+/// constructed from a `str`, its bytes are guaranteed to be valid utf-8.
+#[doc(hidden)]
+pub struct StaticRcStr<const N: usize>(StaticRcInner<[u8; N]>);
+
+impl<const N: usize> StaticRcStr<N> {
+    #[doc(hidden)]
+    pub const fn new(s: &str) -> Self {
+        StaticRcStr(StaticRcInner::new(str_to_array(s)))
+    }
+}
+
+impl Rc<str> {
+    #[doc(hidden)]
+    pub fn from_static_str<const N: usize>(inner: &'static StaticRcStr<N>) -> Rc<str> {
+        let rc: Rc<[u8]> = Rc::from_static(&inner.0);
+        // The bytes are valid utf-8, and `RcBox<str>` has the same layout as `RcBox<[u8]>`.
+        let ptr = rc.ptr() as *mut RcBox<str>;
+        mem::forget(rc);
+        unsafe { Self::from_inner(NonNull::new_unchecked(ptr)) }
+    }
+}
+
+/// Creates an `Rc<[T]>` containing the arguments, like `vec!` but in a single
+/// reference-counted allocation of the exact size.
+///
+/// Unlike `rc_slice!`, the elements are evaluated at runtime.
+///
+/// ```
+/// use reference_counted::{rc, Rc};
+///
+/// let xs: Rc<[i32]> = rc![1, 2, 3];
+/// assert_eq!(&*xs, &[1, 2, 3]);
+///
+/// let zeros: Rc<[u8]> = rc![0; 1024];
+/// assert_eq!(zeros.len(), 1024);
+/// ```
+#[macro_export]
+macro_rules! rc {
+    () => {
+        $crate::Rc::<[_]>::from([])
+    };
+    ($elem:expr; $n:expr) => {
+        $crate::Rc::<[_]>::from_elem($elem, $n)
+    };
+    ($($x:expr),+ $(,)?) => {
+        $crate::Rc::<[_]>::from([$($x),+])
+    };
+}
+
+/// Creates an `Rc<str>` from a string literal (or any constant string expression) without
+/// allocating, by placing it in a `StaticRcInner`.
+///
+/// ```
+/// use reference_counted::{rc_str, Rc};
+///
+/// let greeting: Rc<str> = rc_str!("hello");
+/// assert_eq!(&*greeting, "hello");
+/// ```
+#[macro_export]
+macro_rules! rc_str {
+    ($s:expr) => {{
+        static INNER: $crate::StaticRcStr<{ $s.len() }> = $crate::StaticRcStr::new($s);
+        $crate::Rc::<str>::from_static_str(&INNER)
+    }};
+}
+
+/// Creates an `Rc<[T]>` from constant expressions without allocating, by placing them in a
+/// `StaticRcInner`.
+///
+/// Since the type of a static can not be inferred, the element type has to be given before
+/// the elements.
+///
+/// ```
+/// use reference_counted::{rc_slice, Rc};
+///
+/// let primes: Rc<[u8]> = rc_slice![u8 => 2, 3, 5, 7];
+/// assert_eq!(&*primes, &[2, 3, 5, 7]);
+/// ```
+#[macro_export]
+macro_rules! rc_slice {
+    ($t:ty => $($x:expr),* $(,)?) => {{
+        static INNER: $crate::StaticRcInner<[$t; <[&str]>::len(&[$(stringify!($x)),*])]> =
+            $crate::StaticRcInner::new([$($x),*]);
+        $crate::Rc::<[$t]>::from_static(&INNER)
+    }};
+}
+
+#[cfg(feature = "allocation-id")]
+impl<T: ?Sized> Rc<T> {
+    /// Gets the id of the allocation, which is assigned from a global counter when it is
+    /// created and hence never reused while the process runs. All clones share the id, and it
+    /// is kept when converting to and from a `UniqueRc`.
+    ///
+    /// Static allocations (e.g. from `Rc::from_static`) all have the id zero.
+    ///
+    /// The id is stored in every allocation, adding eight bytes (plus padding) of overhead
+    /// when the `allocation-id` feature is enabled.
+    pub fn allocation_id(this: &Self) -> u64 {
+        this.inner().id
+    }
+}
+
+#[cfg(feature = "allocation-id")]
+impl<T: ?Sized> UniqueRc<T> {
+    /// Gets the id of the allocation, see `Rc::allocation_id`.
+    pub fn allocation_id(this: &Self) -> u64 {
+        Rc::allocation_id(&this.0)
     }
 }
 
@@ -78,11 +722,81 @@ impl<T: ?Sized> Clone for Rc<T> {
     /// This creates another pointer to the same allocation, increasing the reference count.
     #[inline]
     fn clone(&self) -> Rc<T> {
-        self.inc_strong();
+        // Pointers to static memory are not counted.
+        if self.ref_count() != STATIC_REFCOUNT {
+            self.inc_strong();
+        }
         Self::from_inner(self.ptr)
     }
 }
 
+impl<T: ?Sized> Rc<T> {
+    /// Makes `n` clones of the `Rc` pointer, increasing the reference count only once.
+    ///
+    /// The clones are produced by the returned iterator. Those that have not been produced by
+    /// the time it is dropped are released again, with a single update of the reference count.
+    pub fn clone_many(this: &Self, n: usize) -> RcClones<T> {
+        // Pointers to static memory are not counted.
+        let strong = this.ref_count();
+        if strong != STATIC_REFCOUNT {
+            match strong.checked_add(n) {
+                Some(new) if new != STATIC_REFCOUNT => this.inner().strong.set(new),
+                _ => refcount_overflow(),
+            }
+        }
+
+        RcClones { ptr: this.ptr, remaining: n }
+    }
+}
+
+/// An iterator over clones of an `Rc`, whose reference counts have been acquired in advance.
+///
+/// Created by `Rc::clone_many`.
+pub struct RcClones<T: ?Sized> {
+    ptr: NonNull<RcBox<T>>,
+    remaining: usize,
+}
+
+impl<T: ?Sized> Iterator for RcClones<T> {
+    type Item = Rc<T>;
+
+    fn next(&mut self) -> Option<Rc<T>> {
+        if self.remaining == 0 {
+            None
+        } else {
+            self.remaining -= 1;
+            Some(Rc::from_inner(self.ptr))
+        }
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.remaining, Some(self.remaining))
+    }
+}
+
+impl<T: ?Sized> ExactSizeIterator for RcClones<T> {}
+
+impl<T: ?Sized> FusedIterator for RcClones<T> {}
+
+impl<T: ?Sized> Drop for RcClones<T> {
+    fn drop(&mut self) {
+        if self.remaining == 0 {
+            return;
+        }
+
+        let last = Rc::from_inner(self.ptr);
+        let strong = last.ref_count();
+        if strong != STATIC_REFCOUNT {
+            // Release all but one of the remaining references. This can not drop the count to
+            // zero, since `last` still holds a reference.
+            last.inner().strong.set(strong - (self.remaining - 1));
+        }
+        // Dropping the last one goes through the regular path, since the original `Rc` may be
+        // gone already.
+        drop(last);
+    }
+}
+
 impl<T: ?Sized> Drop for Rc<T> {
     /// Drops the `Rc`.
     ///
@@ -109,13 +823,18 @@ impl<T: ?Sized> Drop for Rc<T> {
     /// ```
     #[inline]
     fn drop(&mut self) {
-        unsafe {
-            self.dec_strong();
-            if self.ref_count() == 0 {
-                // destroy the contained object
-                ptr::drop_in_place(self.ptr.as_mut());
+        // Pointers to static memory are not counted, and the memory is never freed.
+        if self.ref_count() == STATIC_REFCOUNT {
+            return;
+        }
+
+        if self.dec_strong() == 0 {
+            unsafe {
+                // Destroy the contained object. Like `Arc`, we are careful to not create a
+                // reference covering the count.
+                ptr::drop_in_place(ptr::addr_of_mut!((*self.ptr()).data));
 
-                dealloc(self.ptr().cast(), Layout::for_value(self.ptr.as_ref()));
+                release_allocation(self.ptr);
             }
         }
     }
@@ -154,335 +873,2834 @@ impl<T: ?Sized + fmt::Debug> fmt::Debug for Rc<T> {
     }
 }
 
-impl<T: ?Sized> fmt::Pointer for Rc<T> {
-    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        fmt::Pointer::fmt(&(&**self as *const T), f)
+impl<T: ?Sized + Error> Error for Rc<T> {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        (**self).source()
     }
 }
 
-impl<T: ?Sized> SmartPointer<T> for Rc<T> {
-    fn new(data: T) -> Rc<T> where T: Sized {
-        Self::from_inner(
-            Box::leak(Box::new(RcBox { strong: Cell::new(1), data })).into(),
-        )
+impl From<String> for Rc<dyn Error> {
+    /// Creates an error whose `Display` and `Debug` output is the given message.
+    fn from(message: String) -> Rc<dyn Error> {
+        Rc::<dyn Error>::from_error(StringError(message))
     }
+}
 
-    fn try_unwrap(this: Self) -> Result<T, Self> where T: Sized {
-        if Rc::ref_count(&this) == 1 {
-            unsafe {
-                let val = ptr::read(&*this); // copy the contained object
-                dealloc(this.ptr().cast(), Layout::for_value(this.ptr.as_ref()));
-                mem::forget(this);
-                Ok(val)
-            }
-        } else {
-            Err(this)
-        }
+impl From<&str> for Rc<dyn Error> {
+    /// Creates an error whose `Display` and `Debug` output is the given message.
+    fn from(message: &str) -> Rc<dyn Error> {
+        Rc::from(String::from(message))
     }
 }
 
-pub struct UniqueRc<T: ?Sized>(Rc<T>);
-
-unsafe impl<T: ?Sized + Sync + Send> Send for UniqueRc<T> {}
-unsafe impl<T: ?Sized + Sync + Send> Sync for UniqueRc<T> {}
-
-impl<T: ?Sized> Deref for UniqueRc<T> {
-    type Target = T;
+/// The error created from a message by `From<String> for Rc<dyn Error>`.
+struct StringError(String);
 
-    #[inline]
-    fn deref(&self) -> &T {
-        self.0.deref()
+impl fmt::Display for StringError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Display::fmt(&self.0, f)
     }
 }
 
-impl<T: ?Sized> borrow::Borrow<T> for UniqueRc<T> {
-    fn borrow(&self) -> &T {
-        self.0.borrow()
+impl fmt::Debug for StringError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Debug::fmt(&self.0, f)
     }
 }
 
-impl<T: ?Sized> AsRef<T> for UniqueRc<T> {
-    fn as_ref(&self) -> &T {
-        self.0.as_ref()
+impl Error for StringError {}
+
+impl<'a> Rc<dyn Error + 'a> {
+    /// Moves an error into a new reference-counted allocation.
+    ///
+    /// This is not a `From` impl, since `Rc<dyn Error>` implements `Error` itself, so a blanket
+    /// impl would overlap with `From<T> for T`. Already boxed errors can be converted with
+    /// `From<Box<T>>`.
+    pub fn from_error<E: Error + 'a>(err: E) -> Self {
+        Rc::from(Box::new(err) as Box<dyn Error + 'a>)
     }
 }
 
-impl<T: ?Sized + fmt::Display> fmt::Display for UniqueRc<T> {
-    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        self.0.fmt(f)
+impl Rc<dyn Any> {
+    /// Attempts to convert the pointer into one to the concrete type `T`, reusing the
+    /// allocation. Returns the pointer unchanged if the value is of a different type.
+    pub fn downcast<T: Any>(this: Self) -> Result<Rc<T>, Self> {
+        if this.is::<T>() {
+            let ptr = this.ptr() as *mut RcBox<T>;
+            mem::forget(this);
+            // The allocation holds a `T`, so the layout of `RcBox<T>` applies.
+            Ok(unsafe { Rc::from_inner(NonNull::new_unchecked(ptr)) })
+        } else {
+            Err(this)
+        }
     }
 }
 
-impl<T: ?Sized + fmt::Debug> fmt::Debug for UniqueRc<T> {
-    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        self.0.fmt(f)
+impl UniqueRc<dyn Any> {
+    /// Attempts to convert the pointer into one to the concrete type `T`, reusing the
+    /// allocation and keeping it unique. Returns the pointer unchanged if the value is of a
+    /// different type.
+    pub fn downcast<T: Any>(this: Self) -> Result<UniqueRc<T>, Self> {
+        Rc::<dyn Any>::downcast(this.0).map(UniqueRc).map_err(UniqueRc)
     }
 }
 
-impl<T: ?Sized> fmt::Pointer for UniqueRc<T> {
-    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        self.0.fmt(f)
+#[cfg(not(feature = "panic-free"))]
+impl<T: ?Sized> Rc<T> {
+    /// Converts the pointer into one to a `U` that `f` borrows from the value, reusing the
+    /// allocation and the reference count.
+    ///
+    /// This is mostly useful for converting a trait object into one of a supertrait, e.g.
+    /// `Rc::upcast(sub, |value| value as &dyn Super)`, see also `upcast_rc!`. On compilers
+    /// without trait upcasting coercion, `f` can call a method of the subtrait that returns
+    /// `&dyn Super`.
+    ///
+    /// Panics if the reference returned by `f` does not cover exactly the value, i.e. if it
+    /// has a different address, size or alignment.
+    pub fn upcast<U: ?Sized>(this: Self, f: impl FnOnce(&T) -> &U) -> Rc<U> {
+        let target = {
+            let value = &*this;
+            let target = f(value);
+            // `assert!` with a message expands to a bare `panic!` in this edition, which is
+            // ambiguous with the prelude glob import.
+            if !(ptr::eq(value as *const T as *const u8, target as *const U as *const u8)
+                && Layout::for_value(value) == Layout::for_value(target))
+            {
+                core::panic!("the upcast reference must cover exactly the value");
+            }
+            target as *const U as *mut U
+        };
+        let ptr = this.ptr();
+        mem::forget(this);
+        // The value is a valid `U` at the same place, and dropping it as a `U` deallocates it
+        // with the same layout.
+        let ptr = unsafe { set_data_ptr(target as *mut RcBox<U>, ptr as *mut u8) };
+        Rc::from_inner(unsafe { NonNull::new_unchecked(ptr) })
     }
 }
 
-impl<T: ?Sized> SmartPointer<T> for UniqueRc<T> {
-    fn new(data: T) -> Self where T: Sized {
-        UniqueRc(Rc::new(data))
+#[cfg(not(feature = "panic-free"))]
+impl<T: ?Sized> UniqueRc<T> {
+    /// Converts the pointer into one to a `U` that `f` borrows from the value, keeping it
+    /// unique. See `Rc::upcast`.
+    pub fn upcast<U: ?Sized>(this: Self, f: impl FnOnce(&T) -> &U) -> UniqueRc<U> {
+        UniqueRc(Rc::upcast(this.0, f))
     }
+}
 
-    fn try_unwrap(this: Self) -> Result<T, Self> where T: Sized {
-        let this = this.0;
+/// Converts an `Rc<dyn Sub>` into an `Rc<dyn Super>`, where `Super` is a supertrait of
+/// `Sub`, reusing the allocation and the reference count.
+///
+/// This expands to a call of `Rc::upcast` and relies on trait upcasting coercion.
+///
+/// ```
+/// use reference_counted::{upcast_rc, Rc};
+///
+/// trait Named {
+///     fn name(&self) -> &str;
+/// }
+///
+/// trait Greeter: Named {
+///     fn greet(&self) -> String {
+///         format!("hello, {}", self.name())
+///     }
+/// }
+///
+/// struct World;
+///
+/// impl Named for World {
+///     fn name(&self) -> &str {
+///         "world"
+///     }
+/// }
+///
+/// impl Greeter for World {}
+///
+/// let greeter: Rc<dyn Greeter> = Rc::from(Box::new(World) as Box<dyn Greeter>);
+/// let named = upcast_rc!(greeter, dyn Greeter => dyn Named);
+/// assert_eq!(named.name(), "world");
+/// ```
+#[cfg(not(feature = "panic-free"))]
+#[macro_export]
+macro_rules! upcast_rc {
+    ($rc:expr, $sub:ty => $sup:ty) => {
+        $crate::Rc::<$sub>::upcast::<$sup>($rc, |value| value)
+    };
+}
 
-        unsafe {
-            let elem = ptr::read(&this.ptr.as_ref().data);
-            dealloc(this.ptr().cast(), Layout::for_value(this.ptr.as_ref()));
+impl Rc<dyn Error> {
+    /// Attempts to convert the pointer into one to the concrete error type `E`, returning it
+    /// unchanged if the error is of a different type.
+    pub fn downcast<E: Error + 'static>(this: Self) -> Result<Rc<E>, Self> {
+        if this.is::<E>() {
+            let ptr = this.ptr() as *mut RcBox<E>;
             mem::forget(this);
-            Ok(elem)
+            // The allocation holds an `E`, so the layout of `RcBox<E>` applies.
+            Ok(unsafe { Rc::from_inner(NonNull::new_unchecked(ptr)) })
+        } else {
+            Err(this)
         }
     }
 }
 
-
-impl<T: ?Sized> DerefMut for UniqueRc<T> {
-    fn deref_mut(&mut self) -> &mut T {
-        // We know this to be uniquely owned
-        unsafe { &mut (*self.0.ptr()).data }
+impl<T: ?Sized> fmt::Pointer for Rc<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Pointer::fmt(&(&**self as *const T), f)
     }
 }
 
-impl<T: ?Sized> BorrowMut<T> for UniqueRc<T> {
-    fn borrow_mut(&mut self) -> &mut T {
-        &mut **self
+impl<T: ?Sized> SmartPointer<T> for Rc<T> {
+    fn new(data: T) -> Rc<T> where T: Sized {
+        Self::from_inner(
+            Box::leak(Box::new(RcBox {
+                strong: Cell::new(1),
+                #[cfg(feature = "weak")]
+                weak: Cell::new(1),
+                #[cfg(feature = "allocation-id")]
+                id: crate::next_allocation_id(),
+                data,
+            }))
+            .into(),
+        )
     }
-}
 
-impl<T: ?Sized> AsMut<T> for UniqueRc<T> {
-    fn as_mut(&mut self) -> &mut T {
-        &mut **self
+    fn try_unwrap(this: Self) -> Result<T, Self> where T: Sized {
+        if Rc::ref_count(&this) == 1 {
+            unsafe {
+                let val = ptr::read(&*this); // copy the contained object
+
+                // The value has been moved out, so the strong count drops to zero without
+                // dropping it.
+                this.inner().strong.set(0);
+                let ptr = this.ptr;
+                mem::forget(this);
+                release_allocation(ptr);
+                Ok(val)
+            }
+        } else {
+            Err(this)
+        }
     }
 }
 
-impl<T: ?Sized> SmartPointerMut<T> for UniqueRc<T> {}
+impl<T: ?Sized> Rc<T> {
+    /// Consumes the `Rc` without releasing its reference, and returns a pointer to the value.
+    /// Use `Rc::from_raw` to turn the pointer back into an `Rc`, or the allocation is leaked.
+    pub fn into_raw(this: Self) -> *const T {
+        let ptr = Self::as_ptr(&this);
+        mem::forget(this);
+        ptr
+    }
 
-impl<T: ?Sized> Into<Rc<T>> for UniqueRc<T> {
-    fn into(self) -> Rc<T> {
-        self.0
+    /// Returns a pointer to the value, without touching the reference count. The pointer is valid
+    /// as long as there are `Rc`s to the allocation.
+    pub fn as_ptr(this: &Self) -> *const T {
+        // This does not create a reference, so the pointer keeps the provenance of the whole
+        // allocation, which `Rc::from_raw` needs.
+        unsafe { ptr::addr_of!((*this.ptr.as_ptr()).data) }
     }
-}
 
-impl<T: ?Sized> IntoMut<T> for Rc<T> {
-    type MutablePointer = UniqueRc<T>;
+    /// Reconstructs an `Rc` from a pointer returned by `Rc::into_raw`, taking over the reference
+    /// that `into_raw` has kept. The reference count is not touched.
+    ///
+    /// # Safety
+    ///
+    /// `ptr` must have been returned by `Rc::into_raw` on an `Rc<T>`, and each such pointer must
+    /// be reconstructed at most once. Reconstructing it again is undefined behavior even if
+    /// other `Rc`s kept the allocation alive so far, since the reference count then drops to
+    /// zero before all `Rc`s are gone, freeing the allocation while it is still in use.
+    pub unsafe fn from_raw(ptr: *const T) -> Self {
+        // The value is still alive, so its alignment can be read through a reference.
+        let offset = data_offset_val(unsafe { &*ptr });
+        let inner = unsafe { set_data_ptr(ptr as *mut T, (ptr as *mut u8).sub(offset)) };
+        Self::from_inner(unsafe { NonNull::new_unchecked(inner as *mut RcBox<T>) })
+    }
 
-    fn can_make_mut(this: &Self) -> bool {
-        this.ref_count() == 1
+    /// Increments the reference count of the allocation behind a pointer returned by
+    /// `Rc::into_raw`, as if an `Rc` to it had been cloned and leaked.
+    ///
+    /// # Safety
+    ///
+    /// `ptr` must have been returned by `Rc::into_raw` on an `Rc<T>`, and the reference kept by
+    /// it must not have been released yet.
+    pub unsafe fn increment_strong_count(ptr: *const T) {
+        // `Clone` takes care of static allocations and of the overflow check in `inc_strong`.
+        // Neither `Rc` is dropped, so the reference held by `ptr` stays where it is.
+        let rc = ManuallyDrop::new(unsafe { Rc::from_raw(ptr) });
+        let _clone: ManuallyDrop<Rc<T>> = rc.clone();
     }
 
-    unsafe fn into_mut_unchecked(this: Self) -> Self::MutablePointer {
-        UniqueRc(this)
+    /// Decrements the reference count of the allocation behind a pointer returned by
+    /// `Rc::into_raw`, as if one of the `Rc`s to it had been dropped. If this releases the last
+    /// reference, the value is dropped and the allocation freed.
+    ///
+    /// # Safety
+    ///
+    /// `ptr` must have been returned by `Rc::into_raw` on an `Rc<T>`, and there must be a
+    /// reference for this call to release, kept by `into_raw` or by `increment_strong_count`.
+    /// `ptr` must not be used again if that was the last reference.
+    pub unsafe fn decrement_strong_count(ptr: *const T) {
+        drop(unsafe { Rc::from_raw(ptr) });
     }
+}
 
-    /// Obtain a mutable reference to the wrapped value without performing runtime checks for
-    /// upholding any invariants.
+impl<T: ?Sized> Rc<T> {
+    /// Consumes the `Rc` without releasing its reference, and returns a reference to the value
+    /// that lives forever.
     ///
-    /// Safety: Calling this is safe if and only if `can_make_mut` returns true.
-    unsafe fn get_mut_unchecked(this: &Self) -> &mut T {
-        // We are careful to *not* create a reference covering the "count" fields, as
-        // this would alias with concurrent access to the reference counts (e.g. by `Weak`).
-        unsafe { &mut (*this.ptr.as_ptr()).data }
+    /// The allocation is never reclaimed. Other pointers to it keep working as before, but
+    /// since the leaked reference stays counted, the `Rc`s can never become unique again, so
+    /// `try_unwrap` and `into_mut` always fail from now on.
+    pub fn leak(this: Self) -> &'static T where T: 'static {
+        let ptr = this.ptr;
+        mem::forget(this);
+        unsafe { &(*ptr.as_ptr()).data }
+    }
+
+    /// Moves the value into a `Box` if this is the only `Rc` to it, returning the `Rc`
+    /// unchanged otherwise. `WeakRc`s to the value can not be upgraded afterwards.
+    pub fn into_box(this: Self) -> Result<Box<T>, Self> {
+        if this.ref_count() != 1 {
+            return Err(this);
+        }
+
+        unsafe {
+            let value_layout = Layout::for_value(&*this);
+            let data = ptr::addr_of_mut!((*this.ptr()).data);
+
+            let buf = if value_layout.size() == 0 {
+                // A dangling, aligned pointer without provenance, like the one `Box` uses for
+                // zero-sized values.
+                ptr::null_mut::<u8>().wrapping_add(value_layout.align())
+            } else {
+                let buf = alloc(value_layout);
+                if buf.is_null() {
+                    handle_alloc_error(value_layout);
+                }
+                buf
+            };
+            ptr::copy_nonoverlapping(data as *const u8, buf, value_layout.size());
+            let boxed = Box::from_raw(set_data_ptr(data, buf));
+
+            // Release the `RcBox` without dropping the value that has been moved out, see
+            // `try_unwrap`.
+            this.inner().strong.set(0);
+            let ptr = this.ptr;
+            mem::forget(this);
+            release_allocation(ptr);
+            Ok(boxed)
+        }
+    }
+
+    /// Borrows the `Rc` as an `RcBorrow`, without touching the reference count.
+    pub fn borrow_rc(this: &Self) -> RcBorrow<'_, T> {
+        RcBorrow { ptr: this.ptr, phantom: PhantomData }
     }
 }
 
-impl<T: ?Sized> ReferenceCounted<T> for Rc<T> {
-    fn reference_count(this: &Self) -> NonZeroUsize {
-        unsafe { NonZeroUsize::new_unchecked(this.ref_count()) }
+#[cfg(feature = "weak")]
+impl<T: ?Sized> Rc<T> {
+    /// Creates a `WeakRc` to the allocation of this `Rc`.
+    ///
+    /// `WeakRc`s to static allocations (see `Rc::from_static`) can always be upgraded.
+    pub fn downgrade(this: &Self) -> WeakRc<T> {
+        let weak = WeakRc { ptr: this.ptr };
+        // Pointers to static memory are not counted.
+        if this.ref_count() != STATIC_REFCOUNT {
+            weak.inc_weak();
+        }
+        weak
+    }
+
+    /// Returns the number of `WeakRc`s to the allocation of this `Rc`. As in `std`, this does
+    /// not include the weak reference that all `Rc`s hold together.
+    ///
+    /// Like `ReferenceCounted::reference_count`, this returns `usize::MAX` for static
+    /// allocations (see `Rc::from_static`).
+    pub fn weak_count(this: &Self) -> usize {
+        if this.ref_count() == STATIC_REFCOUNT {
+            return STATIC_REFCOUNT;
+        }
+        this.inner().weak.get() - 1
     }
 }
 
-impl<T: Default> Default for Rc<T> {
-    /// Creates a new `Rc<T>`, with the `Default` value for `T`.
+#[cfg(feature = "weak")]
+impl<T> Rc<T> {
+    /// Constructs an `Rc` to the value returned by `f`, which gets a `WeakRc` to the allocation
+    /// that the value is placed in. This allows the value to hold a pointer to itself, or to
+    /// hand one to values it owns.
     ///
-    /// # Examples
+    /// Calling `WeakRc::upgrade` fails until `f` has returned, since the value does not exist
+    /// yet. If `f` panics, the allocation is freed once all `WeakRc`s to it are dropped.
     ///
     /// ```
-    /// use std::sync::Rc;
+    /// use reference_counted::{Rc, WeakRc};
+    /// use smart_pointer::SmartPointer;
     ///
-    /// let x: Rc<i32> = Default::default();
-    /// assert_eq!(*x, 0);
+    /// struct Widget {
+    ///     parent: Option<WeakRc<Widget>>,
+    ///     children: Vec<Rc<Widget>>,
+    /// }
+    ///
+    /// let window = Rc::new_cyclic(|this| Widget {
+    ///     parent: None,
+    ///     children: vec![Rc::new(Widget { parent: Some(this.clone()), children: vec![] })],
+    /// });
+    ///
+    /// let parent = window.children[0].parent.as_ref().unwrap().upgrade().unwrap();
+    /// assert!(std::ptr::eq(&*parent, &*window));
     /// ```
-    fn default() -> Rc<T> {
-        Rc::new(Default::default())
+    pub fn new_cyclic<F: FnOnce(&WeakRc<T>) -> T>(f: F) -> Rc<T> {
+        let ptr = unsafe {
+            Self::allocate_for_layout(Layout::new::<T>(), |mem| mem as *mut RcBox<T>)
+        };
+
+        // The strong count starts at zero so that upgrading fails while there is no value. The
+        // `WeakRc` holds the weak reference of all `Rc`s, so it frees the allocation without
+        // touching the value if `f` panics.
+        unsafe { (*ptr).strong.set(0) };
+        let weak = WeakRc { ptr: unsafe { NonNull::new_unchecked(ptr) } };
+
+        let data = f(&weak);
+
+        unsafe {
+            ptr::addr_of_mut!((*ptr).data).write(data);
+            (*ptr).strong.set(1);
+        }
+        mem::forget(weak);
+        Self::from_inner(unsafe { NonNull::new_unchecked(ptr) })
     }
 }
 
-impl<T: Default> Default for UniqueRc<T> {
-    /// Creates a new `UniqueRc<T>`, with the `Default` value for `T`.
-    fn default() -> UniqueRc<T> {
-        UniqueRc::new(Default::default())
-    }
+/// A weak pointer to the allocation of an `Rc`, created by `Rc::downgrade` or
+/// `Rc::new_cyclic`, or a weak pointer to no allocation at all, created by `WeakRc::new`.
+///
+/// A `WeakRc` does not keep the value alive, only the allocation, so that reference cycles
+/// through `WeakRc`s do not leak. `WeakRc::upgrade` returns an `Rc` to the value if there still
+/// is one.
+#[cfg(feature = "weak")]
+pub struct WeakRc<T: ?Sized> {
+    // `DANGLING` for pointers created by `WeakRc::new`. Such pointers have no counts, and must
+    // never be dereferenced.
+    ptr: NonNull<RcBox<T>>,
 }
 
-impl<T: ?Sized + PartialEq> PartialEq for Rc<T> {
-    /// Equality for two `Rc`s.
-    ///
-    /// Two `Rc`s are equal if their inner values are equal, even if they are
-    /// stored in different allocation. This implementation does not check for
-    /// pointer equality.
-    #[inline]
-    fn eq(&self, other: &Rc<T>) -> bool {
-        (**self).eq(&**other)
-    }
+/// The address of the `RcBox` of a `WeakRc` created by `WeakRc::new`. No allocation can start
+/// there, since an `RcBox` is aligned at least as much as a `usize`.
+#[cfg(feature = "weak")]
+const DANGLING: usize = usize::MAX;
 
-    /// Inequality for two `Rc`s.
+/// The address of the value that `WeakRc::as_ptr` returns for a `WeakRc` created by
+/// `WeakRc::new`. It is not null, and a multiple of every possible alignment.
+#[cfg(feature = "weak")]
+const DANGLING_DATA: usize = 1 << (usize::BITS - 1);
+
+#[cfg(feature = "weak")]
+impl<T> WeakRc<T> {
+    /// Creates a `WeakRc` that does not point to any allocation, so that upgrading it always
+    /// fails.
     ///
-    /// Two `Rc`s are unequal if their inner values are unequal. This implementation does not
-    /// check for pointer equality.
-    #[inline]
-    fn ne(&self, other: &Rc<T>) -> bool {
-        (**self).ne(&**other)
+    /// Neither this nor cloning or dropping the result allocates or frees memory.
+    pub const fn new() -> WeakRc<T> {
+        // Offsetting a null pointer rather than casting an integer makes it clear that the
+        // pointer has no provenance, and may never be dereferenced.
+        let ptr = ptr::null_mut::<u8>().wrapping_add(DANGLING).cast::<RcBox<T>>();
+        WeakRc { ptr: unsafe { NonNull::new_unchecked(ptr) } }
     }
 }
 
-impl<T: ?Sized + Eq> Eq for Rc<T> {}
+#[cfg(feature = "weak")]
+impl<T: ?Sized> WeakRc<T> {
+    fn is_dangling(&self) -> bool {
+        self.ptr.as_ptr() as *mut u8 as usize == DANGLING
+    }
 
-impl<T: ?Sized + PartialEq> PartialEq for UniqueRc<T> {
-    /// Equality for two `UniqueRc`s.
+    // The counts are accessed without creating a reference to the whole `RcBox`, since the value
+    // might have been dropped already. They must not be accessed for dangling pointers.
+    fn strong(&self) -> &Cell<usize> {
+        unsafe { &*ptr::addr_of!((*self.ptr.as_ptr()).strong) }
+    }
+
+    fn weak(&self) -> &Cell<usize> {
+        unsafe { &*ptr::addr_of!((*self.ptr.as_ptr()).weak) }
+    }
+
+    /// Increments the weak count of a pointer to a heap allocation.
+    fn inc_weak(&self) {
+        let weak = self.weak().get();
+        // Like `Rc::inc_strong`, panic instead of turning the allocation into a static one.
+        if weak == STATIC_REFCOUNT - 1 {
+            refcount_overflow();
+        }
+        self.weak().set(weak + 1);
+    }
+
+    /// Attempts to create an `Rc` to the value, returning `None` if the value has been dropped
+    /// already.
+    pub fn upgrade(&self) -> Option<Rc<T>> {
+        if self.is_dangling() {
+            return None;
+        }
+
+        let strong = self.strong().get();
+        if strong == 0 {
+            return None;
+        }
+
+        // Pointers to static memory are not counted.
+        if strong != STATIC_REFCOUNT {
+            // See `Rc::inc_strong`.
+            if strong == STATIC_REFCOUNT - 1 {
+                refcount_overflow();
+            }
+            self.strong().set(strong + 1);
+        }
+        Some(Rc::from_inner(self.ptr))
+    }
+
+    /// Returns the number of `Rc`s to the allocation, which is zero if the value has been
+    /// dropped already or if this was created by `WeakRc::new`.
     ///
-    /// Two `UniqueRc`s are equal if their inner values are equal, even if they are
-    /// stored in different allocation. This implementation does not check for
-    /// pointer equality.
-    #[inline]
-    fn eq(&self, other: &UniqueRc<T>) -> bool {
-        (**self).eq(&**other)
+    /// Like `ReferenceCounted::reference_count`, this returns `usize::MAX` for static
+    /// allocations (see `Rc::from_static`).
+    pub fn strong_count(&self) -> usize {
+        if self.is_dangling() {
+            return 0;
+        }
+        self.strong().get()
     }
 
-    /// Inequality for two `Rc`s.
+    /// Returns a pointer to the value, which may have been dropped already. The pointer is
+    /// valid only while there are `Rc`s to the allocation.
     ///
-    /// Two `Rc`s are unequal if their inner values are unequal. This implementation does not
-    /// check for pointer equality.
-    #[inline]
-    fn ne(&self, other: &UniqueRc<T>) -> bool {
-        (**self).ne(&**other)
+    /// For `WeakRc`s created by `WeakRc::new`, the pointer is dangling: it is aligned and not
+    /// null, but must not be dereferenced.
+    pub fn as_ptr(&self) -> *const T {
+        if self.is_dangling() {
+            let data = ptr::null_mut::<u8>().wrapping_add(DANGLING_DATA);
+            return unsafe { set_data_ptr(self.ptr.as_ptr() as *mut T, data) };
+        }
+        // This does not create a reference, since the value might not be valid anymore.
+        unsafe { ptr::addr_of!((*self.ptr.as_ptr()).data) }
+    }
+
+    /// Returns `true` if the two `WeakRc`s point to the same allocation, or if neither points to
+    /// an allocation (see `WeakRc::new`). Pointer metadata is ignored.
+    pub fn ptr_eq(&self, other: &WeakRc<T>) -> bool {
+        self.ptr.as_ptr() as *mut u8 == other.ptr.as_ptr() as *mut u8
     }
 }
 
-impl<T: ?Sized + Eq> Eq for UniqueRc<T> {}
+#[cfg(feature = "weak")]
+impl<T: ?Sized> Clone for WeakRc<T> {
+    /// Makes another `WeakRc` to the same allocation.
+    fn clone(&self) -> WeakRc<T> {
+        // Pointers to static memory and dangling pointers are not counted.
+        if !self.is_dangling() && self.strong().get() != STATIC_REFCOUNT {
+            self.inc_weak();
+        }
+        WeakRc { ptr: self.ptr }
+    }
+}
 
-impl<T: ?Sized + PartialOrd> PartialOrd for Rc<T> {
-    /// Partial comparison for two `Rc`s.
-    ///
-    /// The two are compared by calling `partial_cmp()` on their inner values.
-    fn partial_cmp(&self, other: &Rc<T>) -> Option<Ordering> {
-        (**self).partial_cmp(&**other)
+#[cfg(feature = "weak")]
+impl<T: ?Sized> Drop for WeakRc<T> {
+    /// Drops the `WeakRc`, freeing the allocation if this was the last reference of any kind.
+    fn drop(&mut self) {
+        // Pointers to static memory and dangling pointers are not counted, and there is nothing
+        // to free.
+        if self.is_dangling() || self.strong().get() == STATIC_REFCOUNT {
+            return;
+        }
+
+        let weak = self.weak().get() - 1;
+        self.weak().set(weak);
+        if weak == 0 {
+            // `Layout::for_value` only uses the size and alignment of the value, which are
+            // known from its type and the pointer metadata even after it has been dropped.
+            unsafe {
+                let layout = Layout::for_value(self.ptr.as_ref());
+                dealloc(self.ptr.as_ptr().cast(), layout);
+            }
+        }
     }
+}
 
-    /// Less-than comparison for two `Rc`s.
-    ///
-    /// The two are compared by calling `<` on their inner values.
-    fn lt(&self, other: &Rc<T>) -> bool {
-        *(*self) < *(*other)
+#[cfg(feature = "weak")]
+impl<T> Default for WeakRc<T> {
+    /// Creates a `WeakRc` that does not point to any allocation, see `WeakRc::new`.
+    fn default() -> WeakRc<T> {
+        WeakRc::new()
     }
+}
 
-    /// 'Less than or equal to' comparison for two `Rc`s.
-    ///
-    /// The two are compared by calling `<=` on their inner values.
-    fn le(&self, other: &Rc<T>) -> bool {
-        *(*self) <= *(*other)
+#[cfg(feature = "weak")]
+impl<T: ?Sized> fmt::Debug for WeakRc<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("(WeakRc)")
     }
+}
 
-    /// Greater-than comparison for two `Rc`s.
-    ///
-    /// The two are compared by calling `>` on their inner values.
-    fn gt(&self, other: &Rc<T>) -> bool {
-        *(*self) > *(*other)
+/// Serializes the value as `Some` if the `WeakRc` can be upgraded, and as `None` otherwise, like
+/// serde does for `std::rc::Weak`. See `serde_weak` for alternatives.
+#[cfg(all(feature = "serde", feature = "weak"))]
+impl<T: ?Sized + Serialize> Serialize for WeakRc<T> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        crate::serde_weak::as_option::serialize(self, serializer)
     }
+}
 
-    /// 'Greater than or equal to' comparison for two `Rc`s.
-    ///
-    /// The two are compared by calling `>=` on their inner values.
-    fn ge(&self, other: &Rc<T>) -> bool {
-        *(*self) >= *(*other)
+/// Deserializes an optional value and discards it, resulting in a `WeakRc` that does not point
+/// to any allocation. Links between values must be re-established after deserialization.
+#[cfg(all(feature = "serde", feature = "weak"))]
+impl<'de, T: Deserialize<'de>> Deserialize<'de> for WeakRc<T> {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        crate::serde_weak::as_option::deserialize(deserializer)
     }
 }
 
-impl<T: ?Sized + PartialOrd> PartialOrd for UniqueRc<T> {
-    /// Partial comparison for two `UniqueRc`s.
+/// A borrowed `Rc`, which can be copied freely and be turned into an owned `Rc` if needed.
+///
+/// This is like an `&'a Rc<T>`, but needs only a single indirection to reach the value, and
+/// can be created from a pointer to the value itself (see `RcBorrow::from_raw`).
+pub struct RcBorrow<'a, T: ?Sized> {
+    ptr: NonNull<RcBox<T>>,
+    phantom: PhantomData<&'a T>,
+}
+
+impl<'a, T: ?Sized> RcBorrow<'a, T> {
+    /// Creates an `RcBorrow` from a pointer to the value of an `Rc`, without touching the
+    /// reference count.
     ///
-    /// The two are compared by calling `partial_cmp()` on their inner values.
-    fn partial_cmp(&self, other: &UniqueRc<T>) -> Option<Ordering> {
-        (**self).partial_cmp(&**other)
+    /// # Safety
+    ///
+    /// `ptr` must have been returned by `Rc::as_ptr` or `Rc::into_raw` on an `Rc<T>`, and some
+    /// `Rc` to the allocation must stay alive for `'a`.
+    pub unsafe fn from_raw(ptr: *const T) -> Self {
+        // As in `Rc::from_raw`, the pointer keeps the provenance of the whole allocation.
+        let offset = data_offset_val(unsafe { &*ptr });
+        let inner = unsafe { set_data_ptr(ptr as *mut T, (ptr as *mut u8).sub(offset)) };
+        let ptr = inner as *mut RcBox<T>;
+        RcBorrow { ptr: unsafe { NonNull::new_unchecked(ptr) }, phantom: PhantomData }
     }
 
-    /// Less-than comparison for two `UniqueRc`s.
-    ///
-    /// The two are compared by calling `<` on their inner values.
-    fn lt(&self, other: &UniqueRc<T>) -> bool {
-        *(*self) < *(*other)
+    /// Creates an owned `Rc` pointing to the same allocation, increasing the reference count.
+    pub fn to_rc(this: Self) -> Rc<T> {
+        let rc = ManuallyDrop::new(Rc::from_inner(this.ptr));
+        Rc::clone(&rc)
     }
 
-    /// 'Less than or equal to' comparison for two `UniqueRc`s.
-    ///
-    /// The two are compared by calling `<=` on their inner values.
-    fn le(&self, other: &UniqueRc<T>) -> bool {
-        *(*self) <= *(*other)
+    /// Gets a reference to the value that lives for `'a`, rather than only as long as `this`.
+    pub fn get(this: Self) -> &'a T {
+        unsafe { &(*this.ptr.as_ptr()).data }
     }
+}
 
-    /// Greater-than comparison for two `UniqueRc`s.
-    ///
-    /// The two are compared by calling `>` on their inner values.
-    fn gt(&self, other: &UniqueRc<T>) -> bool {
-        *(*self) > *(*other)
+impl<'a, T: ?Sized> Clone for RcBorrow<'a, T> {
+    fn clone(&self) -> Self {
+        *self
     }
+}
 
-    /// 'Greater than or equal to' comparison for two `UniqueRc`s.
-    ///
-    /// The two are compared by calling `>=` on their inner values.
-    fn ge(&self, other: &UniqueRc<T>) -> bool {
-        *(*self) >= *(*other)
+impl<'a, T: ?Sized> Copy for RcBorrow<'a, T> {}
+
+impl<'a, T: ?Sized> Deref for RcBorrow<'a, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        RcBorrow::get(*self)
     }
 }
 
-impl<T: ?Sized + Ord> Ord for Rc<T> {
-    /// Comparison for two `Rc`s.
-    ///
-    /// The two are compared by calling `cmp()` on their inner values.
-    fn cmp(&self, other: &Rc<T>) -> Ordering {
-        (**self).cmp(&**other)
+impl<'a, T: ?Sized> borrow::Borrow<T> for RcBorrow<'a, T> {
+    fn borrow(&self) -> &T {
+        self
     }
 }
 
-impl<T: ?Sized + Ord> Ord for UniqueRc<T> {
-    /// Comparison for two `UniqueRc`s.
-    ///
-    /// The two are compared by calling `cmp()` on their inner values.
-    fn cmp(&self, other: &UniqueRc<T>) -> Ordering {
-        (**self).cmp(&**other)
+impl<'a, T: ?Sized> AsRef<T> for RcBorrow<'a, T> {
+    fn as_ref(&self) -> &T {
+        self
     }
 }
 
-impl<T> From<T> for Rc<T> {
-    fn from(t: T) -> Self {
-        Rc::new(t)
+impl<'a, T: ?Sized + fmt::Display> fmt::Display for RcBorrow<'a, T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Display::fmt(&**self, f)
     }
 }
 
-impl<T> From<T> for UniqueRc<T> {
-    fn from(t: T) -> Self {
-        UniqueRc::new(t)
+impl<'a, T: ?Sized + fmt::Debug> fmt::Debug for RcBorrow<'a, T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Debug::fmt(&**self, f)
     }
 }
 
-impl<T: ?Sized + Hash> Hash for Rc<T> {
-    fn hash<H: Hasher>(&self, state: &mut H) {
-        (**self).hash(state)
+impl<'a, T: ?Sized> fmt::Pointer for RcBorrow<'a, T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Pointer::fmt(&(&**self as *const T), f)
     }
 }
 
-impl<T: ?Sized + Hash> Hash for UniqueRc<T> {
-    fn hash<H: Hasher>(&self, state: &mut H) {
-        (**self).hash(state)
+pub struct UniqueRc<T: ?Sized>(Rc<T>);
+
+unsafe impl<T: ?Sized + Sync + Send> Send for UniqueRc<T> {}
+unsafe impl<T: ?Sized + Sync + Send> Sync for UniqueRc<T> {}
+
+impl<T: ?Sized> Deref for UniqueRc<T> {
+    type Target = T;
+
+    #[inline]
+    fn deref(&self) -> &T {
+        self.0.deref()
     }
 }
 
-impl<T: ?Sized> Unpin for Rc<T> {}
+impl<T: ?Sized> borrow::Borrow<T> for UniqueRc<T> {
+    fn borrow(&self) -> &T {
+        self.0.borrow()
+    }
+}
 
-impl<T: ?Sized> Unpin for UniqueRc<T> {}
+impl<T: ?Sized> AsRef<T> for UniqueRc<T> {
+    fn as_ref(&self) -> &T {
+        self.0.as_ref()
+    }
+}
+
+impl<T: ?Sized + fmt::Display> fmt::Display for UniqueRc<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+impl<T: ?Sized + fmt::Debug> fmt::Debug for UniqueRc<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+impl<T: ?Sized> fmt::Pointer for UniqueRc<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+impl<T: ?Sized> SmartPointer<T> for UniqueRc<T> {
+    fn new(data: T) -> Self where T: Sized {
+        UniqueRc(Rc::new(data))
+    }
+
+    fn try_unwrap(this: Self) -> Result<T, Self> where T: Sized {
+        let this = this.0;
+
+        // There are no `WeakRc`s, so the allocation can be freed right away.
+        unsafe {
+            let elem = ptr::read(&this.ptr.as_ref().data);
+            dealloc(this.ptr().cast(), Layout::for_value(this.ptr.as_ref()));
+            mem::forget(this);
+            Ok(elem)
+        }
+    }
+}
+
+
+impl<T> UniqueRc<T> {
+    /// Reinterprets the pointer as pointing to a `U`, without touching the allocation.
+    ///
+    /// # Safety
+    ///
+    /// The requirements of `Rc::cast` apply.
+    pub unsafe fn cast<U>(this: Self) -> UniqueRc<U> {
+        UniqueRc(unsafe { Rc::cast(this.0) })
+    }
+}
+
+impl<T> UniqueRc<[T]> {
+    /// Reinterprets the pointer as pointing to a slice of `U`s of the same length, without
+    /// touching the allocation.
+    ///
+    /// # Safety
+    ///
+    /// The requirements of `Rc::cast` apply to the element types `T` and `U`.
+    pub unsafe fn cast_slice<U>(this: Self) -> UniqueRc<[U]> {
+        UniqueRc(unsafe { Rc::cast_slice(this.0) })
+    }
+}
+
+impl<T: ?Sized> DerefMut for UniqueRc<T> {
+    fn deref_mut(&mut self) -> &mut T {
+        // We know this to be uniquely owned
+        unsafe { &mut (*self.0.ptr()).data }
+    }
+}
+
+impl<T: ?Sized> BorrowMut<T> for UniqueRc<T> {
+    fn borrow_mut(&mut self) -> &mut T {
+        &mut **self
+    }
+}
+
+impl<T: ?Sized> AsMut<T> for UniqueRc<T> {
+    fn as_mut(&mut self) -> &mut T {
+        &mut **self
+    }
+}
+
+impl<T: ?Sized> SmartPointerMut<T> for UniqueRc<T> {}
+
+impl<T: ?Sized> Into<Rc<T>> for UniqueRc<T> {
+    fn into(self) -> Rc<T> {
+        self.0
+    }
+}
+
+impl<T: ?Sized> IntoMut<T> for Rc<T> {
+    type MutablePointer = UniqueRc<T>;
+
+    /// Returns `true` if this is the only `Rc` to its allocation and there are no `WeakRc`s,
+    /// which could otherwise be upgraded while the value is being mutated.
+    #[cfg(feature = "weak")]
+    fn can_make_mut(this: &Self) -> bool {
+        this.ref_count() == 1 && this.inner().weak.get() == 1
+    }
+
+    /// Returns `true` if this is the only `Rc` to its allocation.
+    #[cfg(not(feature = "weak"))]
+    fn can_make_mut(this: &Self) -> bool {
+        this.ref_count() == 1
+    }
+
+    unsafe fn into_mut_unchecked(this: Self) -> Self::MutablePointer {
+        UniqueRc(this)
+    }
+
+    /// Obtain a mutable reference to the wrapped value without performing runtime checks for
+    /// upholding any invariants.
+    ///
+    /// Safety: Calling this is safe if and only if `can_make_mut` returns true.
+    unsafe fn get_mut_unchecked(this: &Self) -> &mut T {
+        // We are careful to *not* create a reference covering the "count" fields, as
+        // this would alias with concurrent access to the reference counts (e.g. by `Weak`).
+        unsafe { &mut (*this.ptr.as_ptr()).data }
+    }
+}
+
+impl<T: ?Sized> ReferenceCounted<T> for Rc<T> {
+    fn reference_count(this: &Self) -> NonZeroUsize {
+        unsafe { NonZeroUsize::new_unchecked(this.ref_count()) }
+    }
+}
+
+#[cfg(feature = "weak")]
+impl<T: ?Sized> WeakPointer<T> for WeakRc<T> {
+    type Strong = Rc<T>;
+
+    fn upgrade(&self) -> Option<Rc<T>> {
+        WeakRc::upgrade(self)
+    }
+}
+
+#[cfg(feature = "weak")]
+impl<T: ?Sized> Downgradable<T> for Rc<T> {
+    type Weak = WeakRc<T>;
+
+    fn downgrade(this: &Self) -> WeakRc<T> {
+        Rc::downgrade(this)
+    }
+}
+
+#[cfg(feature = "weak")]
+impl<T: ?Sized> WeaklyReferenceCounted<T> for Rc<T> {
+    fn weak_count(this: &Self) -> usize {
+        Rc::weak_count(this)
+    }
+}
+
+impl<T: Default> Default for Rc<T> {
+    /// Creates a new `Rc<T>`, with the `Default` value for `T`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use std::sync::Rc;
+    ///
+    /// let x: Rc<i32> = Default::default();
+    /// assert_eq!(*x, 0);
+    /// ```
+    fn default() -> Rc<T> {
+        Rc::new(Default::default())
+    }
+}
+
+impl<T> UniqueRc<MaybeUninit<T>> {
+    /// Initializes the contents with `value`, returning a mutable reference to it.
+    ///
+    /// Any previous contents are overwritten without being dropped.
+    pub fn write(&mut self, value: T) -> &mut T {
+        (**self).write(value)
+    }
+
+    /// Converts to `UniqueRc<T>`, reusing the allocation.
+    ///
+    /// # Safety
+    ///
+    /// As with `MaybeUninit::assume_init`, it is up to the caller to guarantee that the
+    /// contents really are initialized. Calling this when the content is not yet fully
+    /// initialized causes immediate undefined behavior.
+    pub unsafe fn assume_init(self) -> UniqueRc<T> {
+        let ptr = self.0.ptr() as *mut RcBox<T>;
+        mem::forget(self);
+        unsafe { UniqueRc(Rc::from_inner(NonNull::new_unchecked(ptr))) }
+    }
+
+    /// Initializes the contents with `value` and converts to `UniqueRc<T>`, reusing the
+    /// allocation.
+    pub fn into_initialized(mut self, value: T) -> UniqueRc<T> {
+        self.write(value);
+        unsafe { self.assume_init() }
+    }
+}
+
+impl<T: Clone> Clone for UniqueRc<T> {
+    /// Clones the value into a new, independent allocation.
+    ///
+    /// Unlike cloning an `Rc`, this does not share the value: mutating either `UniqueRc`
+    /// afterwards does not affect the other.
+    fn clone(&self) -> UniqueRc<T> {
+        UniqueRc::new((**self).clone())
+    }
+}
+
+impl<T: Default> Default for UniqueRc<T> {
+    /// Creates a new `UniqueRc<T>`, with the `Default` value for `T`.
+    fn default() -> UniqueRc<T> {
+        UniqueRc::new(Default::default())
+    }
+}
+
+impl<T: ?Sized + PartialEq<U>, U: ?Sized> PartialEq<Rc<U>> for Rc<T> {
+    /// Equality for two `Rc`s.
+    ///
+    /// Two `Rc`s are equal if their inner values are equal, even if they are
+    /// stored in different allocation. This implementation does not check for
+    /// pointer equality.
+    #[inline]
+    fn eq(&self, other: &Rc<U>) -> bool {
+        (**self).eq(&**other)
+    }
+
+    /// Inequality for two `Rc`s.
+    ///
+    /// Two `Rc`s are unequal if their inner values are unequal. This implementation does not
+    /// check for pointer equality.
+    #[inline]
+    fn ne(&self, other: &Rc<U>) -> bool {
+        (**self).ne(&**other)
+    }
+}
+
+impl<T: ?Sized + Eq> Eq for Rc<T> {}
+
+impl<T: ?Sized + PartialEq<U>, U: ?Sized> PartialEq<UniqueRc<U>> for UniqueRc<T> {
+    /// Equality for two `UniqueRc`s.
+    ///
+    /// Two `UniqueRc`s are equal if their inner values are equal, even if they are
+    /// stored in different allocation. This implementation does not check for
+    /// pointer equality.
+    #[inline]
+    fn eq(&self, other: &UniqueRc<U>) -> bool {
+        (**self).eq(&**other)
+    }
+
+    /// Inequality for two `Rc`s.
+    ///
+    /// Two `Rc`s are unequal if their inner values are unequal. This implementation does not
+    /// check for pointer equality.
+    #[inline]
+    fn ne(&self, other: &UniqueRc<U>) -> bool {
+        (**self).ne(&**other)
+    }
+}
+
+impl<T: ?Sized + Eq> Eq for UniqueRc<T> {}
+
+impl<T: ?Sized + PartialEq<U>, U: ?Sized> PartialEq<UniqueRc<U>> for Rc<T> {
+    /// Equality for an `Rc` and a `UniqueRc`.
+    ///
+    /// They are equal if their inner values are equal.
+    #[inline]
+    fn eq(&self, other: &UniqueRc<U>) -> bool {
+        (**self).eq(&**other)
+    }
+}
+
+impl<T: ?Sized + PartialEq<U>, U: ?Sized> PartialEq<Rc<U>> for UniqueRc<T> {
+    /// Equality for a `UniqueRc` and an `Rc`.
+    ///
+    /// They are equal if their inner values are equal.
+    #[inline]
+    fn eq(&self, other: &Rc<U>) -> bool {
+        (**self).eq(&**other)
+    }
+}
+
+// Comparisons of `Rc<str>` and `Rc<[T]>` with other string and slice types, in both
+// directions, so that e.g. `assert_eq!(value, "expected")` works.
+macro_rules! impl_slice_eq {
+    ([$($vars:tt)*] $lhs:ty, $rhs:ty) => {
+        impl<$($vars)*> PartialEq<$rhs> for $lhs {
+            #[inline]
+            fn eq(&self, other: &$rhs) -> bool {
+                self[..] == other[..]
+            }
+        }
+    };
+}
+
+impl_slice_eq! { [] Rc<str>, str }
+impl_slice_eq! { [] str, Rc<str> }
+impl_slice_eq! { ['a] Rc<str>, &'a str }
+impl_slice_eq! { ['a] &'a str, Rc<str> }
+impl_slice_eq! { [] Rc<str>, String }
+impl_slice_eq! { [] String, Rc<str> }
+impl_slice_eq! { [T: PartialEq<U>, U] Rc<[T]>, [U] }
+impl_slice_eq! { [T: PartialEq<U>, U] [T], Rc<[U]> }
+impl_slice_eq! { ['a, T: PartialEq<U>, U] Rc<[T]>, &'a [U] }
+impl_slice_eq! { ['a, T: PartialEq<U>, U] &'a [T], Rc<[U]> }
+impl_slice_eq! { [T: PartialEq<U>, U] Rc<[T]>, Vec<U> }
+impl_slice_eq! { [T: PartialEq<U>, U] Vec<T>, Rc<[U]> }
+
+impl<T: ?Sized + PartialOrd<U>, U: ?Sized> PartialOrd<Rc<U>> for Rc<T> {
+    /// Partial comparison for two `Rc`s.
+    ///
+    /// The two are compared by calling `partial_cmp()` on their inner values.
+    fn partial_cmp(&self, other: &Rc<U>) -> Option<Ordering> {
+        (**self).partial_cmp(&**other)
+    }
+
+    /// Less-than comparison for two `Rc`s.
+    ///
+    /// The two are compared by calling `<` on their inner values.
+    fn lt(&self, other: &Rc<U>) -> bool {
+        *(*self) < *(*other)
+    }
+
+    /// 'Less than or equal to' comparison for two `Rc`s.
+    ///
+    /// The two are compared by calling `<=` on their inner values.
+    fn le(&self, other: &Rc<U>) -> bool {
+        *(*self) <= *(*other)
+    }
+
+    /// Greater-than comparison for two `Rc`s.
+    ///
+    /// The two are compared by calling `>` on their inner values.
+    fn gt(&self, other: &Rc<U>) -> bool {
+        *(*self) > *(*other)
+    }
+
+    /// 'Greater than or equal to' comparison for two `Rc`s.
+    ///
+    /// The two are compared by calling `>=` on their inner values.
+    fn ge(&self, other: &Rc<U>) -> bool {
+        *(*self) >= *(*other)
+    }
+}
+
+impl<T: ?Sized + PartialOrd<U>, U: ?Sized> PartialOrd<UniqueRc<U>> for UniqueRc<T> {
+    /// Partial comparison for two `UniqueRc`s.
+    ///
+    /// The two are compared by calling `partial_cmp()` on their inner values.
+    fn partial_cmp(&self, other: &UniqueRc<U>) -> Option<Ordering> {
+        (**self).partial_cmp(&**other)
+    }
+
+    /// Less-than comparison for two `UniqueRc`s.
+    ///
+    /// The two are compared by calling `<` on their inner values.
+    fn lt(&self, other: &UniqueRc<U>) -> bool {
+        *(*self) < *(*other)
+    }
+
+    /// 'Less than or equal to' comparison for two `UniqueRc`s.
+    ///
+    /// The two are compared by calling `<=` on their inner values.
+    fn le(&self, other: &UniqueRc<U>) -> bool {
+        *(*self) <= *(*other)
+    }
+
+    /// Greater-than comparison for two `UniqueRc`s.
+    ///
+    /// The two are compared by calling `>` on their inner values.
+    fn gt(&self, other: &UniqueRc<U>) -> bool {
+        *(*self) > *(*other)
+    }
+
+    /// 'Greater than or equal to' comparison for two `UniqueRc`s.
+    ///
+    /// The two are compared by calling `>=` on their inner values.
+    fn ge(&self, other: &UniqueRc<U>) -> bool {
+        *(*self) >= *(*other)
+    }
+}
+
+impl<T: ?Sized + PartialOrd<U>, U: ?Sized> PartialOrd<UniqueRc<U>> for Rc<T> {
+    /// Partial comparison for an `Rc` and a `UniqueRc`.
+    ///
+    /// The two are compared by calling `partial_cmp()` on their inner values.
+    fn partial_cmp(&self, other: &UniqueRc<U>) -> Option<Ordering> {
+        (**self).partial_cmp(&**other)
+    }
+}
+
+impl<T: ?Sized + PartialOrd<U>, U: ?Sized> PartialOrd<Rc<U>> for UniqueRc<T> {
+    /// Partial comparison for a `UniqueRc` and an `Rc`.
+    ///
+    /// The two are compared by calling `partial_cmp()` on their inner values.
+    fn partial_cmp(&self, other: &Rc<U>) -> Option<Ordering> {
+        (**self).partial_cmp(&**other)
+    }
+}
+
+impl<T: ?Sized + Ord> Ord for Rc<T> {
+    /// Comparison for two `Rc`s.
+    ///
+    /// The two are compared by calling `cmp()` on their inner values.
+    fn cmp(&self, other: &Rc<T>) -> Ordering {
+        (**self).cmp(&**other)
+    }
+}
+
+impl<T: ?Sized + Ord> Ord for UniqueRc<T> {
+    /// Comparison for two `UniqueRc`s.
+    ///
+    /// The two are compared by calling `cmp()` on their inner values.
+    fn cmp(&self, other: &UniqueRc<T>) -> Ordering {
+        (**self).cmp(&**other)
+    }
+}
+
+impl<T> From<T> for Rc<T> {
+    fn from(t: T) -> Self {
+        Rc::new(t)
+    }
+}
+
+impl<T> From<T> for UniqueRc<T> {
+    fn from(t: T) -> Self {
+        UniqueRc::new(t)
+    }
+}
+
+impl<T: Clone> From<&[T]> for Rc<[T]> {
+    /// Allocates a reference-counted slice and fills it by cloning `v`'s items.
+    fn from(v: &[T]) -> Rc<[T]> {
+        Rc::from_iter_exact(v.iter().cloned(), v.len())
+    }
+}
+
+impl<T: ?Sized> From<Box<T>> for Rc<T> {
+    /// Moves the value out of the box into a new reference-counted allocation.
+    fn from(v: Box<T>) -> Rc<T> {
+        unsafe {
+            let rc = Rc::copy_from_value(&*v);
+
+            // Free the box allocation without dropping its contents.
+            drop(Box::from_raw(Box::into_raw(v) as *mut ManuallyDrop<T>));
+
+            rc
+        }
+    }
+}
+
+impl From<&CStr> for Rc<CStr> {
+    /// Allocates a reference-counted C string and copies `v` into it, including the
+    /// terminating nul byte.
+    fn from(v: &CStr) -> Rc<CStr> {
+        // `CStr` has no destructor, so the copy does not duplicate any ownership.
+        unsafe { Rc::copy_from_value(v) }
+    }
+}
+
+#[cfg(feature = "std")]
+impl From<&OsStr> for Rc<OsStr> {
+    /// Allocates a reference-counted OS string slice and copies `v` into it.
+    fn from(v: &OsStr) -> Rc<OsStr> {
+        // `OsStr` has no destructor, so the copy does not duplicate any ownership.
+        unsafe { Rc::copy_from_value(v) }
+    }
+}
+
+#[cfg(feature = "std")]
+impl From<OsString> for Rc<OsStr> {
+    /// Allocates a reference-counted OS string slice and copies the contents of `v` into it,
+    /// then frees the buffer of `v`.
+    fn from(v: OsString) -> Rc<OsStr> {
+        Rc::from(v.as_os_str())
+    }
+}
+
+#[cfg(feature = "std")]
+impl From<&Path> for Rc<Path> {
+    /// Allocates a reference-counted path and copies `v` into it.
+    fn from(v: &Path) -> Rc<Path> {
+        // `Path` has no destructor, so the copy does not duplicate any ownership.
+        unsafe { Rc::copy_from_value(v) }
+    }
+}
+
+#[cfg(feature = "std")]
+impl From<PathBuf> for Rc<Path> {
+    /// Allocates a reference-counted path and copies the contents of `v` into it, then frees the
+    /// buffer of `v`.
+    fn from(v: PathBuf) -> Rc<Path> {
+        Rc::from(v.as_path())
+    }
+}
+
+impl<T, const N: usize> From<[T; N]> for Rc<[T]> {
+    /// Allocates a reference-counted slice and moves the items of `v` into it.
+    fn from(v: [T; N]) -> Rc<[T]> {
+        let v = ManuallyDrop::new(v);
+        unsafe {
+            let ptr = Rc::allocate_for_slice(N);
+            ptr::copy_nonoverlapping(v.as_ptr(), ptr::addr_of_mut!((*ptr).data) as *mut T, N);
+            Self::from_inner(NonNull::new_unchecked(ptr))
+        }
+    }
+}
+
+impl<T> From<Vec<T>> for Rc<[T]> {
+    /// Allocates a reference-counted slice and moves `v`'s items into it.
+    fn from(mut v: Vec<T>) -> Rc<[T]> {
+        unsafe {
+            let len = v.len();
+            let ptr = Rc::allocate_for_slice(len);
+            ptr::copy_nonoverlapping(v.as_ptr(), ptr::addr_of_mut!((*ptr).data) as *mut T, len);
+
+            // Allow the vector to free its memory, but not destroy its contents.
+            v.set_len(0);
+
+            Self::from_inner(NonNull::new_unchecked(ptr))
+        }
+    }
+}
+
+impl From<&str> for Rc<str> {
+    /// Allocates a reference-counted string slice and copies `v` into it.
+    fn from(v: &str) -> Rc<str> {
+        let rc = Rc::<[u8]>::copy_from_slice(v.as_bytes());
+        // The bytes are valid utf-8, and `RcBox<str>` has the same layout as `RcBox<[u8]>`.
+        let ptr = rc.ptr() as *mut RcBox<str>;
+        mem::forget(rc);
+        unsafe { Self::from_inner(NonNull::new_unchecked(ptr)) }
+    }
+}
+
+impl From<String> for Rc<str> {
+    /// Allocates a reference-counted string slice and moves the contents of `v` into it.
+    fn from(v: String) -> Rc<str> {
+        let rc = Rc::<[u8]>::from(v.into_bytes());
+        // The bytes are valid utf-8, and `RcBox<str>` has the same layout as `RcBox<[u8]>`.
+        let ptr = rc.ptr() as *mut RcBox<str>;
+        mem::forget(rc);
+        unsafe { Self::from_inner(NonNull::new_unchecked(ptr)) }
+    }
+}
+
+impl<T: Clone> From<Cow<'_, [T]>> for Rc<[T]> {
+    /// Moves the items of an owned vector into a new reference-counted slice, or clones the
+    /// items of a borrowed slice.
+    fn from(v: Cow<'_, [T]>) -> Rc<[T]> {
+        match v {
+            Cow::Borrowed(v) => Rc::from(v),
+            Cow::Owned(v) => Rc::from(v),
+        }
+    }
+}
+
+impl From<Cow<'_, str>> for Rc<str> {
+    /// Moves the contents of an owned string into a new reference-counted string slice, or
+    /// copies a borrowed one.
+    fn from(v: Cow<'_, str>) -> Rc<str> {
+        match v {
+            Cow::Borrowed(v) => Rc::from(v),
+            Cow::Owned(v) => Rc::from(v),
+        }
+    }
+}
+
+impl From<char> for Rc<str> {
+    /// Allocates a reference-counted string slice containing the utf-8 encoding of `c`.
+    fn from(c: char) -> Rc<str> {
+        Rc::from(&*c.encode_utf8(&mut [0; 4]))
+    }
+}
+
+impl FromStr for Rc<str> {
+    type Err = Infallible;
+
+    /// Allocates a reference-counted string slice and copies `s` into it.
+    fn from_str(s: &str) -> Result<Rc<str>, Infallible> {
+        Ok(Rc::from(s))
+    }
+}
+
+impl FromIterator<char> for Rc<str> {
+    /// Since the length of the encoding is not known in advance, the characters are first
+    /// collected into a `String`, and then copied into an allocation of exactly the right size.
+    fn from_iter<I: IntoIterator<Item = char>>(iter: I) -> Rc<str> {
+        Rc::from(&*iter.into_iter().collect::<String>())
+    }
+}
+
+impl<'a> FromIterator<&'a str> for Rc<str> {
+    /// Since the total length is not known in advance, the strings are first collected into a
+    /// `String`, and then copied into an allocation of exactly the right size.
+    fn from_iter<I: IntoIterator<Item = &'a str>>(iter: I) -> Rc<str> {
+        Rc::from(&*iter.into_iter().collect::<String>())
+    }
+}
+
+impl<T: ?Sized> FromOwned<Box<T>> for Rc<T> {
+    fn from_owned(src: Box<T>) -> Self {
+        Rc::from(src)
+    }
+}
+
+impl<T> FromOwned<Vec<T>> for Rc<[T]> {
+    fn from_owned(src: Vec<T>) -> Self {
+        Rc::from(src)
+    }
+}
+
+impl FromOwned<String> for Rc<str> {
+    fn from_owned(src: String) -> Self {
+        Rc::from(src)
+    }
+}
+
+#[cfg(feature = "std")]
+impl<T> Rc<T> {
+    /// Converts a `std::rc::Rc` into an `Rc` without cloning the value, succeeding only if it is
+    /// the only reference to its allocation. Returns the `std::rc::Rc` unchanged otherwise.
+    ///
+    /// The value is moved into a new allocation, since the two pointer types store their
+    /// reference counts differently.
+    pub fn from_std_unique(std: StdRc<T>) -> Result<Rc<T>, StdRc<T>> {
+        StdRc::try_unwrap(std).map(Rc::new)
+    }
+}
+
+#[cfg(feature = "std")]
+impl<T: Clone> From<StdRc<T>> for Rc<T> {
+    /// Converts a `std::rc::Rc` into an `Rc` with an equal value.
+    ///
+    /// The allocation can never be reused, since the two pointer types store their reference
+    /// counts differently. The value is moved into a new allocation if the `std::rc::Rc` was the
+    /// only reference to its allocation, or cloned otherwise.
+    fn from(std: StdRc<T>) -> Rc<T> {
+        StdRc::try_unwrap(std).map_or_else(|std| Rc::new((*std).clone()), Rc::new)
+    }
+}
+
+#[cfg(feature = "std")]
+impl<T: Clone> From<Rc<T>> for StdRc<T> {
+    /// Converts an `Rc` into a `std::rc::Rc` with an equal value.
+    ///
+    /// The allocation can never be reused, since the two pointer types store their reference
+    /// counts differently. The value is moved into a new allocation if the `Rc` was the only
+    /// reference to its allocation, or cloned otherwise.
+    fn from(this: Rc<T>) -> StdRc<T> {
+        Rc::try_unwrap(this).map_or_else(|this| StdRc::new((*this).clone()), StdRc::new)
+    }
+}
+
+impl<T: ?Sized + Hash> Hash for Rc<T> {
+    /// Hashes the inner value, so an `Rc` and a `UniqueRc` with equal inner values have equal
+    /// hashes, consistent with the equality between the two types.
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        (**self).hash(state)
+    }
+}
+
+impl<T: ?Sized + Hash> Hash for UniqueRc<T> {
+    /// Hashes the inner value, so an `Rc` and a `UniqueRc` with equal inner values have equal
+    /// hashes, consistent with the equality between the two types.
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        (**self).hash(state)
+    }
+}
+
+impl<T: ?Sized> Unpin for Rc<T> {}
+
+impl<T: ?Sized> Unpin for UniqueRc<T> {}
+
+// Shared pointers can only hand out shared references, so they forward `Fn` only.
+
+#[cfg(feature = "unstable")]
+impl<Args: Tuple, F: ?Sized + Fn<Args>> FnOnce<Args> for Rc<F> {
+    type Output = F::Output;
+
+    extern "rust-call" fn call_once(self, args: Args) -> F::Output {
+        (*self).call(args)
+    }
+}
+
+#[cfg(feature = "unstable")]
+impl<Args: Tuple, F: ?Sized + Fn<Args>> FnMut<Args> for Rc<F> {
+    extern "rust-call" fn call_mut(&mut self, args: Args) -> F::Output {
+        (**self).call(args)
+    }
+}
+
+#[cfg(feature = "unstable")]
+impl<Args: Tuple, F: ?Sized + Fn<Args>> Fn<Args> for Rc<F> {
+    extern "rust-call" fn call(&self, args: Args) -> F::Output {
+        (**self).call(args)
+    }
+}
+
+#[cfg(feature = "unstable")]
+impl<Args: Tuple, F: ?Sized + FnMut<Args>> FnOnce<Args> for UniqueRc<F> {
+    type Output = F::Output;
+
+    extern "rust-call" fn call_once(mut self, args: Args) -> F::Output {
+        (*self).call_mut(args)
+    }
+}
+
+#[cfg(feature = "unstable")]
+impl<Args: Tuple, F: ?Sized + FnMut<Args>> FnMut<Args> for UniqueRc<F> {
+    extern "rust-call" fn call_mut(&mut self, args: Args) -> F::Output {
+        (**self).call_mut(args)
+    }
+}
+
+// `Pin<UniqueRc<_>>` is covered by the blanket impls for pinned pointers of the futures and tokio
+// crates.
+
+#[cfg(feature = "futures")]
+impl<S: ?Sized + Stream + Unpin> Stream for UniqueRc<S> {
+    type Item = S::Item;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        Pin::new(&mut **self).poll_next(cx)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (**self).size_hint()
+    }
+}
+
+#[cfg(feature = "futures")]
+impl<S: ?Sized + FusedStream + Unpin> FusedStream for UniqueRc<S> {
+    fn is_terminated(&self) -> bool {
+        (**self).is_terminated()
+    }
+}
+
+#[cfg(feature = "futures")]
+impl<S: ?Sized + Sink<Item> + Unpin, Item> Sink<Item> for UniqueRc<S> {
+    type Error = S::Error;
+
+    fn poll_ready(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        Pin::new(&mut **self).poll_ready(cx)
+    }
+
+    fn start_send(mut self: Pin<&mut Self>, item: Item) -> Result<(), Self::Error> {
+        Pin::new(&mut **self).start_send(item)
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        Pin::new(&mut **self).poll_flush(cx)
+    }
+
+    fn poll_close(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        Pin::new(&mut **self).poll_close(cx)
+    }
+}
+
+#[cfg(feature = "tokio-io")]
+impl<T: ?Sized + tokio::io::AsyncRead + Unpin> tokio::io::AsyncRead for UniqueRc<T> {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut tokio::io::ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        Pin::new(&mut **self).poll_read(cx, buf)
+    }
+}
+
+#[cfg(feature = "tokio-io")]
+impl<T: ?Sized + tokio::io::AsyncWrite + Unpin> tokio::io::AsyncWrite for UniqueRc<T> {
+    fn poll_write(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        Pin::new(&mut **self).poll_write(cx, buf)
+    }
+
+    fn poll_write_vectored(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        bufs: &[io::IoSlice<'_>],
+    ) -> Poll<io::Result<usize>> {
+        Pin::new(&mut **self).poll_write_vectored(cx, bufs)
+    }
+
+    fn is_write_vectored(&self) -> bool {
+        (**self).is_write_vectored()
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut **self).poll_flush(cx)
+    }
+
+    fn poll_shutdown(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut **self).poll_shutdown(cx)
+    }
+}
+
+#[cfg(feature = "futures-io")]
+impl<T: ?Sized + futures_io::AsyncRead + Unpin> futures_io::AsyncRead for UniqueRc<T> {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut [u8],
+    ) -> Poll<io::Result<usize>> {
+        Pin::new(&mut **self).poll_read(cx, buf)
+    }
+
+    fn poll_read_vectored(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        bufs: &mut [io::IoSliceMut<'_>],
+    ) -> Poll<io::Result<usize>> {
+        Pin::new(&mut **self).poll_read_vectored(cx, bufs)
+    }
+}
+
+#[cfg(feature = "futures-io")]
+impl<T: ?Sized + futures_io::AsyncWrite + Unpin> futures_io::AsyncWrite for UniqueRc<T> {
+    fn poll_write(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        Pin::new(&mut **self).poll_write(cx, buf)
+    }
+
+    fn poll_write_vectored(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        bufs: &[io::IoSlice<'_>],
+    ) -> Poll<io::Result<usize>> {
+        Pin::new(&mut **self).poll_write_vectored(cx, bufs)
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut **self).poll_flush(cx)
+    }
+
+    fn poll_close(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut **self).poll_close(cx)
+    }
+}
+
+#[cfg(feature = "bincode")]
+impl<T: ?Sized + Encode> Encode for Rc<T> {
+    fn encode<E: Encoder>(&self, encoder: &mut E) -> Result<(), EncodeError> {
+        T::encode(self, encoder)
+    }
+}
+
+#[cfg(feature = "bincode")]
+impl<T: ?Sized + Encode> Encode for UniqueRc<T> {
+    fn encode<E: Encoder>(&self, encoder: &mut E) -> Result<(), EncodeError> {
+        T::encode(self, encoder)
+    }
+}
+
+#[cfg(feature = "bincode")]
+impl<C, T: Decode<C>> Decode<C> for Rc<T> {
+    fn decode<D: Decoder<Context = C>>(decoder: &mut D) -> Result<Self, DecodeError> {
+        Ok(Rc::new(T::decode(decoder)?))
+    }
+}
+
+#[cfg(feature = "bincode")]
+impl<C, T: Decode<C>> Decode<C> for UniqueRc<T> {
+    fn decode<D: Decoder<Context = C>>(decoder: &mut D) -> Result<Self, DecodeError> {
+        Ok(UniqueRc::new(T::decode(decoder)?))
+    }
+}
+
+#[cfg(feature = "bincode")]
+impl<'de, C, T: BorrowDecode<'de, C>> BorrowDecode<'de, C> for Rc<T> {
+    fn borrow_decode<D: BorrowDecoder<'de, Context = C>>(
+        decoder: &mut D,
+    ) -> Result<Self, DecodeError> {
+        Ok(Rc::new(T::borrow_decode(decoder)?))
+    }
+}
+
+#[cfg(feature = "bincode")]
+impl<'de, C, T: BorrowDecode<'de, C>> BorrowDecode<'de, C> for UniqueRc<T> {
+    fn borrow_decode<D: BorrowDecoder<'de, Context = C>>(
+        decoder: &mut D,
+    ) -> Result<Self, DecodeError> {
+        Ok(UniqueRc::new(T::borrow_decode(decoder)?))
+    }
+}
+
+#[cfg(feature = "schemars")]
+impl<T: ?Sized + JsonSchema> JsonSchema for Rc<T> {
+    fn inline_schema() -> bool {
+        T::inline_schema()
+    }
+
+    fn schema_name() -> Cow<'static, str> {
+        T::schema_name()
+    }
+
+    fn schema_id() -> Cow<'static, str> {
+        T::schema_id()
+    }
+
+    fn json_schema(generator: &mut SchemaGenerator) -> Schema {
+        T::json_schema(generator)
+    }
+}
+
+#[cfg(feature = "schemars")]
+impl<T: ?Sized + JsonSchema> JsonSchema for UniqueRc<T> {
+    fn inline_schema() -> bool {
+        T::inline_schema()
+    }
+
+    fn schema_name() -> Cow<'static, str> {
+        T::schema_name()
+    }
+
+    fn schema_id() -> Cow<'static, str> {
+        T::schema_id()
+    }
+
+    fn json_schema(generator: &mut SchemaGenerator) -> Schema {
+        T::json_schema(generator)
+    }
+}
+
+#[cfg(feature = "borsh")]
+impl<T: ?Sized + BorshSerialize> BorshSerialize for Rc<T> {
+    fn serialize<W: borsh_io::Write>(&self, writer: &mut W) -> borsh_io::Result<()> {
+        (**self).serialize(writer)
+    }
+}
+
+#[cfg(feature = "borsh")]
+impl<T: ?Sized + BorshSerialize> BorshSerialize for UniqueRc<T> {
+    fn serialize<W: borsh_io::Write>(&self, writer: &mut W) -> borsh_io::Result<()> {
+        (**self).serialize(writer)
+    }
+}
+
+#[cfg(feature = "borsh")]
+impl<T: BorshDeserialize> BorshDeserialize for Rc<T> {
+    fn deserialize_reader<R: borsh_io::Read>(reader: &mut R) -> borsh_io::Result<Self> {
+        Ok(Rc::new(T::deserialize_reader(reader)?))
+    }
+}
+
+#[cfg(feature = "borsh")]
+impl<T: BorshDeserialize> BorshDeserialize for UniqueRc<T> {
+    fn deserialize_reader<R: borsh_io::Read>(reader: &mut R) -> borsh_io::Result<Self> {
+        Ok(UniqueRc::new(T::deserialize_reader(reader)?))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+
+    use super::*;
+    use crate::test_util::allocations;
+
+    // These mostly check that the heterogeneous impls do not break type inference.
+    #[test]
+    fn comparisons_between_same_types() {
+        let a = Rc::new(1);
+        assert_eq!(a, Rc::new(1));
+        assert!(a < Rc::new(2));
+        assert_eq!(a.partial_cmp(&Rc::new(0)), Some(Ordering::Greater));
+        let bytes: Rc<Vec<u8>> = Rc::new(Vec::new());
+        assert_eq!(bytes, Rc::new(Vec::<u8>::new()));
+        assert_ne!(Rc::new("a"), Rc::new("b"));
+
+        let u = UniqueRc::new(1);
+        assert_eq!(u, UniqueRc::new(1));
+        assert!(u < UniqueRc::new(2));
+        assert_eq!(a, u);
+        assert_eq!(u, a);
+        assert!(u <= a);
+    }
+
+    #[test]
+    fn comparisons_between_different_types() {
+        let string = Rc::new(String::from("abc"));
+        let str: Rc<str> = Rc::from("abc");
+        assert!(string == str);
+        assert!(str != Rc::new(String::from("abd")));
+
+        let unique = UniqueRc::new(String::from("abc"));
+        assert!(unique == str);
+        assert!(str == unique);
+
+        let vec = Rc::new(std::vec![1, 2]);
+        let slice: Rc<[i32]> = Rc::from(&[1, 2][..]);
+        assert!(vec == slice);
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn comparisons_between_paths() {
+        use std::path::{Path, PathBuf};
+
+        let buf = Rc::new(PathBuf::from("/a/b"));
+        let path: Rc<Path> = Rc::from(Path::new("/a/b"));
+        assert!(buf == path);
+        assert!(path != Rc::new(PathBuf::from("/a/c")));
+    }
+
+    #[test]
+    fn borrows_share_the_allocation() {
+        let a = Rc::new(std::string::String::from("abc"));
+        let b = Rc::borrow_rc(&a);
+        let copy = b;
+        assert_eq!(&*copy, "abc");
+        assert_eq!(Rc::reference_count(&a).get(), 1);
+
+        let owned = RcBorrow::to_rc(b);
+        assert!(Rc::ptr_eq(&a, &owned));
+        assert_eq!(Rc::reference_count(&a).get(), 2);
+        drop(owned);
+        assert_eq!(Rc::reference_count(&a).get(), 1);
+    }
+
+    #[test]
+    fn borrows_from_unsized_pointers() {
+        let a: Rc<[u64]> = Rc::from(&[1, 2, 3][..]);
+        let b = unsafe { RcBorrow::from_raw(Rc::as_ptr(&a)) };
+        assert_eq!(RcBorrow::get(b), &[1, 2, 3]);
+        assert!(Rc::ptr_eq(&a, &RcBorrow::to_rc(b)));
+
+        let s: Rc<str> = Rc::from("xyz");
+        let b = unsafe { RcBorrow::from_raw(Rc::as_ptr(&s)) };
+        assert!(Rc::ptr_eq(&s, &RcBorrow::to_rc(b)));
+        assert_eq!(Rc::reference_count(&s).get(), 1);
+    }
+
+    #[test]
+    fn copy_from_slice_keeps_alignment() {
+        let src: std::vec::Vec<u64> = (0..1000).map(|i| i * 0x0101_0101_0101).collect();
+        let copy = Rc::copy_from_slice(&src);
+        assert_eq!(&*copy, &src[..]);
+        assert_eq!(copy.as_ptr() as usize % mem::align_of::<u64>(), 0);
+        assert_eq!(copy, Rc::<[u64]>::from(&src[..]));
+
+        #[derive(Clone, Copy, Debug, PartialEq)]
+        #[repr(align(32))]
+        struct Aligned(u8);
+        let src = [Aligned(1), Aligned(2), Aligned(3)];
+        let copy = Rc::copy_from_slice(&src);
+        assert_eq!(&*copy, &src[..]);
+        assert_eq!(copy.as_ptr() as usize % 32, 0);
+
+        let empty = Rc::<[u64]>::copy_from_slice(&[]);
+        assert!(empty.is_empty());
+    }
+
+    #[cfg(feature = "weak")]
+    #[test]
+    fn weaks_keep_only_the_allocation_alive() {
+        use crate::test_util::deallocations;
+
+        struct Flag<'a>(&'a Cell<bool>);
+
+        impl Drop for Flag<'_> {
+            fn drop(&mut self) {
+                self.0.set(true);
+            }
+        }
+
+        let dropped = Cell::new(false);
+        let rc = Rc::new(Flag(&dropped));
+        let weak = Rc::downgrade(&rc);
+        let weak2 = weak.clone();
+        assert_eq!(Rc::reference_count(&rc).get(), 1);
+        assert_eq!(Rc::weak_count(&rc), 2);
+
+        // The value is dropped with the last `Rc`, the allocation with the last `WeakRc`.
+        let before = deallocations();
+        drop(rc);
+        assert!(dropped.get());
+        assert!(weak.upgrade().is_none());
+        assert_eq!(weak.strong_count(), 0);
+        drop(weak);
+        assert_eq!(deallocations(), before);
+        drop(weak2);
+        assert_eq!(deallocations(), before + 1);
+    }
+
+    #[cfg(feature = "weak")]
+    #[test]
+    fn cyclic_parents_and_children_link_both_ways() {
+        struct Parent {
+            children: std::vec::Vec<Rc<Child>>,
+        }
+
+        struct Child {
+            parent: WeakRc<Parent>,
+        }
+
+        let parent = Rc::new_cyclic(|this| {
+            assert!(this.upgrade().is_none());
+            let children = (0..2).map(|_| Rc::new(Child { parent: this.clone() })).collect();
+            Parent { children }
+        });
+        assert_eq!(Rc::reference_count(&parent).get(), 1);
+        assert_eq!(Rc::weak_count(&parent), 2);
+        for child in &parent.children {
+            assert!(Rc::ptr_eq(&child.parent.upgrade().unwrap(), &parent));
+        }
+
+        let child = parent.children[0].clone();
+        drop(parent);
+        assert!(child.parent.upgrade().is_none());
+    }
+
+    #[cfg(feature = "weak")]
+    #[test]
+    fn cyclic_constructors_can_panic() {
+        use crate::test_util::deallocations;
+        use std::panic::{self, AssertUnwindSafe};
+
+        struct NeverCreated;
+
+        impl Drop for NeverCreated {
+            fn drop(&mut self) {
+                unreachable!("the value was never created");
+            }
+        }
+
+        let mut escaped = None;
+        let result = panic::catch_unwind(AssertUnwindSafe(|| {
+            Rc::<NeverCreated>::new_cyclic(|this| {
+                escaped = Some(this.clone());
+                std::panic!("constructor failed");
+            })
+        }));
+        assert!(result.is_err());
+
+        // The escaped `WeakRc` keeps the allocation, which has no value.
+        let escaped = escaped.unwrap();
+        assert!(escaped.upgrade().is_none());
+        assert_eq!(escaped.strong_count(), 0);
+        let before = deallocations();
+        drop(escaped);
+        assert_eq!(deallocations(), before + 1);
+    }
+
+    #[cfg(feature = "weak")]
+    #[test]
+    fn weaks_compare_by_allocation() {
+        let a = Rc::new(String::from("a"));
+        let b = Rc::new(String::from("a"));
+        let weak_a = Rc::downgrade(&a);
+        assert!(weak_a.ptr_eq(&Rc::downgrade(&a)));
+        assert!(weak_a.ptr_eq(&weak_a.clone()));
+        assert!(!weak_a.ptr_eq(&Rc::downgrade(&b)));
+        assert!(!weak_a.ptr_eq(&WeakRc::new()));
+        assert!(WeakRc::<String>::new().ptr_eq(&WeakRc::new()));
+        assert_eq!(weak_a.as_ptr(), Rc::as_ptr(&a));
+
+        // The pointers stay comparable after the value is gone.
+        let ptr = Rc::as_ptr(&a);
+        drop(a);
+        assert_eq!(weak_a.as_ptr(), ptr);
+        assert!(weak_a.ptr_eq(&weak_a.clone()));
+        assert!(!weak_a.ptr_eq(&Rc::downgrade(&b)));
+
+        let slice: Rc<[u8]> = Rc::from(&[1, 2, 3][..]);
+        let weak = Rc::downgrade(&slice);
+        assert_eq!(weak.as_ptr(), Rc::as_ptr(&slice));
+        assert_eq!(unsafe { &*weak.as_ptr() }, &[1, 2, 3]);
+    }
+
+    #[cfg(feature = "weak")]
+    #[test]
+    fn dangling_weaks_never_allocate_or_upgrade() {
+        #[repr(align(64))]
+        struct Aligned;
+
+        const DANGLING: WeakRc<String> = WeakRc::new();
+
+        let before = allocations();
+        let weak = DANGLING;
+        let clone = weak.clone();
+        assert!(weak.upgrade().is_none());
+        assert!(clone.upgrade().is_none());
+        assert_eq!(weak.strong_count(), 0);
+        assert!(weak.ptr_eq(&clone));
+        assert!(WeakRc::<String>::default().ptr_eq(&weak));
+        drop((weak, clone));
+        assert_eq!(allocations(), before);
+
+        let ptr = WeakRc::<u64>::new().as_ptr();
+        assert!(!ptr.is_null());
+        assert_eq!(ptr as usize % mem::align_of::<u64>(), 0);
+        assert_eq!(WeakRc::<Aligned>::new().as_ptr() as usize % 64, 0);
+    }
+
+    #[test]
+    fn headers_only_hold_the_enabled_counts() {
+        use crate::test_util::deallocations;
+
+        // The header holds a second count only with the `weak` feature.
+        if !cfg!(feature = "allocation-id") {
+            let counts = if cfg!(feature = "weak") { 2 } else { 1 };
+            let header = counts * mem::size_of::<usize>();
+            assert_eq!(mem::offset_of!(RcBox<u8>, data), header);
+            assert_eq!(mem::size_of::<RcBox<usize>>(), header + mem::size_of::<usize>());
+        }
+
+        // Without any weak pointers, the last `Rc` frees the allocation right away.
+        let before = deallocations();
+        drop(Rc::new(1_u64));
+        assert_eq!(deallocations(), before + 1);
+    }
+
+    #[test]
+    fn raw_round_trips_keep_the_reference_count() {
+        // As stored in the user data of a C callback.
+        let rc = Rc::new(String::from("abc"));
+        let user_data = Rc::into_raw(rc.clone()) as *mut base::ffi::c_void;
+        assert_eq!(Rc::reference_count(&rc).get(), 2);
+        let back = unsafe { Rc::from_raw(user_data as *const String) };
+        assert!(Rc::ptr_eq(&rc, &back));
+        assert_eq!(Rc::reference_count(&rc).get(), 2);
+        drop(back);
+        assert_eq!(Rc::reference_count(&rc).get(), 1);
+
+        let slice: Rc<[u16]> = Rc::from(&[1, 2, 3][..]);
+        let ptr = Rc::into_raw(slice.clone());
+        assert_eq!(ptr, Rc::as_ptr(&slice));
+        assert_eq!(Rc::reference_count(&slice).get(), 2);
+        let back = unsafe { Rc::from_raw(ptr) };
+        assert_eq!(&*back, &[1, 2, 3]);
+        assert_eq!(Rc::reference_count(&slice).get(), 2);
+        drop(back);
+        assert_eq!(Rc::reference_count(&slice).get(), 1);
+    }
+
+    #[test]
+    fn data_offsets_match_the_layout() {
+        use base::any::Any;
+
+        #[repr(align(64))]
+        struct Aligned(u8);
+
+        assert_eq!(data_offset_val(&1_u8), mem::offset_of!(RcBox<u8>, data));
+        assert_eq!(data_offset_val(&1_u64), mem::offset_of!(RcBox<u64>, data));
+        assert_eq!(data_offset_val(&Aligned(1)), mem::offset_of!(RcBox<Aligned>, data));
+        assert_eq!(data_offset_val(&Aligned(1)) % 64, 0);
+
+        // The offsets of unsized values lead back from the value to the header.
+        fn header_of<T: ?Sized>(ptr: &Rc<T>) -> *const u8 {
+            Rc::as_ptr(ptr).cast::<u8>().wrapping_sub(data_offset_val(&**ptr))
+        }
+        let slice: Rc<[u64]> = Rc::from(&[1, 2, 3][..]);
+        assert_eq!(data_offset_val(&*slice), mem::offset_of!(RcBox<[u64; 3]>, data));
+        assert_eq!(header_of(&slice), slice.ptr.as_ptr().cast::<u8>());
+        let text: Rc<str> = Rc::from("abc");
+        assert_eq!(data_offset_val(&*text), mem::offset_of!(RcBox<u8>, data));
+        assert_eq!(header_of(&text), text.ptr.as_ptr().cast::<u8>());
+        let dynamic: Rc<dyn Any> = Rc::from(Box::new(Aligned(1)) as Box<dyn Any>);
+        assert_eq!(data_offset_val(&*dynamic), mem::offset_of!(RcBox<Aligned>, data));
+        assert_eq!(header_of(&dynamic), dynamic.ptr.as_ptr().cast::<u8>());
+        assert_eq!(dynamic.downcast_ref::<Aligned>().unwrap().0, 1);
+    }
+
+    static STATIC_INNER: StaticRcInner<[u32; 2]> = StaticRcInner::new([1, 2]);
+    const CONST: Rc<[u32; 2]> = Rc::from_static(&STATIC_INNER);
+
+    #[test]
+    fn const_rcs_need_no_setup() {
+        let before = allocations();
+        let clones = [(); 100].map(|_| CONST.clone());
+        assert!(clones.iter().all(|clone| Rc::ptr_eq(clone, &CONST)));
+        assert_eq!(*CONST, [1, 2]);
+        drop(clones);
+        assert_eq!(allocations(), before);
+        assert_eq!(Rc::reference_count(&CONST).get(), usize::MAX);
+        assert!(Rc::try_unwrap(CONST).is_err());
+    }
+
+    #[test]
+    fn rc_macro_forms() {
+        let empty: Rc<[String]> = crate::rc![];
+        assert!(empty.is_empty());
+
+        let before = allocations();
+        let words = crate::rc![String::from("a"), String::from("b"),];
+        let repeated = crate::rc![String::from("ab"); 3];
+        // One for each string, and one for each slice.
+        assert_eq!(allocations() - before, 7);
+        assert_eq!(&*words, &[String::from("a"), String::from("b")]);
+        assert_eq!(&*repeated, &[String::from("ab"), String::from("ab"), String::from("ab")]);
+
+        let none: Rc<[String]> = crate::rc![String::from("dropped"); 0];
+        assert!(none.is_empty());
+        let single = crate::rc![1,];
+        assert_eq!(&*single, &[1]);
+    }
+
+    // Leaking is the point of this test, so Miri would report it.
+    #[cfg_attr(miri, ignore)]
+    #[test]
+    fn clones_keep_working_after_a_leak() {
+        let rc = Rc::new(String::from("plugin"));
+        let clone = rc.clone();
+        let leaked: &'static String = Rc::leak(rc);
+        assert_eq!(leaked, "plugin");
+        assert_eq!(*clone, "plugin");
+        assert_eq!(Rc::reference_count(&clone).get(), 2);
+
+        let again = clone.clone();
+        drop(clone);
+        assert_eq!(Rc::reference_count(&again).get(), 2);
+        let again = Rc::try_unwrap(again).unwrap_err();
+        assert!(Rc::into_mut(again).is_err());
+        assert_eq!(leaked, "plugin");
+    }
+
+    #[test]
+    fn c_strings_keep_their_nul_terminator() {
+        let empty: Rc<CStr> = Rc::from(<&CStr>::default());
+        assert_eq!(empty.to_bytes_with_nul(), b"\0");
+
+        let c = CStr::from_bytes_until_nul(b"ab\0cd\0").unwrap();
+        let rc: Rc<CStr> = Rc::from(c);
+        assert_eq!(rc.to_bytes_with_nul(), b"ab\0");
+        assert_eq!(&*rc, c);
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn paths_and_os_strings_are_copied() {
+        let empty: Rc<Path> = Rc::from(Path::new(""));
+        assert_eq!(empty.as_os_str().len(), 0);
+        let path: Rc<Path> = Rc::from(PathBuf::from("/a/b"));
+        assert_eq!(&*path, Path::new("/a/b"));
+
+        let os: Rc<OsStr> = Rc::from(OsString::from("abc"));
+        assert_eq!(&*os, OsStr::new("abc"));
+    }
+
+    #[cfg(all(feature = "std", unix))]
+    #[test]
+    fn non_utf8_os_strings() {
+        use std::os::unix::ffi::OsStrExt;
+
+        let bytes = [0x66, 0x6f, 0x80, 0xff];
+        let os: Rc<OsStr> = Rc::from(OsStr::from_bytes(&bytes));
+        assert_eq!(os.as_bytes(), &bytes);
+        assert!(os.to_str().is_none());
+        let path: Rc<Path> = Rc::from(Path::new(OsStr::from_bytes(&bytes)));
+        assert_eq!(path.as_os_str().as_bytes(), &bytes);
+    }
+
+    #[test]
+    fn casts_between_transparent_newtypes() {
+        #[repr(transparent)]
+        struct RawId(u32);
+
+        #[repr(transparent)]
+        struct Id(RawId);
+
+        #[repr(transparent)]
+        struct Name(String);
+
+        let raw = Rc::new(RawId(7));
+        let shared = raw.clone();
+        let id: Rc<Id> = unsafe { Rc::cast(raw) };
+        assert_eq!((id.0).0, 7);
+        assert_eq!(Rc::reference_count(&id).get(), 2);
+        drop(shared);
+
+        // The strings are dropped through whichever pointer comes last.
+        let name = Rc::new(String::from("name"));
+        let cast: Rc<Name> = unsafe { Rc::cast(name.clone()) };
+        drop(name);
+        assert_eq!(cast.0, "name");
+
+        let raw: Rc<[RawId]> = Rc::from(vec![RawId(1), RawId(2), RawId(3)]);
+        let ptr = Rc::as_ptr(&raw) as *const u8;
+        let ids: Rc<[Id]> = unsafe { Rc::cast_slice(raw) };
+        assert_eq!(ids.len(), 3);
+        assert_eq!(ids.iter().map(|id| (id.0).0).collect::<Vec<_>>(), [1, 2, 3]);
+        assert_eq!(Rc::as_ptr(&ids) as *const u8, ptr);
+
+        let names: Rc<[String]> = Rc::from(vec![String::from("a"), String::from("b")]);
+        let names = Rc::into_mut(names).ok().unwrap();
+        let mut names: UniqueRc<[Name]> = unsafe { UniqueRc::cast_slice(names) };
+        names[1].0.push('c');
+        let name = Rc::into_mut(Rc::new(String::new())).ok().unwrap();
+        let mut name: UniqueRc<Name> = unsafe { UniqueRc::cast(name) };
+        // `name.0` would be the field of the `UniqueRc`.
+        (*name).0.push_str(&names[1].0);
+        assert_eq!((*name).0, "bc");
+    }
+
+    #[test]
+    fn dropping_the_last_pointer_drops_sized_slice_and_dyn_values() {
+        use std::cell::Cell;
+
+        // The string lets Miri catch values that are not dropped.
+        struct Counted<'a>(&'a Cell<usize>, String);
+
+        impl Drop for Counted<'_> {
+            fn drop(&mut self) {
+                assert_eq!(self.1, "heap");
+                self.0.set(self.0.get() + 1);
+            }
+        }
+
+        trait Value {}
+
+        impl Value for Counted<'_> {}
+
+        let drops = Cell::new(0);
+        let counted = || Counted(&drops, String::from("heap"));
+
+        let sized = Rc::new(counted());
+        let clone = sized.clone();
+        drop(sized);
+        assert_eq!(drops.get(), 0);
+        drop(clone);
+        assert_eq!(drops.get(), 1);
+
+        let slice: Rc<[Counted<'_>]> = Rc::from(vec![counted(), counted()]);
+        let clone = slice.clone();
+        drop(slice);
+        drop(clone);
+        assert_eq!(drops.get(), 3);
+
+        let boxed: Box<dyn Value + '_> = Box::new(counted());
+        let value: Rc<dyn Value + '_> = Rc::from(boxed);
+        let clone = value.clone();
+        drop(value);
+        assert_eq!(drops.get(), 3);
+        drop(clone);
+        assert_eq!(drops.get(), 4);
+
+        let empty: Rc<[Counted<'_>]> = Rc::from(Vec::new());
+        drop(empty);
+        assert_eq!(drops.get(), 4);
+    }
+
+    #[test]
+    fn failed_allocations_are_reported() {
+        use crate::test_util::failing_allocations;
+        use std::cell::Cell;
+
+        struct Counted<'a>(&'a Cell<usize>);
+
+        impl Drop for Counted<'_> {
+            fn drop(&mut self) {
+                self.0.set(self.0.get() + 1);
+            }
+        }
+
+        let drops = Cell::new(0);
+        let result = failing_allocations(|| Rc::try_new(Counted(&drops)));
+        assert!(matches!(result, Err(AllocError)));
+        assert_eq!(drops.get(), 1);
+        assert!(matches!(failing_allocations(Rc::<u64>::try_new_uninit), Err(AllocError)));
+
+        let rc = Rc::try_new(Counted(&drops)).ok().unwrap();
+        drop(rc);
+        assert_eq!(drops.get(), 2);
+        let mut uninit = Rc::<u64>::try_new_uninit().ok().unwrap();
+        uninit.write(5);
+        assert_eq!(*unsafe { uninit.assume_init() }, 5);
+    }
+
+    #[test]
+    fn clones_and_drops_balance_the_count() {
+        use crate::test_util::deallocations;
+
+        let rc = Rc::new(String::from("count"));
+        let clones: Vec<_> = (0..10).map(|_| rc.clone()).collect();
+        assert_eq!(Rc::reference_count(&rc).get(), 11);
+        drop(clones);
+        assert_eq!(Rc::reference_count(&rc).get(), 1);
+
+        // A fresh clone never frees the value when it is dropped.
+        drop(rc.clone());
+        assert_eq!(*rc, "count");
+
+        // The last drop frees both the string and the allocation.
+        let before = deallocations();
+        drop(rc);
+        assert_eq!(deallocations(), before + 2);
+    }
+
+    #[cfg(feature = "unstable")]
+    #[test]
+    fn pointers_to_closures_are_callable() {
+        fn call_once<F: FnOnce(u32) -> u32>(f: F) -> u32 {
+            f(5)
+        }
+
+        let offset = 10;
+        let add = Rc::new(move |x: u32| x + offset);
+        assert_eq!(add(1), 11);
+        let mapped: Vec<u32> = (0..3).map(add.clone()).collect();
+        assert_eq!(mapped, [10, 11, 12]);
+        // Calling a pointer by value only consumes that pointer.
+        assert_eq!(call_once(add.clone()), 15);
+        assert_eq!(Rc::reference_count(&add).get(), 1);
+
+        let len: Box<dyn Fn(&str) -> usize> = Box::new(str::len);
+        let len: Rc<dyn Fn(&str) -> usize> = Rc::from(len);
+        assert_eq!(len("häuschen"), 9);
+        assert_eq!(len(""), 0);
+
+        // Unique pointers hand out mutable references, so they forward `FnMut` as well.
+        let mut calls = 0;
+        let mut count = UniqueRc::new(|x: u32| {
+            calls += 1;
+            x + calls
+        });
+        assert_eq!(count(0), 1);
+        assert_eq!(count(0), 2);
+        assert_eq!(call_once(count), 8);
+        assert_eq!(calls, 3);
+    }
+
+    #[test]
+    fn clone_many_releases_the_clones_left_over() {
+        use crate::test_util::deallocations;
+
+        let a = Rc::new(String::from("a"));
+        let mut clones = Rc::clone_many(&a, 5);
+        assert_eq!(Rc::reference_count(&a).get(), 6);
+        assert_eq!(clones.len(), 5);
+        let first = clones.next().unwrap();
+        assert!(Rc::ptr_eq(&first, &a));
+        assert_eq!(clones.len(), 4);
+        // Dropping the iterator releases the four clones it has not produced.
+        drop(clones);
+        assert_eq!(Rc::reference_count(&a).get(), 2);
+        drop(first);
+        assert_eq!(Rc::reference_count(&a).get(), 1);
+
+        let all: Vec<Rc<String>> = Rc::clone_many(&a, 3).collect();
+        assert_eq!(Rc::reference_count(&a).get(), 4);
+        drop(all);
+        assert_eq!(Rc::clone_many(&a, 0).count(), 0);
+        assert_eq!(Rc::reference_count(&a).get(), 1);
+
+        // The iterator may outlive the original pointer, and then frees the allocation.
+        let clones = Rc::clone_many(&a, 3);
+        drop(a);
+        let before = deallocations();
+        drop(clones);
+        assert_eq!(deallocations(), before + 2);
+
+        // Pointers to static memory are not counted.
+        static INNER: StaticRcInner<u32> = StaticRcInner::new(7);
+        let shared = Rc::from_static(&INNER);
+        let mut clones = Rc::clone_many(&shared, 3);
+        assert_eq!(*clones.next().unwrap(), 7);
+        drop(clones);
+        assert_eq!(Rc::reference_count(&shared).get(), usize::MAX);
+    }
+
+    #[cfg(not(feature = "panic-free"))]
+    #[test]
+    fn clone_many_checks_for_overflow() {
+        use std::panic::{self, AssertUnwindSafe};
+
+        let a = Rc::new(1);
+        let clone_many = |n| panic::catch_unwind(AssertUnwindSafe(|| drop(Rc::clone_many(&a, n))));
+        assert!(clone_many(usize::MAX).is_err());
+        assert!(clone_many(usize::MAX - 1).is_err());
+        // The failed batches did not touch the count.
+        assert_eq!(Rc::reference_count(&a).get(), 1);
+
+        // The largest batch that fits.
+        let clones = Rc::clone_many(&a, usize::MAX - 2);
+        assert_eq!(Rc::reference_count(&a).get(), usize::MAX - 1);
+        drop(clones);
+        assert_eq!(Rc::reference_count(&a).get(), 1);
+    }
+
+    #[test]
+    fn downcasting_reuses_the_allocation() {
+        let any: Rc<dyn Any> = Rc::from(Box::new(String::from("any")) as Box<dyn Any>);
+        let ptr = Rc::as_ptr(&any) as *const u8;
+        // A failed downcast hands back the original pointer.
+        let any = Rc::<dyn Any>::downcast::<u32>(any).unwrap_err();
+        assert_eq!(Rc::as_ptr(&any) as *const u8, ptr);
+        let other = any.clone();
+        let string = Rc::<dyn Any>::downcast::<String>(any).unwrap();
+        assert_eq!(*string, "any");
+        assert_eq!(Rc::as_ptr(&string) as *const u8, ptr);
+        assert_eq!(Rc::reference_count(&string).get(), 2);
+        drop(other);
+        // Downcasting keeps unique pointers unique, so the value can be mutated.
+        let bytes: Box<dyn Any> = Box::new(std::vec![1_u8]);
+        let unique = Rc::into_mut(Rc::from(bytes)).unwrap();
+        let unique = UniqueRc::<dyn Any>::downcast::<Vec<u16>>(unique).unwrap_err();
+        let mut bytes = UniqueRc::<dyn Any>::downcast::<Vec<u8>>(unique).unwrap();
+        bytes.push(2);
+        let bytes: Rc<Vec<u8>> = bytes.into();
+        assert_eq!(*bytes, [1, 2]);
+        assert_eq!(Rc::reference_count(&bytes).get(), 1);
+    }
+
+    #[test]
+    fn adding_strings_reuses_unique_allocations() {
+        use crate::test_util::deallocations;
+
+        let mut s: Rc<str> = Rc::from("grüß");
+        let before = (allocations(), deallocations());
+        s += ", ";
+        let mut s = s + "gott";
+        // Each append is a single reallocation.
+        assert_eq!((allocations(), deallocations()), (before.0 + 2, before.1 + 2));
+        assert_eq!(&*s, "grüß, gott");
+        s += "";
+        assert_eq!(allocations(), before.0 + 2);
+
+        // Shared strings are copied, and the other pointers keep the old value.
+        let shared = s.clone();
+        let bang: Rc<str> = Rc::from("!");
+        let before = (allocations(), deallocations());
+        let s = s + bang;
+        // The copy is the only allocation, `bang` the only one freed.
+        assert_eq!((allocations(), deallocations()), (before.0 + 1, before.1 + 1));
+        assert_eq!(&*s, "grüß, gott!");
+        assert_eq!(&*shared, "grüß, gott");
+        assert_eq!(Rc::reference_count(&shared).get(), 1);
+        assert_eq!(Rc::reference_count(&s).get(), 1);
+
+        // Static strings are not counted, so they are always copied.
+        let mut greeting = crate::rc_str!("hello");
+        greeting += "";
+        assert_eq!(Rc::reference_count(&greeting).get(), usize::MAX);
+        let before = allocations();
+        greeting += " world";
+        assert_eq!(allocations(), before + 1);
+        assert_eq!(&*greeting, "hello world");
+        assert_eq!(Rc::reference_count(&greeting).get(), 1);
+        assert_eq!(&*(crate::rc_str!("") + crate::rc_str!("static")), "static");
+    }
+
+    #[test]
+    fn adding_strings_allocates_less_than_formatting() {
+        let words = ["reference", "-", "counted", " ", "strings"];
+
+        let before = allocations();
+        let mut added: Rc<str> = Rc::from(words[0]);
+        for word in &words[1..] {
+            added += word;
+        }
+        let adding = allocations() - before;
+
+        let before = allocations();
+        let mut formatted: Rc<str> = Rc::from(words[0]);
+        for word in &words[1..] {
+            formatted = Rc::from(std::format!("{}{}", formatted, word));
+        }
+        let formatting = allocations() - before;
+
+        assert_eq!(added, formatted);
+        // One allocation up front, and one reallocation per appended word.
+        assert_eq!(adding, words.len());
+        // On top of the first allocation, a `String` and a copy of it per appended word.
+        assert!(formatting > 2 * (words.len() - 1));
+    }
+
+    #[test]
+    fn boxed_values_move_into_new_allocations() {
+        use crate::test_util::deallocations;
+        use std::cell::Cell;
+
+        #[derive(Debug)]
+        struct Counted<'a>(&'a Cell<usize>);
+
+        impl Drop for Counted<'_> {
+            fn drop(&mut self) {
+                self.0.set(self.0.get() + 1);
+            }
+        }
+
+        let s: Rc<str> = Rc::from(Box::<str>::from("häuschen"));
+        assert_eq!(&*s, "häuschen");
+        let bytes: Rc<[u8]> = Rc::from(Box::<[u8]>::from(&[1, 2, 3][..]));
+        assert_eq!(&*bytes, [1, 2, 3]);
+        let empty: Rc<[u8]> = Rc::from(Box::<[u8]>::from(&[][..]));
+        assert!(empty.is_empty());
+
+        let drops = Cell::new(0);
+        let boxed: Box<dyn fmt::Debug + '_> = Box::new(Counted(&drops));
+        let before = (allocations(), deallocations());
+        let rc: Rc<dyn fmt::Debug + '_> = Rc::from(boxed);
+        // The box is freed without dropping the value, which now lives in the `Rc`.
+        assert_eq!((allocations(), deallocations()), (before.0 + 1, before.1 + 1));
+        assert_eq!(drops.get(), 0);
+        assert_eq!(std::format!("{:?}", rc), "Counted(Cell { value: 0 })");
+
+        let other = rc.clone();
+        drop(rc);
+        assert_eq!(drops.get(), 0);
+        drop(other);
+        assert_eq!(drops.get(), 1);
+    }
+
+    #[test]
+    fn into_box_moves_unique_values_out() {
+        use std::cell::Cell;
+
+        #[derive(Debug)]
+        struct Counted<'a>(&'a Cell<usize>);
+
+        impl Drop for Counted<'_> {
+            fn drop(&mut self) {
+                self.0.set(self.0.get() + 1);
+            }
+        }
+
+        let boxed = Rc::into_box(Rc::new(String::from("moved"))).unwrap();
+        assert_eq!(*boxed, "moved");
+
+        // Shared values stay where they are, and so does the count.
+        let rc = Rc::new(String::from("shared"));
+        let other = rc.clone();
+        let rc = Rc::into_box(rc).unwrap_err();
+        assert!(Rc::ptr_eq(&rc, &other));
+        assert_eq!(Rc::reference_count(&rc).get(), 2);
+        drop(other);
+        assert_eq!(*Rc::into_box(rc).unwrap(), "shared");
+
+        let s: Rc<str> = Rc::from("häuschen");
+        let s: Box<str> = Rc::into_box(s).unwrap();
+        assert_eq!(&*s, "häuschen");
+        let words: Rc<[String]> = Rc::from(std::vec![String::from("a"), String::from("b")]);
+        assert_eq!(*Rc::into_box(words).unwrap(), ["a", "b"]);
+        let unit: Rc<()> = UniqueRc::new(()).into();
+        assert_eq!(*Rc::into_box(unit).unwrap(), ());
+
+        // The value is moved, not dropped, and then dropped with the box.
+        let drops = Cell::new(0);
+        let dynamic: Box<dyn fmt::Debug + '_> = Box::new(Counted(&drops));
+        let dynamic: Rc<dyn fmt::Debug + '_> = Rc::from(dynamic);
+        let boxed = Rc::into_box(dynamic).unwrap();
+        assert_eq!(drops.get(), 0);
+        drop(boxed);
+        assert_eq!(drops.get(), 1);
+
+        #[cfg(feature = "weak")]
+        {
+            let rc = Rc::new(1);
+            let weak = Rc::downgrade(&rc);
+            assert_eq!(*Rc::into_box(rc).unwrap(), 1);
+            assert!(weak.upgrade().is_none());
+        }
+    }
+
+    #[test]
+    fn cloned_unique_rcs_are_independent() {
+        let mut a = UniqueRc::new(std::vec![1, 2]);
+        let before = allocations();
+        let mut b = a.clone();
+        // One allocation for the `Rc`, one for the cloned vector.
+        assert_eq!(allocations(), before + 2);
+
+        a[0] = 0;
+        b.push(3);
+        assert_eq!(*a, [0, 2]);
+        assert_eq!(*b, [1, 2, 3]);
+
+        // Each converts into an `Rc` in place, so the two stay apart.
+        let before = allocations();
+        let a: Rc<Vec<i32>> = a.into();
+        let b: Rc<Vec<i32>> = b.into();
+        assert_eq!(allocations(), before);
+        assert!(!Rc::ptr_eq(&a, &b));
+        assert_eq!(Rc::reference_count(&a).get(), 1);
+        assert_eq!(Rc::reference_count(&b).get(), 1);
+    }
+
+    #[test]
+    fn error_sources_pass_through_rcs() {
+        #[derive(Debug)]
+        struct Layer(&'static str, Option<Rc<dyn Error>>);
+
+        impl fmt::Display for Layer {
+            fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                f.write_str(self.0)
+            }
+        }
+
+        impl Error for Layer {
+            fn source(&self) -> Option<&(dyn Error + 'static)> {
+                self.1.as_ref().map(|source| source as &(dyn Error + 'static))
+            }
+        }
+
+        let root = Layer("root", None);
+        let middle = Layer("middle", Some(Rc::<dyn Error>::from_error(root)));
+        let top = Layer("top", Some(Rc::<dyn Error>::from_error(middle)));
+
+        let mut chain = Vec::new();
+        let mut next: Option<&dyn Error> = Some(&top);
+        while let Some(err) = next {
+            chain.push(std::format!("{}", err));
+            next = err.source();
+        }
+        assert_eq!(chain, ["top", "middle", "root"]);
+
+        // The links are the `Rc`s themselves, which forward to the errors they point to.
+        let link = top.source().unwrap();
+        assert!(link.is::<Rc<dyn Error>>());
+        assert_eq!(link.source().unwrap().to_string(), "root");
+    }
+
+    #[test]
+    fn uninitialized_rcs_are_initialized_in_place() {
+        use std::cell::Cell;
+
+        struct Counted<'a>(&'a Cell<usize>, String);
+
+        impl Drop for Counted<'_> {
+            fn drop(&mut self) {
+                self.0.set(self.0.get() + 1);
+            }
+        }
+
+        let drops = Cell::new(0);
+        let mut uninit = Rc::<Counted>::new_uninit();
+        let ptr = &*uninit as *const MaybeUninit<Counted> as *const u8;
+        uninit.write(Counted(&drops, String::from("first"))).1.push('!');
+        let mut counted = unsafe { uninit.assume_init() };
+        // The cast reuses the allocation, and the value is fully usable.
+        assert_eq!(&*counted as *const Counted as *const u8, ptr);
+        counted.1.push('?');
+        assert_eq!(counted.1, "first!?");
+        let counted: Rc<Counted> = counted.into();
+        assert_eq!(drops.get(), 0);
+        drop(counted);
+        assert_eq!(drops.get(), 1);
+
+        let counted = Rc::new_uninit().into_initialized(Counted(&drops, String::from("second")));
+        assert_eq!(counted.1, "second");
+        drop(counted);
+        assert_eq!(drops.get(), 2);
+
+        // Dropping before `assume_init` only drops the `MaybeUninit`, not a `Counted`, even if
+        // one has been written (which is then leaked, so it does not own any memory here).
+        drop(Rc::<Counted>::new_uninit());
+        let mut uninit = Rc::<Counted>::new_uninit();
+        let _ = uninit.write(Counted(&drops, String::new()));
+        drop(uninit);
+        assert_eq!(drops.get(), 2);
+
+        let unit = unsafe { Rc::<()>::new_uninit().assume_init() };
+        assert_eq!(*unit, ());
+    }
+
+    #[cfg(feature = "allocation-id")]
+    #[test]
+    fn allocation_ids_follow_the_allocation() {
+        let a = Rc::new(String::from("a"));
+        let b = Rc::new(String::from("a"));
+        let id = Rc::allocation_id(&a);
+        assert_ne!(id, 0);
+        assert_ne!(Rc::allocation_id(&b), id);
+        assert_eq!(Rc::allocation_id(&a.clone()), id);
+
+        // Converting to and from the unique pointer keeps the allocation, and hence the id.
+        let mut unique = Rc::into_mut(a).unwrap();
+        assert_eq!(UniqueRc::allocation_id(&unique), id);
+        unique.push('!');
+        let a: Rc<String> = unique.into();
+        assert_eq!(Rc::allocation_id(&a), id);
+
+        // Copying a shared value makes a new allocation with a fresh id.
+        let s: Rc<str> = Rc::from("s");
+        let mut copy = s.clone();
+        Rc::make_mut(&mut copy).make_ascii_uppercase();
+        let ids = [id, Rc::allocation_id(&b), Rc::allocation_id(&s), Rc::allocation_id(&copy)];
+        assert!((1..ids.len()).all(|i| !ids[..i].contains(&ids[i])));
+        assert_eq!(Rc::allocation_id(&a), id);
+
+        static INNER: StaticRcInner<u32> = StaticRcInner::new(7);
+        assert_eq!(Rc::allocation_id(&Rc::from_static(&INNER)), 0);
+    }
+
+    #[test]
+    fn owned_cows_are_moved_without_cloning() {
+        use crate::test_util::deallocations;
+        use std::cell::Cell;
+
+        #[derive(Debug)]
+        struct Counted<'a>(u32, &'a Cell<usize>);
+
+        impl Clone for Counted<'_> {
+            fn clone(&self) -> Self {
+                self.1.set(self.1.get() + 1);
+                Counted(self.0, self.1)
+            }
+        }
+
+        let clones = Cell::new(0);
+        let items = std::vec![Counted(1, &clones), Counted(2, &clones)];
+        let before = (allocations(), deallocations());
+        let moved: Rc<[Counted]> = Rc::from(Cow::Owned(items));
+        // The items move into the new allocation, and the vector's buffer is freed.
+        assert_eq!((allocations(), deallocations()), (before.0 + 1, before.1 + 1));
+        assert_eq!(clones.get(), 0);
+        assert_eq!(moved.iter().map(|c| c.0).collect::<Vec<_>>(), [1, 2]);
+
+        let borrowed: Rc<[Counted]> = Rc::from(Cow::Borrowed(&*moved));
+        assert_eq!(clones.get(), 2);
+        assert_eq!(borrowed[1].0, 2);
+
+        let s = Cow::<str>::Owned(String::from("häuschen"));
+        let before = (allocations(), deallocations());
+        let s: Rc<str> = Rc::from(s);
+        assert_eq!((allocations(), deallocations()), (before.0 + 1, before.1 + 1));
+        assert_eq!(&*s, "häuschen");
+        let empty: Rc<str> = Rc::from(Cow::<str>::Owned(String::new()));
+        assert_eq!(&*empty, "");
+    }
+
+    #[test]
+    fn strings_from_chars_and_parsing() {
+        let parsed: Rc<str> = "grüße, 世界".parse().unwrap();
+        assert_eq!(&*parsed, "grüße, 世界");
+        let empty: Rc<str> = "".parse().unwrap();
+        assert_eq!(&*empty, "");
+
+        for c in ['a', 'ß', '世', '🦀'].iter() {
+            let s: Rc<str> = Rc::from(*c);
+            assert_eq!(s.len(), c.len_utf8());
+            assert_eq!(s.chars().collect::<Vec<_>>(), [*c]);
+        }
+        let nul: Rc<str> = Rc::from('\0');
+        assert_eq!(&*nul, "\0");
+
+        let collected: Rc<str> = "grüße, 世界".chars().rev().collect();
+        assert_eq!(&*collected, "界世 ,eßürg");
+        assert_eq!(collected.len(), "grüße, 世界".len());
+        let empty: Rc<str> = "".chars().collect();
+        assert_eq!(&*empty, "");
+
+        let joined: Rc<str> = ["grüße", "", ", ", "世界"].iter().copied().collect();
+        assert_eq!(&*joined, "grüße, 世界");
+        let empty: Rc<str> = ["", ""].iter().copied().collect();
+        assert_eq!(&*empty, "");
+    }
+
+    #[test]
+    fn custom_errors_downcast_through_shared_pointers() {
+        #[derive(Debug, PartialEq)]
+        struct Custom(u32);
+
+        impl fmt::Display for Custom {
+            fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                write!(f, "custom error {}", self.0)
+            }
+        }
+
+        impl Error for Custom {}
+
+        let err = Rc::<dyn Error>::from_error(Custom(7));
+        let other = err.clone();
+        assert_eq!(err.to_string(), "custom error 7");
+
+        // A failed downcast hands back the same pointer.
+        let err = Rc::<dyn Error>::downcast::<fmt::Error>(err).unwrap_err();
+        assert!(Rc::ptr_eq(&err, &other));
+        let custom = Rc::<dyn Error>::downcast::<Custom>(err).unwrap();
+        assert_eq!(*custom, Custom(7));
+        assert_eq!(Rc::reference_count(&custom).get(), 2);
+        drop(custom);
+        assert_eq!(Rc::reference_count(&other).get(), 1);
+
+        // Errors made from messages are of a private type.
+        let message: Rc<dyn Error> = Rc::from("message");
+        assert_eq!(std::format!("{} {:?}", message, message), "message \"message\"");
+        assert!(Rc::<dyn Error>::downcast::<Custom>(message).is_err());
+    }
+
+    #[test]
+    fn raw_counts_follow_retains_and_releases() {
+        use crate::test_util::deallocations;
+
+        // A C library that retains the pointer for each of its callbacks and releases them one by
+        // one, with the last release happening after all `Rc`s are gone.
+        let rc = Rc::new(String::from("shared"));
+        let ptr = Rc::into_raw(rc.clone());
+        for retained in 1..=5 {
+            unsafe { Rc::increment_strong_count(ptr) };
+            assert_eq!(Rc::reference_count(&rc).get(), 2 + retained);
+        }
+        for retained in (0..5).rev() {
+            unsafe { Rc::decrement_strong_count(ptr) };
+            assert_eq!(Rc::reference_count(&rc).get(), 2 + retained);
+        }
+        drop(rc);
+        assert_eq!(unsafe { &*ptr }, "shared");
+
+        // Frees both the string and the allocation of the `Rc`.
+        let before = deallocations();
+        unsafe { Rc::decrement_strong_count(ptr) };
+        assert_eq!(deallocations(), before + 2);
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn std_conversions_clone_only_shared_values() {
+        use std::cell::Cell;
+
+        #[derive(Debug)]
+        struct Counted<'a>(u32, &'a Cell<usize>);
+
+        impl Clone for Counted<'_> {
+            fn clone(&self) -> Self {
+                self.1.set(self.1.get() + 1);
+                Counted(self.0, self.1)
+            }
+        }
+
+        let clones = Cell::new(0);
+        let moved = Rc::from_std_unique(StdRc::new(Counted(1, &clones))).unwrap();
+        assert_eq!(moved.0, 1);
+        let moved: Rc<Counted> = Rc::from(StdRc::new(Counted(2, &clones)));
+        assert_eq!(moved.0, 2);
+        assert_eq!(clones.get(), 0);
+
+        // A shared `std::rc::Rc` is handed back unchanged, or cloned by `From`.
+        let std = StdRc::new(Counted(3, &clones));
+        let other = std.clone();
+        let std = Rc::from_std_unique(std).unwrap_err();
+        assert!(StdRc::ptr_eq(&std, &other));
+        assert_eq!(clones.get(), 0);
+        let cloned: Rc<Counted> = Rc::from(std);
+        assert_eq!(cloned.0, 3);
+        assert_eq!(clones.get(), 1);
+        assert_eq!(StdRc::strong_count(&other), 1);
+
+        // The same holds the other way around.
+        let back: StdRc<Counted> = StdRc::from(cloned);
+        assert_eq!(back.0, 3);
+        assert_eq!(clones.get(), 1);
+        let shared = Rc::new(Counted(4, &clones));
+        let other = shared.clone();
+        let back: StdRc<Counted> = StdRc::from(shared);
+        assert_eq!(back.0, 4);
+        assert_eq!(clones.get(), 2);
+        assert_eq!(Rc::reference_count(&other).get(), 1);
+    }
+
+    #[test]
+    fn formatting_into_strings() {
+        // Pads and truncates by characters, like `str` does.
+        struct Padded(&'static str);
+
+        impl fmt::Display for Padded {
+            fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                f.pad(self.0)
+            }
+        }
+
+        let s = Rc::from_display(&Padded("grüße ✓"));
+        assert_eq!(&*s, "grüße ✓");
+        assert_eq!(s.len(), "grüße ✓".len());
+        assert_eq!(&*format_rc!("[{:>9}]", Padded("日本")), "[       日本]");
+        assert_eq!(&*format_rc!("[{:^6.3}]", Padded("grüße")), "[ grü  ]");
+        assert_eq!(&*format_rc!("{}-{:03}", 'ß', 7), "ß-007");
+
+        assert_eq!(&*Rc::from_display(&Padded("")), "");
+        assert_eq!(&*format_rc!(""), "");
+        assert_eq!(&*format_rc!("{}", ""), "");
+    }
+
+    #[test]
+    fn make_mut_copies_shared_slices_and_strings() {
+        let mut slice: Rc<[u32]> = Rc::from(&[1, 2, 3][..]);
+        let sibling = slice.clone();
+        Rc::make_mut(&mut slice)[0] = 10;
+        assert_eq!(&*slice, [10, 2, 3]);
+        assert_eq!(&*sibling, [1, 2, 3]);
+        assert_eq!(Rc::reference_count(&sibling).get(), 1);
+
+        let mut s: Rc<str> = Rc::from("grüße");
+        let sibling = s.clone();
+        Rc::make_mut(&mut s).make_ascii_uppercase();
+        assert_eq!(&*s, "GRüßE");
+        assert_eq!(&*sibling, "grüße");
+
+        // Empty values get an allocation of their own as well.
+        let mut empty: Rc<[u32]> = Rc::from(&[][..]);
+        let sibling = empty.clone();
+        assert!(Rc::make_mut(&mut empty).is_empty());
+        assert!(!Rc::ptr_eq(&empty, &sibling));
+        assert_eq!(Rc::reference_count(&sibling).get(), 1);
+
+        let mut empty: Rc<str> = Rc::from("");
+        let sibling = empty.clone();
+        assert_eq!(Rc::make_mut(&mut empty), "");
+        assert!(!Rc::ptr_eq(&empty, &sibling));
+    }
+
+    #[test]
+    fn make_mut_keeps_unique_slices_and_strings_in_place() {
+        let mut slice: Rc<[String]> = Rc::from(vec![String::from("a")]);
+        let address = slice.as_ptr();
+        Rc::make_mut(&mut slice)[0].push('b');
+        assert_eq!(slice.as_ptr(), address);
+        assert_eq!(&*slice, ["ab"]);
+
+        let mut s: Rc<str> = Rc::from("abc");
+        let address = s.as_ptr();
+        Rc::make_mut(&mut s).make_ascii_uppercase();
+        assert_eq!(s.as_ptr(), address);
+        assert_eq!(&*s, "ABC");
+
+        let mut empty: Rc<str> = Rc::from("");
+        let address = Rc::as_ptr(&empty);
+        assert_eq!(Rc::make_mut(&mut empty) as *mut str as *const str, address);
+    }
+
+    #[cfg(feature = "weak")]
+    #[test]
+    fn make_mut_copies_weakly_referenced_slices() {
+        let mut slice: Rc<[u8]> = Rc::from(&b"abc"[..]);
+        let weak = Rc::downgrade(&slice);
+        Rc::make_mut(&mut slice)[0] = b'x';
+        assert_eq!(&*slice, b"xbc");
+        // The old allocation lost its only `Rc`, so the `WeakRc` can not be upgraded anymore.
+        assert!(weak.upgrade().is_none());
+        assert_eq!(Rc::weak_count(&slice), 0);
+    }
+}
@@ -0,0 +1,209 @@
+use base::borrow;
+use base::cmp::Ordering;
+use base::fmt;
+use base::hash::{Hash, Hasher};
+use base::ops::Deref;
+
+use crate::rc::Rc;
+
+/// A value that is either borrowed or shared through an `Rc`, the non-thread-safe counterpart of
+/// `ArcCow`.
+///
+/// Unlike with `Cow`, the owned variant is an `Rc<T>`, so that `RcCow` is cheap to clone in
+/// both variants, and so that converting it into an owned value allocates at most once. This
+/// makes it a suitable parameter type for functions that only sometimes retain their argument.
+///
+/// Comparisons and hashing consider only the value, regardless of the variant, and `RcCow`s
+/// can be compared with plain values (`T` or `&T`) as well.
+pub enum RcCow<'a, T: ?Sized> {
+    /// A borrowed value.
+    Borrowed(&'a T),
+    /// A value shared through an `Rc`.
+    Shared(Rc<T>),
+}
+
+impl<'a, T: ?Sized> RcCow<'a, T> {
+    /// Returns `true` if this is the `Borrowed` variant.
+    pub fn is_borrowed(&self) -> bool {
+        matches!(self, RcCow::Borrowed(_))
+    }
+
+    /// Returns `true` if this is the `Shared` variant.
+    pub fn is_shared(&self) -> bool {
+        !self.is_borrowed()
+    }
+
+    /// Converts into an `Rc`, allocating only if the value is borrowed.
+    pub fn into_shared(self) -> Rc<T>
+    where
+        Rc<T>: From<&'a T>,
+    {
+        match self {
+            RcCow::Borrowed(value) => Rc::from(value),
+            RcCow::Shared(rc) => rc,
+        }
+    }
+}
+
+impl<'a, T: ?Sized> Clone for RcCow<'a, T> {
+    fn clone(&self) -> Self {
+        match self {
+            RcCow::Borrowed(value) => RcCow::Borrowed(value),
+            RcCow::Shared(rc) => RcCow::Shared(rc.clone()),
+        }
+    }
+}
+
+impl<'a, T: ?Sized> From<&'a T> for RcCow<'a, T> {
+    fn from(value: &'a T) -> Self {
+        RcCow::Borrowed(value)
+    }
+}
+
+impl<'a, T: ?Sized> From<Rc<T>> for RcCow<'a, T> {
+    fn from(rc: Rc<T>) -> Self {
+        RcCow::Shared(rc)
+    }
+}
+
+impl<'a, T: ?Sized> Deref for RcCow<'a, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        match self {
+            RcCow::Borrowed(value) => value,
+            RcCow::Shared(rc) => rc,
+        }
+    }
+}
+
+impl<'a, T: ?Sized> borrow::Borrow<T> for RcCow<'a, T> {
+    fn borrow(&self) -> &T {
+        self
+    }
+}
+
+impl<'a, T: ?Sized> AsRef<T> for RcCow<'a, T> {
+    fn as_ref(&self) -> &T {
+        self
+    }
+}
+
+impl<'a, 'b, T: ?Sized + PartialEq> PartialEq<RcCow<'b, T>> for RcCow<'a, T> {
+    fn eq(&self, other: &RcCow<'b, T>) -> bool {
+        **self == **other
+    }
+}
+
+impl<'a, T: ?Sized + PartialEq> PartialEq<T> for RcCow<'a, T> {
+    fn eq(&self, other: &T) -> bool {
+        **self == *other
+    }
+}
+
+impl<'a, 'b, T: ?Sized + PartialEq> PartialEq<&'b T> for RcCow<'a, T> {
+    fn eq(&self, other: &&'b T) -> bool {
+        **self == **other
+    }
+}
+
+impl<'a, T: ?Sized + Eq> Eq for RcCow<'a, T> {}
+
+impl<'a, 'b, T: ?Sized + PartialOrd> PartialOrd<RcCow<'b, T>> for RcCow<'a, T> {
+    fn partial_cmp(&self, other: &RcCow<'b, T>) -> Option<Ordering> {
+        (**self).partial_cmp(&**other)
+    }
+}
+
+impl<'a, T: ?Sized + Ord> Ord for RcCow<'a, T> {
+    fn cmp(&self, other: &Self) -> Ordering {
+        (**self).cmp(&**other)
+    }
+}
+
+impl<'a, T: ?Sized + Hash> Hash for RcCow<'a, T> {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        (**self).hash(state)
+    }
+}
+
+impl<'a, T: ?Sized + fmt::Display> fmt::Display for RcCow<'a, T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Display::fmt(&**self, f)
+    }
+}
+
+impl<'a, T: ?Sized + fmt::Debug> fmt::Debug for RcCow<'a, T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Debug::fmt(&**self, f)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+
+    use std::collections::hash_map::DefaultHasher;
+    use std::string::String;
+
+    use super::*;
+    use crate::test_util::allocations;
+    use crate::ReferenceCounted;
+    use smart_pointer::SmartPointer;
+
+    fn hash<T: ?Sized + Hash>(value: &T) -> u64 {
+        let mut hasher = DefaultHasher::new();
+        value.hash(&mut hasher);
+        hasher.finish()
+    }
+
+    #[test]
+    fn conversions_from_either_variant() {
+        let s = String::from("hello");
+        let borrowed = RcCow::from(s.as_str());
+        assert!(borrowed.is_borrowed());
+        let p: Rc<str> = Rc::from("hello");
+        let shared = RcCow::from(p.clone());
+        assert!(shared.is_shared());
+
+        // Cloning does not allocate in either variant.
+        let before = allocations();
+        let (borrowed, shared) = (borrowed.clone(), shared.clone());
+        assert_eq!(allocations(), before);
+        assert!(borrowed.is_borrowed());
+        assert_eq!(Rc::reference_count(&p).get(), 3);
+
+        // Only the borrowed variant allocates to become shared.
+        let from_shared = shared.into_shared();
+        assert!(Rc::ptr_eq(&from_shared, &p));
+        assert_eq!(allocations(), before);
+        let from_borrowed = borrowed.into_shared();
+        assert_eq!(allocations(), before + 1);
+        assert_eq!(&*from_borrowed, "hello");
+        assert!(!Rc::ptr_eq(&from_borrowed, &p));
+    }
+
+    #[test]
+    fn comparisons_ignore_the_variant() {
+        let shared: RcCow<str> = RcCow::from(Rc::from("b"));
+        let borrowed: RcCow<str> = RcCow::from("b");
+        assert_eq!(shared, borrowed);
+        assert_eq!(hash(&shared), hash(&borrowed));
+        assert_eq!(shared.cmp(&borrowed), Ordering::Equal);
+        let smaller: RcCow<str> = RcCow::from("a");
+        assert!(smaller < shared);
+        assert!(borrowed < RcCow::<str>::Shared(Rc::from("c")));
+
+        // Either variant compares with plain values.
+        assert!(shared == "b");
+        assert!(borrowed == *"b");
+        assert!(shared != "c");
+        let number = RcCow::Shared(Rc::new(3));
+        assert!(number == 3);
+        let three = &3;
+        assert!(RcCow::Borrowed(&3) == three);
+        assert!(number != RcCow::Borrowed(&4));
+
+        assert_eq!(std::format!("{} {:?}", borrowed, shared), "b \"b\"");
+    }
+}
@@ -0,0 +1,3 @@
+use crate::rc::{Rc, UniqueRc};
+
+crate::view::shared_views!(Rc, UniqueRc, RcStr, RcSlice);
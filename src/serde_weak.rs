@@ -0,0 +1,130 @@
+//! Modules for serializing weak pointers with serde's `with` field attribute.
+//!
+//! `WeakArc` and `WeakRc` implement `Serialize` and `Deserialize` like `as_option`, matching
+//! what serde does for the weak pointers of the standard library. `skip` is for fields whose
+//! value is serialized elsewhere, e.g. the parent links of a tree:
+//!
+//! ```ignore
+//! #[derive(Serialize, Deserialize)]
+//! struct Node {
+//!     #[serde(with = "reference_counted::serde_weak::skip")]
+//!     parent: WeakArc<Node>,
+//!     children: Vec<Arc<Node>>,
+//! }
+//! ```
+//!
+//! Neither module can restore the link to an allocation: deserializing always results in a weak
+//! pointer that does not point to any allocation, and upgrading it fails. Links must be
+//! re-established after deserialization.
+
+/// Serializes the value of a weak pointer as `Some` if the pointer can be upgraded, and as `None`
+/// otherwise. Deserializing reads such an option and discards it.
+pub mod as_option {
+    use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+    use crate::WeakPointer;
+
+    /// Serializes the upgraded value of `weak` as an option.
+    pub fn serialize<T, W, S>(weak: &W, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        T: ?Sized + Serialize,
+        W: WeakPointer<T>,
+        S: Serializer,
+    {
+        weak.upgrade().as_deref().serialize(serializer)
+    }
+
+    /// Deserializes an optional value and discards it, returning a weak pointer that does not
+    /// point to any allocation.
+    pub fn deserialize<'de, T, W, D>(deserializer: D) -> Result<W, D::Error>
+    where
+        T: Deserialize<'de>,
+        W: WeakPointer<T> + Default,
+        D: Deserializer<'de>,
+    {
+        Option::<T>::deserialize(deserializer)?;
+        Ok(W::default())
+    }
+}
+
+/// Serializes a weak pointer as a unit, regardless of its value. Deserializing reads a unit.
+pub mod skip {
+    use serde::{Deserialize, Deserializer, Serializer};
+
+    use crate::WeakPointer;
+
+    /// Serializes a unit.
+    pub fn serialize<T, W, S>(_weak: &W, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        T: ?Sized,
+        W: WeakPointer<T>,
+        S: Serializer,
+    {
+        serializer.serialize_unit()
+    }
+
+    /// Deserializes a unit, returning a weak pointer that does not point to any allocation.
+    pub fn deserialize<'de, T, W, D>(deserializer: D) -> Result<W, D::Error>
+    where
+        T: ?Sized,
+        W: WeakPointer<T> + Default,
+        D: Deserializer<'de>,
+    {
+        <()>::deserialize(deserializer)?;
+        Ok(W::default())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+
+    use std::string::{String, ToString};
+    use std::vec::Vec;
+
+    use serde_json::{Deserializer, Serializer};
+
+    use super::*;
+    use crate::{Downgradable, WeakPointer};
+
+    fn skipped<T, W: WeakPointer<T>>(weak: &W) -> String {
+        let mut out = Vec::new();
+        skip::serialize(weak, &mut Serializer::new(&mut out)).unwrap();
+        String::from_utf8(out).unwrap()
+    }
+
+    fn check<P: Downgradable<String>>()
+    where
+        P::Weak: Default + serde::Serialize + for<'de> serde::Deserialize<'de>,
+    {
+        let value = P::new("abc".to_string());
+        let weak = P::downgrade(&value);
+        assert_eq!(serde_json::to_string(&weak).unwrap(), "\"abc\"");
+        assert_eq!(skipped(&weak), "null");
+
+        let back: P::Weak = serde_json::from_str("\"abc\"").unwrap();
+        assert!(back.upgrade().is_none());
+        let back: P::Weak = serde_json::from_str("null").unwrap();
+        assert!(back.upgrade().is_none());
+        let back: P::Weak = skip::deserialize(&mut Deserializer::from_str("null")).unwrap();
+        assert!(back.upgrade().is_none());
+        assert!(skip::deserialize::<String, P::Weak, _>(&mut Deserializer::from_str("1")).is_err());
+
+        drop(value);
+        assert_eq!(serde_json::to_string(&weak).unwrap(), "null");
+        assert_eq!(serde_json::to_string(&P::Weak::default()).unwrap(), "null");
+        assert_eq!(skipped(&weak), "null");
+    }
+
+    #[cfg(feature = "arc")]
+    #[test]
+    fn weak_arcs_round_trip_to_dangling() {
+        check::<crate::Arc<String>>();
+    }
+
+    #[cfg(feature = "rc")]
+    #[test]
+    fn weak_rcs_round_trip_to_dangling() {
+        check::<crate::Rc<String>>();
+    }
+}
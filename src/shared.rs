@@ -0,0 +1,102 @@
+//! A pointer type that is an `Arc` or an `Rc`, depending on whether the `threadsafe` feature
+//! is enabled.
+//!
+//! Code that only uses `Shared`, `SharedUnique` and the constructor functions of this module
+//! compiles with either choice.
+
+use base::string::String;
+use base::vec::Vec;
+
+use smart_pointer::SmartPointer;
+
+#[cfg(feature = "threadsafe")]
+use crate::arc::{Arc, UniqueArc};
+#[cfg(not(feature = "threadsafe"))]
+use crate::rc::{Rc, UniqueRc};
+
+/// A reference-counted pointer: `Arc` if the `threadsafe` feature is enabled, `Rc` otherwise.
+#[cfg(feature = "threadsafe")]
+pub type Shared<T> = Arc<T>;
+/// A reference-counted pointer: `Arc` if the `threadsafe` feature is enabled, `Rc` otherwise.
+#[cfg(not(feature = "threadsafe"))]
+pub type Shared<T> = Rc<T>;
+
+/// The unique counterpart of `Shared`: `UniqueArc` if the `threadsafe` feature is enabled,
+/// `UniqueRc` otherwise.
+#[cfg(feature = "threadsafe")]
+pub type SharedUnique<T> = UniqueArc<T>;
+/// The unique counterpart of `Shared`: `UniqueArc` if the `threadsafe` feature is enabled,
+/// `UniqueRc` otherwise.
+#[cfg(not(feature = "threadsafe"))]
+pub type SharedUnique<T> = UniqueRc<T>;
+
+/// Moves `value` into a new `Shared` allocation.
+pub fn shared_new<T>(value: T) -> Shared<T> {
+    Shared::new(value)
+}
+
+/// Moves `value` into a new `SharedUnique` allocation.
+pub fn shared_unique_new<T>(value: T) -> SharedUnique<T> {
+    SharedUnique::new(value)
+}
+
+/// Moves the items of `v` into a new shared slice.
+pub fn shared_from_vec<T>(v: Vec<T>) -> Shared<[T]> {
+    Shared::from(v)
+}
+
+/// Clones the items of `v` into a new shared slice.
+pub fn shared_from_slice<T: Clone>(v: &[T]) -> Shared<[T]> {
+    Shared::from(v)
+}
+
+/// Copies `s` into a new shared string slice.
+pub fn shared_from_str(s: &str) -> Shared<str> {
+    Shared::from(s)
+}
+
+/// Moves the contents of `s` into a new shared string slice.
+pub fn shared_from_string(s: String) -> Shared<str> {
+    Shared::from(s)
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+
+    use super::*;
+    use smart_pointer::IntoMut;
+
+    use crate::ReferenceCounted;
+
+    // Run with and without the `threadsafe` feature, this only uses the common surface.
+    #[test]
+    fn the_facade_works_with_either_pointer() {
+        let a = shared_new(5);
+        let b = a.clone();
+        assert_eq!(Shared::reference_count(&a).get(), 2);
+        let a = Shared::into_mut(a).unwrap_err();
+        drop(b);
+
+        let mut unique = Shared::into_mut(a).unwrap();
+        *unique += 1;
+        let a: Shared<i32> = unique.into();
+        assert_eq!(*a, 6);
+        assert_eq!(*shared_unique_new(7), 7);
+
+        assert_eq!(&*shared_from_vec(std::vec![1, 2]), &[1, 2]);
+        assert_eq!(&*shared_from_slice(&[3, 4]), &[3, 4]);
+        assert_eq!(&*shared_from_str("ab"), "ab");
+        assert_eq!(&*shared_from_string(String::from("cd")), "cd");
+    }
+
+    #[cfg(feature = "threadsafe")]
+    #[test]
+    fn threadsafe_pointers_can_be_sent() {
+        let a = shared_from_str("sent");
+        let b = a.clone();
+        let len = std::thread::spawn(move || b.len()).join().unwrap();
+        assert_eq!(len, 4);
+        assert_eq!(Shared::reference_count(&a).get(), 1);
+    }
+}
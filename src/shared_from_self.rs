@@ -0,0 +1,146 @@
+use base::fmt;
+
+use crate::arc::{Arc, WeakArc};
+
+/// A link from a value to its own allocation, which lets methods that only get `&self` obtain
+/// an `Arc` to the value, like `std::enable_shared_from_this` in C++.
+///
+/// Values embed a `SharedFromSelf` as a field, and are constructed through
+/// `Arc::new_shared_from_self`, which hands out the linked field. A value that was constructed
+/// in any other way, e.g. on the stack with `SharedFromSelf::new`, is not linked, so that
+/// `SharedFromSelf::shared_from_this` returns `None`.
+///
+/// ```
+/// use reference_counted::{Arc, SharedFromSelf};
+///
+/// struct Job {
+///     this: SharedFromSelf<Job>,
+///     name: &'static str,
+/// }
+///
+/// impl Job {
+///     // Hands out an owning pointer, e.g. to be moved into a callback.
+///     fn handle(&self) -> Arc<Job> {
+///         self.this.shared_from_this().expect("jobs are constructed in an `Arc`")
+///     }
+/// }
+///
+/// let job = Arc::new_shared_from_self(|this| Job { this, name: "build" });
+/// assert_eq!(job.handle().name, "build");
+///
+/// let local = Job { this: SharedFromSelf::new(), name: "local" };
+/// assert!(local.this.shared_from_this().is_none());
+/// ```
+pub struct SharedFromSelf<T> {
+    this: WeakArc<T>,
+}
+
+impl<T> SharedFromSelf<T> {
+    /// Creates a `SharedFromSelf` that is not linked to any allocation.
+    pub const fn new() -> Self {
+        SharedFromSelf { this: WeakArc::new() }
+    }
+
+    /// Returns an `Arc` to the value that embeds this, or `None` if it was not constructed
+    /// through `Arc::new_shared_from_self`, is still being constructed, or is being dropped.
+    pub fn shared_from_this(&self) -> Option<Arc<T>> {
+        self.this.upgrade()
+    }
+
+    /// Returns a `WeakArc` to the value that embeds this. It can not be upgraded if the value
+    /// was not constructed through `Arc::new_shared_from_self`.
+    pub fn weak_from_this(&self) -> WeakArc<T> {
+        self.this.clone()
+    }
+}
+
+impl<T> Default for SharedFromSelf<T> {
+    /// Creates a `SharedFromSelf` that is not linked to any allocation, see
+    /// `SharedFromSelf::new`.
+    fn default() -> Self {
+        SharedFromSelf::new()
+    }
+}
+
+impl<T> Clone for SharedFromSelf<T> {
+    /// Creates a `SharedFromSelf` that is not linked to any allocation, since a clone of the
+    /// embedding value lives somewhere else.
+    fn clone(&self) -> Self {
+        SharedFromSelf::new()
+    }
+}
+
+impl<T> fmt::Debug for SharedFromSelf<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("SharedFromSelf")
+    }
+}
+
+impl<T> Arc<T> {
+    /// Constructs an `Arc` to the value returned by `f`, which gets a `SharedFromSelf` linked to
+    /// the allocation to embed in the value.
+    ///
+    /// As with `Arc::new_cyclic`, `SharedFromSelf::shared_from_this` returns `None` until `f`
+    /// has returned.
+    pub fn new_shared_from_self<F: FnOnce(SharedFromSelf<T>) -> T>(f: F) -> Arc<T> {
+        Arc::new_cyclic(|weak| f(SharedFromSelf { this: weak.clone() }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+
+    use std::boxed::Box;
+    use std::cell::RefCell;
+    use std::vec::Vec;
+
+    use super::*;
+    use crate::ReferenceCounted;
+    use smart_pointer::SmartPointer;
+
+    struct Button {
+        this: SharedFromSelf<Button>,
+        clicks: RefCell<u32>,
+    }
+
+    impl Button {
+        fn on_click(&self, callbacks: &mut Vec<Box<dyn Fn()>>) {
+            let this = self.this.shared_from_this().unwrap();
+            callbacks.push(Box::new(move || *this.clicks.borrow_mut() += 1));
+        }
+    }
+
+    #[test]
+    fn callbacks_keep_the_value_alive() {
+        let button = Arc::new_shared_from_self(|this| {
+            assert!(this.shared_from_this().is_none());
+            Button { this, clicks: RefCell::new(0) }
+        });
+        let mut callbacks = Vec::new();
+        button.on_click(&mut callbacks);
+        button.on_click(&mut callbacks);
+        assert_eq!(Arc::reference_count(&button).get(), 3);
+
+        let weak = button.this.weak_from_this();
+        drop(button);
+        callbacks.iter().for_each(|callback| callback());
+        assert_eq!(*weak.upgrade().unwrap().clicks.borrow(), 2);
+
+        drop(callbacks);
+        assert!(weak.upgrade().is_none());
+    }
+
+    #[test]
+    fn unlinked_values_have_no_pointer() {
+        let local = Button { this: SharedFromSelf::new(), clicks: RefCell::new(0) };
+        assert!(local.this.shared_from_this().is_none());
+        assert!(local.this.weak_from_this().upgrade().is_none());
+        assert_eq!(*local.clicks.borrow(), 0);
+
+        // Clones of a linked value are not linked.
+        let linked = Arc::new_shared_from_self(|this| Button { this, clicks: RefCell::new(0) });
+        assert!(Arc::ptr_eq(&linked.this.shared_from_this().unwrap(), &linked));
+        assert!(linked.this.clone().shared_from_this().is_none());
+    }
+}
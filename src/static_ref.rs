@@ -0,0 +1,320 @@
+use base::borrow::{self, BorrowMut};
+use base::boxed::Box;
+use base::cmp::Ordering;
+use base::fmt;
+use base::hash::{Hash, Hasher};
+use base::num::NonZeroUsize;
+use base::ops::{Deref, DerefMut};
+#[cfg(not(feature = "panic-free"))]
+use base::ops::Range;
+use base::string::String;
+use base::vec::Vec;
+
+use smart_pointer::{IntoMut, SmartPointer, SmartPointerMut};
+
+use crate::{FromOwned, ReferenceCounted};
+#[cfg(not(feature = "panic-free"))]
+use crate::SharedSlice;
+
+/// A `ReferenceCounted` pointer to a value that lives forever, which never allocates when
+/// created from a `&'static T` and which is `Copy`.
+///
+/// This allows instantiating data structures that are generic over `ReferenceCounted` in an
+/// allocation-free configuration, e.g. for constant default values.
+///
+/// Since any number of copies may exist, a `StaticRef` is never unique: `reference_count`
+/// always returns `usize::MAX` (the same sentinel used by the static allocations of `Arc` and
+/// `Rc`), and `IntoMut::can_make_mut` always returns `false`. Consequently, `into_mut` and
+/// `get_mut` always fail, and `try_unwrap` always returns the pointer unchanged.
+///
+/// `SmartPointer::new` moves the value into a leaked heap allocation.
+pub struct StaticRef<T: ?Sized + 'static>(&'static T);
+
+impl<T: ?Sized> StaticRef<T> {
+    /// Wraps a reference to a value that lives forever.
+    pub const fn new_static(value: &'static T) -> Self {
+        StaticRef(value)
+    }
+
+    /// Gets the wrapped reference.
+    pub const fn get(this: Self) -> &'static T {
+        this.0
+    }
+}
+
+impl<T: ?Sized> Clone for StaticRef<T> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<T: ?Sized> Copy for StaticRef<T> {}
+
+impl<T: ?Sized> From<&'static T> for StaticRef<T> {
+    fn from(value: &'static T) -> Self {
+        StaticRef(value)
+    }
+}
+
+/// Leaks the allocation of the container.
+impl<T: ?Sized> FromOwned<Box<T>> for StaticRef<T> {
+    fn from_owned(src: Box<T>) -> Self {
+        StaticRef(Box::leak(src))
+    }
+}
+
+/// Leaks the allocation of the vector, after shrinking it to fit.
+impl<T> FromOwned<Vec<T>> for StaticRef<[T]> {
+    fn from_owned(src: Vec<T>) -> Self {
+        StaticRef(Box::leak(src.into_boxed_slice()))
+    }
+}
+
+/// Leaks the allocation of the string, after shrinking it to fit.
+impl FromOwned<String> for StaticRef<str> {
+    fn from_owned(src: String) -> Self {
+        StaticRef(Box::leak(src.into_boxed_str()))
+    }
+}
+
+impl<T: ?Sized> Deref for StaticRef<T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        self.0
+    }
+}
+
+impl<T: ?Sized> borrow::Borrow<T> for StaticRef<T> {
+    fn borrow(&self) -> &T {
+        self.0
+    }
+}
+
+impl<T: ?Sized> AsRef<T> for StaticRef<T> {
+    fn as_ref(&self) -> &T {
+        self.0
+    }
+}
+
+impl<T: ?Sized + PartialEq> PartialEq for StaticRef<T> {
+    fn eq(&self, other: &Self) -> bool {
+        **self == **other
+    }
+}
+
+impl<T: ?Sized + Eq> Eq for StaticRef<T> {}
+
+impl<T: ?Sized + PartialOrd> PartialOrd for StaticRef<T> {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        (**self).partial_cmp(&**other)
+    }
+}
+
+impl<T: ?Sized + Ord> Ord for StaticRef<T> {
+    fn cmp(&self, other: &Self) -> Ordering {
+        (**self).cmp(&**other)
+    }
+}
+
+impl<T: ?Sized + Hash> Hash for StaticRef<T> {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        (**self).hash(state)
+    }
+}
+
+impl<T: ?Sized + fmt::Display> fmt::Display for StaticRef<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Display::fmt(&**self, f)
+    }
+}
+
+impl<T: ?Sized + fmt::Debug> fmt::Debug for StaticRef<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Debug::fmt(&**self, f)
+    }
+}
+
+impl<T: ?Sized> fmt::Pointer for StaticRef<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Pointer::fmt(&(self.0 as *const T), f)
+    }
+}
+
+impl<T: ?Sized> SmartPointer<T> for StaticRef<T> {
+    fn new(data: T) -> Self where T: Sized {
+        StaticRef(Box::leak(Box::new(data)))
+    }
+
+    fn try_unwrap(this: Self) -> Result<T, Self> where T: Sized {
+        Err(this)
+    }
+}
+
+impl<T: ?Sized> IntoMut<T> for StaticRef<T> {
+    type MutablePointer = StaticMut<T>;
+
+    fn can_make_mut(_this: &Self) -> bool {
+        false
+    }
+
+    /// Since `can_make_mut` never returns `true`, calling this is never safe.
+    unsafe fn into_mut_unchecked(_this: Self) -> Self::MutablePointer {
+        unreachable!("a StaticRef is never unique")
+    }
+
+    /// Since `can_make_mut` never returns `true`, calling this is never safe.
+    unsafe fn get_mut_unchecked(_this: &Self) -> &mut T {
+        unreachable!("a StaticRef is never unique")
+    }
+}
+
+impl<T: ?Sized> ReferenceCounted<T> for StaticRef<T> {
+    fn reference_count(_this: &Self) -> NonZeroUsize {
+        unsafe { NonZeroUsize::new_unchecked(usize::MAX) }
+    }
+}
+
+#[cfg(not(feature = "panic-free"))]
+impl<T> SharedSlice<T> for StaticRef<[T]> {
+    type Unique = StaticMut<[T]>;
+
+    fn slice(this: Self, range: Range<usize>) -> Self {
+        StaticRef(&this.0[range])
+    }
+
+    /// Always fails, since a `StaticRef` is never unique.
+    fn try_into_unique(this: Self) -> Result<StaticMut<[T]>, Self> {
+        Err(this)
+    }
+}
+
+/// A unique pointer to a value that lives forever, the `IntoMut::MutablePointer` of
+/// `StaticRef`.
+///
+/// It can be created from a `&'static mut T` (e.g. from `Box::leak`), and converted into a
+/// `StaticRef`.
+pub struct StaticMut<T: ?Sized + 'static>(&'static mut T);
+
+impl<T: ?Sized> StaticMut<T> {
+    /// Wraps a unique reference to a value that lives forever.
+    pub fn new_static(value: &'static mut T) -> Self {
+        StaticMut(value)
+    }
+
+    /// Gets the wrapped reference.
+    pub fn get(this: Self) -> &'static mut T {
+        this.0
+    }
+}
+
+impl<T: ?Sized> From<&'static mut T> for StaticMut<T> {
+    fn from(value: &'static mut T) -> Self {
+        StaticMut(value)
+    }
+}
+
+impl<T: ?Sized> From<StaticMut<T>> for StaticRef<T> {
+    fn from(unique: StaticMut<T>) -> Self {
+        StaticRef(unique.0)
+    }
+}
+
+impl<T: ?Sized> Deref for StaticMut<T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        self.0
+    }
+}
+
+impl<T: ?Sized> DerefMut for StaticMut<T> {
+    fn deref_mut(&mut self) -> &mut T {
+        self.0
+    }
+}
+
+impl<T: ?Sized> borrow::Borrow<T> for StaticMut<T> {
+    fn borrow(&self) -> &T {
+        self.0
+    }
+}
+
+impl<T: ?Sized> BorrowMut<T> for StaticMut<T> {
+    fn borrow_mut(&mut self) -> &mut T {
+        self.0
+    }
+}
+
+impl<T: ?Sized> AsRef<T> for StaticMut<T> {
+    fn as_ref(&self) -> &T {
+        self.0
+    }
+}
+
+impl<T: ?Sized> AsMut<T> for StaticMut<T> {
+    fn as_mut(&mut self) -> &mut T {
+        self.0
+    }
+}
+
+impl<T: ?Sized + fmt::Debug> fmt::Debug for StaticMut<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Debug::fmt(&**self, f)
+    }
+}
+
+impl<T: ?Sized> fmt::Pointer for StaticMut<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Pointer::fmt(&(&*self.0 as *const T), f)
+    }
+}
+
+impl<T: ?Sized> SmartPointer<T> for StaticMut<T> {
+    fn new(data: T) -> Self where T: Sized {
+        StaticMut(Box::leak(Box::new(data)))
+    }
+
+    /// Always fails, since the value can not be moved out of a reference.
+    fn try_unwrap(this: Self) -> Result<T, Self> where T: Sized {
+        Err(this)
+    }
+}
+
+impl<T: ?Sized> SmartPointerMut<T> for StaticMut<T> {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::allocations;
+
+    fn describe<P: ReferenceCounted<u32>>(p: P) -> (u32, usize, bool) {
+        let copy = p.clone();
+        (*copy, P::reference_count(&p).get(), P::can_make_mut(&p))
+    }
+
+    static ANSWER: u32 = 42;
+
+    #[test]
+    fn static_refs_work_in_generic_code_without_allocating() {
+        let before = allocations();
+        let p = StaticRef::new_static(&ANSWER);
+        assert_eq!(describe(p), (42, usize::MAX, false));
+        assert!(StaticRef::ptr_eq(&p, &StaticRef::from(&ANSWER)));
+        assert_eq!(StaticRef::try_unwrap(p).unwrap_err(), p);
+        assert!(StaticRef::into_mut(p).is_err());
+        assert_eq!(allocations(), before);
+    }
+
+    // These allocations are leaked on purpose, which Miri reports.
+    #[cfg_attr(miri, ignore)]
+    #[test]
+    fn leaked_values_are_never_unique() {
+        let p: StaticRef<u32> = SmartPointer::new(7);
+        assert_eq!(describe(p), (7, usize::MAX, false));
+
+        let s = StaticRef::<str>::from_owned(String::from("leaked"));
+        assert_eq!(&*s, "leaked");
+        assert_eq!(StaticRef::reference_count(&s).get(), usize::MAX);
+    }
+}
@@ -0,0 +1,190 @@
+use base::fmt;
+use base::marker::PhantomData;
+use base::mem;
+use base::ops::Deref;
+use base::ptr::NonNull;
+
+use crate::arc::{Arc, ArcBorrow, ArcInner};
+
+/// An `Arc` that stores a small integer tag of `BITS` bits in the unused low bits of its
+/// pointer, so that the tag takes up no additional space.
+///
+/// The allocation of an `Arc` is at least as aligned as a `usize`, so up to two (32-bit
+/// targets) or three (64-bit targets) bits are always available, more if `T` has a larger
+/// alignment. Using a `BITS` that the alignment does not allow fails to compile.
+///
+/// Cloning and dropping remove the tag before touching the reference count; the tag is copied
+/// to clones.
+pub struct TaggedArc<T, const BITS: u32> {
+    // The pointer to the `ArcInner<T>`, offset by the tag.
+    ptr: NonNull<u8>,
+    phantom: PhantomData<Arc<T>>,
+}
+
+unsafe impl<T: Sync + Send, const BITS: u32> Send for TaggedArc<T, BITS> {}
+unsafe impl<T: Sync + Send, const BITS: u32> Sync for TaggedArc<T, BITS> {}
+
+impl<T, const BITS: u32> TaggedArc<T, BITS> {
+    /// The largest tag that can be stored.
+    pub const MAX_TAG: usize = {
+        assert!(
+            BITS <= mem::align_of::<ArcInner<T>>().trailing_zeros(),
+            "the alignment of the allocation does not leave room for that many tag bits",
+        );
+        (1 << BITS) - 1
+    };
+
+    /// Combines an `Arc` and a tag.
+    ///
+    /// Panics if the tag is larger than `MAX_TAG`.
+    pub fn new(arc: Arc<T>, tag: usize) -> Self {
+        assert!(tag <= Self::MAX_TAG, "tag {} does not fit into {} bits", tag, BITS);
+        let ptr = Arc::into_inner(arc).as_ptr() as *mut u8;
+        // The tag only sets bits that are zero in the aligned pointer, so the result is not
+        // null. Offsetting the pointer rather than creating it from an integer preserves its
+        // provenance.
+        let ptr = unsafe { NonNull::new_unchecked(ptr.wrapping_add(tag)) };
+        TaggedArc { ptr, phantom: PhantomData }
+    }
+
+    /// Gets the tag.
+    pub fn tag(this: &Self) -> usize {
+        this.ptr.as_ptr() as usize & Self::MAX_TAG
+    }
+
+    /// Replaces the tag.
+    ///
+    /// Panics if the tag is larger than `MAX_TAG`.
+    pub fn set_tag(this: &mut Self, tag: usize) {
+        assert!(tag <= Self::MAX_TAG, "tag {} does not fit into {} bits", tag, BITS);
+        let untagged = this.untagged().as_ptr() as *mut u8;
+        this.ptr = unsafe { NonNull::new_unchecked(untagged.wrapping_add(tag)) };
+    }
+
+    /// Borrows the `Arc`, without touching the reference count.
+    pub fn arc(this: &Self) -> ArcBorrow<'_, T> {
+        ArcBorrow::from_inner(this.untagged())
+    }
+
+    /// Removes the tag, returning the `Arc`.
+    pub fn into_arc(this: Self) -> Arc<T> {
+        let inner = this.untagged();
+        mem::forget(this);
+        Arc::from_inner(inner)
+    }
+
+    /// Splits into the `Arc` and the tag.
+    pub fn into_parts(this: Self) -> (Arc<T>, usize) {
+        let tag = Self::tag(&this);
+        (Self::into_arc(this), tag)
+    }
+
+    fn untagged(&self) -> NonNull<ArcInner<T>> {
+        let ptr = self.ptr.as_ptr().wrapping_sub(Self::tag(self)) as *mut ArcInner<T>;
+        unsafe { NonNull::new_unchecked(ptr) }
+    }
+}
+
+impl<T, const BITS: u32> Clone for TaggedArc<T, BITS> {
+    fn clone(&self) -> Self {
+        TaggedArc::new(ArcBorrow::to_arc(Self::arc(self)), Self::tag(self))
+    }
+}
+
+impl<T, const BITS: u32> Drop for TaggedArc<T, BITS> {
+    fn drop(&mut self) {
+        drop(Arc::from_inner(self.untagged()));
+    }
+}
+
+impl<T, const BITS: u32> Deref for TaggedArc<T, BITS> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        unsafe { &(*self.untagged().as_ptr()).data }
+    }
+}
+
+impl<T: fmt::Debug, const BITS: u32> fmt::Debug for TaggedArc<T, BITS> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("TaggedArc")
+            .field("value", &**self)
+            .field("tag", &Self::tag(self))
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+
+    use super::*;
+    use crate::ReferenceCounted;
+    use smart_pointer::SmartPointer;
+    use std::string::String;
+
+    #[test]
+    fn tags_round_trip() {
+        let arc = Arc::new(String::from("node"));
+        for tag in 0..=TaggedArc::<String, 2>::MAX_TAG {
+            let tagged = TaggedArc::<_, 2>::new(arc.clone(), tag);
+            assert_eq!(TaggedArc::tag(&tagged), tag);
+            assert_eq!(*tagged, "node");
+
+            let (back, back_tag) = TaggedArc::into_parts(tagged);
+            assert_eq!(back_tag, tag);
+            assert!(Arc::ptr_eq(&back, &arc));
+        }
+        assert_eq!(Arc::reference_count(&arc).get(), 1);
+    }
+
+    #[test]
+    fn set_tag_keeps_the_value() {
+        let mut tagged = TaggedArc::<_, 2>::new(Arc::new(String::from("node")), 1);
+        TaggedArc::set_tag(&mut tagged, 3);
+        assert_eq!(TaggedArc::tag(&tagged), 3);
+        TaggedArc::set_tag(&mut tagged, 0);
+        assert_eq!(TaggedArc::tag(&tagged), 0);
+        assert_eq!(*tagged, "node");
+        assert_eq!(*TaggedArc::into_arc(tagged), "node");
+    }
+
+    #[test]
+    fn clones_untag_before_counting() {
+        let arc = Arc::new(String::from("node"));
+        let tagged = TaggedArc::<_, 2>::new(arc.clone(), 2);
+        let clone = tagged.clone();
+        assert_eq!(TaggedArc::tag(&clone), 2);
+        assert_eq!(Arc::reference_count(&arc).get(), 3);
+
+        let borrowed = TaggedArc::arc(&clone);
+        assert_eq!(&*borrowed, "node");
+        assert!(Arc::ptr_eq(&ArcBorrow::to_arc(borrowed), &arc));
+
+        drop((tagged, clone));
+        assert_eq!(Arc::reference_count(&arc).get(), 1);
+    }
+
+    #[test]
+    fn large_alignments_leave_more_bits() {
+        #[repr(align(64))]
+        struct Aligned(u8);
+
+        let tagged = TaggedArc::<_, 6>::new(Arc::new(Aligned(7)), 63);
+        assert_eq!(TaggedArc::<Aligned, 6>::MAX_TAG, 63);
+        assert_eq!(TaggedArc::tag(&tagged), 63);
+        assert_eq!(tagged.0, 7);
+        assert_eq!(TaggedArc::into_arc(tagged).0, 7);
+    }
+
+    #[test]
+    fn the_last_tagged_owner_drops_the_value() {
+        let counted = Arc::new(String::from("counted"));
+        let tagged = TaggedArc::<_, 1>::new(Arc::new(counted.clone()), 1);
+        let clone = tagged.clone();
+        drop(tagged);
+        assert_eq!(Arc::reference_count(&counted).get(), 2);
+        drop(clone);
+        assert_eq!(Arc::reference_count(&counted).get(), 1);
+    }
+}
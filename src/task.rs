@@ -0,0 +1,123 @@
+use base::mem::ManuallyDrop;
+use base::ptr::NonNull;
+use base::task::{RawWaker, RawWakerVTable, Waker};
+
+use crate::arc::{Arc, ArcInner};
+
+/// The implementation of waking a task on an executor, for use with `waker_from_arc`.
+///
+/// This is the counterpart of `std::task::Wake` for this crate's `Arc`. Since `Arc` can not be
+/// used as the type of a `self` receiver on stable Rust, the methods take the pointer as an
+/// ordinary argument instead.
+pub trait Wake {
+    /// Wakes this task, consuming the pointer.
+    fn wake(this: Arc<Self>);
+
+    /// Wakes this task without consuming the pointer.
+    ///
+    /// The default implementation clones the pointer and calls `wake` on the clone.
+    fn wake_by_ref(this: &Arc<Self>) {
+        Self::wake(this.clone());
+    }
+}
+
+/// Creates a `Waker` that calls the methods of `W` and owns the given reference to it.
+///
+/// Cloning the waker clones the `Arc`, and dropping or waking the waker releases it again.
+pub fn waker_from_arc<W: Wake + Send + Sync + 'static>(waker: Arc<W>) -> Waker {
+    // The `RawWaker` is built according to the contract of `RawWakerVTable`.
+    unsafe { Waker::from_raw(raw_waker(waker)) }
+}
+
+impl<W: Wake + Send + Sync + 'static> From<Arc<W>> for Waker {
+    fn from(waker: Arc<W>) -> Waker {
+        waker_from_arc(waker)
+    }
+}
+
+fn raw_waker<W: Wake + Send + Sync + 'static>(waker: Arc<W>) -> RawWaker {
+    RawWaker::new(
+        Arc::into_inner(waker).as_ptr() as *const (),
+        &RawWakerVTable::new(clone_waker::<W>, wake::<W>, wake_by_ref::<W>, drop_waker::<W>),
+    )
+}
+
+/// Reclaims the `Arc` whose reference the `RawWaker` data pointer owns.
+unsafe fn from_data<W>(data: *const ()) -> Arc<W> {
+    Arc::from_inner(unsafe { NonNull::new_unchecked(data as *mut ArcInner<W>) })
+}
+
+unsafe fn clone_waker<W: Wake + Send + Sync + 'static>(data: *const ()) -> RawWaker {
+    let waker = ManuallyDrop::new(unsafe { from_data::<W>(data) });
+    raw_waker(Arc::clone(&waker))
+}
+
+unsafe fn wake<W: Wake + Send + Sync + 'static>(data: *const ()) {
+    W::wake(unsafe { from_data(data) });
+}
+
+unsafe fn wake_by_ref<W: Wake + Send + Sync + 'static>(data: *const ()) {
+    let waker = ManuallyDrop::new(unsafe { from_data::<W>(data) });
+    W::wake_by_ref(&waker);
+}
+
+unsafe fn drop_waker<W: Wake + Send + Sync + 'static>(data: *const ()) {
+    drop(unsafe { from_data::<W>(data) });
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+
+    use std::sync::atomic::{AtomicUsize, Ordering::Relaxed};
+
+    use super::*;
+    use crate::ReferenceCounted;
+    use smart_pointer::SmartPointer;
+
+    struct Task {
+        wakes: AtomicUsize,
+        drops: &'static AtomicUsize,
+    }
+
+    impl Wake for Task {
+        fn wake(this: Arc<Self>) {
+            this.wakes.fetch_add(1, Relaxed);
+        }
+    }
+
+    impl Drop for Task {
+        fn drop(&mut self) {
+            self.drops.fetch_add(1, Relaxed);
+        }
+    }
+
+    #[test]
+    fn wakers_keep_the_count_balanced() {
+        static DROPS: AtomicUsize = AtomicUsize::new(0);
+        let task = Arc::new(Task { wakes: AtomicUsize::new(0), drops: &DROPS });
+        let count = || Arc::reference_count(&task).get();
+
+        let waker = waker_from_arc(task.clone());
+        let other = waker.clone();
+        assert_eq!(count(), 3);
+        assert!(waker.will_wake(&other));
+
+        // Waking by reference goes through a temporary clone, and does not consume the waker.
+        waker.wake_by_ref();
+        other.wake_by_ref();
+        assert_eq!(task.wakes.load(Relaxed), 2);
+        assert_eq!(count(), 3);
+
+        other.wake();
+        assert_eq!(task.wakes.load(Relaxed), 3);
+        assert_eq!(count(), 2);
+        drop(waker);
+        assert_eq!(count(), 1);
+
+        drop(Waker::from(task.clone()));
+        assert_eq!(count(), 1);
+        drop(task);
+        assert_eq!(DROPS.load(Relaxed), 1);
+    }
+}
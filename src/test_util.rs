@@ -0,0 +1,79 @@
+//! Helpers shared by the unit tests.
+
+extern crate std;
+
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::cell::Cell;
+
+/// Counts allocations and deallocations per thread, so that tests running in parallel do not
+/// see each other's allocations.
+struct CountingAllocator;
+
+std::thread_local! {
+    static ALLOCATED: Cell<usize> = const { Cell::new(0) };
+    static DEALLOCATED: Cell<usize> = const { Cell::new(0) };
+    static FAILING: Cell<bool> = const { Cell::new(false) };
+}
+
+fn count(counter: &'static std::thread::LocalKey<Cell<usize>>) {
+    // The counter has no destructor, but may still be inaccessible while the thread exits.
+    let _ = counter.try_with(|n| n.set(n.get() + 1));
+}
+
+fn failing() -> bool {
+    FAILING.try_with(Cell::get).unwrap_or(false)
+}
+
+unsafe impl GlobalAlloc for CountingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        if failing() {
+            return std::ptr::null_mut();
+        }
+        count(&ALLOCATED);
+        unsafe { System.alloc(layout) }
+    }
+
+    unsafe fn alloc_zeroed(&self, layout: Layout) -> *mut u8 {
+        if failing() {
+            return std::ptr::null_mut();
+        }
+        count(&ALLOCATED);
+        unsafe { System.alloc_zeroed(layout) }
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        count(&DEALLOCATED);
+        unsafe { System.dealloc(ptr, layout) }
+    }
+}
+
+#[global_allocator]
+static ALLOCATOR: CountingAllocator = CountingAllocator;
+
+/// The number of allocations the current thread has made so far. Reallocations count as
+/// allocations as well.
+pub fn allocations() -> usize {
+    ALLOCATED.with(Cell::get)
+}
+
+/// The number of deallocations the current thread has made so far. Reallocations count as
+/// deallocations as well.
+pub fn deallocations() -> usize {
+    DEALLOCATED.with(Cell::get)
+}
+
+/// Runs `f` with all allocations of the current thread failing, to reach the error paths of the
+/// fallible constructors.
+pub fn failing_allocations<R>(f: impl FnOnce() -> R) -> R {
+    struct Reset;
+
+    impl Drop for Reset {
+        fn drop(&mut self) {
+            FAILING.with(|failing| failing.set(false));
+        }
+    }
+
+    FAILING.with(|failing| failing.set(true));
+    let _reset = Reset;
+    f()
+}
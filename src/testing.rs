@@ -0,0 +1,358 @@
+use base::borrow::{self, BorrowMut};
+use base::cell::RefCell;
+use base::fmt;
+use base::mem;
+use base::num::NonZeroUsize;
+use base::ops::{Deref, DerefMut};
+use base::ptr;
+use base::sync::atomic::{AtomicUsize, Ordering::Relaxed};
+use base::vec::Vec;
+
+use smart_pointer::{IntoMut, SmartPointer, SmartPointerMut};
+
+use crate::rc::{Rc, UniqueRc};
+use crate::ReferenceCounted;
+
+/// Something that happened to a `MockRefCounted` or `MockUniqueRc`.
+///
+/// Every allocation created via `SmartPointer::new` gets a fresh `id`, which all pointers to
+/// it share.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum MockEvent {
+    /// A new allocation was created.
+    New { id: usize },
+    /// A pointer was cloned, `count` is the reference count after cloning.
+    Clone { id: usize, count: usize },
+    /// A pointer was dropped, `count` is the reference count after dropping. A count of zero
+    /// means the value was dropped.
+    Drop { id: usize, count: usize },
+    /// A pointer was dereferenced.
+    Deref { id: usize },
+    /// `IntoMut::can_make_mut` was queried, `unique` is the answer.
+    CanMakeMut { id: usize, unique: bool },
+    /// A shared pointer was converted into a unique one.
+    IntoMut { id: usize },
+    /// `SmartPointer::try_unwrap` was called, `success` tells whether it returned the value.
+    TryUnwrap { id: usize, success: bool },
+}
+
+static NEXT_ID: AtomicUsize = AtomicUsize::new(0);
+
+base::thread_local! {
+    static EVENTS: RefCell<Vec<MockEvent>> = const { RefCell::new(Vec::new()) };
+}
+
+fn log(event: MockEvent) {
+    EVENTS.with(|events| events.borrow_mut().push(event));
+}
+
+/// Returns all events that have been recorded on the current thread.
+///
+/// Events are logged per thread, so that tests running in parallel do not interfere.
+pub fn mock_events() -> Vec<MockEvent> {
+    EVENTS.with(|events| events.borrow().clone())
+}
+
+/// Forgets all events that have been recorded on the current thread.
+pub fn clear_mock_events() {
+    EVENTS.with(|events| events.borrow_mut().clear());
+}
+
+/// Runs `f` and returns its result, together with all events it caused on the current thread.
+pub fn record_mock_events<R>(f: impl FnOnce() -> R) -> (R, Vec<MockEvent>) {
+    let start = EVENTS.with(|events| events.borrow().len());
+    let result = f();
+    let recorded = EVENTS.with(|events| {
+        let events = events.borrow();
+        let start = start.min(events.len());
+        events[start..].to_vec()
+    });
+    (result, recorded)
+}
+
+/// Runs `f` and returns its result, panicking if it cloned any mock pointer.
+pub fn assert_no_clones_during<R>(f: impl FnOnce() -> R) -> R {
+    let (result, events) = record_mock_events(f);
+    let clones = events.iter().filter(|e| matches!(e, MockEvent::Clone { .. })).count();
+    if clones > 0 {
+        panic!("expected no clones, but {} mock pointers were cloned", clones);
+    }
+    result
+}
+
+/// A reference-counted pointer that records everything that happens to it, for testing code
+/// that is generic over `ReferenceCounted`.
+///
+/// Internally, this is an `Rc` plus an identifier of the allocation. All events are logged as
+/// `MockEvent`s on the current thread, see `mock_events` and `record_mock_events`.
+pub struct MockRefCounted<T: ?Sized> {
+    id: usize,
+    inner: Rc<T>,
+}
+
+impl<T: ?Sized> MockRefCounted<T> {
+    /// Gets the identifier of the allocation, as used in the `MockEvent`s.
+    pub fn id(this: &Self) -> usize {
+        this.id
+    }
+
+    /// Moves the fields out, without dropping (and logging) `this`.
+    fn into_parts(this: Self) -> (usize, Rc<T>) {
+        let id = this.id;
+        let inner = unsafe { ptr::read(&this.inner) };
+        mem::forget(this);
+        (id, inner)
+    }
+}
+
+impl<T: ?Sized> Clone for MockRefCounted<T> {
+    fn clone(&self) -> Self {
+        let inner = self.inner.clone();
+        log(MockEvent::Clone { id: self.id, count: Rc::reference_count(&inner).get() });
+        MockRefCounted { id: self.id, inner }
+    }
+}
+
+impl<T: ?Sized> Drop for MockRefCounted<T> {
+    fn drop(&mut self) {
+        let count = Rc::reference_count(&self.inner).get() - 1;
+        log(MockEvent::Drop { id: self.id, count });
+    }
+}
+
+impl<T: ?Sized> Deref for MockRefCounted<T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        log(MockEvent::Deref { id: self.id });
+        &self.inner
+    }
+}
+
+impl<T: ?Sized> borrow::Borrow<T> for MockRefCounted<T> {
+    fn borrow(&self) -> &T {
+        self
+    }
+}
+
+impl<T: ?Sized> AsRef<T> for MockRefCounted<T> {
+    fn as_ref(&self) -> &T {
+        self
+    }
+}
+
+impl<T: ?Sized + fmt::Debug> fmt::Debug for MockRefCounted<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Debug::fmt(&self.inner, f)
+    }
+}
+
+impl<T: ?Sized> fmt::Pointer for MockRefCounted<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Pointer::fmt(&self.inner, f)
+    }
+}
+
+impl<T: ?Sized> SmartPointer<T> for MockRefCounted<T> {
+    fn new(data: T) -> Self where T: Sized {
+        let id = NEXT_ID.fetch_add(1, Relaxed);
+        log(MockEvent::New { id });
+        MockRefCounted { id, inner: Rc::new(data) }
+    }
+
+    fn try_unwrap(this: Self) -> Result<T, Self> where T: Sized {
+        let (id, inner) = MockRefCounted::into_parts(this);
+        let result = Rc::try_unwrap(inner);
+        log(MockEvent::TryUnwrap { id, success: result.is_ok() });
+        result.map_err(|inner| MockRefCounted { id, inner })
+    }
+}
+
+impl<T: ?Sized> IntoMut<T> for MockRefCounted<T> {
+    type MutablePointer = MockUniqueRc<T>;
+
+    fn can_make_mut(this: &Self) -> bool {
+        let unique = Rc::can_make_mut(&this.inner);
+        log(MockEvent::CanMakeMut { id: this.id, unique });
+        unique
+    }
+
+    unsafe fn into_mut_unchecked(this: Self) -> Self::MutablePointer {
+        let (id, inner) = MockRefCounted::into_parts(this);
+        log(MockEvent::IntoMut { id });
+        MockUniqueRc { id, inner: unsafe { Rc::into_mut_unchecked(inner) } }
+    }
+
+    unsafe fn get_mut_unchecked(this: &Self) -> &mut T {
+        unsafe { Rc::get_mut_unchecked(&this.inner) }
+    }
+}
+
+impl<T: ?Sized> ReferenceCounted<T> for MockRefCounted<T> {
+    fn reference_count(this: &Self) -> NonZeroUsize {
+        Rc::reference_count(&this.inner)
+    }
+}
+
+/// The unique counterpart of `MockRefCounted`, which records everything that happens to it.
+pub struct MockUniqueRc<T: ?Sized> {
+    id: usize,
+    inner: UniqueRc<T>,
+}
+
+impl<T: ?Sized> MockUniqueRc<T> {
+    /// Gets the identifier of the allocation, as used in the `MockEvent`s.
+    pub fn id(this: &Self) -> usize {
+        this.id
+    }
+
+    /// Moves the fields out, without dropping (and logging) `this`.
+    fn into_parts(this: Self) -> (usize, UniqueRc<T>) {
+        let id = this.id;
+        let inner = unsafe { ptr::read(&this.inner) };
+        mem::forget(this);
+        (id, inner)
+    }
+}
+
+impl<T: ?Sized> Drop for MockUniqueRc<T> {
+    fn drop(&mut self) {
+        log(MockEvent::Drop { id: self.id, count: 0 });
+    }
+}
+
+impl<T: ?Sized> Deref for MockUniqueRc<T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        log(MockEvent::Deref { id: self.id });
+        &self.inner
+    }
+}
+
+impl<T: ?Sized> DerefMut for MockUniqueRc<T> {
+    fn deref_mut(&mut self) -> &mut T {
+        log(MockEvent::Deref { id: self.id });
+        &mut self.inner
+    }
+}
+
+impl<T: ?Sized> borrow::Borrow<T> for MockUniqueRc<T> {
+    fn borrow(&self) -> &T {
+        self
+    }
+}
+
+impl<T: ?Sized> BorrowMut<T> for MockUniqueRc<T> {
+    fn borrow_mut(&mut self) -> &mut T {
+        self
+    }
+}
+
+impl<T: ?Sized> AsRef<T> for MockUniqueRc<T> {
+    fn as_ref(&self) -> &T {
+        self
+    }
+}
+
+impl<T: ?Sized> AsMut<T> for MockUniqueRc<T> {
+    fn as_mut(&mut self) -> &mut T {
+        self
+    }
+}
+
+impl<T: ?Sized + fmt::Debug> fmt::Debug for MockUniqueRc<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Debug::fmt(&self.inner, f)
+    }
+}
+
+impl<T: ?Sized> fmt::Pointer for MockUniqueRc<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Pointer::fmt(&self.inner, f)
+    }
+}
+
+impl<T: ?Sized> SmartPointer<T> for MockUniqueRc<T> {
+    fn new(data: T) -> Self where T: Sized {
+        let id = NEXT_ID.fetch_add(1, Relaxed);
+        log(MockEvent::New { id });
+        MockUniqueRc { id, inner: UniqueRc::new(data) }
+    }
+
+    fn try_unwrap(this: Self) -> Result<T, Self> where T: Sized {
+        let (id, inner) = MockUniqueRc::into_parts(this);
+        let result = UniqueRc::try_unwrap(inner);
+        log(MockEvent::TryUnwrap { id, success: result.is_ok() });
+        result.map_err(|inner| MockUniqueRc { id, inner })
+    }
+}
+
+impl<T: ?Sized> SmartPointerMut<T> for MockUniqueRc<T> {}
+
+impl<T: ?Sized> From<MockUniqueRc<T>> for MockRefCounted<T> {
+    fn from(unique: MockUniqueRc<T>) -> Self {
+        let (id, inner) = MockUniqueRc::into_parts(unique);
+        MockRefCounted { id, inner: inner.into() }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Generic code of the kind `MockRefCounted` is meant to test.
+    fn make_unique<P: ReferenceCounted<u32>>(p: P) -> P {
+        match P::into_mut(p) {
+            Ok(unique) => unique.into(),
+            Err(shared) => P::new(*shared),
+        }
+    }
+
+    #[test]
+    fn records_clones_and_drops() {
+        let (id, events) = record_mock_events(|| {
+            let a = MockRefCounted::new(1u32);
+            let id = MockRefCounted::id(&a);
+            let b = a.clone();
+            drop(a);
+            drop(b);
+            id
+        });
+        assert_eq!(
+            events,
+            [
+                MockEvent::New { id },
+                MockEvent::Clone { id, count: 2 },
+                MockEvent::Drop { id, count: 1 },
+                MockEvent::Drop { id, count: 0 },
+            ]
+        );
+    }
+
+    #[test]
+    fn unique_path_does_not_clone() {
+        let p = assert_no_clones_during(|| make_unique(MockRefCounted::new(5u32)));
+        assert_eq!(*p, 5);
+        assert_eq!(MockRefCounted::reference_count(&p).get(), 1);
+    }
+
+    #[test]
+    fn shared_path_is_recorded() {
+        let a = MockRefCounted::new(5u32);
+        let b = a.clone();
+        let (c, events) = record_mock_events(|| make_unique(b));
+        let id = MockRefCounted::id(&a);
+        assert!(events.contains(&MockEvent::CanMakeMut { id, unique: false }));
+        assert!(!events.contains(&MockEvent::IntoMut { id }));
+        assert!(events.contains(&MockEvent::Drop { id, count: 1 }));
+        assert_ne!(MockRefCounted::id(&c), id);
+    }
+
+    #[test]
+    #[should_panic(expected = "expected no clones")]
+    fn assert_no_clones_detects_clones() {
+        let a = MockRefCounted::new(());
+        assert_no_clones_during(|| a.clone());
+    }
+}
@@ -0,0 +1,513 @@
+/// Panics if `start..end` does not start and end on `char` boundaries of `s`.
+pub(crate) fn check_boundaries(s: &str, start: usize, end: usize) {
+    if !s.is_char_boundary(start) || !s.is_char_boundary(end) {
+        panic!("substring {}..{} does not lie on char boundaries", start, end);
+    }
+}
+
+/// Defines the substring and subslice views of a pointer type (`ArcStr` and `ArcSlice` for
+/// `Arc`, `RcStr` and `RcSlice` for `Rc`), along with the methods of the pointer that create
+/// them. Invoked once in the module of each view.
+macro_rules! shared_views {
+    ($ptr:ident, $unique:ident, $str_view:ident, $slice_view:ident) => {
+        use base::borrow;
+        use base::cmp::Ordering;
+        use base::fmt;
+        use base::hash::{Hash, Hasher};
+        use base::ops::{Deref, Range, RangeBounds};
+        use base::str;
+
+        use smart_pointer::IntoMut;
+
+        use crate::view::check_boundaries;
+        use crate::{resolve_range, SharedSlice};
+
+        #[doc = concat!(
+            "A substring of an `", stringify!($ptr), "<str>`, which keeps the whole string alive ",
+            "instead of copying the substring into an allocation of its own.\n\n",
+            "Created by `", stringify!($ptr), "::<str>::substr` and by the splitting iterators ",
+            "such as `", stringify!($ptr), "::<str>::split_shared`. Cloning an `",
+            stringify!($str_view), "` clones the `", stringify!($ptr), "`, and comparisons and ",
+            "hashing consider only the contents of the substring.",
+        )]
+        #[derive(Clone)]
+        pub struct $str_view {
+            parent: $ptr<str>,
+            start: usize,
+            end: usize,
+        }
+
+        impl $str_view {
+            /// Returns a view of a part of this substring, sharing the same allocation.
+            ///
+            /// Panics if the range is out of bounds, or if it does not start and end on `char`
+            /// boundaries.
+            pub fn substr(&self, range: impl RangeBounds<usize>) -> $str_view {
+                let (start, end) = resolve_range(range, self.len());
+                check_boundaries(self, start, end);
+                let parent = self.parent.clone();
+                $str_view { parent, start: self.start + start, end: self.start + end }
+            }
+
+            /// Gets the string that this is a substring of.
+            pub fn parent(&self) -> &$ptr<str> {
+                &self.parent
+            }
+
+            /// Gets the byte range of this substring within its parent.
+            pub fn range(&self) -> Range<usize> {
+                self.start..self.end
+            }
+
+            /// Gets the view of the substring `s` of the parent.
+            fn of_part(parent: &$ptr<str>, s: &str) -> $str_view {
+                let start = s.as_ptr() as usize - parent.as_ptr() as usize;
+                $str_view { parent: parent.clone(), start, end: start + s.len() }
+            }
+        }
+
+        impl $ptr<str> {
+            /// Returns a view of a substring, sharing the allocation of `this`.
+            ///
+            /// Panics if the range is out of bounds, or if it does not start and end on `char`
+            /// boundaries.
+            pub fn substr(this: &Self, range: impl RangeBounds<usize>) -> $str_view {
+                let (start, end) = resolve_range(range, this.len());
+                check_boundaries(this, start, end);
+                $str_view { parent: this.clone(), start, end }
+            }
+
+            /// Like `str::split` with a `char` pattern, but yields views that share the
+            /// allocation of `this` instead of borrowing from it.
+            pub fn split_shared(
+                this: Self,
+                pattern: char,
+            ) -> impl DoubleEndedIterator<Item = $str_view> {
+                SharedParts::new(this, |s| s.split(pattern))
+            }
+
+            /// Like `str::lines`, but yields views that share the allocation of `this` instead
+            /// of borrowing from it.
+            pub fn lines_shared(this: Self) -> impl DoubleEndedIterator<Item = $str_view> {
+                SharedParts::new(this, str::lines)
+            }
+
+            /// Like `str::split_whitespace`, but yields views that share the allocation of
+            /// `this` instead of borrowing from it.
+            pub fn split_whitespace_shared(
+                this: Self,
+            ) -> impl DoubleEndedIterator<Item = $str_view> {
+                SharedParts::new(this, str::split_whitespace)
+            }
+        }
+
+        /// Turns an iterator over substrings of `parent` into an iterator over views.
+        struct SharedParts<I> {
+            // Borrows from `parent`. The string does not move when the pointer does, and it is
+            // kept alive for as long as `parts` by `parent`.
+            parts: I,
+            parent: $ptr<str>,
+        }
+
+        impl<I: Iterator<Item = &'static str>> SharedParts<I> {
+            fn new(parent: $ptr<str>, split: impl FnOnce(&'static str) -> I) -> Self {
+                let s: &'static str = unsafe { &*(&*parent as *const str) };
+                SharedParts { parts: split(s), parent }
+            }
+        }
+
+        impl<I: Iterator<Item = &'static str>> Iterator for SharedParts<I> {
+            type Item = $str_view;
+
+            fn next(&mut self) -> Option<$str_view> {
+                self.parts.next().map(|s| $str_view::of_part(&self.parent, s))
+            }
+
+            fn size_hint(&self) -> (usize, Option<usize>) {
+                self.parts.size_hint()
+            }
+        }
+
+        impl<I: DoubleEndedIterator<Item = &'static str>> DoubleEndedIterator for SharedParts<I> {
+            fn next_back(&mut self) -> Option<$str_view> {
+                self.parts.next_back().map(|s| $str_view::of_part(&self.parent, s))
+            }
+        }
+
+        impl From<$ptr<str>> for $str_view {
+            /// Creates a view of the whole string.
+            fn from(parent: $ptr<str>) -> Self {
+                let end = parent.len();
+                $str_view { parent, start: 0, end }
+            }
+        }
+
+        impl Deref for $str_view {
+            type Target = str;
+
+            fn deref(&self) -> &str {
+                // The range has been checked on construction.
+                unsafe { self.parent.get_unchecked(self.start..self.end) }
+            }
+        }
+
+        impl borrow::Borrow<str> for $str_view {
+            fn borrow(&self) -> &str {
+                self
+            }
+        }
+
+        impl AsRef<str> for $str_view {
+            fn as_ref(&self) -> &str {
+                self
+            }
+        }
+
+        impl PartialEq for $str_view {
+            fn eq(&self, other: &$str_view) -> bool {
+                **self == **other
+            }
+        }
+
+        impl Eq for $str_view {}
+
+        impl PartialEq<str> for $str_view {
+            fn eq(&self, other: &str) -> bool {
+                &**self == other
+            }
+        }
+
+        impl PartialEq<&str> for $str_view {
+            fn eq(&self, other: &&str) -> bool {
+                &**self == *other
+            }
+        }
+
+        impl PartialOrd for $str_view {
+            fn partial_cmp(&self, other: &$str_view) -> Option<Ordering> {
+                Some(self.cmp(other))
+            }
+        }
+
+        impl Ord for $str_view {
+            fn cmp(&self, other: &$str_view) -> Ordering {
+                (**self).cmp(&**other)
+            }
+        }
+
+        impl Hash for $str_view {
+            fn hash<H: Hasher>(&self, state: &mut H) {
+                (**self).hash(state)
+            }
+        }
+
+        impl fmt::Display for $str_view {
+            fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                fmt::Display::fmt(&**self, f)
+            }
+        }
+
+        impl fmt::Debug for $str_view {
+            fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                fmt::Debug::fmt(&**self, f)
+            }
+        }
+
+        #[doc = concat!(
+            "A subslice of an `", stringify!($ptr), "<[T]>`, which keeps the whole slice alive ",
+            "instead of copying the subslice into an allocation of its own.\n\n",
+            "Created by `", stringify!($ptr), "::<[T]>::slice`. Cloning an `",
+            stringify!($slice_view), "` clones the `", stringify!($ptr), "`, and comparisons ",
+            "and hashing consider only the items of the subslice.",
+        )]
+        pub struct $slice_view<T> {
+            parent: $ptr<[T]>,
+            start: usize,
+            end: usize,
+        }
+
+        impl<T> $slice_view<T> {
+            /// Returns a view of a part of this subslice, sharing the same allocation.
+            ///
+            /// Panics if the range is out of bounds.
+            pub fn slice(&self, range: impl RangeBounds<usize>) -> $slice_view<T> {
+                let (start, end) = resolve_range(range, self.len());
+                let parent = self.parent.clone();
+                $slice_view { parent, start: self.start + start, end: self.start + end }
+            }
+
+            /// Gets the slice that this is a subslice of.
+            pub fn parent(&self) -> &$ptr<[T]> {
+                &self.parent
+            }
+
+            /// Gets the range of this subslice within its parent.
+            pub fn range(&self) -> Range<usize> {
+                self.start..self.end
+            }
+        }
+
+        impl<T> $ptr<[T]> {
+            /// Returns a view of a subslice, sharing the allocation of `this`.
+            ///
+            /// Panics if the range is out of bounds.
+            pub fn slice(this: &Self, range: impl RangeBounds<usize>) -> $slice_view<T> {
+                let (start, end) = resolve_range(range, this.len());
+                $slice_view { parent: this.clone(), start, end }
+            }
+        }
+
+        impl<T> SharedSlice<T> for $slice_view<T> {
+            type Unique = $unique<[T]>;
+
+            fn slice(this: Self, range: Range<usize>) -> Self {
+                let (start, end) = resolve_range(range, this.len());
+                let start = this.start + start;
+                $slice_view { parent: this.parent, start, end: this.start + end }
+            }
+
+            fn try_into_unique(this: Self) -> Result<$unique<[T]>, Self> {
+                let whole = this.start == 0 && this.end == this.parent.len();
+                if whole && $ptr::can_make_mut(&this.parent) {
+                    Ok(unsafe { $ptr::into_mut_unchecked(this.parent) })
+                } else {
+                    Err(this)
+                }
+            }
+        }
+
+        impl<T> Clone for $slice_view<T> {
+            fn clone(&self) -> Self {
+                $slice_view { parent: self.parent.clone(), start: self.start, end: self.end }
+            }
+        }
+
+        impl<T> From<$ptr<[T]>> for $slice_view<T> {
+            /// Creates a view of the whole slice.
+            fn from(parent: $ptr<[T]>) -> Self {
+                let end = parent.len();
+                $slice_view { parent, start: 0, end }
+            }
+        }
+
+        impl<T> Deref for $slice_view<T> {
+            type Target = [T];
+
+            fn deref(&self) -> &[T] {
+                // The range has been checked on construction.
+                unsafe { self.parent.get_unchecked(self.start..self.end) }
+            }
+        }
+
+        impl<T> borrow::Borrow<[T]> for $slice_view<T> {
+            fn borrow(&self) -> &[T] {
+                self
+            }
+        }
+
+        impl<T> AsRef<[T]> for $slice_view<T> {
+            fn as_ref(&self) -> &[T] {
+                self
+            }
+        }
+
+        impl<T: PartialEq> PartialEq for $slice_view<T> {
+            fn eq(&self, other: &$slice_view<T>) -> bool {
+                **self == **other
+            }
+        }
+
+        impl<T: Eq> Eq for $slice_view<T> {}
+
+        impl<T: PartialEq> PartialEq<[T]> for $slice_view<T> {
+            fn eq(&self, other: &[T]) -> bool {
+                &**self == other
+            }
+        }
+
+        impl<T: PartialOrd> PartialOrd for $slice_view<T> {
+            fn partial_cmp(&self, other: &$slice_view<T>) -> Option<Ordering> {
+                (**self).partial_cmp(&**other)
+            }
+        }
+
+        impl<T: Ord> Ord for $slice_view<T> {
+            fn cmp(&self, other: &$slice_view<T>) -> Ordering {
+                (**self).cmp(&**other)
+            }
+        }
+
+        impl<T: Hash> Hash for $slice_view<T> {
+            fn hash<H: Hasher>(&self, state: &mut H) {
+                (**self).hash(state)
+            }
+        }
+
+        impl<T: fmt::Debug> fmt::Debug for $slice_view<T> {
+            fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                fmt::Debug::fmt(&**self, f)
+            }
+        }
+
+        #[cfg(test)]
+        mod tests {
+            extern crate std;
+
+            use super::*;
+            use smart_pointer::SmartPointer;
+            use std::string::String;
+            use std::vec::Vec;
+
+            // A small xorshift generator, so that the inputs are random but reproducible.
+            fn random_strings() -> impl Iterator<Item = String> {
+                const ALPHABET: [char; 9] = ['a', 'b', ',', ' ', '\t', '\n', '\r', 'é', '\u{3000}'];
+                let mut state: u64 = 0x2545_f491_4f6c_dd1d;
+                let mut next = move || {
+                    state ^= state << 13;
+                    state ^= state >> 7;
+                    state ^= state << 17;
+                    state
+                };
+                let count = if cfg!(miri) { 20 } else { 500 };
+                (0..count).map(move |_| {
+                    let len = (next() % 24) as usize;
+                    (0..len).map(|_| ALPHABET[(next() % ALPHABET.len() as u64) as usize]).collect()
+                })
+            }
+
+            fn check<'a, S, V>(s: &'a str, std_parts: S, views: V)
+            where
+                S: DoubleEndedIterator<Item = &'a str> + Clone,
+                V: DoubleEndedIterator<Item = $str_view>,
+            {
+                let views: Vec<$str_view> = views.collect();
+                let expected: Vec<&str> = std_parts.clone().collect();
+                assert_eq!(views.iter().map(|v| &**v).collect::<Vec<_>>(), expected, "{:?}", s);
+                for (view, part) in views.iter().zip(std_parts) {
+                    assert_eq!(view.range().start, part.as_ptr() as usize - s.as_ptr() as usize);
+                }
+            }
+
+            #[test]
+            fn splitting_matches_str() {
+                for s in random_strings() {
+                    let p: $ptr<str> = $ptr::from(&*s);
+                    let parent: &str = &p;
+                    check(parent, parent.split(','), $ptr::split_shared(p.clone(), ','));
+                    check(parent, parent.split('é'), $ptr::split_shared(p.clone(), 'é'));
+                    check(parent, parent.lines(), $ptr::lines_shared(p.clone()));
+                    let words = $ptr::split_whitespace_shared(p.clone());
+                    check(parent, parent.split_whitespace(), words);
+
+                    let backwards: Vec<$str_view> =
+                        $ptr::split_shared(p.clone(), ',').rev().collect();
+                    assert_eq!(
+                        backwards.iter().map(|v| &**v).collect::<Vec<_>>(),
+                        parent.split(',').rev().collect::<Vec<_>>(),
+                    );
+                    let backwards: Vec<$str_view> = $ptr::lines_shared(p.clone()).rev().collect();
+                    assert_eq!(
+                        backwards.iter().map(|v| &**v).collect::<Vec<_>>(),
+                        parent.lines().rev().collect::<Vec<_>>(),
+                    );
+                }
+            }
+
+            #[test]
+            fn views_keep_the_parent_alive() {
+                let p: $ptr<str> = $ptr::from(String::from("a,,b,").as_str());
+                let parts: Vec<$str_view> = $ptr::split_shared(p, ',').collect();
+                assert_eq!(parts, ["a", "", "b", ""]);
+                assert!($ptr::ptr_eq(parts[0].parent(), parts[3].parent()));
+                let b = parts[2].clone();
+                drop(parts);
+                assert_eq!(b, "b");
+                assert_eq!(b.range(), 3..4);
+                assert_eq!(&*b.parent().clone(), "a,,b,");
+            }
+
+            #[test]
+            fn nested_views_are_relative_to_their_view() {
+                let p: $ptr<str> = $ptr::from("grüße, welt");
+                let greeting = $ptr::substr(&p, ..8);
+                assert_eq!(greeting, "grüße,");
+                let inner = greeting.substr(2..);
+                assert_eq!(inner, "üße,");
+                assert_eq!(inner.range(), 2..8);
+                let innermost = inner.substr(2..4).substr(..=1);
+                assert_eq!(innermost, "ß");
+                assert_eq!(innermost.range(), 4..6);
+                assert_eq!(inner.substr(5..5), "");
+                assert!($ptr::ptr_eq(innermost.parent(), &p));
+
+                let p: $ptr<[u8]> = $ptr::from(&[0, 1, 2, 3, 4, 5][..]);
+                let view = $ptr::slice(&p, 1..).slice(1..4).slice(1..);
+                assert_eq!(&*view, &[3, 4]);
+                assert_eq!(view.range(), 3..5);
+                let view = SharedSlice::slice(view, 1..2);
+                assert_eq!(&*view, &[4]);
+                assert_eq!(view.range(), 4..5);
+                assert!(view.slice(1..).is_empty());
+            }
+
+            fn panic_message(f: impl FnOnce()) -> String {
+                use std::panic::{self, AssertUnwindSafe};
+
+                let payload = panic::catch_unwind(AssertUnwindSafe(f)).unwrap_err();
+                match payload.downcast::<String>() {
+                    Ok(message) => *message,
+                    Err(payload) => String::from(*payload.downcast::<&str>().unwrap()),
+                }
+            }
+
+            #[test]
+            fn out_of_range_views_panic() {
+                use std::ops::Bound::{Excluded, Included};
+
+                let p: $ptr<str> = $ptr::from("grüße");
+                let out_of_range = "range end 8 out of range for length 7";
+                assert_eq!(panic_message(|| drop($ptr::substr(&p, 2..8))), out_of_range);
+                let decreasing = "range starts at 3 but ends at 2";
+                let range = (Included(3), Excluded(2));
+                assert_eq!(panic_message(|| drop($ptr::substr(&p, range))), decreasing);
+                let boundaries = "substring 0..3 does not lie on char boundaries";
+                assert_eq!(panic_message(|| drop($ptr::substr(&p, ..3))), boundaries);
+
+                // Nested views check the range against themselves, not against the parent.
+                let view = $ptr::substr(&p, 1..4);
+                let out_of_range = "range end 4 out of range for length 3";
+                assert_eq!(panic_message(|| drop(view.substr(..4))), out_of_range);
+                let boundaries = "substring 0..2 does not lie on char boundaries";
+                assert_eq!(panic_message(|| drop(view.substr(..2))), boundaries);
+
+                let p: $ptr<[u8]> = $ptr::from(&[0, 1, 2, 3][..]);
+                let out_of_range = "range end 5 out of range for length 4";
+                assert_eq!(panic_message(|| drop($ptr::slice(&p, ..5))), out_of_range);
+                let view = $ptr::slice(&p, 2..);
+                let out_of_range = "range end 3 out of range for length 2";
+                assert_eq!(panic_message(|| drop(view.slice(1..3))), out_of_range);
+                let view = SharedSlice::slice(view, 0..1);
+                let decreasing = "range starts at 2 but ends at 1";
+                assert_eq!(panic_message(|| drop(view.slice(2..))), decreasing);
+            }
+
+            #[test]
+            fn subslices_become_unique_only_when_whole() {
+                let p: $ptr<[u8]> = $ptr::from(&[1, 2, 3, 4][..]);
+                let view = $ptr::slice(&p, 1..3);
+                assert_eq!(&*view, &[2, 3]);
+                assert_eq!(&*view.slice(1..), &[3]);
+                drop(p);
+
+                let view = SharedSlice::try_into_unique(view).unwrap_err();
+                let whole = $slice_view::from($ptr::clone(view.parent()));
+                drop(view);
+                let mut unique = SharedSlice::try_into_unique(whole).unwrap();
+                unique[0] = 5;
+                assert_eq!(&*unique, &[5, 2, 3, 4]);
+            }
+        }
+    };
+}
+
+pub(crate) use shared_views;
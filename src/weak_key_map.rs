@@ -0,0 +1,181 @@
+use base::collections::HashMap;
+use base::fmt;
+
+use base::prelude::v1::*;
+
+use crate::arc::{Arc, WeakArc};
+
+/// A map whose keys are `WeakArc`s, compared by the allocation they point to, e.g. to attach
+/// metadata to shared values without extending them. An entry is dropped once its key is gone.
+///
+/// Entries are looked up by the address of the allocation of their key. Since each entry keeps
+/// the allocation of its key alive (though not the key itself), no other allocation can get the
+/// same address while the entry exists, so lookups never confuse allocations.
+///
+/// Entries whose key has been dropped are not removed right away. The lookups ignore them, and
+/// `WeakKeyHashMap::purge` removes them. Inserting into a full map removes them as well before
+/// the map grows.
+///
+/// ```
+/// use reference_counted::{Arc, WeakKeyHashMap};
+/// use smart_pointer::SmartPointer;
+///
+/// let mut labels = WeakKeyHashMap::new();
+/// let node = Arc::new(vec![1, 2, 3]);
+/// labels.insert(&node, "root");
+/// assert_eq!(labels.get(&node), Some(&"root"));
+/// // Equal values in other allocations are different keys.
+/// assert_eq!(labels.get(&Arc::new(vec![1, 2, 3])), None);
+///
+/// drop(node);
+/// assert_eq!(labels.iter().count(), 0);
+/// ```
+pub struct WeakKeyHashMap<K: ?Sized, V> {
+    entries: HashMap<usize, (WeakArc<K>, V)>,
+}
+
+fn address<K: ?Sized>(key: &Arc<K>) -> usize {
+    Arc::as_ptr(key) as *const u8 as usize
+}
+
+impl<K: ?Sized, V> WeakKeyHashMap<K, V> {
+    /// Creates an empty map.
+    pub fn new() -> Self {
+        WeakKeyHashMap { entries: HashMap::new() }
+    }
+
+    /// Gets the number of entries in the map, including those whose key has been dropped but
+    /// that have not been removed yet.
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    /// Returns `true` if the map contains no entries, not even ones whose key has been dropped.
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Associates `value` with the allocation of `key`, returning the value that was associated
+    /// with it before.
+    pub fn insert(&mut self, key: &Arc<K>, value: V) -> Option<V> {
+        // Clean up before the table grows, rather than growing it for dead entries.
+        if self.entries.len() == self.entries.capacity() {
+            self.purge();
+        }
+        self.entries.insert(address(key), (Arc::downgrade(key), value)).map(|(_, old)| old)
+    }
+
+    /// Gets the value associated with the allocation of `key`.
+    pub fn get(&self, key: &Arc<K>) -> Option<&V> {
+        self.entries.get(&address(key)).map(|(_, value)| value)
+    }
+
+    /// Gets the value associated with the allocation of `key` for mutation.
+    pub fn get_mut(&mut self, key: &Arc<K>) -> Option<&mut V> {
+        self.entries.get_mut(&address(key)).map(|(_, value)| value)
+    }
+
+    /// Removes the value associated with the allocation of `key`, and returns it.
+    pub fn remove(&mut self, key: &Arc<K>) -> Option<V> {
+        self.entries.remove(&address(key)).map(|(_, value)| value)
+    }
+
+    /// Removes all entries whose key has been dropped, as well as those for which `f` returns
+    /// `false`.
+    pub fn retain_live<F: FnMut(&Arc<K>, &mut V) -> bool>(&mut self, mut f: F) {
+        self.entries.retain(|_, (weak, value)| match weak.upgrade() {
+            Some(key) => f(&key, value),
+            None => false,
+        });
+    }
+
+    /// Removes all entries whose key has been dropped.
+    pub fn purge(&mut self) {
+        self.entries.retain(|_, (weak, _)| weak.strong_count() != 0);
+    }
+
+    /// Iterates over the entries whose key is still alive, in arbitrary order.
+    pub fn iter(&self) -> impl Iterator<Item = (Arc<K>, &V)> + '_ {
+        self.entries.values().filter_map(|(weak, value)| Some((weak.upgrade()?, value)))
+    }
+}
+
+impl<K: ?Sized, V> Default for WeakKeyHashMap<K, V> {
+    fn default() -> Self {
+        WeakKeyHashMap::new()
+    }
+}
+
+impl<K: ?Sized, V: fmt::Debug> fmt::Debug for WeakKeyHashMap<K, V> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_list().entries(self.iter().map(|(_, value)| value)).finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+
+    use super::*;
+    use smart_pointer::SmartPointer;
+
+    #[test]
+    fn entries_vanish_with_their_key() {
+        let mut map = WeakKeyHashMap::new();
+        let a = Arc::new(String::from("a"));
+        let b = Arc::new(String::from("a"));
+        assert_eq!(map.insert(&a, 1), None);
+        assert_eq!(map.insert(&b, 2), None);
+        assert_eq!(map.insert(&a.clone(), 3), Some(1));
+        assert_eq!(map.get(&a), Some(&3));
+        *map.get_mut(&b).unwrap() += 10;
+        assert_eq!(map.get(&b), Some(&12));
+
+        drop(a);
+        let live: Vec<_> = map.iter().map(|(key, value)| (key, *value)).collect();
+        assert_eq!(live.len(), 1);
+        assert!(Arc::ptr_eq(&live[0].0, &b));
+        assert_eq!(live[0].1, 12);
+        assert_eq!(map.len(), 2);
+
+        map.purge();
+        assert_eq!(map.len(), 1);
+        assert_eq!(map.remove(&b), Some(12));
+        assert!(map.is_empty());
+    }
+
+    #[test]
+    fn retain_live_filters_live_entries() {
+        let mut map = WeakKeyHashMap::new();
+        let keys: Vec<Arc<u32>> = (0..6).map(Arc::new).collect();
+        for key in &keys {
+            map.insert(key, **key * 10);
+        }
+        map.insert(&Arc::new(99), 990);
+        assert_eq!(map.len(), 7);
+
+        map.retain_live(|key, value| {
+            *value += 1;
+            **key % 2 == 0
+        });
+        assert_eq!(map.len(), 3);
+        assert_eq!(map.get(&keys[2]), Some(&21));
+        assert_eq!(map.get(&keys[3]), None);
+    }
+
+    #[test]
+    fn full_maps_purge_before_growing() {
+        let mut map = WeakKeyHashMap::new();
+        let kept = Arc::new(0);
+        map.insert(&kept, 0);
+        while map.len() < map.entries.capacity() {
+            map.insert(&Arc::new(1), 1);
+        }
+        let capacity = map.entries.capacity();
+
+        map.insert(&Arc::new(2), 2);
+        assert_eq!(map.entries.capacity(), capacity);
+        assert_eq!(map.len(), 2);
+        assert_eq!(map.get(&kept), Some(&0));
+    }
+}
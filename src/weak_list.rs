@@ -0,0 +1,220 @@
+use base::fmt;
+#[cfg(feature = "std")]
+use base::sync::{Mutex, MutexGuard, PoisonError};
+
+use base::prelude::v1::*;
+
+use crate::arc::{Arc, WeakArc};
+
+/// A list of `WeakArc`s to subscribers, e.g. the observers of an event source, which does not
+/// keep the subscribers alive.
+///
+/// `WeakList::for_each_alive` visits the subscribers that are still alive, and removes the
+/// others in the same pass. Use a `SyncWeakList` to share the list between threads, or to
+/// subscribe while visiting the subscribers.
+///
+/// ```
+/// use reference_counted::{Arc, WeakList};
+/// use smart_pointer::SmartPointer;
+///
+/// let mut observers = WeakList::new();
+/// let a = Arc::new("a");
+/// let b = Arc::new("b");
+/// observers.subscribe(&a);
+/// observers.subscribe(&b);
+///
+/// drop(b);
+/// let mut notified = Vec::new();
+/// observers.for_each_alive(|o| notified.push(**o));
+/// assert_eq!(notified, ["a"]);
+/// assert_eq!(observers.len_alive(), 1);
+/// ```
+pub struct WeakList<T: ?Sized> {
+    entries: Vec<WeakArc<T>>,
+}
+
+impl<T: ?Sized> WeakList<T> {
+    /// Creates an empty list.
+    pub const fn new() -> Self {
+        WeakList { entries: Vec::new() }
+    }
+
+    /// Adds a `WeakArc` to `subscriber` to the end of the list.
+    pub fn subscribe(&mut self, subscriber: &Arc<T>) {
+        self.entries.push(Arc::downgrade(subscriber));
+    }
+
+    /// Calls `f` with each subscriber that is still alive, in the order of subscription, and
+    /// removes the subscribers that are not.
+    ///
+    /// A subscriber whose last `Arc` is dropped by `f` is not visited anymore, and removed as
+    /// well.
+    pub fn for_each_alive<F: FnMut(&Arc<T>)>(&mut self, mut f: F) {
+        self.entries.retain(|weak| match weak.upgrade() {
+            Some(subscriber) => {
+                f(&subscriber);
+                drop(subscriber);
+                weak.strong_count() != 0
+            }
+            None => false,
+        });
+    }
+
+    /// Gets the number of subscribers that are still alive.
+    pub fn len_alive(&self) -> usize {
+        self.entries.iter().filter(|weak| weak.strong_count() != 0).count()
+    }
+}
+
+impl<T: ?Sized> Default for WeakList<T> {
+    fn default() -> Self {
+        WeakList::new()
+    }
+}
+
+impl<T: ?Sized> fmt::Debug for WeakList<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("WeakList").field("len", &self.entries.len()).finish()
+    }
+}
+
+/// A `WeakList` behind a `Mutex`, which can be shared between threads.
+///
+/// The lock is not held while the subscribers are visited, so they may subscribe others (or
+/// themselves) in the meantime. Subscribers that are added while `SyncWeakList::for_each_alive`
+/// runs are first visited by the next call.
+#[cfg(feature = "std")]
+pub struct SyncWeakList<T: ?Sized> {
+    list: Mutex<WeakList<T>>,
+}
+
+#[cfg(feature = "std")]
+impl<T: ?Sized> SyncWeakList<T> {
+    /// Creates an empty list.
+    pub const fn new() -> Self {
+        SyncWeakList { list: Mutex::new(WeakList::new()) }
+    }
+
+    // The list is consistent at all times, so a panic while it was locked does not matter.
+    fn lock(&self) -> MutexGuard<'_, WeakList<T>> {
+        self.list.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// Adds a `WeakArc` to `subscriber` to the end of the list.
+    pub fn subscribe(&self, subscriber: &Arc<T>) {
+        self.lock().subscribe(subscriber);
+    }
+
+    /// Calls `f` with each subscriber that is still alive, in the order of subscription, and
+    /// removes the subscribers that are not.
+    ///
+    /// The subscribers to visit are determined before `f` is first called, and are kept alive
+    /// until all of them have been visited.
+    pub fn for_each_alive<F: FnMut(&Arc<T>)>(&self, f: F) {
+        let mut alive = Vec::new();
+        self.lock().for_each_alive(|subscriber| alive.push(subscriber.clone()));
+        alive.iter().for_each(f);
+    }
+
+    /// Gets the number of subscribers that are still alive.
+    pub fn len_alive(&self) -> usize {
+        self.lock().len_alive()
+    }
+}
+
+#[cfg(feature = "std")]
+impl<T: ?Sized> Default for SyncWeakList<T> {
+    fn default() -> Self {
+        SyncWeakList::new()
+    }
+}
+
+#[cfg(feature = "std")]
+impl<T: ?Sized> fmt::Debug for SyncWeakList<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("SyncWeakList").field("len", &self.lock().entries.len()).finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+
+    use std::cell::RefCell;
+    use std::vec;
+
+    use super::*;
+    use smart_pointer::SmartPointer;
+
+    #[test]
+    fn subscribers_can_drop_during_iteration() {
+        let mut list = WeakList::new();
+        let held = RefCell::new(vec![Arc::new(1), Arc::new(2), Arc::new(3), Arc::new(4)]);
+        held.borrow().iter().for_each(|s| list.subscribe(s));
+        assert_eq!(list.len_alive(), 4);
+
+        let mut seen = Vec::new();
+        list.for_each_alive(|s| {
+            seen.push(**s);
+            // The first subscriber drops a later one, the third drops itself.
+            match **s {
+                1 => held.borrow_mut().retain(|h| **h != 2),
+                3 => held.borrow_mut().retain(|h| **h != 3),
+                _ => {}
+            }
+        });
+        assert_eq!(seen, [1, 3, 4]);
+        assert_eq!(list.entries.len(), 2);
+        assert_eq!(list.len_alive(), 2);
+
+        drop(held);
+        assert_eq!(list.len_alive(), 0);
+        list.for_each_alive(|_| unreachable!());
+        assert!(list.entries.is_empty());
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn subscribers_can_subscribe_during_iteration() {
+        let list = SyncWeakList::new();
+        let first = Arc::new(0);
+        list.subscribe(&first);
+
+        let mut added = Vec::new();
+        let mut seen = Vec::new();
+        list.for_each_alive(|s| {
+            seen.push(**s);
+            let next = Arc::new(**s + 1);
+            list.subscribe(&next);
+            list.subscribe(s);
+            added.push(next);
+        });
+        assert_eq!(seen, [0]);
+        assert_eq!(list.len_alive(), 3);
+
+        seen.clear();
+        list.for_each_alive(|s| seen.push(**s));
+        assert_eq!(seen, [0, 1, 0]);
+
+        drop((first, added));
+        assert_eq!(list.len_alive(), 0);
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn sync_lists_are_shared_between_threads() {
+        let list = SyncWeakList::new();
+        let subscribers: Vec<Arc<usize>> = (0..4).map(Arc::new).collect();
+        std::thread::scope(|scope| {
+            for subscriber in &subscribers {
+                let list = &list;
+                scope.spawn(move || list.subscribe(subscriber));
+            }
+        });
+
+        let mut seen = Vec::new();
+        list.for_each_alive(|s| seen.push(**s));
+        seen.sort_unstable();
+        assert_eq!(seen, [0, 1, 2, 3]);
+    }
+}
@@ -0,0 +1,147 @@
+use base::borrow::Borrow;
+use base::collections::HashMap;
+use base::fmt;
+use base::hash::Hash;
+
+use base::prelude::v1::*;
+
+use crate::arc::{Arc, WeakArc};
+
+/// A map from keys to `WeakArc`s, for caches that hand out shared values by key without keeping
+/// them alive: an entry lives only as long as there are `Arc`s to its value elsewhere.
+///
+/// Entries whose value has been dropped are not removed right away. `WeakValueMap::get` ignores
+/// them, `WeakValueMap::insert` replaces them, and `WeakValueMap::purge` removes all of them.
+///
+/// ```
+/// use reference_counted::{Arc, WeakValueMap};
+/// use smart_pointer::SmartPointer;
+///
+/// let mut fonts = WeakValueMap::new();
+/// let mono = Arc::new(String::from("monospace"));
+/// fonts.insert("mono", &mono);
+/// assert!(Arc::ptr_eq(&fonts.get("mono").unwrap(), &mono));
+///
+/// drop(mono);
+/// assert!(fonts.get("mono").is_none());
+/// ```
+pub struct WeakValueMap<K, T: ?Sized> {
+    entries: HashMap<K, WeakArc<T>>,
+}
+
+impl<K, T: ?Sized> WeakValueMap<K, T> {
+    /// Creates an empty map.
+    pub fn new() -> Self {
+        WeakValueMap { entries: HashMap::new() }
+    }
+
+    /// Gets the number of entries in the map, including those whose value has been dropped but
+    /// that have not been removed yet.
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    /// Returns `true` if the map contains no entries, not even ones whose value has been
+    /// dropped.
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Removes all entries whose value has been dropped.
+    pub fn purge(&mut self) {
+        self.entries.retain(|_, weak| weak.strong_count() != 0);
+    }
+}
+
+impl<K: Hash + Eq, T: ?Sized> WeakValueMap<K, T> {
+    /// Returns an `Arc` to the value of `key`, or `None` if there is no entry for `key` or its
+    /// value has been dropped.
+    pub fn get<Q: ?Sized + Hash + Eq>(&self, key: &Q) -> Option<Arc<T>>
+    where
+        K: Borrow<Q>,
+    {
+        self.entries.get(key).and_then(WeakArc::upgrade)
+    }
+
+    /// Stores a `WeakArc` to `value` under `key`, returning the previous value of `key` if it
+    /// was still alive.
+    pub fn insert(&mut self, key: K, value: &Arc<T>) -> Option<Arc<T>> {
+        self.entries.insert(key, Arc::downgrade(value)).and_then(|old| old.upgrade())
+    }
+
+    /// Removes the entry of `key`, returning its value if it was still alive.
+    pub fn remove<Q: ?Sized + Hash + Eq>(&mut self, key: &Q) -> Option<Arc<T>>
+    where
+        K: Borrow<Q>,
+    {
+        self.entries.remove(key).and_then(|old| old.upgrade())
+    }
+}
+
+impl<K, T: ?Sized> Default for WeakValueMap<K, T> {
+    fn default() -> Self {
+        WeakValueMap::new()
+    }
+}
+
+impl<K: fmt::Debug, T: ?Sized> fmt::Debug for WeakValueMap<K, T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_set().entries(self.entries.keys()).finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+
+    use super::*;
+    use smart_pointer::SmartPointer;
+
+    #[test]
+    fn entries_die_with_their_last_arc() {
+        let mut map = WeakValueMap::new();
+        let value = Arc::new(String::from("a"));
+        let other = value.clone();
+        assert!(map.insert(1, &value).is_none());
+        assert!(Arc::ptr_eq(&map.get(&1).unwrap(), &value));
+        assert!(map.get(&2).is_none());
+
+        drop(value);
+        assert_eq!(*map.get(&1).unwrap(), "a");
+        drop(other);
+        assert!(map.get(&1).is_none());
+        assert_eq!(map.len(), 1);
+    }
+
+    #[test]
+    fn dead_entries_are_replaced_on_insert() {
+        let mut map = WeakValueMap::new();
+        drop(map.insert("key", &Arc::new(1)));
+        assert!(map.get("key").is_none());
+
+        let fresh = Arc::new(2);
+        assert!(map.insert("key", &fresh).is_none());
+        assert_eq!(*map.get("key").unwrap(), 2);
+        assert_eq!(map.len(), 1);
+
+        // Replacing a live entry hands back its value.
+        let newer = Arc::new(3);
+        assert!(Arc::ptr_eq(&map.insert("key", &newer).unwrap(), &fresh));
+        assert!(Arc::ptr_eq(&map.remove("key").unwrap(), &newer));
+        assert!(map.is_empty());
+    }
+
+    #[test]
+    fn purge_removes_only_dead_entries() {
+        let mut map = WeakValueMap::new();
+        let kept: Arc<str> = Arc::from("kept");
+        map.insert(1, &kept);
+        map.insert(2, &Arc::from("dropped"));
+        map.insert(3, &Arc::from("dropped"));
+        assert_eq!(map.len(), 3);
+
+        map.purge();
+        assert_eq!(map.len(), 1);
+        assert_eq!(&*map.get(&1).unwrap(), "kept");
+    }
+}
@@ -0,0 +1,153 @@
+//! Checks that creating, cloning and dropping pointers compiles to code without any panicking
+//! paths when the `panic-free` feature is enabled.
+//!
+//! This builds `examples/panic_free.rs` and the library itself in release mode with
+//! `panic = "abort"`, emits their LLVM IR, and walks the call graph of the example's functions.
+//! Every function they can reach must either be defined in one of the two IR files (and then is
+//! checked in turn) or be one of a few known external functions, none of which can panic.
+#![cfg(all(feature = "panic-free", feature = "rc", feature = "arc"))]
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+/// External functions the checked code may call.
+const ALLOWED: &[&str] = &[
+    "__rust_alloc",
+    "__rust_dealloc",
+    "__rust_realloc",
+    "__rust_no_alloc_shim_is_unstable",
+    "handle_alloc_error",
+    "process5abort",
+    "llvm.",
+];
+
+/// Builds the given target with `--emit=llvm-ir` and returns the path of the emitted file.
+fn emit_ir(target: &[&str], ir_dir: &str, prefix: &str) -> PathBuf {
+    let target_dir = Path::new(env!("CARGO_TARGET_TMPDIR")).join("panic-free");
+    let status = Command::new(env!("CARGO"))
+        .arg("rustc")
+        .arg("--quiet")
+        .arg("--release")
+        .arg("--manifest-path")
+        .arg(Path::new(env!("CARGO_MANIFEST_DIR")).join("Cargo.toml"))
+        .args(target)
+        .args(["--features", "panic-free", "--", "--emit=llvm-ir"])
+        .env("CARGO_TARGET_DIR", &target_dir)
+        .env("CARGO_PROFILE_RELEASE_PANIC", "abort")
+        .env("CARGO_PROFILE_RELEASE_CODEGEN_UNITS", "1")
+        .status()
+        .expect("failed to run cargo");
+    assert!(status.success(), "building {:?} failed", target);
+
+    // Pick the most recently written file, older builds may have left other hashes around.
+    fs::read_dir(target_dir.join("release").join(ir_dir))
+        .unwrap()
+        .map(|entry| entry.unwrap().path())
+        .filter(|path| {
+            let name = path.file_name().unwrap().to_str().unwrap();
+            name.starts_with(prefix) && name.ends_with(".ll")
+        })
+        .max_by_key(|path| fs::metadata(path).unwrap().modified().unwrap())
+        .expect("no LLVM IR was emitted")
+}
+
+/// Maps every function defined in the IR to the names of the functions it calls, and every
+/// alias to the function it stands for.
+fn call_graph(ir: &str) -> BTreeMap<String, BTreeSet<String>> {
+    let mut graph = BTreeMap::new();
+    let mut current: Option<(String, BTreeSet<String>)> = None;
+    for line in ir.lines() {
+        if line.starts_with("define ") {
+            current = Some((symbol_after_at(line).unwrap(), BTreeSet::new()));
+        } else if line.starts_with('@') && line.contains(" alias ") {
+            // Functions with identical bodies are merged, the others become aliases.
+            let target = symbol_after_at(&line[line.rfind('@').unwrap()..]).unwrap();
+            graph.insert(symbol_after_at(line).unwrap(), std::iter::once(target).collect());
+        } else if line == "}" {
+            if let Some((name, callees)) = current.take() {
+                graph.insert(name, callees);
+            }
+        } else if let Some((_, callees)) = current.as_mut() {
+            let line = line.trim_start();
+            let line = line.split_once(" = ").map_or(line, |(_, rest)| rest);
+            let line = line.strip_prefix("tail ").unwrap_or(line);
+            if line.starts_with("call ") || line.starts_with("invoke ") {
+                if let Some(callee) = symbol_after_at(line) {
+                    callees.insert(callee);
+                }
+            }
+        }
+    }
+    graph
+}
+
+/// Returns the first global symbol (`@name`) of an IR line.
+fn symbol_after_at(line: &str) -> Option<String> {
+    let start = line.find('@')? + 1;
+    let name = &line[start..];
+    let name = name.strip_prefix('"').unwrap_or(name);
+    let end = name
+        .find(|c: char| !(c.is_ascii_alphanumeric() || "_.$".contains(c)))
+        .unwrap_or(name.len());
+    Some(name[..end].to_string())
+}
+
+/// Asserts that everything reachable from `roots` only ends up calling `allowed` externals.
+fn assert_reaches_only(
+    graph: &BTreeMap<String, BTreeSet<String>>,
+    roots: &[&str],
+    allowed: &[&str],
+) {
+    let mut seen = BTreeSet::new();
+    let mut stack: Vec<String> = roots.iter().map(|root| root.to_string()).collect();
+    for root in roots {
+        assert!(graph.contains_key(*root), "`{}` is not defined in the IR", root);
+    }
+
+    while let Some(function) = stack.pop() {
+        if !seen.insert(function.clone()) {
+            continue;
+        }
+        assert!(
+            !function.contains("panic"),
+            "`{}` is reachable from {:?}",
+            function,
+            roots
+        );
+        match graph.get(&function) {
+            Some(callees) => stack.extend(callees.iter().cloned()),
+            None => assert!(
+                allowed.iter().any(|allowed| function.contains(allowed)),
+                "`{}` is reachable from {:?}, but not known to be panic-free",
+                function,
+                roots
+            ),
+        }
+    }
+}
+
+#[test]
+fn creating_cloning_and_dropping_does_not_panic() {
+    let example = emit_ir(&["--example", "panic_free"], "examples", "panic_free-");
+    let mut graph = call_graph(&fs::read_to_string(example).unwrap());
+
+    // The example calls the non-generic functions of the library, such as `refcount_overflow`,
+    // which are only defined in the IR of the library.
+    let lib = emit_ir(&["--lib"], "deps", "reference_counted-");
+    for (function, callees) in call_graph(&fs::read_to_string(lib).unwrap()) {
+        graph.entry(function).or_insert(callees);
+    }
+
+    let canaries = [
+        "canary_arc",
+        "canary_rc",
+        "canary_arc_slice",
+        "canary_rc_slice",
+        "canary_arc_str",
+        "canary_rc_str",
+        "canary_aligned",
+    ];
+    assert_reaches_only(&graph, &canaries, ALLOWED);
+}